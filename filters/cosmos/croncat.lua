@@ -0,0 +1,20 @@
+-- Successful CronCat `proxy_call`s, from the `wasm` events of the transaction.
+function proxy_call(tx)
+    if tx.code ~= 0 then
+        return false
+    end
+    for _, event in ipairs(tx.events) do
+        if event.type == "wasm" then
+            for _, attribute in ipairs(event.attributes) do
+                if attribute.key == "action" and attribute.value == "proxy_call" then
+                    return true
+                end
+            end
+        end
+    end
+    return false
+end
+
+return {
+    proxy_call = proxy_call
+}
@@ -0,0 +1,12 @@
+-- `proxy_call`s of the CronCat manager, from its wasm events.
+local manager = "juno1croncatmanager000000000000000000000000000"
+
+function manager_proxy_call(event)
+    return event.type == "wasm"
+        and event.attrs._contract_address == manager
+        and event.attrs.action == "proxy_call"
+end
+
+return {
+    manager_proxy_call = manager_proxy_call
+}
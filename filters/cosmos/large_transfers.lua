@@ -0,0 +1,27 @@
+-- Bank sends of more than 100 000 JUNO. Amounts are strings: compare their digits rather than
+-- converting them to numbers.
+local threshold = "100000000000"
+
+local function above(amount)
+    if #amount ~= #threshold then
+        return #amount > #threshold
+    end
+    return amount > threshold
+end
+
+function large_transfer(tx)
+    for _, message in ipairs(tx.messages) do
+        if message.type_url == "/cosmos.bank.v1beta1.MsgSend" then
+            for _, coin in ipairs(message.amount) do
+                if coin.denom == "ujuno" and above(coin.amount) then
+                    return true
+                end
+            end
+        end
+    end
+    return false
+end
+
+return {
+    large_transfer = large_transfer
+}
@@ -0,0 +1,7 @@
+function filter(tx)
+    return tx.from == "0xDEADBEEF"
+end
+
+return {
+    filter = filter
+}
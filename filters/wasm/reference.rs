@@ -0,0 +1,111 @@
+//! The reference WebAssembly filters of the tests, for the `WasmBackend`.
+//!
+//! Each module is built from this file with a `--cfg` naming it:
+//!
+//! ```sh
+//! for module in rules watch broken limits; do
+//!     rustc --target wasm32-unknown-unknown --crate-type cdylib -C opt-level=s \
+//!         -C panic=abort -C strip=symbols --cfg $module \
+//!         -o filters/wasm/$module.wasm filters/wasm/reference.rs
+//! done
+//! ```
+//!
+//! Values are serialized by serde_json, so the fields are looked up in the compact JSON the
+//! host writes rather than parsed.
+
+#![no_std]
+// Each module only uses the helpers its filters need.
+#![allow(dead_code)]
+
+use core::arch::wasm32;
+
+#[panic_handler]
+fn panic(_: &core::panic::PanicInfo) -> ! {
+    wasm32::unreachable()
+}
+
+const CAPACITY: usize = 64 * 1024;
+
+static mut INPUT: [u8; CAPACITY] = [0; CAPACITY];
+
+/// Where the host writes a value of `len` bytes, or 0 if it doesn't fit.
+#[no_mangle]
+pub extern "C" fn alloc(len: usize) -> *mut u8 {
+    match len <= CAPACITY {
+        true => core::ptr::addr_of_mut!(INPUT).cast(),
+        false => core::ptr::null_mut(),
+    }
+}
+
+unsafe fn input<'a>(ptr: *const u8, len: usize) -> &'a [u8] {
+    core::slice::from_raw_parts(ptr, len)
+}
+
+/// The raw JSON of the field `name` of `value`, up to the next `,` or `}`.
+fn field<'a>(value: &'a [u8], name: &[u8]) -> Option<&'a [u8]> {
+    let mut key = [0u8; 32];
+    let key = {
+        key[0] = b'"';
+        key[1..=name.len()].copy_from_slice(name);
+        key[name.len() + 1] = b'"';
+        key[name.len() + 2] = b':';
+        &key[..name.len() + 3]
+    };
+    let start = value.windows(key.len()).position(|window| window == key)? + key.len();
+    let rest = &value[start..];
+    let end = rest
+        .iter()
+        .position(|byte| *byte == b',' || *byte == b'}')
+        .unwrap_or(rest.len());
+    Some(&rest[..end])
+}
+
+fn amount(value: &[u8]) -> u64 {
+    let digits = field(value, b"amount").unwrap_or(b"0");
+    let digits = digits.iter().take_while(|byte| byte.is_ascii_digit());
+    digits.fold(0, |amount, digit| amount * 10 + u64::from(digit - b'0'))
+}
+
+#[cfg(rules)]
+#[no_mangle]
+pub unsafe extern "C" fn filter_big(ptr: *const u8, len: usize) -> i32 {
+    (amount(input(ptr, len)) > 100) as i32
+}
+
+#[cfg(rules)]
+#[no_mangle]
+pub unsafe extern "C" fn filter_manager(ptr: *const u8, len: usize) -> i32 {
+    (field(input(ptr, len), b"from") == Some(b"\"0xDEADBEEF\"")) as i32
+}
+
+#[cfg(watch)]
+#[no_mangle]
+pub unsafe extern "C" fn filter(ptr: *const u8, len: usize) -> i32 {
+    (amount(input(ptr, len)) > 10) as i32
+}
+
+#[cfg(broken)]
+#[no_mangle]
+pub unsafe extern "C" fn filter(_ptr: *const u8, _len: usize) -> i32 {
+    wasm32::unreachable()
+}
+
+/// Grows the memory by a megabyte on every call.
+#[cfg(limits)]
+#[no_mangle]
+pub extern "C" fn filter_greedy(_ptr: *const u8, _len: usize) -> i32 {
+    (wasm32::memory_grow(0, 16) == usize::MAX) as i32
+}
+
+/// Never returns.
+#[cfg(limits)]
+#[no_mangle]
+pub extern "C" fn filter_spin(_ptr: *const u8, len: usize) -> i32 {
+    let mut count = len;
+    loop {
+        count = core::hint::black_box(count.wrapping_add(1));
+        if count == 0 {
+            return 0;
+        }
+    }
+}
@@ -0,0 +1,54 @@
+//! Reading the files of a configuration without blocking an async runtime.
+//!
+//! Only available with the `tokio` feature. The files are read with `tokio::fs`, a bounded
+//! number at a time, and handed to the same loading code as the ones read synchronously, so
+//! loading fails the same way and yields the same filters either way.
+
+use std::{
+    io,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use tokio::task::JoinSet;
+
+/// How many files are read at once.
+const CONCURRENT_READS: usize = 32;
+
+/// Read the files at `paths`, returning their contents in order, with how long reading each
+/// took.
+pub(crate) async fn read_all(paths: &[PathBuf]) -> Vec<(io::Result<Vec<u8>>, Duration)> {
+    let mut contents: Vec<Option<(io::Result<Vec<u8>>, Duration)>> =
+        paths.iter().map(|_| None).collect();
+    let mut reads = JoinSet::new();
+    let mut pending = paths.iter().cloned().enumerate();
+    loop {
+        while reads.len() < CONCURRENT_READS {
+            let Some((index, path)) = pending.next() else {
+                break;
+            };
+            reads.spawn(async move {
+                let started = Instant::now();
+                let content = tokio::fs::read(path).await;
+                (index, (content, started.elapsed()))
+            });
+        }
+        let Some(read) = reads.join_next().await else {
+            break;
+        };
+        // A read can only fail to join if the runtime is shutting down; the file is then
+        // reported as unreadable.
+        if let Ok((index, content)) = read {
+            contents[index] = Some(content);
+        }
+    }
+    contents
+        .into_iter()
+        .map(|content| {
+            content.unwrap_or_else(|| {
+                let cancelled = io::Error::other("read was cancelled");
+                (Err(cancelled), Duration::ZERO)
+            })
+        })
+        .collect()
+}
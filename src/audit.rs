@@ -0,0 +1,213 @@
+//! An audit trail of filtering decisions, see
+//! [`FilterSystem::filter_audited`](crate::FilterSystem::filter_audited).
+//!
+//! Each audited value yields an [`AuditRecord`] holding a snapshot of the value, what every
+//! filter made of it and whether it was kept, handed to the [`AuditSink`] of the filter
+//! system. [`NdjsonFileSink`] appends them to rotated files, [`ChannelSink`] passes them on.
+
+use std::{
+    collections::BTreeMap,
+    fs::{File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::mpsc,
+    time::SystemTime,
+};
+
+use serde::{Serialize, Serializer};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+/// The decision taken on a value, as audited.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct AuditRecord {
+    /// When the value was filtered, serialized as RFC 3339.
+    #[serde(serialize_with = "rfc3339")]
+    pub timestamp: SystemTime,
+    /// The value, as serialized by serde.
+    pub value: serde_json::Value,
+    /// The verdict of each filter, by id; filters that failed or ran out of fuel didn't
+    /// match.
+    pub verdicts: BTreeMap<String, bool>,
+    /// The reasons filters gave for their verdicts, as `(filter id, reason)` pairs.
+    pub reasons: Vec<(String, String)>,
+    /// Whether the value was kept.
+    pub kept: bool,
+}
+
+pub(crate) fn rfc3339<S: Serializer>(
+    timestamp: &SystemTime,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let timestamp = OffsetDateTime::from(*timestamp)
+        .format(&Rfc3339)
+        .map_err(serde::ser::Error::custom)?;
+    serializer.serialize_str(&timestamp)
+}
+
+/// Where a filter system sends its audit records, see
+/// [`FilterSystem::set_audit_sink`](crate::FilterSystem::set_audit_sink).
+///
+/// Sinks are called synchronously, once per audited value.
+pub trait AuditSink {
+    fn record(&mut self, record: AuditRecord) -> io::Result<()>;
+}
+
+/// Appends audit records to a file as NDJSON, one record per line, rotating it by size.
+///
+/// Once the file would grow over its size limit, it is renamed with a `.1` suffix, the
+/// previous `.1` becoming `.2` and so on, the oldest beyond the file limit being removed, and
+/// a new file is started.
+pub struct NdjsonFileSink {
+    path: PathBuf,
+    file: BufWriter<File>,
+    size: u64,
+    max_bytes: u64,
+    max_files: usize,
+}
+
+impl NdjsonFileSink {
+    /// Append to the file at `path`, creating it if need be. Files are rotated past 64 MiB,
+    /// keeping 5 rotated files.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path,
+            file: BufWriter::new(file),
+            size,
+            max_bytes: 64 * 1024 * 1024,
+            max_files: 5,
+        })
+    }
+
+    /// Rotate the file once it would grow over `bytes`.
+    pub fn with_max_bytes(mut self, bytes: u64) -> Self {
+        self.max_bytes = bytes;
+        self
+    }
+
+    /// Keep `files` rotated files besides the current one.
+    pub fn with_max_files(mut self, files: usize) -> Self {
+        self.max_files = files;
+        self
+    }
+
+    /// The path of the `index`th rotated file.
+    fn rotated(&self, index: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{index}"));
+        path.into()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        match self.max_files {
+            0 => std::fs::remove_file(&self.path)?,
+            max_files => {
+                for index in (1..max_files).rev() {
+                    let from = self.rotated(index);
+                    if from.exists() {
+                        std::fs::rename(from, self.rotated(index + 1))?;
+                    }
+                }
+                std::fs::rename(&self.path, self.rotated(1))?;
+            }
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.file = BufWriter::new(file);
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl AuditSink for NdjsonFileSink {
+    fn record(&mut self, record: AuditRecord) -> io::Result<()> {
+        let mut line = serde_json::to_vec(&record)?;
+        line.push(b'\n');
+        let len = line.len() as u64;
+        // A record larger than the limit still gets a file of its own.
+        if self.size > 0 && self.size + len > self.max_bytes {
+            self.rotate()?;
+        }
+        self.file.write_all(&line)?;
+        self.file.flush()?;
+        self.size += len;
+        Ok(())
+    }
+}
+
+/// Sends audit records down a channel, for the host to handle as it sees fit.
+pub struct ChannelSink(mpsc::Sender<AuditRecord>);
+
+impl ChannelSink {
+    /// A sink and the receiving end of its channel.
+    pub fn new() -> (Self, mpsc::Receiver<AuditRecord>) {
+        let (sender, receiver) = mpsc::channel();
+        (Self(sender), receiver)
+    }
+}
+
+impl From<mpsc::Sender<AuditRecord>> for ChannelSink {
+    fn from(sender: mpsc::Sender<AuditRecord>) -> Self {
+        Self(sender)
+    }
+}
+
+impl AuditSink for ChannelSink {
+    fn record(&mut self, record: AuditRecord) -> io::Result<()> {
+        self.0.send(record).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "the audit channel's receiver was dropped",
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ndjson_rotation() {
+        let dir = std::env::temp_dir().join(format!(
+            "croncat-indexer-filter-audit-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("audit.ndjson");
+        let record = |kept| AuditRecord {
+            timestamp: SystemTime::UNIX_EPOCH,
+            value: serde_json::json!({ "amount": 10 }),
+            verdicts: [("whale".to_string(), kept)].into(),
+            reasons: Vec::new(),
+            kept,
+        };
+        let line = r#"{"timestamp":"1970-01-01T00:00:00Z","value":{"amount":10},"verdicts":{"whale":false},"reasons":[],"kept":false}"#;
+
+        let mut sink = NdjsonFileSink::open(&path)
+            .unwrap()
+            .with_max_bytes(2 * (line.len() as u64 + 1))
+            .with_max_files(2);
+        for _ in 0..7 {
+            sink.record(record(false)).unwrap();
+        }
+        let read = |path: &Path| std::fs::read_to_string(path).unwrap();
+        assert_eq!(read(&path), format!("{line}\n"));
+        assert_eq!(read(&sink.rotated(1)), format!("{line}\n{line}\n"));
+        assert_eq!(read(&sink.rotated(2)), format!("{line}\n{line}\n"));
+        assert!(!sink.rotated(3).exists());
+
+        // Reopening appends to the current file.
+        drop(sink);
+        let mut sink = NdjsonFileSink::open(&path).unwrap();
+        sink.record(record(true)).unwrap();
+        assert_eq!(read(&path).lines().count(), 2);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
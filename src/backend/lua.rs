@@ -0,0 +1,106 @@
+//! The Lua backend, running filters on a runtime set up like a [`FilterRuntime`]'s.
+//!
+//! [`FilterRuntime`]: crate::FilterRuntime
+
+use std::path::Path;
+
+use mlua::{Lua, RegistryKey};
+use serde::Serialize;
+
+use super::{decode_source, BackendError, Decision, FilterBackend};
+use crate::{
+    api_version, convert, create_runtime, module_functions, precompile, FilterConfig,
+    RuntimeOptions, ScriptLanguage, API_VERSIONS,
+};
+
+/// Runs filters written in Lua, the scripts a [`FilterSystem`](crate::FilterSystem) loads.
+///
+/// The runtime has the helpers, limits and conversion options of its [`RuntimeOptions`].
+/// Filters get the value as converted into Lua, whatever their [`API_VERSIONS`] entry, and
+/// give their verdict by Lua truthiness, optionally followed by a string reason.
+pub struct LuaBackend {
+    lua: Lua,
+}
+
+impl LuaBackend {
+    /// A backend with the default options.
+    pub fn new() -> Self {
+        Self::new_with_options(RuntimeOptions::default())
+            .expect("failed to set up the filter runtime")
+    }
+
+    /// A backend with the given options.
+    pub fn new_with_options(options: RuntimeOptions) -> Result<Self, mlua::Error> {
+        Ok(Self {
+            lua: create_runtime(options)?,
+        })
+    }
+
+    /// The runtime the filters run on.
+    pub fn lua(&self) -> &Lua {
+        &self.lua
+    }
+}
+
+impl Default for LuaBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FilterBackend for LuaBackend {
+    type Filter = RegistryKey;
+
+    fn compile(
+        &mut self,
+        config: &FilterConfig,
+        source: &[u8],
+    ) -> Result<Vec<(String, RegistryKey)>, BackendError> {
+        if let Some(language @ ScriptLanguage::Rhai) = config.language {
+            return Err(format!("the Lua backend doesn't run {language}").into());
+        }
+        let source = decode_source(source)?;
+        let module: mlua::Value = self
+            .lua
+            .load(&source)
+            .set_name(precompile::chunk_name(&config.script))
+            .eval()?;
+        let functions = module_functions(&module, &config.name)??;
+        if let Err(version) = api_version(&module) {
+            let (first, last) = (API_VERSIONS.start(), API_VERSIONS.end());
+            let message =
+                format!("it declares api_version {version}, this runtime supports {first}-{last}");
+            return Err(message.into());
+        }
+        let functions = functions.into_iter();
+        let compiled = functions
+            .map(|(name, function)| Ok((name, self.lua.create_registry_value(function)?)))
+            .collect::<mlua::Result<_>>()?;
+        Ok(compiled)
+    }
+
+    fn call<V: Serialize>(
+        &self,
+        filter: &RegistryKey,
+        value: &V,
+    ) -> Result<Decision, BackendError> {
+        let function: mlua::Function = self.lua.registry_value(filter)?;
+        let argument = convert::to_lua(&self.lua, value)?;
+        let (verdict, reason): (mlua::Value, mlua::Value) = function.call(argument)?;
+        Ok(Decision {
+            matched: !matches!(verdict, mlua::Value::Nil | mlua::Value::Boolean(false)),
+            reason: reason.as_str().map(str::to_string),
+        })
+    }
+
+    fn load_library(&mut self, name: &str, path: &Path, source: &[u8]) -> Result<(), BackendError> {
+        let source = decode_source(source)?;
+        let library: mlua::Value = self
+            .lua
+            .load(&source)
+            .set_name(precompile::chunk_name(path))
+            .eval()?;
+        self.lua.globals().set(name, library)?;
+        Ok(())
+    }
+}
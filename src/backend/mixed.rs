@@ -0,0 +1,85 @@
+//! A backend running Lua and Rhai scripts side by side, with the `rhai` feature.
+
+use std::path::Path;
+
+use mlua::RegistryKey;
+use serde::Serialize;
+
+use super::{BackendError, Decision, FilterBackend, LuaBackend, RhaiBackend, RhaiFilter};
+use crate::{FilterConfig, ScriptLanguage};
+
+/// Runs each script on the backend of its language, Lua unless the configuration says
+/// otherwise, see [`FilterConfig::language`].
+///
+/// Libraries whose file has the `rhai` extension are loaded on the Rhai backend, the others on
+/// the Lua one, so scripts only see the libraries of their language.
+#[derive(Default)]
+pub struct MixedBackend {
+    lua: LuaBackend,
+    rhai: RhaiBackend,
+}
+
+/// A filter compiled by a [`MixedBackend`].
+pub enum MixedFilter {
+    Lua(RegistryKey),
+    Rhai(RhaiFilter),
+}
+
+impl MixedBackend {
+    /// Run scripts on `lua` and `rhai`.
+    pub fn new(lua: LuaBackend, rhai: RhaiBackend) -> Self {
+        Self { lua, rhai }
+    }
+
+    pub fn lua(&self) -> &LuaBackend {
+        &self.lua
+    }
+
+    pub fn rhai_mut(&mut self) -> &mut RhaiBackend {
+        &mut self.rhai
+    }
+}
+
+impl FilterBackend for MixedBackend {
+    type Filter = MixedFilter;
+
+    fn compile(
+        &mut self,
+        config: &FilterConfig,
+        source: &[u8],
+    ) -> Result<Vec<(String, MixedFilter)>, BackendError> {
+        let filters = match config.language.unwrap_or_default() {
+            ScriptLanguage::Lua => self.lua.compile(config, source)?,
+            ScriptLanguage::Rhai => {
+                let filters = self.rhai.compile(config, source)?.into_iter();
+                let filters = filters.map(|(name, filter)| (name, MixedFilter::Rhai(filter)));
+                return Ok(filters.collect());
+            }
+        };
+        let filters = filters.into_iter();
+        Ok(filters
+            .map(|(name, filter)| (name, MixedFilter::Lua(filter)))
+            .collect())
+    }
+
+    fn call<V: Serialize>(
+        &self,
+        filter: &MixedFilter,
+        value: &V,
+    ) -> Result<Decision, BackendError> {
+        match filter {
+            MixedFilter::Lua(filter) => self.lua.call(filter, value),
+            MixedFilter::Rhai(filter) => self.rhai.call(filter, value),
+        }
+    }
+
+    fn load_library(&mut self, name: &str, path: &Path, source: &[u8]) -> Result<(), BackendError> {
+        match path
+            .extension()
+            .is_some_and(|extension| extension == "rhai")
+        {
+            true => self.rhai.load_library(name, path, source),
+            false => self.lua.load_library(name, path, source),
+        }
+    }
+}
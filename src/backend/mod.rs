@@ -0,0 +1,94 @@
+//! Filtering with engines other than Lua, through the [`FilterBackend`] trait.
+//!
+//! A backend compiles the scripts of a configuration into filters and calls them with values.
+//! A [`FilterSystem`] created with [`FilterSystem::with_backend`] loads its scripts on one,
+//! and calls the filters it compiled like its own, with its error policy, dry runs, verdicts,
+//! stats and the rest. What happens within a call, such as fuel, timeouts, sandboxing and
+//! chain constants, is up to the backend. [`LuaBackend`] runs Lua scripts on a runtime of its
+//! own; a `FilterSystem<'lua, T>`, the default, runs them on its own runtime instead.
+//!
+//! [`FilterSystem`]: crate::FilterSystem
+//! [`FilterSystem::with_backend`]: crate::FilterSystem::with_backend
+
+use std::{io, path::Path};
+
+use serde::Serialize;
+
+use crate::{encoding, FilterConfig, LoadError, LoadOrigin};
+
+mod lua;
+#[cfg(feature = "rhai")]
+mod mixed;
+#[cfg(feature = "rhai")]
+mod rhai;
+#[cfg(feature = "wasm")]
+mod wasm;
+
+#[cfg(feature = "rhai")]
+pub use self::rhai::{RhaiBackend, RhaiFilter};
+pub use lua::LuaBackend;
+#[cfg(feature = "rhai")]
+pub use mixed::{MixedBackend, MixedFilter};
+#[cfg(feature = "wasm")]
+pub use wasm::{WasmBackend, WasmFilter};
+
+/// An error a backend raised, while compiling a script or calling a filter.
+pub type BackendError = Box<dyn std::error::Error + Send + Sync>;
+
+/// An engine running filters.
+pub trait FilterBackend {
+    /// A compiled filter, as the backend keeps it.
+    type Filter;
+
+    /// Compile `source`, the contents of the script of `config`, into its filters, by name.
+    ///
+    /// Backends running text scripts decode them with [`decode_source`], so undecodable
+    /// scripts fail the load with a [`LoadError::Encoding`].
+    ///
+    /// Like Lua scripts, a script may define several filters; one defining a single filter
+    /// names it after `config`. The language of `config` is the one the configuration gives
+    /// the script or its chain, if any; backends refuse the languages they don't run.
+    fn compile(
+        &mut self,
+        config: &FilterConfig,
+        source: &[u8],
+    ) -> Result<Vec<(String, Self::Filter)>, BackendError>;
+
+    /// Call `filter` with `value`.
+    fn call<V: Serialize>(
+        &self,
+        filter: &Self::Filter,
+        value: &V,
+    ) -> Result<Decision, BackendError>;
+
+    /// Evaluate `source`, the shared library `name` read from `path`, for the scripts compiled
+    /// after it.
+    ///
+    /// Backends without libraries refuse configurations that have some.
+    fn load_library(&mut self, name: &str, path: &Path, source: &[u8]) -> Result<(), BackendError> {
+        let _ = (path, source);
+        Err(format!("library {name} can't be loaded: this backend has no libraries").into())
+    }
+}
+
+/// Decode the contents of a text script or library, see [`FilterBackend::compile`].
+pub fn decode_source(source: &[u8]) -> io::Result<String> {
+    encoding::decode(source.to_vec())
+}
+
+/// The load error of `origin` for a backend `error`, taking reading errors such as those of
+/// [`decode_source`] as the library or script failing to be read.
+pub(crate) fn load_error(origin: Box<LoadOrigin>, error: BackendError) -> LoadError {
+    match error.downcast::<io::Error>() {
+        Ok(error) => LoadError::read(origin, *error),
+        Err(error) => LoadError::Backend { origin, error },
+    }
+}
+
+/// What a filter decided about a value.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Decision {
+    pub matched: bool,
+    /// Why, if the filter said, see [`Verdict::reasons`](crate::Verdict::reasons).
+    pub reason: Option<String>,
+}
@@ -0,0 +1,205 @@
+//! The Rhai backend, with the `rhai` feature.
+
+use std::{cell::RefCell, fmt::Display, path::Path, rc::Rc};
+
+use regex::Regex;
+use rhai::{Dynamic, Engine, FnAccess, FnPtr, ImmutableString, Map, Module, Scope, AST};
+use serde::Serialize;
+
+use super::{decode_source, BackendError, Decision, FilterBackend};
+use crate::{helpers::cache::LruCache, FilterConfig, RuntimeOptions, ScriptLanguage};
+
+/// A filter compiled by a [`RhaiBackend`].
+pub struct RhaiFilter {
+    ast: Rc<AST>,
+    function: FnPtr,
+}
+
+/// Runs filters written in Rhai.
+///
+/// A script defines its filters as functions taking the value, named after them; `private`
+/// functions are helpers. A script may instead evaluate to a closure, `|tx| tx.amount > 100`,
+/// named after its filter like a Lua script returning a single function. Values are passed as
+/// Rhai maps, converted through their `Serialize` implementation. Filters return a boolean, or
+/// a verdict map such as `#{ matched: false, reason: "sender not allowlisted" }`.
+///
+/// Libraries are Rhai modules: the variables they `export` and their functions are available
+/// to scripts as `name::item`. Scripts get `json::encode`, `json::decode`, `re::is_match`,
+/// `re::find` and `re::captures`, the Rhai equivalents of the Lua helpers.
+pub struct RhaiBackend {
+    engine: Engine,
+}
+
+impl RhaiBackend {
+    /// A backend with the helpers, and the default limits of Rhai.
+    pub fn new() -> Self {
+        let mut engine = Engine::new();
+        engine.register_static_module("json", json().into());
+        let cache_size = RuntimeOptions::default().regex_cache_size;
+        engine.register_static_module("re", re(cache_size).into());
+        Self { engine }
+    }
+
+    /// The engine the filters run on, to set its limits or register more functions.
+    pub fn engine_mut(&mut self) -> &mut Engine {
+        &mut self.engine
+    }
+}
+
+impl Default for RhaiBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FilterBackend for RhaiBackend {
+    type Filter = RhaiFilter;
+
+    fn compile(
+        &mut self,
+        config: &FilterConfig,
+        source: &[u8],
+    ) -> Result<Vec<(String, RhaiFilter)>, BackendError> {
+        if let Some(language @ ScriptLanguage::Lua) = config.language {
+            return Err(format!("the Rhai backend doesn't run {language}").into());
+        }
+        let ast = self.engine.compile(decode_source(source)?).map_err(error)?;
+        // Closures are compiled into functions too, named `anon$` and a hash.
+        let names: Vec<String> = ast
+            .iter_functions()
+            .filter(|function| {
+                function.access != FnAccess::Private
+                    && function.params.len() == 1
+                    && !function.name.starts_with("anon$")
+            })
+            .map(|function| function.name.to_string())
+            .collect();
+        let ast = Rc::new(ast);
+        if !names.is_empty() {
+            let filters = names.into_iter().map(|name| {
+                let function = FnPtr::new(name.as_str()).map_err(error)?;
+                let ast = ast.clone();
+                Ok((name, RhaiFilter { ast, function }))
+            });
+            return filters.collect();
+        }
+        let module: Dynamic = self.engine.eval_ast(&ast).map_err(error)?;
+        match module.try_cast_result::<FnPtr>() {
+            Ok(function) => Ok(vec![(config.name.clone(), RhaiFilter { ast, function })]),
+            Err(module) => Err(format!(
+                "it returned a value of type {}; scripts define filter functions taking the value, \
+                 or evaluate to a closure",
+                module.type_name()
+            )
+            .into()),
+        }
+    }
+
+    fn call<V: Serialize>(&self, filter: &RhaiFilter, value: &V) -> Result<Decision, BackendError> {
+        let value = rhai::serde::to_dynamic(value).map_err(error)?;
+        let verdict: Dynamic = filter
+            .function
+            .call(&self.engine, &filter.ast, (value,))
+            .map_err(error)?;
+        if let Some(matched) = verdict.clone().try_cast::<bool>() {
+            return Ok(Decision {
+                matched,
+                reason: None,
+            });
+        }
+        let type_name = verdict.type_name();
+        let Some(verdict) = verdict.try_cast::<Map>() else {
+            return Err(format!(
+                "it returned a {type_name} rather than a boolean or a verdict map"
+            )
+            .into());
+        };
+        let matched = verdict
+            .get("matched")
+            .and_then(|matched| matched.as_bool().ok());
+        let reason = verdict.get("reason").map(|reason| reason.to_string());
+        match matched {
+            Some(matched) => Ok(Decision { matched, reason }),
+            None => Err("it returned a verdict map without a boolean `matched`".into()),
+        }
+    }
+
+    fn load_library(
+        &mut self,
+        name: &str,
+        _path: &Path,
+        source: &[u8],
+    ) -> Result<(), BackendError> {
+        let ast = self.engine.compile(decode_source(source)?).map_err(error)?;
+        let module = Module::eval_ast_as_new(Scope::new(), &ast, &self.engine).map_err(error)?;
+        self.engine.register_static_module(name, module.into());
+        Ok(())
+    }
+}
+
+/// Rhai errors hold values that aren't `Send`, so only their message is kept.
+fn error(error: impl Display) -> BackendError {
+    error.to_string().into()
+}
+
+fn json() -> Module {
+    let mut json = Module::new();
+    json.set_native_fn("encode", |value: Dynamic| {
+        serde_json::to_string(&value).map_err(|err| format!("json::encode: {err}").into())
+    });
+    json.set_native_fn("decode", |input: ImmutableString| {
+        serde_json::from_str::<Dynamic>(&input).map_err(|err| format!("json::decode: {err}").into())
+    });
+    json
+}
+
+fn re(cache_size: usize) -> Module {
+    let cache = Rc::new(RefCell::new(LruCache::new(cache_size)));
+    let compile = move |pattern: &str| -> Result<Regex, Box<rhai::EvalAltResult>> {
+        let mut cache = cache.borrow_mut();
+        if let Some(regex) = cache.get(pattern) {
+            return Ok(Regex::clone(regex));
+        }
+        let regex = Regex::new(pattern).map_err(|err| format!("re: invalid pattern: {err}"))?;
+        cache.insert(pattern.to_string(), regex.clone());
+        Ok(regex)
+    };
+    let mut re = Module::new();
+    let patterns = compile.clone();
+    re.set_native_fn(
+        "is_match",
+        move |pattern: ImmutableString, text: ImmutableString| {
+            Ok(patterns(&pattern)?.is_match(&text))
+        },
+    );
+    let patterns = compile.clone();
+    re.set_native_fn(
+        "find",
+        move |pattern: ImmutableString, text: ImmutableString| {
+            let found = patterns(&pattern)?.find(&text);
+            Ok(found.map_or(Dynamic::UNIT, |found| found.as_str().into()))
+        },
+    );
+    re.set_native_fn(
+        "captures",
+        move |pattern: ImmutableString, text: ImmutableString| {
+            let regex = compile(&pattern)?;
+            let Some(captures) = regex.captures(&text) else {
+                return Ok(Dynamic::UNIT);
+            };
+            let mut groups = Map::new();
+            for (index, group) in captures.iter().enumerate() {
+                if let Some(group) = group {
+                    groups.insert(index.to_string().into(), group.as_str().into());
+                }
+            }
+            for name in regex.capture_names().flatten() {
+                if let Some(group) = captures.name(name) {
+                    groups.insert(name.into(), group.as_str().into());
+                }
+            }
+            Ok(Dynamic::from_map(groups))
+        },
+    );
+    re
+}
@@ -0,0 +1,154 @@
+//! The WebAssembly backend, with the `wasm` feature.
+
+use std::{cell::RefCell, rc::Rc};
+
+use serde::Serialize;
+use wasmtime::{
+    Engine, ExternType, Instance, Memory, Module, Store, StoreLimits, StoreLimitsBuilder, TypedFunc,
+};
+
+use super::{BackendError, Decision, FilterBackend};
+use crate::FilterConfig;
+
+/// An instance of a module, shared by its filters.
+struct WasmInstance {
+    store: RefCell<Store<StoreLimits>>,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    fuel: Option<u64>,
+    max_call_memory: Option<u64>,
+}
+
+/// A filter compiled by a [`WasmBackend`].
+pub struct WasmFilter {
+    instance: Rc<WasmInstance>,
+    function: TypedFunc<(i32, i32), i32>,
+}
+
+/// Runs filters compiled to WebAssembly, each module in its own store.
+///
+/// The script of a filter is a `.wasm` module exporting its `memory`, an `alloc(len) -> ptr`
+/// function giving where the host may write `len` bytes, 0 if it can't, and its filters:
+/// `filter`, named after its filter, or `filter_<name>` functions named `<name>`. Filters take
+/// the pointer and length of the value serialized as JSON, and return 1 if they match and 0 if
+/// they don't. A module gets no imports, so it can't reach anything outside its memory.
+///
+/// The limits of the filter configuration apply to each call: a call may burn up to its
+/// `fuel_budget` of wasmtime fuel, and grow the module's memory by up to its `max_call_memory`
+/// bytes. Instantiating the module, which runs its start function if it has one, gets the same
+/// limits. Without them, calls are unlimited. There are no libraries.
+///
+/// The filters in `filters/wasm` of the repository are a reference written in Rust.
+pub struct WasmBackend {
+    engine: Engine,
+}
+
+impl WasmBackend {
+    /// A backend metering fuel, so fuel budgets apply.
+    pub fn new() -> Result<Self, BackendError> {
+        let mut config = wasmtime::Config::new();
+        config.consume_fuel(true);
+        Ok(Self {
+            engine: Engine::new(&config)?,
+        })
+    }
+}
+
+impl FilterBackend for WasmBackend {
+    type Filter = WasmFilter;
+
+    fn compile(
+        &mut self,
+        config: &FilterConfig,
+        source: &[u8],
+    ) -> Result<Vec<(String, WasmFilter)>, BackendError> {
+        let module = Module::new(&self.engine, source)?;
+        let size = match module.get_export("memory") {
+            Some(ExternType::Memory(memory)) => memory.minimum().saturating_mul(memory.page_size()),
+            _ => return Err("it exports no `memory`".into()),
+        };
+        let limits = limits(size, config.max_call_memory);
+        let mut store = Store::new(&self.engine, limits);
+        store.limiter(|limits| limits);
+        store.set_fuel(config.fuel_budget.unwrap_or(u64::MAX))?;
+        let instance = Instance::new(&mut store, &module, &[]).map_err(trap)?;
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or("it exports no `memory`")?;
+        let alloc = instance.get_typed_func(&mut store, "alloc")?;
+        let mut functions = Vec::new();
+        for export in module.exports() {
+            let name = match export.name() {
+                "filter" => config.name.clone(),
+                name => match name.strip_prefix("filter_") {
+                    Some(name) => name.to_string(),
+                    None => continue,
+                },
+            };
+            if let ExternType::Func(_) = export.ty() {
+                functions.push((name, instance.get_typed_func(&mut store, export.name())?));
+            }
+        }
+        if functions.is_empty() {
+            return Err("it exports no `filter` or `filter_<name>` function".into());
+        }
+        let instance = Rc::new(WasmInstance {
+            store: RefCell::new(store),
+            memory,
+            alloc,
+            fuel: config.fuel_budget,
+            max_call_memory: config.max_call_memory,
+        });
+        let filters = functions.into_iter().map(|(name, function)| {
+            let instance = instance.clone();
+            (name, WasmFilter { instance, function })
+        });
+        Ok(filters.collect())
+    }
+
+    fn call<V: Serialize>(&self, filter: &WasmFilter, value: &V) -> Result<Decision, BackendError> {
+        let value = serde_json::to_vec(value)?;
+        let instance = &filter.instance;
+        let mut store = instance.store.borrow_mut();
+        store.set_fuel(instance.fuel.unwrap_or(u64::MAX))?;
+        if instance.max_call_memory.is_some() {
+            let size = instance.memory.data_size(&*store) as u64;
+            *store.data_mut() = limits(size, instance.max_call_memory);
+        }
+        let len = i32::try_from(value.len())?;
+        let ptr = instance.alloc.call(&mut *store, len).map_err(trap)?;
+        if ptr == 0 {
+            return Err(format!("the module couldn't allocate {len} bytes for the value").into());
+        }
+        instance
+            .memory
+            .write(&mut *store, ptr as u32 as usize, &value)?;
+        let verdict = filter.function.call(&mut *store, (ptr, len));
+        match verdict.map_err(trap)? {
+            0 => Ok(Decision::default()),
+            1 => Ok(Decision {
+                matched: true,
+                reason: None,
+            }),
+            verdict => Err(format!("it returned {verdict} rather than 0 or 1").into()),
+        }
+    }
+}
+
+/// The limits of a store whose memory is `size` bytes, letting it grow by up to `max_memory`
+/// more.
+fn limits(size: u64, max_memory: Option<u64>) -> StoreLimits {
+    let Some(max_memory) = max_memory else {
+        return StoreLimits::default();
+    };
+    let limit = usize::try_from(size.saturating_add(max_memory)).unwrap_or(usize::MAX);
+    StoreLimitsBuilder::new()
+        .memory_size(limit)
+        .trap_on_grow_failure(true)
+        .build()
+}
+
+/// The error of a trapping call, without the wasm backtrace leading to it.
+fn trap(error: wasmtime::Error) -> BackendError {
+    error.root_cause().to_string().into()
+}
@@ -0,0 +1,162 @@
+//! Filters NDJSON from stdin through a filter configuration, with the `cli` feature.
+//!
+//! ```sh
+//! cat txs.ndjson | croncat-filter --config filters.yaml --chain uni-5
+//! ```
+//!
+//! Each line is read as JSON and given to the filters of the chain, and the lines they keep are
+//! written to stdout as they were read. With `--detailed`, a kept line is followed by a tab and
+//! the names of the filters that matched it, separated by commas. With `--lenient`, a filter
+//! failing on a line counts as not matching it rather than stopping the run. Lines that aren't
+//! JSON are reported and skipped. The counters of each filter are written to stderr at the end.
+
+use std::{
+    error::Error,
+    io::{self, BufRead, Write},
+    path::PathBuf,
+    process::ExitCode,
+};
+
+use croncat_indexer_filter::{Config, ErrorPolicy, FilterRuntime, FilterSystem, WILDCARD_CHAIN};
+use serde::Serialize;
+
+const USAGE: &str = "usage: croncat-filter --config <path> --chain <name> [--detailed] [--lenient]";
+
+/// A line of the input, as the filters see it.
+#[derive(Serialize)]
+#[serde(transparent)]
+struct Line(serde_json::Value);
+
+impl mlua::UserData for Line {}
+
+struct Args {
+    config: PathBuf,
+    chain: String,
+    detailed: bool,
+    lenient: bool,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let (mut config, mut chain) = (None, None);
+        let (mut detailed, mut lenient) = (false, false);
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| args.next().ok_or(format!("{name} takes a value"));
+            match arg.as_str() {
+                "--config" => config = Some(PathBuf::from(value("--config")?)),
+                "--chain" => chain = Some(value("--chain")?),
+                "--detailed" => detailed = true,
+                "--lenient" => lenient = true,
+                arg => return Err(format!("unexpected argument `{arg}`")),
+            }
+        }
+        Ok(Self {
+            config: config.ok_or("--config is required")?,
+            chain: chain.ok_or("--chain is required")?,
+            detailed,
+            lenient,
+        })
+    }
+}
+
+/// What went through the filters.
+#[derive(Default)]
+struct Counts {
+    lines: u64,
+    kept: u64,
+    invalid: u64,
+}
+
+fn main() -> ExitCode {
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("croncat-filter: {message}\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("croncat-filter: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: &Args) -> Result<(), Box<dyn Error>> {
+    let mut config = Config::from_path(&args.config)?;
+    if !config.chains.contains_key(&args.chain) {
+        return Err(format!("the configuration has no chain `{}`", args.chain).into());
+    }
+    // The filters of the wildcard chain run on every chain, so they stay.
+    config
+        .chains
+        .retain(|chain, _| *chain == args.chain || chain == WILDCARD_CHAIN);
+    let runtime = FilterRuntime::<Line>::new();
+    let mut system = runtime.load(config)?;
+    if args.lenient {
+        system.set_error_policy(ErrorPolicy::Lenient);
+    }
+    let mut counts = Counts::default();
+    let result = filter_lines(&system, args, io::stdin().lock(), &mut counts);
+    // The counters are reported whatever stopped the run.
+    eprintln!(
+        "{} lines, {} kept, {} not JSON",
+        counts.lines, counts.kept, counts.invalid
+    );
+    for stats in system.stats() {
+        eprintln!(
+            "{}: {} matched of {}, {} errors",
+            stats.id, stats.matches, stats.invocations, stats.errors
+        );
+    }
+    result
+}
+
+fn filter_lines(
+    system: &FilterSystem<Line>,
+    args: &Args,
+    input: impl BufRead,
+    counts: &mut Counts,
+) -> Result<(), Box<dyn Error>> {
+    let mut output = io::stdout().lock();
+    for (index, line) in input.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        counts.lines += 1;
+        let value = match serde_json::from_str(&line) {
+            Ok(value) => Line(value),
+            Err(err) => {
+                eprintln!("croncat-filter: line {} isn't JSON: {err}", index + 1);
+                counts.invalid += 1;
+                continue;
+            }
+        };
+        let written = match args.detailed {
+            true => {
+                let verdict = system.filter_one_detailed(value)?;
+                if !verdict.matched {
+                    continue;
+                }
+                writeln!(output, "{line}\t{}", verdict.matched_by.join(","))
+            }
+            false => {
+                if !system.filter_one(value)? {
+                    continue;
+                }
+                writeln!(output, "{line}")
+            }
+        };
+        counts.kept += 1;
+        match written {
+            Ok(()) => {}
+            // Whatever reads the output, such as `head`, has seen enough.
+            Err(err) if err.kind() == io::ErrorKind::BrokenPipe => return Ok(()),
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Ok(())
+}
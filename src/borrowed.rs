@@ -0,0 +1,191 @@
+//! Passing borrowed values as userdata, see [`ValuePassing::UserData`].
+//!
+//! mlua only creates userdata from references to `'static` types. Values are instead wrapped
+//! in a [`Borrowed`] created as non-static userdata, whose methods and fields are the ones the
+//! `UserData` implementation of the value's type registers, called with the borrowed value.
+//! Methods and setters taking the value mutably raise an error, as the value is shared.
+//!
+//! Non-static userdata get a metatable of their own, built again for every value.
+//!
+//! [`ValuePassing::UserData`]: crate::ValuePassing::UserData
+
+use std::marker::PhantomData;
+
+use mlua::{
+    AnyUserData, FromLua, FromLuaMulti, IntoLua, IntoLuaMulti, Lua, UserData, UserDataFields,
+    UserDataMethods, UserDataRegistry,
+};
+
+/// A borrowed value, as userdata.
+pub(crate) struct Borrowed<'a, T>(pub(crate) &'a T);
+
+impl<'a, T: UserData> UserData for Borrowed<'a, T> {
+    fn add_fields<'lua, F: UserDataFields<'lua, Self>>(fields: &mut F) {
+        T::add_fields(&mut Fields(fields, PhantomData));
+    }
+
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        T::add_methods(&mut Methods(methods, PhantomData));
+    }
+}
+
+/// The error of methods and setters taking the value mutably.
+fn shared() -> mlua::Error {
+    mlua::Error::UserDataBorrowMutError
+}
+
+/// Registers the methods of `T` on [`Borrowed`] values.
+struct Methods<'m, 'a, M>(&'m mut M, PhantomData<&'a ()>);
+
+impl<'lua, 'a, T: 'a, M> UserDataMethods<'lua, T> for Methods<'_, 'a, M>
+where
+    M: UserDataMethods<'lua, Borrowed<'a, T>>,
+{
+    fn add_method<F, A, R>(&mut self, name: impl AsRef<str>, method: F)
+    where
+        F: Fn(&'lua Lua, &T, A) -> mlua::Result<R> + 'static,
+        A: FromLuaMulti<'lua>,
+        R: IntoLuaMulti<'lua>,
+    {
+        let method = move |lua, this: &Borrowed<'a, T>, args| method(lua, this.0, args);
+        self.0.add_method(name, method);
+    }
+
+    fn add_method_mut<F, A, R>(&mut self, name: impl AsRef<str>, _: F)
+    where
+        F: FnMut(&'lua Lua, &mut T, A) -> mlua::Result<R> + 'static,
+        A: FromLuaMulti<'lua>,
+        R: IntoLuaMulti<'lua>,
+    {
+        let method = |_, _: &Borrowed<'a, T>, _: A| -> mlua::Result<R> { Err(shared()) };
+        self.0.add_method(name, method);
+    }
+
+    fn add_function<F, A, R>(&mut self, name: impl AsRef<str>, function: F)
+    where
+        F: Fn(&'lua Lua, A) -> mlua::Result<R> + 'static,
+        A: FromLuaMulti<'lua>,
+        R: IntoLuaMulti<'lua>,
+    {
+        self.0.add_function(name, function);
+    }
+
+    fn add_function_mut<F, A, R>(&mut self, name: impl AsRef<str>, function: F)
+    where
+        F: FnMut(&'lua Lua, A) -> mlua::Result<R> + 'static,
+        A: FromLuaMulti<'lua>,
+        R: IntoLuaMulti<'lua>,
+    {
+        self.0.add_function_mut(name, function);
+    }
+
+    fn add_meta_method<F, A, R>(&mut self, name: impl AsRef<str>, method: F)
+    where
+        F: Fn(&'lua Lua, &T, A) -> mlua::Result<R> + 'static,
+        A: FromLuaMulti<'lua>,
+        R: IntoLuaMulti<'lua>,
+    {
+        let method = move |lua, this: &Borrowed<'a, T>, args| method(lua, this.0, args);
+        self.0.add_meta_method(name, method);
+    }
+
+    fn add_meta_method_mut<F, A, R>(&mut self, name: impl AsRef<str>, _: F)
+    where
+        F: FnMut(&'lua Lua, &mut T, A) -> mlua::Result<R> + 'static,
+        A: FromLuaMulti<'lua>,
+        R: IntoLuaMulti<'lua>,
+    {
+        let method = |_, _: &Borrowed<'a, T>, _: A| -> mlua::Result<R> { Err(shared()) };
+        self.0.add_meta_method(name, method);
+    }
+
+    fn add_meta_function<F, A, R>(&mut self, name: impl AsRef<str>, function: F)
+    where
+        F: Fn(&'lua Lua, A) -> mlua::Result<R> + 'static,
+        A: FromLuaMulti<'lua>,
+        R: IntoLuaMulti<'lua>,
+    {
+        self.0.add_meta_function(name, function);
+    }
+
+    fn add_meta_function_mut<F, A, R>(&mut self, name: impl AsRef<str>, function: F)
+    where
+        F: FnMut(&'lua Lua, A) -> mlua::Result<R> + 'static,
+        A: FromLuaMulti<'lua>,
+        R: IntoLuaMulti<'lua>,
+    {
+        self.0.add_meta_function_mut(name, function);
+    }
+
+    fn append_methods_from<S>(&mut self, other: UserDataRegistry<'lua, S>) {
+        self.0.append_methods_from(other);
+    }
+}
+
+/// Registers the fields of `T` on [`Borrowed`] values.
+struct Fields<'f, 'a, F>(&'f mut F, PhantomData<&'a ()>);
+
+impl<'lua, 'a, T: 'a, F> UserDataFields<'lua, T> for Fields<'_, 'a, F>
+where
+    F: UserDataFields<'lua, Borrowed<'a, T>>,
+{
+    fn add_field<V>(&mut self, name: impl AsRef<str>, value: V)
+    where
+        V: IntoLua<'lua> + Clone + 'static,
+    {
+        self.0.add_field(name, value);
+    }
+
+    fn add_field_method_get<M, R>(&mut self, name: impl AsRef<str>, method: M)
+    where
+        M: Fn(&'lua Lua, &T) -> mlua::Result<R> + 'static,
+        R: IntoLua<'lua>,
+    {
+        let method = move |lua, this: &Borrowed<'a, T>| method(lua, this.0);
+        self.0.add_field_method_get(name, method);
+    }
+
+    fn add_field_method_set<M, A>(&mut self, name: impl AsRef<str>, _: M)
+    where
+        M: FnMut(&'lua Lua, &mut T, A) -> mlua::Result<()> + 'static,
+        A: FromLua<'lua>,
+    {
+        let method = |_, _: &mut Borrowed<'a, T>, _: A| Err(shared());
+        self.0.add_field_method_set(name, method);
+    }
+
+    fn add_field_function_get<G, R>(&mut self, name: impl AsRef<str>, function: G)
+    where
+        G: Fn(&'lua Lua, AnyUserData<'lua>) -> mlua::Result<R> + 'static,
+        R: IntoLua<'lua>,
+    {
+        self.0.add_field_function_get(name, function);
+    }
+
+    fn add_field_function_set<G, A>(&mut self, name: impl AsRef<str>, function: G)
+    where
+        G: FnMut(&'lua Lua, AnyUserData<'lua>, A) -> mlua::Result<()> + 'static,
+        A: FromLua<'lua>,
+    {
+        self.0.add_field_function_set(name, function);
+    }
+
+    fn add_meta_field<V>(&mut self, name: impl AsRef<str>, value: V)
+    where
+        V: IntoLua<'lua> + Clone + 'static,
+    {
+        self.0.add_meta_field(name, value);
+    }
+
+    fn add_meta_field_with<G, R>(&mut self, name: impl AsRef<str>, f: G)
+    where
+        G: Fn(&'lua Lua) -> mlua::Result<R> + 'static,
+        R: IntoLua<'lua>,
+    {
+        self.0.add_meta_field_with(name, f);
+    }
+
+    fn append_fields_from<S>(&mut self, other: UserDataRegistry<'lua, S>) {
+        self.0.append_fields_from(other);
+    }
+}
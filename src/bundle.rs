@@ -0,0 +1,168 @@
+//! Snapshots of the filters a system runs, to deploy without their files, see
+//! [`FilterSystem::export_bundle`](crate::FilterSystem::export_bundle).
+//!
+//! A bundle holds the sources of the scripts and libraries along with the configuration they
+//! were loaded with, so loading it goes through the same steps as loading that configuration,
+//! reading nothing from disk.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    rc::Rc,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{BundleError, Config, FilterConfig};
+
+/// The version of the bundle format this crate writes and reads.
+pub const BUNDLE_VERSION: u32 = 1;
+
+/// The filters of a system, with everything needed to load them again.
+///
+/// Written as JSON with [`to_json`](Self::to_json), and loaded with
+/// [`FilterRuntime::load_bundle`](crate::FilterRuntime::load_bundle).
+#[derive(Clone, Serialize, Deserialize)]
+pub struct FilterBundle {
+    /// The format of the bundle, [`BUNDLE_VERSION`] for those this crate writes.
+    pub version: u32,
+    /// The shared libraries, in name order.
+    pub libraries: Vec<BundledLibrary>,
+    /// The scripts, in loading order.
+    pub scripts: Vec<BundledScript>,
+    pub constants: HashMap<String, serde_yaml::Value>,
+    pub expose_env: Vec<String>,
+    pub expressions: HashMap<String, String>,
+    pub trusted_keys: Vec<String>,
+}
+
+/// A shared library of a [`FilterBundle`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BundledLibrary {
+    pub name: String,
+    /// Where the library was read from, to name it in errors.
+    pub path: PathBuf,
+    pub source: String,
+}
+
+/// A filter script of a [`FilterBundle`].
+#[derive(Clone, Serialize, Deserialize)]
+pub struct BundledScript {
+    pub chain: String,
+    /// The filter of the configuration, with the language of the script. Its script path
+    /// only names it in errors.
+    pub config: FilterConfig,
+    pub source: String,
+    /// The functions of the script that were loaded; the others are left out.
+    pub functions: Vec<BundledFunction>,
+}
+
+/// A filter function of a [`BundledScript`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BundledFunction {
+    pub name: String,
+    /// Whether it was in dry-run mode, see [`Filter::with_dry_run`](crate::Filter::with_dry_run).
+    pub dry_run: bool,
+}
+
+/// The source a filter was loaded from, shared by the functions of its script.
+pub(crate) struct ScriptSource {
+    pub(crate) config: FilterConfig,
+    pub(crate) source: String,
+}
+
+/// What a system loaded that its filters don't keep: the libraries and chain constants.
+#[derive(Default)]
+pub(crate) struct Loaded {
+    pub(crate) libraries: BTreeMap<String, BundledLibrary>,
+    pub(crate) constants: HashMap<String, serde_yaml::Value>,
+}
+
+/// A bundle taken apart for loading: its configuration, the sources of its libraries and
+/// scripts in loading order, and the functions to keep of each script.
+pub(crate) struct Unbundled {
+    pub(crate) config: Config,
+    pub(crate) libraries: Vec<String>,
+    pub(crate) scripts: Vec<String>,
+    pub(crate) functions: Vec<Vec<BundledFunction>>,
+}
+
+impl FilterBundle {
+    /// The bundle as JSON.
+    ///
+    /// Fails if chain constants have keys that aren't strings, which JSON can't represent.
+    pub fn to_json(&self) -> Result<String, BundleError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Read a bundle from JSON, refusing versions of the format other than
+    /// [`BUNDLE_VERSION`] before reading the rest.
+    pub fn from_json(json: &str) -> Result<Self, BundleError> {
+        #[derive(Deserialize)]
+        struct Versioned {
+            version: u64,
+        }
+        check_version(serde_json::from_str::<Versioned>(json)?.version)?;
+        Ok(serde_json::from_str(json)?)
+    }
+
+    pub(crate) fn unbundle(self) -> Result<Unbundled, BundleError> {
+        check_version(self.version.into())?;
+        let mut chains: HashMap<String, Vec<FilterConfig>> = HashMap::new();
+        let mut sources: HashMap<String, Vec<(String, Vec<BundledFunction>)>> = HashMap::new();
+        for script in self.scripts {
+            chains
+                .entry(script.chain.clone())
+                .or_default()
+                .push(script.config);
+            let chain = sources.entry(script.chain).or_default();
+            chain.push((script.source, script.functions));
+        }
+        let (mut libraries, mut library_sources) = (BTreeMap::new(), BTreeMap::new());
+        for library in self.libraries {
+            libraries.insert(library.name.clone(), library.path);
+            library_sources.insert(library.name, library.source);
+        }
+        let config = Config {
+            chains,
+            libraries,
+            expose_env: self.expose_env,
+            constants: self.constants,
+            expressions: self.expressions,
+            trusted_keys: self.trusted_keys,
+            ..Default::default()
+        };
+        // Scripts load in the order of the chains of the configuration.
+        let (mut scripts, mut functions) = (Vec::new(), Vec::new());
+        for chain in config.chains.keys() {
+            for (source, kept) in sources.remove(chain).unwrap_or_default() {
+                scripts.push(source);
+                functions.push(kept);
+            }
+        }
+        Ok(Unbundled {
+            config,
+            libraries: library_sources.into_values().collect(),
+            scripts,
+            functions,
+        })
+    }
+}
+
+impl BundledScript {
+    pub(crate) fn new(chain: &str, source: &Rc<ScriptSource>) -> Self {
+        Self {
+            chain: chain.to_string(),
+            config: source.config.clone(),
+            source: source.source.clone(),
+            functions: Vec::new(),
+        }
+    }
+}
+
+fn check_version(version: u64) -> Result<(), BundleError> {
+    match version == u64::from(BUNDLE_VERSION) {
+        true => Ok(()),
+        false => Err(BundleError::Version { version }),
+    }
+}
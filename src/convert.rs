@@ -0,0 +1,656 @@
+//! Converting values into Lua.
+//!
+//! Values go through mlua's serde support, wrapped in a serializer adapter that applies the
+//! runtime's conversion settings on the way. Runtimes interning strings use the serializer of
+//! [`crate::intern`] instead.
+//!
+//! Both recurse into nested values, so they count the tables they open and give up with
+//! [`TooDeep`] past the runtime's maximum depth, before the stack runs out.
+
+use std::{cell::Cell, fmt};
+
+use mlua::{Lua, LuaSerdeExt};
+use serde::ser::{self, Serialize, Serializer};
+
+use crate::{intern::Interning, ValuePassing};
+
+/// The largest integer a Lua number can hold exactly.
+pub(crate) const MAX_SAFE_INTEGER: u64 = 1 << 53;
+
+/// How deep values may be nested unless the runtime options say otherwise.
+pub(crate) const DEFAULT_MAX_DEPTH: usize = 128;
+
+/// The conversion settings of a runtime, stored in its app data.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ValueConversion {
+    /// Pass integers a Lua number can't hold exactly as decimal strings.
+    pub big_integers_as_strings: bool,
+    /// How filtered values reach the filter functions.
+    pub value_passing: ValuePassing,
+    /// Reuse the Lua strings of repeated strings, see [`crate::intern`].
+    pub intern_strings: bool,
+    /// How many tables deep a converted value may go.
+    pub max_depth: usize,
+}
+
+impl Default for ValueConversion {
+    fn default() -> Self {
+        Self {
+            big_integers_as_strings: false,
+            value_passing: ValuePassing::default(),
+            intern_strings: false,
+            max_depth: DEFAULT_MAX_DEPTH,
+        }
+    }
+}
+
+impl ValueConversion {
+    /// The conversion settings of a runtime, falling back to the defaults.
+    pub(crate) fn of(lua: &Lua) -> Self {
+        lua.app_data_ref::<ValueConversion>()
+            .map(|conversion| *conversion)
+            .unwrap_or_default()
+    }
+
+    fn is_plain(&self) -> bool {
+        !self.big_integers_as_strings && self.max_depth == usize::MAX
+    }
+}
+
+/// Convert a value into Lua using the runtime's conversion settings.
+pub(crate) fn to_lua<'lua, T: Serialize + ?Sized>(
+    lua: &'lua Lua,
+    value: &T,
+) -> mlua::Result<mlua::Value<'lua>> {
+    nested_to_lua(lua, value, 0)
+}
+
+/// [`to_lua`] for a value found `depth` tables deep in the value being converted.
+pub(crate) fn nested_to_lua<'lua, T: Serialize + ?Sized>(
+    lua: &'lua Lua,
+    value: &T,
+    depth: usize,
+) -> mlua::Result<mlua::Value<'lua>> {
+    let conversion = ValueConversion::of(lua);
+    let remaining = conversion.max_depth.saturating_sub(depth);
+    if conversion.intern_strings {
+        return value.serialize(Interning::new(lua, conversion, remaining));
+    }
+    if conversion.is_plain() {
+        return lua.to_value(value);
+    }
+    // mlua's serializer only takes errors as messages, so the adapter reports going too deep
+    // on the side.
+    let exceeded = Cell::new(false);
+    let depth = Depth {
+        remaining,
+        exceeded: &exceeded,
+    };
+    lua.to_value(&Adapted {
+        value,
+        conversion,
+        depth,
+    })
+    .map_err(|err| match exceeded.get() {
+        true => mlua::Error::external(TooDeep {
+            depth: conversion.max_depth,
+        }),
+        false => err,
+    })
+}
+
+/// A value was nested deeper than the runtime allows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct TooDeep {
+    /// The maximum depth of the runtime.
+    pub depth: usize,
+}
+
+impl fmt::Display for TooDeep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the value is nested deeper than {} levels", self.depth)
+    }
+}
+
+impl std::error::Error for TooDeep {}
+
+impl TooDeep {
+    /// Find the depth error that caused a Lua error, if any.
+    pub(crate) fn find(err: &mlua::Error) -> Option<TooDeep> {
+        match err {
+            mlua::Error::ExternalError(err) => err.downcast_ref::<TooDeep>().copied(),
+            mlua::Error::CallbackError { cause, .. } => TooDeep::find(cause),
+            mlua::Error::WithContext { cause, .. } => TooDeep::find(cause),
+            _ => None,
+        }
+    }
+}
+
+/// The nesting left once `levels` more tables are opened, with `remaining` left before, if
+/// the runtime allows them.
+pub(crate) fn nest(max_depth: usize, remaining: usize, levels: usize) -> mlua::Result<usize> {
+    remaining
+        .checked_sub(levels)
+        .ok_or_else(|| mlua::Error::external(TooDeep { depth: max_depth }))
+}
+
+/// How many more tables the adapter may open, and where it records running out of them.
+#[derive(Clone, Copy)]
+struct Depth<'a> {
+    remaining: usize,
+    exceeded: &'a Cell<bool>,
+}
+
+impl Depth<'_> {
+    fn nest<E: ser::Error>(self, levels: usize) -> Result<Self, E> {
+        match self.remaining.checked_sub(levels) {
+            Some(remaining) => Ok(Depth { remaining, ..self }),
+            None => {
+                self.exceeded.set(true);
+                Err(E::custom("the value is nested too deep"))
+            }
+        }
+    }
+}
+
+/// A value serialized through the adapter.
+struct Adapted<'a, 'd, T: ?Sized> {
+    value: &'a T,
+    conversion: ValueConversion,
+    depth: Depth<'d>,
+}
+
+impl<T: Serialize + ?Sized> Serialize for Adapted<'_, '_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.value.serialize(Adapter {
+            inner: serializer,
+            conversion: self.conversion,
+            depth: self.depth,
+        })
+    }
+}
+
+/// A serializer forwarding to `inner`, applying `conversion` to every nested value.
+struct Adapter<'d, S> {
+    inner: S,
+    conversion: ValueConversion,
+    depth: Depth<'d>,
+}
+
+impl<'d, S> Adapter<'d, S> {
+    fn adapt<'a, T: ?Sized>(&self, value: &'a T) -> Adapted<'a, 'd, T> {
+        Adapted {
+            value,
+            conversion: self.conversion,
+            depth: self.depth,
+        }
+    }
+
+    fn is_big(&self, magnitude: u128) -> bool {
+        self.conversion.big_integers_as_strings && magnitude > u128::from(MAX_SAFE_INTEGER)
+    }
+}
+
+impl<'d, S: Serializer> Serializer for Adapter<'d, S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+    type SerializeSeq = Adapter<'d, S::SerializeSeq>;
+    type SerializeTuple = Adapter<'d, S::SerializeTuple>;
+    type SerializeTupleStruct = Adapter<'d, S::SerializeTupleStruct>;
+    type SerializeTupleVariant = Adapter<'d, S::SerializeTupleVariant>;
+    type SerializeMap = Adapter<'d, S::SerializeMap>;
+    type SerializeStruct = Adapter<'d, S::SerializeStruct>;
+    type SerializeStructVariant = Adapter<'d, S::SerializeStructVariant>;
+
+    fn serialize_bool(self, v: bool) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_bool(v)
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_i8(v)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_i16(v)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_i32(v)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<S::Ok, S::Error> {
+        if self.is_big(v.unsigned_abs().into()) {
+            return self.inner.serialize_str(&v.to_string());
+        }
+        self.inner.serialize_i64(v)
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<S::Ok, S::Error> {
+        if self.is_big(v.unsigned_abs()) {
+            return self.inner.serialize_str(&v.to_string());
+        }
+        self.inner.serialize_i128(v)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_u8(v)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_u16(v)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_u32(v)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<S::Ok, S::Error> {
+        if self.is_big(v.into()) {
+            return self.inner.serialize_str(&v.to_string());
+        }
+        self.inner.serialize_u64(v)
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<S::Ok, S::Error> {
+        if self.is_big(v) {
+            return self.inner.serialize_str(&v.to_string());
+        }
+        self.inner.serialize_u128(v)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_f32(v)
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_f64(v)
+    }
+
+    fn serialize_char(self, v: char) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_char(v)
+    }
+
+    fn serialize_str(self, v: &str) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_str(v)
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_bytes(v)
+    }
+
+    fn serialize_none(self) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_none()
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<S::Ok, S::Error> {
+        let value = self.adapt(value);
+        self.inner.serialize_some(&value)
+    }
+
+    fn serialize_unit(self) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_unit()
+    }
+
+    fn serialize_unit_struct(self, name: &'static str) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_unit_struct(name)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+    ) -> Result<S::Ok, S::Error> {
+        self.inner
+            .serialize_unit_variant(name, variant_index, variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<S::Ok, S::Error> {
+        let value = self.adapt(value);
+        self.inner.serialize_newtype_struct(name, &value)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<S::Ok, S::Error> {
+        // The value goes in a table under the variant name.
+        let value = Adapted {
+            depth: self.depth.nest(1)?,
+            ..self.adapt(value)
+        };
+        self.inner
+            .serialize_newtype_variant(name, variant_index, variant, &value)
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, S::Error> {
+        let depth = self.depth.nest(1)?;
+        let conversion = self.conversion;
+        let inner = self.inner.serialize_seq(len)?;
+        Ok(Adapter {
+            inner,
+            conversion,
+            depth,
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, S::Error> {
+        let depth = self.depth.nest(1)?;
+        let conversion = self.conversion;
+        let inner = self.inner.serialize_tuple(len)?;
+        Ok(Adapter {
+            inner,
+            conversion,
+            depth,
+        })
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct, S::Error> {
+        let depth = self.depth.nest(1)?;
+        let conversion = self.conversion;
+        let inner = self.inner.serialize_tuple_struct(name, len)?;
+        Ok(Adapter {
+            inner,
+            conversion,
+            depth,
+        })
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant, S::Error> {
+        let depth = self.depth.nest(2)?;
+        let conversion = self.conversion;
+        let inner = self
+            .inner
+            .serialize_tuple_variant(name, variant_index, variant, len)?;
+        Ok(Adapter {
+            inner,
+            conversion,
+            depth,
+        })
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, S::Error> {
+        let depth = self.depth.nest(1)?;
+        let conversion = self.conversion;
+        let inner = self.inner.serialize_map(len)?;
+        Ok(Adapter {
+            inner,
+            conversion,
+            depth,
+        })
+    }
+
+    fn serialize_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStruct, S::Error> {
+        let depth = self.depth.nest(1)?;
+        let conversion = self.conversion;
+        let inner = self.inner.serialize_struct(name, len)?;
+        Ok(Adapter {
+            inner,
+            conversion,
+            depth,
+        })
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStructVariant, S::Error> {
+        let depth = self.depth.nest(2)?;
+        let conversion = self.conversion;
+        let inner = self
+            .inner
+            .serialize_struct_variant(name, variant_index, variant, len)?;
+        Ok(Adapter {
+            inner,
+            conversion,
+            depth,
+        })
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.inner.is_human_readable()
+    }
+}
+
+impl<S: ser::SerializeSeq> ser::SerializeSeq for Adapter<'_, S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), S::Error> {
+        let value = self.adapt(value);
+        self.inner.serialize_element(&value)
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        self.inner.end()
+    }
+}
+
+impl<S: ser::SerializeTuple> ser::SerializeTuple for Adapter<'_, S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), S::Error> {
+        let value = self.adapt(value);
+        self.inner.serialize_element(&value)
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        self.inner.end()
+    }
+}
+
+impl<S: ser::SerializeTupleStruct> ser::SerializeTupleStruct for Adapter<'_, S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), S::Error> {
+        let value = self.adapt(value);
+        self.inner.serialize_field(&value)
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        self.inner.end()
+    }
+}
+
+impl<S: ser::SerializeTupleVariant> ser::SerializeTupleVariant for Adapter<'_, S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), S::Error> {
+        let value = self.adapt(value);
+        self.inner.serialize_field(&value)
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        self.inner.end()
+    }
+}
+
+impl<S: ser::SerializeMap> ser::SerializeMap for Adapter<'_, S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), S::Error> {
+        let key = self.adapt(key);
+        self.inner.serialize_key(&key)
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), S::Error> {
+        let value = self.adapt(value);
+        self.inner.serialize_value(&value)
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        self.inner.end()
+    }
+}
+
+impl<S: ser::SerializeStruct> ser::SerializeStruct for Adapter<'_, S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), S::Error> {
+        let value = self.adapt(value);
+        self.inner.serialize_field(key, &value)
+    }
+
+    fn skip_field(&mut self, key: &'static str) -> Result<(), S::Error> {
+        self.inner.skip_field(key)
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        self.inner.end()
+    }
+}
+
+impl<S: ser::SerializeStructVariant> ser::SerializeStructVariant for Adapter<'_, S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), S::Error> {
+        let value = self.adapt(value);
+        self.inner.serialize_field(key, &value)
+    }
+
+    fn skip_field(&mut self, key: &'static str) -> Result<(), S::Error> {
+        self.inner.skip_field(key)
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        self.inner.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Serialize;
+
+    use super::*;
+
+    #[derive(Serialize)]
+    struct Coins {
+        small: u64,
+        big: u64,
+        huge: u128,
+        negative: i64,
+        nested: Vec<Option<u64>>,
+    }
+
+    fn coins() -> Coins {
+        Coins {
+            small: 42,
+            big: u64::MAX,
+            huge: u128::MAX,
+            negative: -(1 << 60),
+            nested: vec![Some(MAX_SAFE_INTEGER + 1), None],
+        }
+    }
+
+    #[test]
+    fn big_integers_as_strings() {
+        let lua = Lua::new();
+        lua.set_app_data(ValueConversion {
+            big_integers_as_strings: true,
+            ..Default::default()
+        });
+        let value = to_lua(&lua, &coins()).unwrap();
+        lua.globals().set("coins", value).unwrap();
+        let result: bool = lua
+            .load(
+                r#"return coins.small == 42 and coins.big == "18446744073709551615"
+                and coins.huge == "340282366920938463463374607431768211455"
+                and coins.negative == "-1152921504606846976"
+                and coins.nested[1] == "9007199254740993""#,
+            )
+            .eval()
+            .unwrap();
+        assert!(result);
+    }
+
+    #[test]
+    fn plain_conversion() {
+        let lua = Lua::new();
+        let value = to_lua(&lua, &coins()).unwrap();
+        lua.globals().set("coins", value).unwrap();
+        let big_is_number: bool = lua
+            .load("return type(coins.big) == 'number'")
+            .eval()
+            .unwrap();
+        assert!(big_is_number);
+    }
+
+    /// Whether `value` converts, checking the error if it doesn't.
+    fn fits<T: Serialize>(lua: &Lua, value: &T) -> bool {
+        match to_lua(lua, value) {
+            Ok(_) => true,
+            Err(err) => {
+                assert_eq!(TooDeep::find(&err), Some(TooDeep { depth: 3 }), "{err}");
+                false
+            }
+        }
+    }
+
+    #[test]
+    fn max_depth() {
+        #[derive(Serialize)]
+        enum Msg {
+            Batch(Vec<Vec<u8>>),
+            Pair(Vec<u8>, u8),
+        }
+        #[derive(Serialize)]
+        struct Wrapper(Option<Vec<Vec<Vec<u8>>>>);
+
+        for intern_strings in [false, true] {
+            let lua = Lua::new();
+            lua.set_app_data(ValueConversion {
+                intern_strings,
+                max_depth: 3,
+                ..Default::default()
+            });
+            assert!(fits(&lua, &vec![vec![vec![1u8]]]));
+            assert!(!fits(&lua, &vec![vec![vec![vec![1u8]]]]));
+            assert!(fits(&lua, &Wrapper(Some(vec![vec![vec![1]]]))));
+            // Variants holding data are tables holding their data.
+            assert!(fits(&lua, &Msg::Batch(vec![vec![1]])));
+            assert!(!fits(&lua, &vec![Msg::Batch(vec![vec![1]])]));
+            assert!(fits(&lua, &Msg::Pair(vec![1], 2)));
+            assert!(!fits(&lua, &vec![Msg::Pair(vec![1], 2)]));
+            assert!(nested_to_lua(&lua, &vec![vec![1u8]], 1).is_ok());
+            assert!(nested_to_lua(&lua, &vec![vec![vec![1u8]]], 1).is_err());
+        }
+    }
+}
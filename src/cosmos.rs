@@ -0,0 +1,351 @@
+//! Cosmos transactions and events ready to filter, with the `cosmos` feature.
+//!
+//! [`CosmosTx`] is built from a decoded [`cosmrs::Tx`], or from what the Tendermint RPC
+//! returns: a `/tx` query response or the transaction of a `tm.event = 'Tx'` subscription.
+//! Scripts see it as a table shaped for filtering: messages decoded where their type is known,
+//! events as arrays of `{ type, attributes = { { key, value } } }`, hashes hex-encoded and
+//! coin amounts as strings, since they often don't fit a Lua number.
+//!
+//! [`WasmEvent`] is the friendlier shape of the events of contracts, with their attributes by
+//! key.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use cosmos_sdk_proto::{
+    cosmos::{
+        bank::v1beta1::MsgSend, base::v1beta1::Coin,
+        distribution::v1beta1::MsgWithdrawDelegatorReward, staking::v1beta1::MsgDelegate,
+    },
+    cosmwasm::wasm::v1::MsgExecuteContract,
+    prost::Message,
+};
+use cosmrs::tendermint::abci;
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+/// The bytes of a transaction aren't a valid protobuf `Tx`.
+#[derive(Debug, Error)]
+#[error("invalid transaction: {0}")]
+pub struct TxDecodeError(cosmrs::ErrorReport);
+
+/// A Cosmos transaction, with its result when it comes from the RPC.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CosmosTx {
+    /// The SHA-256 hash of the transaction bytes, upper-case hex like explorers show it, when
+    /// they are known.
+    pub hash: Option<String>,
+    pub height: Option<u64>,
+    /// The index of the transaction in its block, when it is known.
+    pub index: Option<u32>,
+    /// The result code, 0 for success, when the result is known.
+    pub code: Option<u32>,
+    pub memo: String,
+    /// The height after which the transaction isn't valid anymore, 0 for none.
+    pub timeout_height: u64,
+    pub messages: Vec<TxMessage>,
+    pub fee: TxFee,
+    pub gas_wanted: Option<u64>,
+    pub gas_used: Option<u64>,
+    /// The events the transaction emitted, in order.
+    pub events: Vec<TxEvent>,
+}
+
+impl mlua::UserData for CosmosTx {}
+
+/// A message of a transaction.
+///
+/// Messages of the types `proto.decode_any` knows have their fields alongside `type_url`, in
+/// the same shape; the others only have `type_url` and `value`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TxMessage {
+    pub type_url: String,
+    /// The protobuf encoding of the message, in base64.
+    pub value: String,
+    /// The decoded fields of the message.
+    #[serde(flatten)]
+    pub fields: Map<String, Value>,
+}
+
+/// The fee of a transaction.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TxFee {
+    pub amount: Vec<TxCoin>,
+    pub gas_limit: u64,
+    pub payer: Option<String>,
+    pub granter: Option<String>,
+}
+
+/// An amount of a denomination.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TxCoin {
+    pub denom: String,
+    pub amount: String,
+}
+
+/// An event emitted by a transaction or a block.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TxEvent {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub attributes: Vec<TxAttribute>,
+}
+
+impl mlua::UserData for TxEvent {}
+
+/// An attribute of an event.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TxAttribute {
+    pub key: String,
+    pub value: String,
+}
+
+impl TxEvent {
+    /// The value of the first attribute named `key`.
+    pub fn attribute(&self, key: &str) -> Option<&str> {
+        let attribute = self
+            .attributes
+            .iter()
+            .find(|attribute| attribute.key == key);
+        attribute.map(|attribute| attribute.value.as_str())
+    }
+}
+
+impl From<abci::Event> for TxEvent {
+    fn from(event: abci::Event) -> Self {
+        let attributes = event.attributes.into_iter();
+        TxEvent {
+            kind: event.kind,
+            attributes: attributes
+                .map(|attribute| TxAttribute {
+                    key: attribute.key,
+                    value: attribute.value,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// An event emitted by a contract, with its attributes by key.
+///
+/// Scripts read attributes as `event.attrs._contract_address`. A key set more than once maps
+/// to its first value in `attrs`; `attrs_all` keeps every attribute, in order.
+///
+/// Tendermint before 0.34 base64-encoded the keys and values of attributes. An event whose
+/// keys all decode to names, and whose values all decode to UTF-8, is taken as encoded that
+/// way and decoded.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WasmEvent {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub attrs: BTreeMap<String, String>,
+    pub attrs_all: Vec<TxAttribute>,
+    /// The hash of the transaction that emitted the event, when it is known.
+    pub tx_hash: Option<String>,
+    /// The index of that transaction in its block, when it is known.
+    pub tx_index: Option<u32>,
+}
+
+impl mlua::UserData for WasmEvent {}
+
+impl From<TxEvent> for WasmEvent {
+    fn from(event: TxEvent) -> Self {
+        let attrs_all = legacy_attributes(&event.attributes).unwrap_or(event.attributes);
+        let mut attrs = BTreeMap::new();
+        for attribute in &attrs_all {
+            let key = attribute.key.clone();
+            attrs.entry(key).or_insert_with(|| attribute.value.clone());
+        }
+        WasmEvent {
+            kind: event.kind,
+            attrs,
+            attrs_all,
+            ..Default::default()
+        }
+    }
+}
+
+impl From<abci::Event> for WasmEvent {
+    fn from(event: abci::Event) -> Self {
+        WasmEvent::from(TxEvent::from(event))
+    }
+}
+
+/// The attributes decoded from base64, if they all look encoded.
+fn legacy_attributes(attributes: &[TxAttribute]) -> Option<Vec<TxAttribute>> {
+    let decode = |text: &str| String::from_utf8(STANDARD.decode(text).ok()?).ok();
+    let name = |key: &String| {
+        let mut chars = key.chars();
+        !key.is_empty() && chars.all(|c| c.is_ascii_alphanumeric() || "_-.".contains(c))
+    };
+    let attributes = attributes.iter().map(|attribute| {
+        let key = decode(&attribute.key).filter(name)?;
+        let value = decode(&attribute.value)?;
+        Some(TxAttribute { key, value })
+    });
+    let attributes: Option<Vec<_>> = attributes.collect();
+    attributes.filter(|attributes| !attributes.is_empty())
+}
+
+impl From<cosmrs::Tx> for CosmosTx {
+    fn from(tx: cosmrs::Tx) -> Self {
+        let fee = tx.auth_info.fee;
+        CosmosTx {
+            memo: tx.body.memo,
+            timeout_height: tx.body.timeout_height.value(),
+            messages: tx.body.messages.iter().map(TxMessage::from).collect(),
+            fee: TxFee {
+                amount: fee.amount.iter().map(TxCoin::from).collect(),
+                gas_limit: fee.gas_limit,
+                payer: fee.payer.map(|payer| payer.to_string()),
+                granter: fee.granter.map(|granter| granter.to_string()),
+            },
+            ..Default::default()
+        }
+    }
+}
+
+impl TryFrom<tendermint_rpc::endpoint::tx::Response> for CosmosTx {
+    type Error = TxDecodeError;
+
+    fn try_from(response: tendermint_rpc::endpoint::tx::Response) -> Result<Self, Self::Error> {
+        let result = response.tx_result;
+        Ok(CosmosTx {
+            hash: Some(response.hash.to_string()),
+            height: Some(response.height.value()),
+            index: Some(response.index),
+            code: Some(result.code.value()),
+            gas_wanted: u64::try_from(result.gas_wanted).ok(),
+            gas_used: u64::try_from(result.gas_used).ok(),
+            events: result.events.into_iter().map(TxEvent::from).collect(),
+            ..CosmosTx::decode(&response.tx)?
+        })
+    }
+}
+
+impl TryFrom<tendermint_rpc::event::TxInfo> for CosmosTx {
+    type Error = TxDecodeError;
+
+    /// The transaction of a `Tx` event, whose result has no code: only successful
+    /// transactions emit events.
+    fn try_from(info: tendermint_rpc::event::TxInfo) -> Result<Self, Self::Error> {
+        let result = info.result;
+        let gas = |gas: Option<String>| gas.and_then(|gas| gas.parse().ok());
+        Ok(CosmosTx {
+            height: u64::try_from(info.height).ok(),
+            index: info.index.and_then(|index| u32::try_from(index).ok()),
+            code: Some(0),
+            gas_wanted: gas(result.gas_wanted),
+            gas_used: gas(result.gas_used),
+            events: result.events.into_iter().map(TxEvent::from).collect(),
+            ..CosmosTx::decode(&info.tx)?
+        })
+    }
+}
+
+impl CosmosTx {
+    /// The events emitted by contracts, `wasm` and the custom `wasm-*` ones, recording this
+    /// transaction as their origin.
+    pub fn wasm_events(&self) -> Vec<WasmEvent> {
+        let events = self
+            .events
+            .iter()
+            .filter(|event| event.kind == "wasm" || event.kind.starts_with("wasm-"));
+        let events = events.map(|event| WasmEvent {
+            tx_hash: self.hash.clone(),
+            tx_index: self.index,
+            ..WasmEvent::from(event.clone())
+        });
+        events.collect()
+    }
+
+    /// Decode the protobuf encoding of a transaction, hashing it.
+    pub fn decode(bytes: &[u8]) -> Result<Self, TxDecodeError> {
+        let tx = cosmrs::Tx::from_bytes(bytes).map_err(TxDecodeError)?;
+        Ok(CosmosTx {
+            hash: Some(hex::encode_upper(Sha256::digest(bytes))),
+            ..CosmosTx::from(tx)
+        })
+    }
+}
+
+impl From<&cosmrs::Any> for TxMessage {
+    fn from(message: &cosmrs::Any) -> Self {
+        TxMessage {
+            type_url: message.type_url.clone(),
+            value: STANDARD.encode(&message.value),
+            fields: fields(&message.type_url, &message.value).unwrap_or_default(),
+        }
+    }
+}
+
+impl From<&cosmrs::Coin> for TxCoin {
+    fn from(coin: &cosmrs::Coin) -> Self {
+        TxCoin {
+            denom: coin.denom.to_string(),
+            amount: coin.amount.to_string(),
+        }
+    }
+}
+
+/// The fields of a message of one of the types `proto.decode_any` knows, if it is one and
+/// decodes.
+fn fields(type_url: &str, bytes: &[u8]) -> Option<Map<String, Value>> {
+    let coins = |coins: &[Coin]| -> Value {
+        let coins = coins.iter();
+        coins
+            .map(|coin| json!({ "denom": coin.denom, "amount": coin.amount }))
+            .collect()
+    };
+    let fields = match type_url {
+        "/cosmos.bank.v1beta1.MsgSend" => {
+            let msg = MsgSend::decode(bytes).ok()?;
+            json!({
+                "from_address": msg.from_address,
+                "to_address": msg.to_address,
+                "amount": coins(&msg.amount),
+            })
+        }
+        "/cosmwasm.wasm.v1.MsgExecuteContract" => {
+            let msg = MsgExecuteContract::decode(bytes).ok()?;
+            let mut fields = json!({
+                "sender": msg.sender,
+                "contract": msg.contract,
+                "funds": coins(&msg.funds),
+            });
+            // Like `proto.decode_any`, keep the raw message around when it isn't JSON.
+            if let Ok(decoded) = serde_json::from_slice::<Value>(&msg.msg) {
+                fields["msg"] = decoded;
+            }
+            if let Ok(raw) = String::from_utf8(msg.msg) {
+                fields["msg_raw"] = raw.into();
+            }
+            fields
+        }
+        "/cosmos.staking.v1beta1.MsgDelegate" => {
+            let msg = MsgDelegate::decode(bytes).ok()?;
+            let mut fields = json!({
+                "delegator_address": msg.delegator_address,
+                "validator_address": msg.validator_address,
+            });
+            if let Some(amount) = &msg.amount {
+                fields["amount"] = json!({ "denom": amount.denom, "amount": amount.amount });
+            }
+            fields
+        }
+        "/cosmos.distribution.v1beta1.MsgWithdrawDelegatorReward" => {
+            let msg = MsgWithdrawDelegatorReward::decode(bytes).ok()?;
+            json!({
+                "delegator_address": msg.delegator_address,
+                "validator_address": msg.validator_address,
+            })
+        }
+        _ => return None,
+    };
+    match fields {
+        Value::Object(fields) => Some(fields),
+        _ => None,
+    }
+}
@@ -0,0 +1,58 @@
+//! Deterministic mode: stubbing out the sources of wall-clock time and randomness.
+//!
+//! Used when replaying historical blocks, where a verdict must only depend on the value.
+
+use mlua::Lua;
+
+use crate::{rng, RuntimeOptions};
+
+const STUBS: &str = r#"
+local secs = ...
+local time, date = os.time, os.date
+os.time = function(t)
+    if t == nil then
+        return secs
+    end
+    return time(t)
+end
+os.date = function(format, t)
+    return date(format, t or secs)
+end
+os.clock = function()
+    return 0
+end
+"#;
+
+pub(crate) fn install(lua: &Lua, options: &RuntimeOptions) -> mlua::Result<()> {
+    let (secs, _) = options.fixed_now().unwrap_or_default();
+    lua.load(STUBS)
+        .set_name("deterministic")
+        .call::<_, ()>(secs)?;
+    rng::seed(lua, options.random_seed)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use super::*;
+
+    #[test]
+    fn stubs() {
+        let lua = Lua::new();
+        let options = RuntimeOptions {
+            deterministic: true,
+            fixed_time: SystemTime::UNIX_EPOCH + Duration::from_secs(1673352000),
+            ..Default::default()
+        };
+        install(&lua, &options).unwrap();
+        let (time, date, clock): (i64, String, f64) = lua
+            .load(r#"return os.time(), os.date("!%Y-%m-%d"), os.clock()"#)
+            .eval()
+            .unwrap();
+        assert_eq!(
+            (time, date.as_str(), clock),
+            (1673352000, "2023-01-10", 0.0)
+        );
+    }
+}
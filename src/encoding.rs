@@ -0,0 +1,123 @@
+//! Decoding the files of libraries and scripts.
+//!
+//! Files are read as bytes and decoded as UTF-8, without the byte order mark editors may
+//! prepend. Files starting with a UTF-16 byte order mark, as some Windows editors save them,
+//! are transcoded. Anything else that isn't UTF-8 is refused with the offset of the first
+//! invalid byte: Lua would take it, but such bytes are far more often a mangled file than
+//! Latin-1 meant to be there, and would end up in the strings of the script.
+
+use std::{fmt, io, path::Path};
+
+/// Why a file couldn't be decoded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EncodingError {
+    /// The file starts with a UTF-16 byte order mark, but isn't valid UTF-16 past it.
+    InvalidUtf16,
+    /// The file isn't valid UTF-8 from the byte at `offset`, counted from 0 with any byte
+    /// order mark.
+    InvalidUtf8 { offset: usize },
+}
+
+impl fmt::Display for EncodingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncodingError::InvalidUtf16 => {
+                write!(f, "it appears to be UTF-16 encoded, but isn't valid UTF-16")
+            }
+            EncodingError::InvalidUtf8 { offset } => {
+                write!(f, "it isn't valid UTF-8 at byte {offset}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for EncodingError {}
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+const UTF16_LE_BOM: &[u8] = b"\xFF\xFE";
+const UTF16_BE_BOM: &[u8] = b"\xFE\xFF";
+
+/// Read the file at `path` as text, see [`decode`].
+pub(crate) fn read_to_string(path: &Path) -> io::Result<String> {
+    decode(std::fs::read(path)?)
+}
+
+/// Decode the contents of a file, failing with an [`io::ErrorKind::InvalidData`] error
+/// wrapping an [`EncodingError`] if it can't be.
+pub(crate) fn decode(bytes: Vec<u8>) -> io::Result<String> {
+    let invalid = |error| io::Error::new(io::ErrorKind::InvalidData, error);
+    if let Some(rest) = bytes.strip_prefix(UTF8_BOM) {
+        return std::str::from_utf8(rest)
+            .map(str::to_string)
+            .map_err(|error| {
+                invalid(EncodingError::InvalidUtf8 {
+                    offset: UTF8_BOM.len() + error.valid_up_to(),
+                })
+            });
+    }
+    let utf16: Option<fn([u8; 2]) -> u16> = if bytes.starts_with(UTF16_LE_BOM) {
+        Some(u16::from_le_bytes)
+    } else if bytes.starts_with(UTF16_BE_BOM) {
+        Some(u16::from_be_bytes)
+    } else {
+        None
+    };
+    if let Some(unit) = utf16 {
+        let units = bytes[2..].chunks(2).map(|pair| match pair {
+            [a, b] => Ok(unit([*a, *b])),
+            _ => Err(()),
+        });
+        let units: Result<Vec<u16>, ()> = units.collect();
+        return units
+            .ok()
+            .and_then(|units| String::from_utf16(&units).ok())
+            .ok_or_else(|| invalid(EncodingError::InvalidUtf16));
+    }
+    String::from_utf8(bytes).map_err(|error| {
+        invalid(EncodingError::InvalidUtf8 {
+            offset: error.utf8_error().valid_up_to(),
+        })
+    })
+}
+
+/// The encoding error `error` wraps, if any.
+pub(crate) fn encoding_error(error: &io::Error) -> Option<&EncodingError> {
+    error.get_ref()?.downcast_ref()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decoded(bytes: &[u8]) -> Result<String, EncodingError> {
+        decode(bytes.to_vec()).map_err(|error| encoding_error(&error).unwrap().clone())
+    }
+
+    #[test]
+    fn decoding() {
+        let source = "return { é = 1 }";
+        assert_eq!(decoded(source.as_bytes()).unwrap(), source);
+        assert_eq!(
+            decoded(&[UTF8_BOM, source.as_bytes()].concat()).unwrap(),
+            source
+        );
+
+        let le: Vec<u8> = source.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        assert_eq!(decoded(&[UTF16_LE_BOM, &le].concat()).unwrap(), source);
+        let be: Vec<u8> = source.encode_utf16().flat_map(u16::to_be_bytes).collect();
+        assert_eq!(decoded(&[UTF16_BE_BOM, &be].concat()).unwrap(), source);
+        assert_eq!(
+            decoded(&[UTF16_LE_BOM, &le[1..]].concat()),
+            Err(EncodingError::InvalidUtf16)
+        );
+
+        assert_eq!(
+            decoded(b"-- caf\xE9\nreturn {}"),
+            Err(EncodingError::InvalidUtf8 { offset: 6 })
+        );
+        assert_eq!(
+            decoded(&[UTF8_BOM, b"\xFF"].concat()),
+            Err(EncodingError::InvalidUtf8 { offset: 3 })
+        );
+    }
+}
@@ -0,0 +1,81 @@
+//! The `env` function: read access to allowlisted environment variables.
+//!
+//! `env(name)` returns the variable if the configuration of the filter system running the script
+//! exposes it, and `nil` otherwise. Requests for other names are counted, so they show up in the
+//! filter stats.
+
+use std::collections::HashSet;
+
+use mlua::Lua;
+
+/// The allowlist and denied-access counter of a runtime, kept in its app data.
+#[derive(Default)]
+struct EnvAccess {
+    allowed: HashSet<String>,
+    denied: u64,
+}
+
+pub(crate) fn install(lua: &Lua) -> mlua::Result<()> {
+    lua.set_app_data(EnvAccess::default());
+    let env = lua.create_function(|lua, name: String| {
+        let mut access = lua
+            .app_data_mut::<EnvAccess>()
+            .ok_or_else(|| mlua::Error::runtime("env: not available"))?;
+        if access.allowed.contains(&name) {
+            Ok(std::env::var(name).ok())
+        } else {
+            access.denied += 1;
+            Ok(None)
+        }
+    })?;
+    lua.globals().set("env", env)
+}
+
+/// Expose the variables in `names` to scripts, and only them.
+pub(crate) fn set(lua: &Lua, names: &[String]) {
+    if let Some(mut access) = lua.app_data_mut::<EnvAccess>() {
+        access.allowed = names.iter().cloned().collect();
+    }
+}
+
+/// How many requests for variables outside the allowlist were made so far.
+pub(crate) fn denied(lua: &Lua) -> u64 {
+    lua.app_data_ref::<EnvAccess>()
+        .map_or(0, |access| access.denied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allowlist() {
+        std::env::set_var("CRONCAT_ENV_TEST_ALLOWED", "juno1operator");
+        std::env::set_var("CRONCAT_ENV_TEST_DENIED", "secret");
+        let lua = Lua::new();
+        install(&lua).unwrap();
+
+        // Without an allowlist every lookup is nil.
+        let value: Option<String> = lua
+            .load(r#"return env("CRONCAT_ENV_TEST_ALLOWED")"#)
+            .eval()
+            .unwrap();
+        assert_eq!(value, None);
+
+        set(&lua, &["CRONCAT_ENV_TEST_ALLOWED".to_string()]);
+        let (allowed, denied): (Option<String>, Option<String>) = lua
+            .load(r#"return env("CRONCAT_ENV_TEST_ALLOWED"), env("CRONCAT_ENV_TEST_DENIED")"#)
+            .eval()
+            .unwrap();
+        assert_eq!(allowed.as_deref(), Some("juno1operator"));
+        assert_eq!(denied, None);
+        assert_eq!(self::denied(&lua), 2);
+
+        set(&lua, &[]);
+        let value: Option<String> = lua
+            .load(r#"return env("CRONCAT_ENV_TEST_ALLOWED")"#)
+            .eval()
+            .unwrap();
+        assert_eq!(value, None);
+    }
+}
@@ -0,0 +1,554 @@
+//! Errors raised while loading configurations and filtering values.
+
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use thiserror::Error;
+
+use crate::{
+    convert::TooDeep, encoding, watchdog::Trip, BackendError, EncodingError, FilterId, LintReport,
+    ScriptLanguage, API_VERSIONS, BUNDLE_VERSION,
+};
+
+/// Any error of this crate, for callers that handle them all alike.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    #[error(transparent)]
+    Load(#[from] LoadError),
+    #[error(transparent)]
+    Filter(#[from] FilterError),
+}
+
+/// An error reading or validating a filter configuration.
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    /// The configuration file couldn't be read.
+    #[error("failed to read the configuration at {}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    /// The configuration isn't YAML of the expected structure.
+    #[error("failed to parse the configuration: {0}")]
+    Parse(#[from] serde_yaml::Error),
+
+    /// A filter of `chain` has no script.
+    #[error("filter `{filter}` of chain `{chain}` has no script")]
+    MissingScript { chain: String, filter: String },
+
+    /// The trusted key at `index` of [`Config::trusted_keys`](crate::Config::trusted_keys)
+    /// isn't a base64 Ed25519 public key.
+    #[error("trusted key {index} is invalid: {message}")]
+    TrustedKey { index: usize, message: String },
+
+    /// The expression of `chain` doesn't parse, going wrong at `column`, see
+    /// [`Config::expressions`](crate::Config::expressions).
+    #[error("the expression of chain `{chain}` is invalid at column {column}: {message}")]
+    Expression {
+        chain: String,
+        column: usize,
+        message: String,
+    },
+
+    /// The configuration is over a load limit of the runtime, such as
+    /// [`RuntimeOptions::max_scripts`](crate::RuntimeOptions::max_scripts): `what` counted
+    /// `count`, over the `max` allowed by `limit`.
+    #[error("{what}, {} over the `{limit}` limit of {max}", count - max)]
+    OverLimit {
+        what: String,
+        limit: &'static str,
+        count: u64,
+        max: u64,
+    },
+}
+
+/// An error reading or writing a [`FilterBundle`](crate::FilterBundle).
+#[derive(Debug, Error)]
+pub enum BundleError {
+    /// The bundle isn't JSON of the expected structure, or can't be written as JSON.
+    #[error("invalid bundle: {0}")]
+    Json(#[from] serde_json::Error),
+
+    /// The bundle is in a version of the format this crate doesn't read.
+    #[error(
+        "bundle format version {version} isn't supported, this crate reads version {}",
+        BUNDLE_VERSION
+    )]
+    Version { version: u64 },
+}
+
+/// An error loading a filter configuration into a runtime.
+///
+/// Reading errors may be worth retrying; the others come back until the configuration or its
+/// scripts change.
+#[derive(Debug, Error)]
+pub enum LoadError {
+    /// The configuration is invalid, or over the load limits of the runtime.
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+
+    /// A bundle can't be loaded by this crate.
+    #[error(transparent)]
+    Bundle(#[from] BundleError),
+
+    /// A library or script couldn't be read.
+    #[error("failed to read {origin}: {error}")]
+    Io {
+        origin: Box<LoadOrigin>,
+        #[source]
+        error: io::Error,
+    },
+
+    /// A library or script isn't UTF-8, or UTF-16 with a byte order mark.
+    #[error("failed to decode {origin}: {error}")]
+    Encoding {
+        origin: Box<LoadOrigin>,
+        error: EncodingError,
+    },
+
+    /// Evaluating a library or script raised an error.
+    #[error("failed to evaluate {origin}: {error}")]
+    Lua {
+        origin: Box<LoadOrigin>,
+        #[source]
+        error: mlua::Error,
+    },
+
+    /// A script didn't evaluate to a table of filter functions, or a filter function.
+    #[error("{origin} returned no filters: {message}")]
+    Module {
+        origin: Box<LoadOrigin>,
+        message: String,
+    },
+
+    /// A script declares an `api_version` outside of [`API_VERSIONS`](crate::API_VERSIONS).
+    #[error(
+        "{origin} declares api_version {version}, this runtime supports {}-{}",
+        API_VERSIONS.start(),
+        API_VERSIONS.end()
+    )]
+    ApiVersion {
+        origin: Box<LoadOrigin>,
+        version: String,
+    },
+
+    /// The signature of the script of `filter` doesn't check out against `tried`, the
+    /// fingerprints of the trusted keys.
+    #[error("{origin} of filter `{filter}` {problem}, tried keys {}", tried.join(", "))]
+    Signature {
+        origin: Box<LoadOrigin>,
+        filter: String,
+        tried: Vec<String>,
+        problem: SignatureProblem,
+    },
+
+    /// The expression of `chain` names `filter` at `column`, which isn't one of the filters
+    /// of the chain.
+    #[error(
+        "the expression of chain `{chain}` names `{filter}` at column {column}, which isn't a \
+         filter of the chain"
+    )]
+    Expression {
+        chain: String,
+        filter: String,
+        column: usize,
+    },
+
+    /// A script is written in a language the filter system doesn't run, see
+    /// [`FilterConfig::language`](crate::FilterConfig::language).
+    #[error("{origin} is written in {language}, which this filter system doesn't run")]
+    Language {
+        origin: Box<LoadOrigin>,
+        language: ScriptLanguage,
+    },
+
+    /// A [`FilterBackend`](crate::FilterBackend) failed to load a library or compile a script.
+    #[error("failed to load {origin}: {error}")]
+    Backend {
+        origin: Box<LoadOrigin>,
+        #[source]
+        error: BackendError,
+    },
+
+    /// A script has lint findings while [`strict_lint`](crate::FilterSystem::strict_lint) is
+    /// on.
+    #[error("lint failed for {0}")]
+    Lint(Box<LintReport>),
+
+    /// The runtime failed outside of any script, such as while setting up their environments.
+    #[error(transparent)]
+    Runtime(#[from] mlua::Error),
+}
+
+impl LoadError {
+    /// Reading the library or script `origin` failed with `error`.
+    pub(crate) fn read(origin: Box<LoadOrigin>, error: io::Error) -> Self {
+        match encoding::encoding_error(&error) {
+            Some(encoding) => LoadError::Encoding {
+                origin,
+                error: encoding.clone(),
+            },
+            None => LoadError::Io { origin, error },
+        }
+    }
+}
+
+/// What is wrong with the signature of a script, see [`LoadError::Signature`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Error)]
+pub enum SignatureProblem {
+    /// The filter has no signature, while there are trusted keys.
+    #[error("is unsigned")]
+    Unsigned,
+    /// The signature isn't a base64 Ed25519 signature.
+    #[error("has a malformed signature")]
+    Malformed,
+    /// No trusted key signed the script as it is.
+    #[error("isn't signed by a trusted key")]
+    Untrusted,
+}
+
+/// The library or script a [`LoadError`] is about.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LoadOrigin {
+    /// The shared library exposed as the global `name`.
+    Library { name: String, path: PathBuf },
+    /// A filter script of `chain`.
+    Script { chain: String, path: PathBuf },
+}
+
+impl LoadOrigin {
+    /// The path of the library or script.
+    pub fn path(&self) -> &Path {
+        match self {
+            LoadOrigin::Library { path, .. } | LoadOrigin::Script { path, .. } => path,
+        }
+    }
+
+    /// The chain of the script, or none for a library.
+    pub fn chain(&self) -> Option<&str> {
+        match self {
+            LoadOrigin::Library { .. } => None,
+            LoadOrigin::Script { chain, .. } => Some(chain),
+        }
+    }
+}
+
+impl fmt::Display for LoadOrigin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadOrigin::Library { name, path } => {
+                write!(f, "library `{name}` at {}", path.display())
+            }
+            LoadOrigin::Script { chain, path } => {
+                write!(f, "script {} of chain `{chain}`", path.display())
+            }
+        }
+    }
+}
+
+/// An error raised by a filter system.
+#[derive(Debug, Error)]
+pub enum FilterError {
+    /// The Lua runtime raised an error.
+    #[error(transparent)]
+    Lua(mlua::Error),
+
+    /// A value was nested deeper than [`RuntimeOptions::max_value_depth`] allows, so it wasn't
+    /// converted into Lua.
+    ///
+    /// [`RuntimeOptions::max_value_depth`]: crate::RuntimeOptions::max_value_depth
+    #[error("the value is nested deeper than {depth} levels")]
+    ValueTooDeep { depth: usize },
+
+    /// Filtering was cancelled before every value was processed.
+    ///
+    /// `kept` holds the positions of the values that matched before the cancellation.
+    #[error("filtering was cancelled after {processed} values")]
+    Cancelled { processed: usize, kept: Vec<usize> },
+
+    /// A filter call was aborted through an [`InterruptHandle`](crate::InterruptHandle).
+    #[error("filter {filter} was interrupted")]
+    Interrupted { filter: String },
+
+    /// A filter call ran past the timeout set with
+    /// [`FilterSystem::set_call_timeout`](crate::FilterSystem::set_call_timeout).
+    #[error("filter {filter} timed out after {elapsed:?}")]
+    Timeout { filter: String, elapsed: Duration },
+
+    /// A filter call panicked, in the `Serialize` impl of the value or in a registered function.
+    ///
+    /// Whatever the panicking code was changing isn't rolled back; the Lua runtime itself stays
+    /// usable unless [`FilterError::Poisoned`] is reported afterwards.
+    #[error("filter {filter} panicked: {message}")]
+    Panic { filter: String, message: String },
+
+    /// A filter returned a value over the limits of [`RuntimeOptions::max_return_string_len`]
+    /// or [`RuntimeOptions::max_return_table_entries`], which wasn't read.
+    ///
+    /// `size` is the length of the string or the number of entries of the table.
+    ///
+    /// [`RuntimeOptions::max_return_string_len`]: crate::RuntimeOptions::max_return_string_len
+    /// [`RuntimeOptions::max_return_table_entries`]: crate::RuntimeOptions::max_return_table_entries
+    #[error("filter {filter} returned a {kind} of size {size}, over the limit")]
+    OversizedReturn {
+        filter: String,
+        kind: ReturnKind,
+        size: usize,
+    },
+
+    /// A filter's verdict wasn't a boolean, under
+    /// [`FilterSystem::strict_returns`](crate::FilterSystem::strict_returns).
+    #[error("filter {filter} returned a {got_type} verdict rather than a boolean")]
+    InvalidReturn {
+        filter: String,
+        got_type: &'static str,
+    },
+
+    /// A filter call grew the runtime's memory by more than its
+    /// [`Filter::with_max_call_memory`](crate::Filter::with_max_call_memory) limit.
+    #[error("filter {filter} grew memory by {bytes} bytes in a call, over its limit of {limit}")]
+    CallMemoryExceeded {
+        filter: String,
+        bytes: u64,
+        limit: u64,
+    },
+
+    /// A filter a [`FilterBackend`](crate::FilterBackend) compiled failed.
+    #[error("filter {filter} failed: {error}")]
+    Backend {
+        filter: String,
+        #[source]
+        error: BackendError,
+    },
+
+    /// What a filter returned for a value didn't deserialize into the output type of
+    /// [`FilterSystem::filter_transform`](crate::FilterSystem::filter_transform).
+    ///
+    /// `path` is where in the output it failed, `.` for the output itself, and `field` the
+    /// name of the offending field, if there is one.
+    #[error("filter {filter} returned an output that doesn't deserialize at {path}: {message}")]
+    Transform {
+        filter: String,
+        path: String,
+        field: Option<String>,
+        message: String,
+    },
+
+    /// The context provider of
+    /// [`FilterSystem::set_context_provider`](crate::FilterSystem::set_context_provider)
+    /// panicked, or its context didn't convert into Lua, failing the batch before any filter
+    /// ran.
+    #[error("the context provider failed: {message}")]
+    ContextProvider { message: String },
+
+    /// A previous panic left the runtime unusable, so no more filters are run on it.
+    #[error("the filter runtime is poisoned by an earlier panic")]
+    Poisoned,
+
+    /// A filter raised an error, on every attempt if it has retries.
+    ///
+    /// The Lua traceback is only displayed by the alternate format, `{:#}`, see
+    /// [`FilterError::traceback`].
+    #[error(transparent)]
+    Script(Box<ScriptError>),
+}
+
+/// The error a filter script raised, in [`FilterError::Script`].
+#[derive(Debug)]
+pub struct ScriptError {
+    pub filter: String,
+    /// The script the filter was loaded from, if it came from a configuration.
+    pub script: Option<PathBuf>,
+    /// How many times the filter was called, retries included.
+    pub attempts: u32,
+    /// The Lua stack where the error was raised, when Lua recorded it.
+    pub traceback: Option<String>,
+    /// The error, without its traceback.
+    pub error: mlua::Error,
+}
+
+impl ScriptError {
+    /// Split the traceback Lua appended to `error` off it.
+    pub(crate) fn new(
+        filter: String,
+        script: Option<PathBuf>,
+        attempts: u32,
+        error: mlua::Error,
+    ) -> Self {
+        let (error, traceback) = match error {
+            mlua::Error::RuntimeError(message) => match message.split_once("\nstack traceback:") {
+                Some((message, traceback)) => (
+                    mlua::Error::RuntimeError(message.to_string()),
+                    Some(format!("stack traceback:{traceback}")),
+                ),
+                None => (mlua::Error::RuntimeError(message), None),
+            },
+            mlua::Error::CallbackError { traceback, cause } => ((*cause).clone(), Some(traceback)),
+            error => (error, None),
+        };
+        Self {
+            filter,
+            script,
+            attempts,
+            traceback,
+            error,
+        }
+    }
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "filter {}", self.filter)?;
+        if let Some(script) = &self.script {
+            write!(f, " of {}", script.display())?;
+        }
+        f.write_str(" failed")?;
+        if self.attempts > 1 {
+            write!(f, " after {} attempts", self.attempts)?;
+        }
+        write!(f, ": {}", self.error)?;
+        match &self.traceback {
+            Some(traceback) if f.alternate() => write!(f, "\n{traceback}"),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl std::error::Error for ScriptError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// An error ending [`FilterSystem::filter_streaming`](crate::FilterSystem::filter_streaming).
+#[derive(Debug, Error)]
+pub enum StreamError<E> {
+    /// Filtering failed.
+    #[error(transparent)]
+    Filter(#[from] FilterError),
+    /// The sink refused a kept value, which stopped the stream.
+    #[error("the sink failed: {0}")]
+    Sink(E),
+}
+
+/// An error recording verdicts, or replaying a recording or an export, see
+/// [`FilterSystem::record`](crate::FilterSystem::record).
+#[derive(Debug, Error)]
+pub enum RecordingError {
+    /// Filtering a value failed.
+    #[error(transparent)]
+    Filter(#[from] FilterError),
+    /// Writing or reading the recording failed.
+    #[error("failed to access the recording: {0}")]
+    Io(#[from] io::Error),
+    /// A value couldn't be serialized, or a line of the recording couldn't be read back.
+    #[error("line {line} of the recording: {error}")]
+    Json {
+        /// The line of the value, from 1.
+        line: usize,
+        error: serde_json::Error,
+    },
+}
+
+/// An error filtering values with auditing, see
+/// [`FilterSystem::filter_audited`](crate::FilterSystem::filter_audited).
+#[derive(Debug, Error)]
+pub enum AuditError {
+    /// Filtering a value failed.
+    #[error(transparent)]
+    Filter(#[from] FilterError),
+    /// A value couldn't be serialized for its snapshot.
+    #[error("failed to snapshot a value: {0}")]
+    Snapshot(serde_json::Error),
+    /// The audit sink failed to take a record.
+    #[error("the audit sink failed: {0}")]
+    Sink(io::Error),
+}
+
+/// A name that doesn't designate a single filter, see
+/// [`FilterSystem::filter_id`](crate::FilterSystem::filter_id).
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub enum ResolveError {
+    /// No filter is called `name`.
+    #[error("no filter is called `{name}`")]
+    Unknown { name: String },
+    /// Several filters are called `name`, with these ids.
+    #[error("`{name}` is ambiguous, it names {}", quoted(candidates))]
+    Ambiguous {
+        name: String,
+        candidates: Vec<FilterId>,
+    },
+}
+
+/// `ids` quoted and separated by commas.
+fn quoted(ids: &[FilterId]) -> String {
+    let ids: Vec<String> = ids.iter().map(|id| format!("`{id}`")).collect();
+    ids.join(", ")
+}
+
+/// What kind of value was over the return limits, in [`FilterError::OversizedReturn`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReturnKind {
+    String,
+    Table,
+}
+
+impl fmt::Display for ReturnKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReturnKind::String => f.write_str("string"),
+            ReturnKind::Table => f.write_str("table"),
+        }
+    }
+}
+
+impl From<mlua::Error> for FilterError {
+    fn from(err: mlua::Error) -> Self {
+        match TooDeep::find(&err) {
+            Some(TooDeep { depth }) => FilterError::ValueTooDeep { depth },
+            None => FilterError::Lua(err),
+        }
+    }
+}
+
+impl FilterError {
+    /// The watchdog trip behind this error, if any.
+    pub(crate) fn trip(&self) -> Option<Trip> {
+        match self {
+            FilterError::Lua(err) => Trip::find(err),
+            FilterError::Script(err) => Trip::find(&err.error),
+            FilterError::Cancelled { .. } => Some(Trip::Cancelled),
+            FilterError::Interrupted { .. } => Some(Trip::Interrupted),
+            FilterError::Timeout { elapsed, .. } => Some(Trip::Timeout(*elapsed)),
+            FilterError::ValueTooDeep { .. }
+            | FilterError::OversizedReturn { .. }
+            | FilterError::InvalidReturn { .. }
+            | FilterError::CallMemoryExceeded { .. }
+            | FilterError::Backend { .. }
+            | FilterError::Transform { .. }
+            | FilterError::ContextProvider { .. }
+            | FilterError::Panic { .. }
+            | FilterError::Poisoned => None,
+        }
+    }
+
+    /// The Lua traceback of a script error, listing the functions it was raised through.
+    pub fn traceback(&self) -> Option<&str> {
+        match self {
+            FilterError::Script(err) => err.traceback.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Whether this error comes from a panic, which error policies never swallow.
+    pub(crate) fn is_panic(&self) -> bool {
+        matches!(self, FilterError::Panic { .. } | FilterError::Poisoned)
+    }
+}
@@ -0,0 +1,289 @@
+//! Boolean expressions over the verdicts of the filters of a chain, see
+//! [`Config::expressions`](crate::Config::expressions).
+//!
+//! `AND` binds tighter than `OR`, and `NOT` tighter than both; anything else that isn't a
+//! parenthesis or whitespace names a filter. Positions are 1-based columns, in characters.
+
+use std::{fmt, iter::Peekable, str::CharIndices};
+
+/// A parsed expression.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Expression {
+    /// The verdict of the filters called `name`, written at `column`.
+    Filter {
+        name: String,
+        column: usize,
+    },
+    Not(Box<Expression>),
+    And(Box<Expression>, Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+}
+
+/// Why an expression doesn't parse, and the column where it went wrong.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct ParseError {
+    pub(crate) column: usize,
+    pub(crate) message: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+    Name(String),
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+    /// The next token and its column, once peeked.
+    peeked: Option<Option<(Token, usize)>>,
+}
+
+impl Expression {
+    pub(crate) fn parse(source: &str) -> Result<Self, ParseError> {
+        let mut parser = Parser {
+            source,
+            chars: source.char_indices().peekable(),
+            peeked: None,
+        };
+        let expression = parser.or()?;
+        match parser.next() {
+            None => Ok(expression),
+            Some((_, column)) => Err(ParseError {
+                column,
+                message: "expected an operator".to_string(),
+            }),
+        }
+    }
+
+    /// The filters the expression names, with their columns, in order.
+    pub(crate) fn filters(&self) -> Vec<(&str, usize)> {
+        match self {
+            Expression::Filter { name, column } => vec![(name, *column)],
+            Expression::Not(operand) => operand.filters(),
+            Expression::And(left, right) | Expression::Or(left, right) => {
+                let mut filters = left.filters();
+                filters.extend(right.filters());
+                filters
+            }
+        }
+    }
+
+    /// The value of the expression, getting the verdicts of filters from `verdict` as they are
+    /// needed, once each.
+    pub(crate) fn evaluate<E>(
+        &self,
+        verdict: &mut impl FnMut(&str) -> Result<bool, E>,
+    ) -> Result<bool, E> {
+        let mut known = Vec::new();
+        self.evaluate_with(verdict, &mut known)
+    }
+
+    fn evaluate_with<'a, E>(
+        &'a self,
+        verdict: &mut impl FnMut(&str) -> Result<bool, E>,
+        known: &mut Vec<(&'a str, bool)>,
+    ) -> Result<bool, E> {
+        Ok(match self {
+            Expression::Filter { name, .. } => {
+                match known.iter().find(|(known, _)| known == name) {
+                    Some((_, matched)) => *matched,
+                    None => {
+                        let matched = verdict(name)?;
+                        known.push((name, matched));
+                        matched
+                    }
+                }
+            }
+            Expression::Not(operand) => !operand.evaluate_with(verdict, known)?,
+            Expression::And(left, right) => {
+                left.evaluate_with(verdict, known)? && right.evaluate_with(verdict, known)?
+            }
+            Expression::Or(left, right) => {
+                left.evaluate_with(verdict, known)? || right.evaluate_with(verdict, known)?
+            }
+        })
+    }
+}
+
+/// The expression with the parentheses it needs, parsing back to the same expression.
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Operands that bind looser than their operator are parenthesized.
+        let operand =
+            |f: &mut fmt::Formatter<'_>, operand: &Expression, looser: fn(&Expression) -> bool| {
+                match looser(operand) {
+                    true => write!(f, "({operand})"),
+                    false => write!(f, "{operand}"),
+                }
+            };
+        let binary = |expression: &Expression| {
+            matches!(expression, Expression::And(..) | Expression::Or(..))
+        };
+        let or = |expression: &Expression| matches!(expression, Expression::Or(..));
+        match self {
+            Expression::Filter { name, .. } => f.write_str(name),
+            Expression::Not(inner) => {
+                f.write_str("NOT ")?;
+                operand(f, inner, binary)
+            }
+            Expression::And(left, right) => {
+                operand(f, left, or)?;
+                f.write_str(" AND ")?;
+                operand(f, right, binary)
+            }
+            Expression::Or(left, right) => {
+                operand(f, left, |_| false)?;
+                f.write_str(" OR ")?;
+                operand(f, right, binary)
+            }
+        }
+    }
+}
+
+impl Parser<'_> {
+    fn or(&mut self) -> Result<Expression, ParseError> {
+        let mut expression = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            expression = Expression::Or(Box::new(expression), Box::new(self.and()?));
+        }
+        Ok(expression)
+    }
+
+    fn and(&mut self) -> Result<Expression, ParseError> {
+        let mut expression = self.not()?;
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            expression = Expression::And(Box::new(expression), Box::new(self.not()?));
+        }
+        Ok(expression)
+    }
+
+    fn not(&mut self) -> Result<Expression, ParseError> {
+        match self.next() {
+            Some((Token::Not, _)) => Ok(Expression::Not(Box::new(self.not()?))),
+            Some((Token::Name(name), column)) => Ok(Expression::Filter { name, column }),
+            Some((Token::Open, column)) => {
+                let expression = self.or()?;
+                match self.next() {
+                    Some((Token::Close, _)) => Ok(expression),
+                    Some((_, column)) => Err(ParseError {
+                        column,
+                        message: "expected `)`".to_string(),
+                    }),
+                    None => Err(ParseError {
+                        column,
+                        message: "`(` is never closed".to_string(),
+                    }),
+                }
+            }
+            Some((_, column)) => Err(ParseError {
+                column,
+                message: "expected a filter name, `NOT` or `(`".to_string(),
+            }),
+            None => Err(ParseError {
+                column: self.source.chars().count() + 1,
+                message: "unexpected end of the expression".to_string(),
+            }),
+        }
+    }
+
+    fn peek(&mut self) -> Option<&Token> {
+        if self.peeked.is_none() {
+            self.peeked = Some(self.token());
+        }
+        self.peeked
+            .as_ref()
+            .and_then(|token| token.as_ref())
+            .map(|(token, _)| token)
+    }
+
+    fn next(&mut self) -> Option<(Token, usize)> {
+        match self.peeked.take() {
+            Some(token) => token,
+            None => self.token(),
+        }
+    }
+
+    fn token(&mut self) -> Option<(Token, usize)> {
+        while self.chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+        let (start, c) = self.chars.next()?;
+        let column = self.source[..start].chars().count() + 1;
+        let token = match c {
+            '(' => Token::Open,
+            ')' => Token::Close,
+            _ => {
+                let mut end = start + c.len_utf8();
+                while let Some((index, c)) = self
+                    .chars
+                    .next_if(|(_, c)| !c.is_whitespace() && !matches!(c, '(' | ')'))
+                {
+                    end = index + c.len_utf8();
+                }
+                match &self.source[start..end] {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    name => Token::Name(name.to_string()),
+                }
+            }
+        };
+        Some((token, column))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(name: &str, column: usize) -> Box<Expression> {
+        Box::new(Expression::Filter {
+            name: name.to_string(),
+            column,
+        })
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            Expression::parse("(croncat AND NOT blocked) OR admin").unwrap(),
+            Expression::Or(
+                Box::new(Expression::And(
+                    name("croncat", 2),
+                    Box::new(Expression::Not(name("blocked", 18)))
+                )),
+                name("admin", 30)
+            )
+        );
+        // AND binds tighter than OR.
+        assert_eq!(
+            Expression::parse("a OR b AND c").unwrap(),
+            Expression::Or(
+                name("a", 1),
+                Box::new(Expression::And(name("b", 6), name("c", 12)))
+            )
+        );
+
+        for (source, column, message) in [
+            ("", 1, "unexpected end of the expression"),
+            ("a AND", 6, "unexpected end of the expression"),
+            ("(a OR b", 1, "`(` is never closed"),
+            ("a b", 3, "expected an operator"),
+            ("a OR )", 6, "expected a filter name, `NOT` or `(`"),
+            ("(a b)", 4, "expected `)`"),
+            ("é AND OR", 7, "expected a filter name, `NOT` or `(`"),
+        ] {
+            let err = Expression::parse(source).unwrap_err();
+            assert_eq!(
+                (err.column, err.message.as_str()),
+                (column, message),
+                "{source}"
+            );
+        }
+    }
+}
@@ -0,0 +1,87 @@
+//! Fully qualified names of filters, see [`FilterId`].
+
+use std::fmt;
+
+use serde::{Serialize, Serializer};
+
+/// Which filter of a system this is: the function of the script a filter of the configuration
+/// loaded for a chain.
+///
+/// Rendered as `uni-5/Testnet Manager/filter`, chain, configuration name and function, the
+/// parts a filter added by hand doesn't have being left out. Methods taking the name of
+/// filters also take the rendered id or a suffix of it, `Testnet Manager/filter` say.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FilterId {
+    /// The chain the filter was loaded for, if it came from a configuration.
+    pub chain: Option<String>,
+    /// The name of the filter in the configuration, shared by the functions of its script.
+    pub config_name: Option<String>,
+    /// The name of the function, also the name of the filter.
+    pub function_name: String,
+}
+
+impl FilterId {
+    /// Whether `name` is this id rendered, or a suffix of it of whole parts.
+    ///
+    /// The bare function name is such a suffix, so it matches the filters of every chain and
+    /// script that have a function of that name.
+    pub fn matches(&self, name: &str) -> bool {
+        matches(&self.to_string(), name)
+    }
+}
+
+/// Whether `name` is the rendered id `id`, or a suffix of it of whole parts.
+pub(crate) fn matches(id: &str, name: &str) -> bool {
+    id == name
+        || id
+            .strip_suffix(name)
+            .is_some_and(|prefix| prefix.ends_with('/'))
+}
+
+impl fmt::Display for FilterId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for part in [&self.chain, &self.config_name].into_iter().flatten() {
+            write!(f, "{part}/")?;
+        }
+        f.write_str(&self.function_name)
+    }
+}
+
+/// Serialized as its rendering.
+impl Serialize for FilterId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches() {
+        let id = FilterId {
+            chain: Some("uni-5".to_string()),
+            config_name: Some("Testnet Manager".to_string()),
+            function_name: "filter".to_string(),
+        };
+        assert_eq!(id.to_string(), "uni-5/Testnet Manager/filter");
+        for name in [
+            "uni-5/Testnet Manager/filter",
+            "Testnet Manager/filter",
+            "filter",
+        ] {
+            assert!(id.matches(name), "{name}");
+        }
+        for name in ["", "ilter", "Manager/filter", "uni-5/filter", "uni-5"] {
+            assert!(!id.matches(name), "{name}");
+        }
+
+        let id = FilterId {
+            function_name: "filter".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(id.to_string(), "filter");
+        assert!(id.matches("filter"));
+    }
+}
@@ -0,0 +1,79 @@
+//! Read-only tables shared between filters.
+//!
+//! A frozen table is an empty proxy whose metatable forwards reads to the real data and rejects
+//! writes, so one filter can't change what another sees. Nested tables are frozen too. Being
+//! proxies, frozen tables don't support `pairs` or `#`.
+
+use mlua::{Lua, Table, Value};
+
+const PROXY: &str = r#"
+local data, name = ...
+return setmetatable({}, {
+    __index = data,
+    __newindex = function(_, key)
+        error(name .. " is read-only (tried to set " .. tostring(key) .. ")", 2)
+    end,
+    __metatable = false,
+})
+"#;
+
+/// Freeze `value` if it is a table, naming it `name` in the errors raised on writes.
+pub(crate) fn freeze<'lua>(
+    lua: &'lua Lua,
+    name: &str,
+    value: Value<'lua>,
+) -> mlua::Result<Value<'lua>> {
+    let Value::Table(table) = value else {
+        return Ok(value);
+    };
+    let data = lua.create_table()?;
+    for pair in table.pairs::<Value, Value>() {
+        let (key, value) = pair?;
+        let nested = match &key {
+            Value::String(key) => format!("{name}.{}", key.to_string_lossy()),
+            key => format!("{name}[{}]", key.to_string().unwrap_or_default()),
+        };
+        data.raw_set(key, freeze(lua, &nested, value)?)?;
+    }
+    lua.load(PROXY)
+        .set_name("frozen")
+        .call::<_, Table>((data, name))
+        .map(Value::Table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_and_writes() {
+        let lua = Lua::new();
+        let constants = lua
+            .load(r#"return { denom = "ujuno", contracts = { factory = "juno1factory" } }"#)
+            .eval()
+            .unwrap();
+        let constants = freeze(&lua, "chain", constants).unwrap();
+        lua.globals().set("chain", constants).unwrap();
+
+        let (denom, factory): (String, String) = lua
+            .load("return chain.denom, chain.contracts.factory")
+            .eval()
+            .unwrap();
+        assert_eq!(
+            (denom.as_str(), factory.as_str()),
+            ("ujuno", "juno1factory")
+        );
+
+        for (script, message) in [
+            (r#"chain.denom = "uosmo""#, "chain is read-only"),
+            (
+                r#"chain.contracts.factory = "juno1evil""#,
+                "chain.contracts is read-only",
+            ),
+            ("setmetatable(chain, nil)", "protected metatable"),
+        ] {
+            let err = lua.load(script).exec().unwrap_err();
+            assert!(err.to_string().contains(message), "{err}");
+        }
+    }
+}
@@ -0,0 +1,146 @@
+//! Controlling the Lua garbage collector from the host.
+
+use mlua::Lua;
+
+/// How the garbage collector of a runtime runs, see [`FilterRuntime::gc_config`].
+///
+/// [`FilterRuntime::gc_config`]: crate::FilterRuntime::gc_config
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GcConfig {
+    /// How long the collector waits before starting a new cycle, as a percentage of the memory
+    /// in use after the previous one. Under Luau this is the heap goal instead. Zero keeps the
+    /// current value.
+    pub pause: i32,
+    /// How fast the collector runs relative to allocation, as a percentage. Zero keeps the
+    /// current value.
+    pub step_multiplier: i32,
+    pub mode: GcMode,
+}
+
+/// The collection strategy of a [`GcConfig`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GcMode {
+    /// Collect incrementally, interleaved with the scripts.
+    #[default]
+    Incremental,
+    /// Collect young objects more often than old ones. Lua 5.4 only.
+    ///
+    /// `step_multiplier` sets the minor multiplier and `pause` the major one.
+    Generational,
+    /// Never collect on its own; only [`GcAfterBatch`] or an explicit collection frees memory.
+    Stopped,
+}
+
+/// What a filter system does with the collector once a batch is filtered.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GcAfterBatch {
+    /// Leave collection to the collector (the default).
+    #[default]
+    Off,
+    /// Run a single collection step.
+    Step,
+    /// Run a full collection cycle.
+    Full,
+}
+
+/// Marks a runtime whose collector is [`GcMode::Stopped`], in its app data.
+struct Stopped;
+
+/// Apply `config` to the collector of `lua`.
+pub(crate) fn configure(lua: &Lua, config: GcConfig) -> mlua::Result<()> {
+    match config.mode {
+        GcMode::Incremental => {
+            lua.remove_app_data::<Stopped>();
+            lua.gc_restart();
+            lua.gc_inc(config.pause, config.step_multiplier, 0);
+        }
+        #[cfg(feature = "lua54")]
+        GcMode::Generational => {
+            lua.remove_app_data::<Stopped>();
+            lua.gc_restart();
+            lua.gc_gen(config.step_multiplier, config.pause);
+        }
+        #[cfg(not(feature = "lua54"))]
+        GcMode::Generational => {
+            return Err(mlua::Error::runtime(
+                "gc_config: the generational collector requires Lua 5.4",
+            ))
+        }
+        GcMode::Stopped => {
+            lua.gc_inc(config.pause, config.step_multiplier, 0);
+            lua.gc_stop();
+            lua.set_app_data(Stopped);
+        }
+    }
+    Ok(())
+}
+
+/// Run `f` with the collector of `lua` stopped, so the garbage it makes stays in
+/// [`Lua::used_memory`] until it returns.
+pub(crate) fn paused<R>(lua: &Lua, f: impl FnOnce() -> R) -> R {
+    lua.gc_stop();
+    let result = f();
+    if lua.app_data_ref::<Stopped>().is_none() {
+        lua.gc_restart();
+    }
+    result
+}
+
+/// Collect per `policy`, returning whether anything was run.
+pub(crate) fn after_batch(lua: &Lua, policy: GcAfterBatch) -> mlua::Result<bool> {
+    match policy {
+        GcAfterBatch::Off => return Ok(false),
+        GcAfterBatch::Step => {
+            lua.gc_step()?;
+        }
+        GcAfterBatch::Full => lua.gc_collect()?,
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn modes() {
+        let lua = Lua::new();
+        configure(
+            &lua,
+            GcConfig {
+                pause: 150,
+                step_multiplier: 300,
+                mode: GcMode::Incremental,
+            },
+        )
+        .unwrap();
+
+        configure(
+            &lua,
+            GcConfig {
+                mode: GcMode::Stopped,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let before = lua.used_memory();
+        lua.load("for i = 1, 10000 do local t = { i } end")
+            .exec()
+            .unwrap();
+        let garbage = lua.used_memory();
+        assert!(garbage > before);
+        assert!(after_batch(&lua, GcAfterBatch::Step).unwrap());
+        assert!(after_batch(&lua, GcAfterBatch::Full).unwrap());
+        assert!(lua.used_memory() < garbage);
+        assert!(!after_batch(&lua, GcAfterBatch::Off).unwrap());
+
+        let generational = configure(
+            &lua,
+            GcConfig {
+                mode: GcMode::Generational,
+                ..Default::default()
+            },
+        );
+        assert_eq!(generational.is_ok(), cfg!(feature = "lua54"));
+    }
+}
@@ -0,0 +1,153 @@
+//! The health of a filter system, for readiness probes, see
+//! [`FilterSystem::health`](crate::FilterSystem::health).
+
+use std::{
+    fmt,
+    time::{Duration, SystemTime},
+};
+
+use serde::{Serialize, Serializer};
+
+/// A snapshot of the health of a filter system.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Health {
+    pub status: Status,
+    /// Why the status isn't [`Status::Healthy`], worst first.
+    pub problems: Vec<String>,
+    /// How many filters are loaded.
+    pub filters: usize,
+    /// How many of them are skipped for now, their fuel budget for the batch burnt.
+    pub disabled_filters: usize,
+    /// How many of them are quarantined for failing too often.
+    pub quarantined_filters: usize,
+    /// The memory the runtime uses, in bytes.
+    pub used_memory: usize,
+    /// The memory limit of the thresholds, if any.
+    pub memory_limit: Option<usize>,
+    /// How long ago a value was last filtered without error, if one ever was.
+    #[serde(serialize_with = "seconds")]
+    pub since_last_success: Option<Duration>,
+    /// How the last load went, if there was one.
+    pub last_load: Option<LoadOutcome>,
+    /// Whether a panic left the runtime unusable.
+    pub poisoned: bool,
+}
+
+/// The overall health of a filter system.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Healthy,
+    /// Working, but with a problem worth looking into.
+    Degraded,
+    /// Not filtering as it should.
+    Unhealthy,
+}
+
+/// When a filter system last loaded a configuration, or part of one, and whether it worked.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct LoadOutcome {
+    #[serde(serialize_with = "crate::audit::rfc3339")]
+    pub at: SystemTime,
+    /// The error the load failed with, if it did.
+    pub error: Option<String>,
+}
+
+impl LoadOutcome {
+    pub(crate) fn of<R, E: fmt::Display>(result: &Result<R, E>) -> Self {
+        Self {
+            at: SystemTime::now(),
+            error: result.as_ref().err().map(ToString::to_string),
+        }
+    }
+}
+
+/// When a filter system stops being healthy, see
+/// [`FilterSystem::set_health_thresholds`](crate::FilterSystem::set_health_thresholds).
+///
+/// Whatever the thresholds, a system without filters or with a poisoned runtime is
+/// unhealthy, and one whose last load failed or with disabled or quarantined filters is
+/// degraded.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HealthThresholds {
+    /// The memory the runtime may use: the system is unhealthy above it, and degraded above
+    /// `memory_warning` of it.
+    pub memory_limit: Option<usize>,
+    /// The share of `memory_limit` over which the system is degraded.
+    pub memory_warning: f64,
+    /// How long the system may go without filtering a value successfully before it is
+    /// degraded, once it has filtered one.
+    pub max_idle: Option<Duration>,
+}
+
+impl Default for HealthThresholds {
+    fn default() -> Self {
+        Self {
+            memory_limit: None,
+            memory_warning: 0.8,
+            max_idle: None,
+        }
+    }
+}
+
+impl Health {
+    /// Derive the status and its problems from the rest of the snapshot.
+    pub(crate) fn assess(mut self, thresholds: &HealthThresholds) -> Self {
+        let mut problems = Vec::new();
+        if self.poisoned {
+            problems.push((Status::Unhealthy, "the runtime is poisoned".to_string()));
+        }
+        if self.filters == 0 {
+            problems.push((Status::Unhealthy, "no filters are loaded".to_string()));
+        }
+        if let Some(limit) = thresholds.memory_limit {
+            let used = self.used_memory;
+            if used > limit {
+                let problem = format!("{used} bytes of memory used, over the limit of {limit}");
+                problems.push((Status::Unhealthy, problem));
+            } else if used as f64 > limit as f64 * thresholds.memory_warning {
+                let problem = format!("{used} bytes of memory used, near the limit of {limit}");
+                problems.push((Status::Degraded, problem));
+            }
+        }
+        if let Some(error) = self.last_load.as_ref().and_then(|load| load.error.as_ref()) {
+            problems.push((Status::Degraded, format!("the last load failed: {error}")));
+        }
+        if self.disabled_filters > 0 {
+            let problem = filters(self.disabled_filters, "disabled");
+            problems.push((Status::Degraded, problem));
+        }
+        if self.quarantined_filters > 0 {
+            let problem = filters(self.quarantined_filters, "quarantined");
+            problems.push((Status::Degraded, problem));
+        }
+        if let (Some(max_idle), Some(idle)) = (thresholds.max_idle, self.since_last_success) {
+            if idle > max_idle {
+                let problem = format!("no value was filtered successfully for {idle:?}");
+                problems.push((Status::Degraded, problem));
+            }
+        }
+        problems.sort_by_key(|(status, _)| std::cmp::Reverse(*status));
+        self.status = problems
+            .first()
+            .map_or(Status::Healthy, |(status, _)| *status);
+        self.problems = problems.into_iter().map(|(_, problem)| problem).collect();
+        self.memory_limit = thresholds.memory_limit;
+        self
+    }
+}
+
+/// How many filters are in `state`, as a sentence.
+fn filters(count: usize, state: &str) -> String {
+    match count {
+        1 => format!("1 filter is {state}"),
+        _ => format!("{count} filters are {state}"),
+    }
+}
+
+fn seconds<S: Serializer>(duration: &Option<Duration>, serializer: S) -> Result<S::Ok, S::Error> {
+    match duration {
+        Some(duration) => serializer.serialize_some(&duration.as_secs_f64()),
+        None => serializer.serialize_none(),
+    }
+}
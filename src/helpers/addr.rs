@@ -0,0 +1,97 @@
+//! The `addr` module: checking Cosmos addresses.
+//!
+//! An address is valid when it decodes as classic bech32 with a correct checksum and a
+//! non-empty payload, so strings that merely start with the right prefix are rejected.
+
+use ::bech32::Variant;
+use mlua::Lua;
+
+use super::bech32;
+use crate::RuntimeOptions;
+
+pub(crate) fn install(lua: &Lua, _options: &RuntimeOptions) -> mlua::Result<()> {
+    let module = lua.create_table()?;
+    module.set(
+        "is_valid",
+        lua.create_function(|_, s: String| Ok(prefix(&s).is_some()))?,
+    )?;
+    module.set(
+        "prefix",
+        lua.create_function(|_, s: String| Ok(prefix(&s)))?,
+    )?;
+    module.set(
+        "has_prefix",
+        lua.create_function(|_, (s, expected): (String, String)| {
+            Ok(prefix(&s).is_some_and(|hrp| hrp == expected))
+        })?,
+    )?;
+    lua.globals().set("addr", module)
+}
+
+/// The human-readable prefix of a valid address.
+fn prefix(s: &str) -> Option<String> {
+    match bech32::decode(s) {
+        Ok((hrp, data, Variant::Bech32)) if !data.is_empty() => Some(hrp),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::bech32::ToBase32;
+    use indoc::indoc;
+
+    use super::*;
+
+    fn juno() -> String {
+        ::bech32::encode("juno", [0x42; 20].to_base32(), Variant::Bech32).unwrap()
+    }
+
+    fn lua() -> Lua {
+        let lua = Lua::new();
+        install(&lua, &RuntimeOptions::default()).unwrap();
+        lua
+    }
+
+    #[test]
+    fn valid_address() {
+        let lua = lua();
+        let result: bool = lua
+            .load(indoc! {r#"
+            local address = ...
+            return addr.is_valid(address) and addr.prefix(address) == "juno"
+                and addr.has_prefix(address, "juno") and not addr.has_prefix(address, "osmo")
+            "#})
+            .call(juno())
+            .unwrap();
+        assert!(result);
+    }
+
+    #[test]
+    fn flipped_character() {
+        let lua = lua();
+        let address = juno();
+        let last = address.len() - 1;
+        let flipped = if address.ends_with('q') { "p" } else { "q" };
+        let address = format!("{}{flipped}", &address[..last]);
+        let result: bool = lua
+            .load(indoc! {r#"
+            local address = ...
+            return address:sub(1, 5) == "juno1" and not addr.is_valid(address)
+                and addr.prefix(address) == nil and not addr.has_prefix(address, "juno")
+            "#})
+            .call(address)
+            .unwrap();
+        assert!(result);
+    }
+
+    #[test]
+    fn garbage() {
+        let lua = lua();
+        let result: bool = lua
+            .load(r#"return not addr.is_valid("juno1") and not addr.is_valid("juno1garbage")"#)
+            .eval()
+            .unwrap();
+        assert!(result);
+    }
+}
@@ -0,0 +1,99 @@
+//! The `b64` module: standard base64 encoding, plus `decode_json` for base64-wrapped JSON.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use mlua::Lua;
+
+use super::json;
+use crate::RuntimeOptions;
+
+pub(crate) fn install(lua: &Lua, options: &RuntimeOptions) -> mlua::Result<()> {
+    let big_integers_as_strings = options.json_big_integers_as_strings;
+
+    let b64 = lua.create_table()?;
+    b64.set(
+        "encode",
+        lua.create_function(|_, input: mlua::String| Ok(STANDARD.encode(input.as_bytes())))?,
+    )?;
+    b64.set(
+        "decode",
+        lua.create_function(|lua, input: mlua::String| {
+            lua.create_string(decode(input.as_bytes())?)
+        })?,
+    )?;
+    b64.set(
+        "decode_json",
+        lua.create_function(move |lua, input: mlua::String| {
+            json::decode(lua, &decode(input.as_bytes())?, big_integers_as_strings)
+        })?,
+    )?;
+    lua.globals().set("b64", b64)
+}
+
+/// Decode standard base64, surrounding whitespace allowed.
+pub(crate) fn decode(input: &[u8]) -> mlua::Result<Vec<u8>> {
+    STANDARD
+        .decode(input.trim_ascii())
+        .map_err(|err| mlua::Error::runtime(format!("b64.decode: {err}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+
+    fn lua() -> Lua {
+        let lua = Lua::new();
+        let options = RuntimeOptions::default();
+        json::install(&lua, &options).unwrap();
+        install(&lua, &options).unwrap();
+        lua
+    }
+
+    #[test]
+    fn round_trip() {
+        let lua = lua();
+        let result: bool = lua
+            .load(indoc! {r#"
+            return b64.encode("croncat") == "Y3JvbmNhdA=="
+                and b64.decode("Y3JvbmNhdA==") == "croncat"
+                and b64.decode(b64.encode("")) == ""
+            "#})
+            .eval()
+            .unwrap();
+        assert!(result);
+    }
+
+    #[test]
+    fn binary_payloads() {
+        let lua = lua();
+        let bytes: mlua::String = lua.load(r#"return b64.decode("AP/+gA==")"#).eval().unwrap();
+        assert_eq!(bytes.as_bytes(), &[0x00, 0xff, 0xfe, 0x80]);
+
+        let encoded: String = lua
+            .load(r#"return b64.encode(string.char(0, 255, 254, 128))"#)
+            .eval()
+            .unwrap();
+        assert_eq!(encoded, "AP/+gA==");
+    }
+
+    #[test]
+    fn decode_json() {
+        let lua = lua();
+        let key: String = lua
+            .load(r#"return next(b64.decode_json("eyJ0aWNrIjp7fX0="))"#)
+            .eval()
+            .unwrap();
+        assert_eq!(key, "tick");
+    }
+
+    #[test]
+    fn invalid_input() {
+        let lua = lua();
+        let message: String = lua
+            .load(r#"local ok, err = pcall(b64.decode, "not base64!") return tostring(err)"#)
+            .eval()
+            .unwrap();
+        assert!(message.contains("b64.decode"), "{message}");
+    }
+}
@@ -0,0 +1,131 @@
+//! The `bech32` module: decoding, encoding and re-prefixing bech32 and bech32m addresses.
+
+use bech32::{FromBase32, ToBase32, Variant};
+use mlua::Lua;
+
+use crate::RuntimeOptions;
+
+pub(crate) fn install(lua: &Lua, _options: &RuntimeOptions) -> mlua::Result<()> {
+    let module = lua.create_table()?;
+    module.set(
+        "decode",
+        lua.create_function(|lua, addr: String| {
+            let (hrp, data, variant) = decode(&addr)?;
+            let decoded = lua.create_table()?;
+            decoded.set("hrp", hrp)?;
+            decoded.set("data", lua.create_string(data)?)?;
+            decoded.set("variant", variant_name(variant))?;
+            Ok(decoded)
+        })?,
+    )?;
+    module.set(
+        "encode",
+        lua.create_function(
+            |_, (hrp, data, variant): (String, mlua::String, Option<String>)| {
+                let variant = match variant.as_deref() {
+                    None | Some("bech32") => Variant::Bech32,
+                    Some("bech32m") => Variant::Bech32m,
+                    Some(other) => {
+                        return Err(mlua::Error::runtime(format!(
+                            "bech32.encode: unknown variant {other:?}"
+                        )))
+                    }
+                };
+                encode(&hrp, data.as_bytes(), variant)
+            },
+        )?,
+    )?;
+    module.set(
+        "convert",
+        lua.create_function(|_, (addr, hrp): (String, String)| {
+            let (_, data, variant) = decode(&addr)?;
+            encode(&hrp, &data, variant)
+        })?,
+    )?;
+    lua.globals().set("bech32", module)
+}
+
+/// Decode an address into its human readable part, payload bytes and checksum variant.
+pub(crate) fn decode(addr: &str) -> mlua::Result<(String, Vec<u8>, Variant)> {
+    let (hrp, data, variant) = bech32::decode(addr)
+        .map_err(|err| mlua::Error::runtime(format!("bech32.decode: {err}")))?;
+    let data = Vec::<u8>::from_base32(&data)
+        .map_err(|err| mlua::Error::runtime(format!("bech32.decode: {err}")))?;
+    Ok((hrp, data, variant))
+}
+
+fn encode(hrp: &str, data: &[u8], variant: Variant) -> mlua::Result<String> {
+    bech32::encode(hrp, data.to_base32(), variant)
+        .map_err(|err| mlua::Error::runtime(format!("bech32.encode: {err}")))
+}
+
+fn variant_name(variant: Variant) -> &'static str {
+    match variant {
+        Variant::Bech32 => "bech32",
+        Variant::Bech32m => "bech32m",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+
+    fn juno() -> String {
+        encode("juno", &[0x42; 20], Variant::Bech32).unwrap()
+    }
+
+    fn lua() -> Lua {
+        let lua = Lua::new();
+        install(&lua, &RuntimeOptions::default()).unwrap();
+        lua
+    }
+
+    #[test]
+    fn decode_and_convert() {
+        let lua = lua();
+        let osmo: String = lua
+            .load(indoc! {r#"
+            local addr = ...
+            local decoded = bech32.decode(addr)
+            assert(decoded.hrp == "juno" and #decoded.data == 20 and decoded.variant == "bech32")
+            local osmo = bech32.convert(addr, "osmo")
+            assert(bech32.decode(osmo).data == decoded.data)
+            assert(bech32.convert(osmo, "juno") == addr)
+            return osmo
+            "#})
+            .call(juno())
+            .unwrap();
+        assert!(osmo.starts_with("osmo1"));
+    }
+
+    #[test]
+    fn bech32m_variant() {
+        let lua = lua();
+        let result: bool = lua
+            .load(indoc! {r#"
+            local addr = bech32.encode("test", "payload", "bech32m")
+            local decoded = bech32.decode(addr)
+            return decoded.variant == "bech32m" and decoded.data == "payload"
+                and bech32.decode(bech32.convert(addr, "other")).variant == "bech32m"
+            "#})
+            .eval()
+            .unwrap();
+        assert!(result);
+    }
+
+    #[test]
+    fn invalid_checksum() {
+        let lua = lua();
+        let mut broken = juno();
+        let last = if broken.ends_with('q') { 'p' } else { 'q' };
+        broken.pop();
+        broken.push(last);
+        let message: String = lua
+            .load(r#"local ok, err = pcall(bech32.decode, ...) return tostring(err)"#)
+            .call(broken)
+            .unwrap();
+        assert!(message.contains("bech32.decode"), "{message}");
+    }
+}
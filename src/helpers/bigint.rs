@@ -0,0 +1,140 @@
+//! The `bigint` module: exact arithmetic on decimal integer strings.
+//!
+//! Every function accepts decimal strings (leading zeros and a sign allowed) or integral Lua
+//! numbers, and returns results as decimal strings.
+
+use std::cmp::Ordering;
+
+use mlua::{Lua, Value};
+use num_bigint::BigInt;
+
+use crate::RuntimeOptions;
+
+pub(crate) fn install(lua: &Lua, _options: &RuntimeOptions) -> mlua::Result<()> {
+    let module = lua.create_table()?;
+    module.set(
+        "cmp",
+        lua.create_function(|_, (a, b): (Value, Value)| {
+            Ok(
+                match parse("bigint.cmp", &a)?.cmp(&parse("bigint.cmp", &b)?) {
+                    Ordering::Less => -1,
+                    Ordering::Equal => 0,
+                    Ordering::Greater => 1,
+                },
+            )
+        })?,
+    )?;
+    module.set(
+        "gte",
+        lua.create_function(|_, (a, b): (Value, Value)| {
+            Ok(parse("bigint.gte", &a)? >= parse("bigint.gte", &b)?)
+        })?,
+    )?;
+    module.set(
+        "add",
+        lua.create_function(|_, (a, b): (Value, Value)| {
+            Ok((parse("bigint.add", &a)? + parse("bigint.add", &b)?).to_string())
+        })?,
+    )?;
+    module.set(
+        "sub",
+        lua.create_function(|_, (a, b): (Value, Value)| {
+            Ok((parse("bigint.sub", &a)? - parse("bigint.sub", &b)?).to_string())
+        })?,
+    )?;
+    lua.globals().set("bigint", module)
+}
+
+/// Parse a decimal string or an integral number into a big integer.
+pub(crate) fn parse(function: &str, value: &Value) -> mlua::Result<BigInt> {
+    let invalid = || {
+        mlua::Error::runtime(format!(
+            "{function}: expected an integer or a decimal string, got {}",
+            describe(value)
+        ))
+    };
+    match value {
+        Value::Integer(n) => Ok(BigInt::from(*n)),
+        // Fixed-point formatting is exact, where casting saturates past the range of i128.
+        Value::Number(n) if n.fract() == 0.0 && n.is_finite() => {
+            format!("{n:.0}").parse().map_err(|_| invalid())
+        }
+        Value::String(s) => {
+            let s = s.to_str().map_err(|_| invalid())?.trim();
+            let digits = s.strip_prefix(['+', '-']).unwrap_or(s);
+            if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(invalid());
+            }
+            s.parse().map_err(|_| invalid())
+        }
+        _ => Err(invalid()),
+    }
+}
+
+fn describe(value: &Value) -> String {
+    match value {
+        Value::String(s) => format!("{:?}", s.to_string_lossy()),
+        Value::Number(n) => n.to_string(),
+        other => other.type_name().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+
+    fn lua() -> Lua {
+        let lua = Lua::new();
+        install(&lua, &RuntimeOptions::default()).unwrap();
+        lua
+    }
+
+    #[test]
+    fn arithmetic() {
+        let lua = lua();
+        let result: bool = lua
+            .load(indoc! {r#"
+            return bigint.cmp("18446744073709551615", "18446744073709551616") == -1
+                and bigint.cmp("007", 7) == 0
+                and bigint.cmp("-1", "-2") == 1
+                and bigint.gte("340282366920938463463374607431768211455", "1")
+                and bigint.add("18446744073709551615", "1") == "18446744073709551616"
+                and bigint.sub("1", "9007199254740993") == "-9007199254740992"
+            "#})
+            .eval()
+            .unwrap();
+        assert!(result);
+    }
+
+    #[test]
+    fn large_numbers() {
+        let lua = lua();
+        let sum: String = lua.load("return bigint.add(1e40, 0)").eval().unwrap();
+        assert_eq!(sum, "10000000000000000303786028427003666890752");
+        let result: bool = lua
+            .load(indoc! {r#"
+            return bigint.cmp(1e40, "170141183460469231731687303715884105727") == 1
+                and bigint.cmp(-1e40, "-170141183460469231731687303715884105728") == -1
+                and bigint.gte(2^127, "170141183460469231731687303715884105728")
+            "#})
+            .eval()
+            .unwrap();
+        assert!(result);
+    }
+
+    #[test]
+    fn invalid_input() {
+        let lua = lua();
+        for input in ["'12a'", "1.5", "{}", "''"] {
+            let message: String = lua
+                .load(format!(
+                    "local ok, err = pcall(bigint.cmp, {input}, 1) return tostring(err)"
+                ))
+                .eval()
+                .unwrap();
+            assert!(message.contains("bigint.cmp: expected"), "{message}");
+        }
+    }
+}
@@ -0,0 +1,93 @@
+//! The `coins` module: lookups and exact comparisons over `[{denom, amount}]` lists.
+
+use mlua::{Lua, Table, Value};
+use num_bigint::BigInt;
+
+use super::bigint;
+use crate::RuntimeOptions;
+
+pub(crate) fn install(lua: &Lua, _options: &RuntimeOptions) -> mlua::Result<()> {
+    let module = lua.create_table()?;
+    module.set(
+        "get",
+        lua.create_function(|_, (list, denom): (Table, String)| {
+            for coin in list.sequence_values::<Table>() {
+                let coin = coin?;
+                if coin.get::<_, Option<String>>("denom")?.as_deref() == Some(denom.as_str()) {
+                    let amount = coin.get::<_, Value>("amount")?;
+                    return Ok(Some(bigint::parse("coins.get", &amount)?.to_string()));
+                }
+            }
+            Ok(None)
+        })?,
+    )?;
+    module.set(
+        "gte",
+        lua.create_function(|_, (a, b): (Value, Value)| {
+            Ok(bigint::parse("coins.gte", &a)? >= bigint::parse("coins.gte", &b)?)
+        })?,
+    )?;
+    module.set(
+        "total",
+        lua.create_function(|_, (list, denom): (Table, String)| {
+            let mut total = BigInt::default();
+            for coin in list.sequence_values::<Table>() {
+                let coin = coin?;
+                if coin.get::<_, Option<String>>("denom")?.as_deref() == Some(denom.as_str()) {
+                    total += bigint::parse("coins.total", &coin.get::<_, Value>("amount")?)?;
+                }
+            }
+            Ok(total.to_string())
+        })?,
+    )?;
+    lua.globals().set("coins", module)
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+
+    fn lua() -> Lua {
+        let lua = Lua::new();
+        install(&lua, &RuntimeOptions::default()).unwrap();
+        lua
+    }
+
+    #[test]
+    fn lookups() {
+        let lua = lua();
+        let result: bool = lua
+            .load(indoc! {r#"
+            local funds = {
+                { denom = "ujuno", amount = "000150" },
+                { denom = "uosmo", amount = 7 },
+                { denom = "ujuno", amount = "123456789012345678901234567890" },
+            }
+            return coins.get(funds, "ujuno") == "150"
+                and coins.get(funds, "uosmo") == "7"
+                and coins.get(funds, "uatom") == nil
+                and coins.total(funds, "ujuno") == "123456789012345678901234568040"
+                and coins.total(funds, "uatom") == "0"
+            "#})
+            .eval()
+            .unwrap();
+        assert!(result);
+    }
+
+    #[test]
+    fn exact_comparisons() {
+        let lua = lua();
+        let result: bool = lua
+            .load(indoc! {r#"
+            return coins.gte("123456789012345678901234567890", "123456789012345678901234567889")
+                and not coins.gte("123456789012345678901234567889", "123456789012345678901234567890")
+                and coins.gte("0010", "9")
+                and coins.gte("18446744073709551616", "18446744073709551615")
+            "#})
+            .eval()
+            .unwrap();
+        assert!(result);
+    }
+}
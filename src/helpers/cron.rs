@@ -0,0 +1,331 @@
+//! The `cron` module: matching unix timestamps against crontab expressions.
+//!
+//! Expressions have five fields (minute, hour, day of month, month, day of week) or six with a
+//! leading seconds field, as croncat tasks use. Fields take `*`, numbers, ranges `a-b`, steps
+//! `*/n` and `a-b/n`, and lists `a,b`; months and days of the week also take three-letter
+//! names. As in crontab, when both the day of month and the day of week are restricted, a day
+//! matching either one matches. Times are in UTC.
+
+use mlua::Lua;
+use time::OffsetDateTime;
+
+use crate::RuntimeOptions;
+
+/// How far ahead `cron.next` looks before giving up, in seconds (about five years).
+const SEARCH_LIMIT: i64 = 5 * 366 * 24 * 3600;
+
+const MONTHS: [&str; 12] = [
+    "JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC",
+];
+const DAYS: [&str; 7] = ["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"];
+
+pub(crate) fn install(lua: &Lua, _options: &RuntimeOptions) -> mlua::Result<()> {
+    let module = lua.create_table()?;
+    module.set(
+        "matches",
+        lua.create_function(|_, (expr, ts): (String, i64)| {
+            let schedule = Schedule::parse(&expr).map_err(|err| error("cron.matches", err))?;
+            Ok(schedule.matches(datetime("cron.matches", ts)?))
+        })?,
+    )?;
+    module.set(
+        "next",
+        lua.create_function(|_, (expr, ts): (String, i64)| {
+            let schedule = Schedule::parse(&expr).map_err(|err| error("cron.next", err))?;
+            schedule.next(ts)
+        })?,
+    )?;
+    lua.globals().set("cron", module)
+}
+
+fn error(function: &str, (position, message): (usize, String)) -> mlua::Error {
+    mlua::Error::runtime(format!(
+        "{function}: invalid expression at position {position}: {message}"
+    ))
+}
+
+fn datetime(function: &str, ts: i64) -> mlua::Result<OffsetDateTime> {
+    OffsetDateTime::from_unix_timestamp(ts)
+        .map_err(|err| mlua::Error::runtime(format!("{function}: {err}")))
+}
+
+/// A parsed expression: the allowed values of each field, as bit sets.
+#[derive(Debug, PartialEq, Eq)]
+struct Schedule {
+    seconds: u64,
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    /// Whether the day of month or the day of week field is `*`.
+    any_day_of_month: bool,
+    any_day_of_week: bool,
+}
+
+impl Schedule {
+    /// Parse an expression, failing with the 1-based position of the offending field.
+    fn parse(expr: &str) -> Result<Self, (usize, String)> {
+        let mut fields = Vec::new();
+        let mut start = None;
+        for (i, c) in expr.char_indices().chain([(expr.len(), ' ')]) {
+            match (c.is_whitespace(), start) {
+                (true, Some(s)) => {
+                    fields.push((s + 1, &expr[s..i]));
+                    start = None;
+                }
+                (false, None) => start = Some(i),
+                _ => {}
+            }
+        }
+        let fields = match fields.len() {
+            5 => [&[(0, "0")][..], &fields[..]].concat(),
+            6 => fields,
+            n => return Err((1, format!("expected 5 or 6 fields, got {n}"))),
+        };
+
+        let bits = |index: usize, min: u32, max: u32, names: &[&str]| {
+            let (position, field) = fields[index];
+            parse_field(field, min, max, names)
+                .map_err(|(offset, message)| (position + offset, message))
+        };
+        let mut days_of_week = bits(5, 0, 7, &DAYS)?;
+        // Sunday is both 0 and 7.
+        if days_of_week & (1 << 7) != 0 {
+            days_of_week = (days_of_week | 1) & !(1 << 7);
+        }
+        Ok(Self {
+            seconds: bits(0, 0, 59, &[])?,
+            minutes: bits(1, 0, 59, &[])?,
+            hours: bits(2, 0, 23, &[])?,
+            days_of_month: bits(3, 1, 31, &[])?,
+            months: bits(4, 1, 12, &MONTHS)?,
+            days_of_week,
+            any_day_of_month: fields[3].1 == "*",
+            any_day_of_week: fields[5].1 == "*",
+        })
+    }
+
+    fn matches(&self, time: OffsetDateTime) -> bool {
+        self.matches_day(time)
+            && has(self.hours, time.hour().into())
+            && has(self.minutes, time.minute().into())
+            && has(self.seconds, time.second().into())
+    }
+
+    fn matches_day(&self, time: OffsetDateTime) -> bool {
+        let day_of_month = has(self.days_of_month, time.day().into());
+        let day_of_week = has(
+            self.days_of_week,
+            time.weekday().number_days_from_sunday().into(),
+        );
+        let day = match (self.any_day_of_month, self.any_day_of_week) {
+            (false, false) => day_of_month || day_of_week,
+            _ => day_of_month && day_of_week,
+        };
+        day && has(self.months, u8::from(time.month()).into())
+    }
+
+    /// The first matching time strictly after `ts`, if there is one within the search limit.
+    fn next(&self, ts: i64) -> mlua::Result<Option<i64>> {
+        // Timestamps out of the range of dates fail here, before any arithmetic on them.
+        datetime("cron.next", ts)?;
+        let mut ts = ts + 1;
+        let limit = ts + SEARCH_LIMIT;
+        while ts <= limit {
+            let time = datetime("cron.next", ts)?;
+            let (hour, minute, second) = time.to_hms();
+            let since_midnight =
+                i64::from(hour) * 3600 + i64::from(minute) * 60 + i64::from(second);
+            if !self.matches_day(time) {
+                ts += 86400 - since_midnight;
+            } else if !has(self.hours, hour.into()) {
+                ts += 3600 - i64::from(minute) * 60 - i64::from(second);
+            } else if !has(self.minutes, minute.into()) {
+                ts += 60 - i64::from(second);
+            } else if !has(self.seconds, second.into()) {
+                ts += 1;
+            } else {
+                return Ok(Some(ts));
+            }
+        }
+        Ok(None)
+    }
+}
+
+fn has(bits: u64, value: u32) -> bool {
+    bits & (1 << value) != 0
+}
+
+/// Parse one field into a bit set, failing with the 0-based offset of the offending part.
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str]) -> Result<u64, (usize, String)> {
+    let mut bits = 0;
+    let mut offset = 0;
+    for part in field.split(',') {
+        let fail = |message: String| (offset, message);
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step
+                    .parse()
+                    .ok()
+                    .filter(|&step| step > 0)
+                    .ok_or_else(|| fail(format!("invalid step `{step}`")))?;
+                (range, step)
+            }
+            None => (part, 1),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            range => {
+                let value = |s: &str| {
+                    let value = names
+                        .iter()
+                        .position(|name| name.eq_ignore_ascii_case(s))
+                        .map(|index| index as u32 + min)
+                        .or_else(|| s.parse().ok())
+                        .ok_or_else(|| fail(format!("invalid value `{s}`")))?;
+                    if (min..=max).contains(&value) {
+                        Ok(value)
+                    } else {
+                        Err(fail(format!("`{s}` is out of range {min}-{max}")))
+                    }
+                };
+                match range.split_once('-') {
+                    Some((start, end)) => (value(start)?, value(end)?),
+                    // `a/n` means every n-th value from `a` on.
+                    None if step > 1 => (value(range)?, max),
+                    None => {
+                        let value = value(range)?;
+                        (value, value)
+                    }
+                }
+            }
+        };
+        if start > end {
+            return Err(fail(format!("range `{range}` is backwards")));
+        }
+        for value in (start..=end).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+        offset += part.len() + 1;
+    }
+    Ok(bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+
+    fn lua() -> Lua {
+        let lua = Lua::new();
+        install(&lua, &RuntimeOptions::default()).unwrap();
+        lua
+    }
+
+    // 2023-01-10T12:00:00Z, a Tuesday.
+    const NOON: i64 = 1673352000;
+
+    fn next_fire(expr: &str, ts: i64) -> Option<i64> {
+        Schedule::parse(expr).unwrap().next(ts).unwrap()
+    }
+
+    #[test]
+    fn matches() {
+        let matches = |expr: &str, ts: i64| {
+            Schedule::parse(expr)
+                .unwrap()
+                .matches(OffsetDateTime::from_unix_timestamp(ts).unwrap())
+        };
+        assert!(matches("0 12 * * *", NOON));
+        assert!(matches("0 0 12 * * *", NOON));
+        assert!(!matches("0 0 12 * * *", NOON + 1));
+        assert!(matches("*/15 * * * *", NOON));
+        assert!(matches("0 12 10 JAN TUE", NOON));
+        assert!(matches("0 12 * * 2", NOON));
+        assert!(!matches("0 12 * * SUN", NOON));
+        // Either day field matches when both are restricted.
+        assert!(matches("0 12 1 * TUE", NOON));
+        assert!(matches("0 12 10 * SUN", NOON));
+        assert!(matches("0 12 * * 7", NOON + 5 * 86400));
+    }
+
+    #[test]
+    fn next() {
+        assert_eq!(next_fire("* * * * *", NOON), Some(NOON + 60));
+        assert_eq!(next_fire("* * * * * *", NOON), Some(NOON + 1));
+        assert_eq!(next_fire("30 12 * * *", NOON), Some(NOON + 1800));
+        assert_eq!(next_fire("0 12 * * *", NOON), Some(NOON + 86400));
+        assert_eq!(next_fire("0 0 1 FEB *", NOON), Some(1675209600));
+        assert_eq!(next_fire("0 0 29 2 *", NOON), Some(1709164800));
+        assert_eq!(next_fire("0 0 30 2 *", NOON), None);
+
+        // Timestamps out of the range of dates are refused rather than overflowing.
+        let schedule = Schedule::parse("* * * * *").unwrap();
+        for ts in [i64::MAX, i64::MAX - SEARCH_LIMIT, i64::MIN] {
+            let err = schedule.next(ts).unwrap_err().to_string();
+            assert!(err.starts_with("runtime error: cron.next: "), "{err}");
+        }
+        let lua = lua();
+        let message: String = lua
+            .load("local ok, err = pcall(cron.next, '* * * * *', 2^62) return tostring(err)")
+            .eval()
+            .unwrap();
+        assert!(message.contains("cron.next: "), "{message}");
+    }
+
+    #[test]
+    fn parse_errors() {
+        for (expr, position) in [
+            ("* * *", 1),
+            ("* 24 * * *", 3),
+            ("* * * FOO *", 7),
+            ("* * 1,2,x * *", 9),
+            ("*/0 * * * *", 1),
+            ("5-1 * * * *", 1),
+        ] {
+            let (at, message) = Schedule::parse(expr).unwrap_err();
+            assert_eq!(at, position, "{expr}: {message}");
+        }
+
+        let lua = lua();
+        let message: String = lua
+            .load(r#"local ok, err = pcall(cron.next, "* 99 * * *", 0) return tostring(err)"#)
+            .eval()
+            .unwrap();
+        assert!(
+            message.contains("cron.next: invalid expression at position 3"),
+            "{message}"
+        );
+    }
+
+    #[test]
+    fn tasks_due_within_a_minute() {
+        let lua = lua();
+        let due: Vec<String> = lua
+            .load(indoc! {r#"
+            local function filter(task, ctx)
+                local next = cron.next(task.cron, ctx.block_time)
+                return next ~= nil and next <= ctx.block_time + 60
+            end
+            local ctx = { block_time = ... }
+            local tasks = {
+                { id = "every-minute", cron = "* * * * *" },
+                { id = "half-past", cron = "30 12 * * *" },
+                { id = "noon-seconds", cron = "30 0 12 * * *" },
+                { id = "never", cron = "0 0 30 2 *" },
+            }
+            local due = {}
+            for _, task in ipairs(tasks) do
+                if filter(task, ctx) then
+                    table.insert(due, task.id)
+                end
+            end
+            return due
+            "#})
+            .call(NOON)
+            .unwrap();
+        assert_eq!(due, ["every-minute", "noon-seconds"]);
+    }
+}
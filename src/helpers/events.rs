@@ -0,0 +1,165 @@
+//! The `events` module: looking up attributes in Tendermint events.
+//!
+//! Events are lists of `{type = ..., attributes = {{key = ..., value = ...}, ...}}`. The
+//! attribute lookups take an optional trailing `{base64 = true}` table for events from older
+//! Tendermint versions, whose attribute keys and values are base64 encoded; attributes that
+//! don't decode are used as they are.
+
+use mlua::{Lua, Table, Value};
+
+use super::b64;
+use crate::RuntimeOptions;
+
+pub(crate) fn install(lua: &Lua, _options: &RuntimeOptions) -> mlua::Result<()> {
+    let module = lua.create_table()?;
+    module.set(
+        "find",
+        lua.create_function(
+            |lua, (events, kind, key, options): (Table, mlua::String, mlua::String, Option<Table>)| {
+                let base64 = base64(options)?;
+                for event in events.sequence_values::<Table>() {
+                    let event = event?;
+                    if is_type(&event, &kind)? {
+                        if let Some(value) = attr(lua, &event, key.as_bytes(), base64)? {
+                            return Ok(Value::String(value));
+                        }
+                    }
+                }
+                Ok(Value::Nil)
+            },
+        )?,
+    )?;
+    module.set(
+        "all",
+        lua.create_function(|lua, (events, kind): (Table, mlua::String)| {
+            let matching = lua.create_table()?;
+            for event in events.sequence_values::<Table>() {
+                let event = event?;
+                if is_type(&event, &kind)? {
+                    matching.raw_push(event)?;
+                }
+            }
+            Ok(matching)
+        })?,
+    )?;
+    module.set(
+        "attr",
+        lua.create_function(
+            |lua, (event, key, options): (Table, mlua::String, Option<Table>)| {
+                attr(lua, &event, key.as_bytes(), base64(options)?)
+            },
+        )?,
+    )?;
+    lua.globals().set("events", module)
+}
+
+fn base64(options: Option<Table>) -> mlua::Result<bool> {
+    options.map_or(Ok(false), |options| {
+        Ok(options.get::<_, Option<bool>>("base64")?.unwrap_or(false))
+    })
+}
+
+fn is_type(event: &Table, kind: &mlua::String) -> mlua::Result<bool> {
+    Ok(event
+        .get::<_, Option<mlua::String>>("type")?
+        .is_some_and(|t| t.as_bytes() == kind.as_bytes()))
+}
+
+/// The value of the first attribute of `event` named `key`.
+fn attr<'lua>(
+    lua: &'lua Lua,
+    event: &Table<'lua>,
+    key: &[u8],
+    base64: bool,
+) -> mlua::Result<Option<mlua::String<'lua>>> {
+    let Some(attributes) = event.get::<_, Option<Table>>("attributes")? else {
+        return Ok(None);
+    };
+    for attribute in attributes.sequence_values::<Table>() {
+        let attribute = attribute?;
+        let Some(name) = attribute.get::<_, Option<mlua::String>>("key")? else {
+            continue;
+        };
+        if decoded(lua, name, base64)?.as_bytes() != key {
+            continue;
+        }
+        return match attribute.get::<_, Option<mlua::String>>("value")? {
+            Some(value) => decoded(lua, value, base64).map(Some),
+            None => Ok(None),
+        };
+    }
+    Ok(None)
+}
+
+fn decoded<'lua>(
+    lua: &'lua Lua,
+    s: mlua::String<'lua>,
+    base64: bool,
+) -> mlua::Result<mlua::String<'lua>> {
+    if !base64 {
+        return Ok(s);
+    }
+    match b64::decode(s.as_bytes()) {
+        Ok(bytes) => lua.create_string(bytes),
+        Err(_) => Ok(s),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+
+    fn lua() -> Lua {
+        let lua = Lua::new();
+        install(&lua, &RuntimeOptions::default()).unwrap();
+        lua
+    }
+
+    #[test]
+    fn plain_events() {
+        let lua = lua();
+        let result: bool = lua
+            .load(indoc! {r#"
+            local evs = {
+                { type = "message", attributes = { { key = "sender", value = "juno1agent" } } },
+                { type = "wasm", attributes = {
+                    { key = "_contract_address", value = "juno1manager" },
+                    { key = "action", value = "proxy_call" },
+                } },
+                { type = "wasm", attributes = { { key = "_contract_address", value = "juno1tasks" } } },
+            }
+            local wasm = events.all(evs, "wasm")
+            return events.find(evs, "wasm", "_contract_address") == "juno1manager"
+                and events.find(evs, "wasm", "missing") == nil
+                and events.find(evs, "transfer", "sender") == nil
+                and #wasm == 2 and events.attr(wasm[2], "_contract_address") == "juno1tasks"
+            "#})
+            .eval()
+            .unwrap();
+        assert!(result);
+    }
+
+    #[test]
+    fn base64_events() {
+        let lua = lua();
+        let result: bool = lua
+            .load(indoc! {r#"
+            -- _contract_address = juno1manager, action = proxy_call
+            local evs = {
+                { type = "wasm", attributes = {
+                    { key = "X2NvbnRyYWN0X2FkZHJlc3M=", value = "anVubzFtYW5hZ2Vy" },
+                    { key = "YWN0aW9u", value = "cHJveHlfY2FsbA==" },
+                } },
+            }
+            local options = { base64 = true }
+            return events.find(evs, "wasm", "_contract_address", options) == "juno1manager"
+                and events.attr(evs[1], "action", options) == "proxy_call"
+                and events.find(evs, "wasm", "_contract_address") == nil
+            "#})
+            .eval()
+            .unwrap();
+        assert!(result);
+    }
+}
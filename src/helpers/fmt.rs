@@ -0,0 +1,100 @@
+//! The `fmt` module: formatting integers without losing precision.
+//!
+//! Lua's `tostring` switches to scientific notation for large numbers and can't represent
+//! integers above 2^53 anyway. These functions accept the same inputs as `bigint`: decimal
+//! strings or integral numbers.
+
+use mlua::{Lua, Value};
+use num_bigint::Sign;
+
+use super::bigint;
+use crate::RuntimeOptions;
+
+pub(crate) fn install(lua: &Lua, _options: &RuntimeOptions) -> mlua::Result<()> {
+    let module = lua.create_table()?;
+    module.set(
+        "int",
+        lua.create_function(|_, n: Value| Ok(bigint::parse("fmt.int", &n)?.to_string()))?,
+    )?;
+    module.set(
+        "amount",
+        lua.create_function(|_, (amount, decimals): (Value, u32)| {
+            let amount = bigint::parse("fmt.amount", &amount)?;
+            Ok(amount_string(
+                amount.sign() == Sign::Minus,
+                &amount.magnitude().to_string(),
+                decimals as usize,
+            ))
+        })?,
+    )?;
+    lua.globals().set("fmt", module)
+}
+
+/// Place the decimal point `decimals` digits from the right of `digits`, trimming trailing zeros.
+fn amount_string(negative: bool, digits: &str, decimals: usize) -> String {
+    let digits = format!("{digits:0>width$}", width = decimals + 1);
+    let (whole, fraction) = digits.split_at(digits.len() - decimals);
+    let fraction = fraction.trim_end_matches('0');
+    let sign = if negative { "-" } else { "" };
+    if fraction.is_empty() {
+        format!("{sign}{whole}")
+    } else {
+        format!("{sign}{whole}.{fraction}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+
+    fn lua() -> Lua {
+        let lua = Lua::new();
+        install(&lua, &RuntimeOptions::default()).unwrap();
+        lua
+    }
+
+    #[test]
+    fn int() {
+        let lua = lua();
+        let result: bool = lua
+            .load(indoc! {r#"
+            return fmt.int(1e18) == "1000000000000000000"
+                and fmt.int("9007199254740993") == "9007199254740993"
+                and fmt.int("-00042") == "-42"
+                and fmt.int(fmt.int("340282366920938463463374607431768211456"))
+                    == "340282366920938463463374607431768211456"
+            "#})
+            .eval()
+            .unwrap();
+        assert!(result);
+    }
+
+    #[test]
+    fn amount() {
+        let lua = lua();
+        let result: bool = lua
+            .load(indoc! {r#"
+            return fmt.amount("1500000", 6) == "1.5"
+                and fmt.amount(1000000, 6) == "1"
+                and fmt.amount("5", 6) == "0.000005"
+                and fmt.amount("-2500000", 6) == "-2.5"
+                and fmt.amount("42", 0) == "42"
+                and fmt.amount("9007199254740993000000", 18) == "9007.199254740993"
+            "#})
+            .eval()
+            .unwrap();
+        assert!(result);
+    }
+
+    #[test]
+    fn invalid_input() {
+        let lua = lua();
+        let message: String = lua
+            .load(r#"local ok, err = pcall(fmt.int, "1.5") return tostring(err)"#)
+            .eval()
+            .unwrap();
+        assert!(message.contains("fmt.int"), "{message}");
+    }
+}
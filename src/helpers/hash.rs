@@ -0,0 +1,95 @@
+//! The `hash` module: sha256 and ripemd160 digests, as hex or raw bytes.
+//!
+//! Only available with the `crypto-helpers` feature.
+
+use mlua::Lua;
+use ripemd::Ripemd160;
+use sha2::{Digest, Sha256};
+
+use crate::RuntimeOptions;
+
+pub(crate) fn install(lua: &Lua, _options: &RuntimeOptions) -> mlua::Result<()> {
+    let module = lua.create_table()?;
+    module.set(
+        "sha256",
+        lua.create_function(|_, input: mlua::String| {
+            Ok(hex::encode(Sha256::digest(input.as_bytes())))
+        })?,
+    )?;
+    module.set(
+        "sha256_raw",
+        lua.create_function(|lua, input: mlua::String| {
+            lua.create_string(Sha256::digest(input.as_bytes()))
+        })?,
+    )?;
+    module.set(
+        "ripemd160",
+        lua.create_function(|_, input: mlua::String| {
+            Ok(hex::encode(Ripemd160::digest(input.as_bytes())))
+        })?,
+    )?;
+    module.set(
+        "ripemd160_raw",
+        lua.create_function(|lua, input: mlua::String| {
+            lua.create_string(Ripemd160::digest(input.as_bytes()))
+        })?,
+    )?;
+    lua.globals().set("hash", module)
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+
+    fn lua() -> Lua {
+        let lua = Lua::new();
+        install(&lua, &RuntimeOptions::default()).unwrap();
+        lua
+    }
+
+    #[test]
+    fn test_vectors() {
+        let lua = lua();
+        let digests: (String, String, usize) = lua
+            .load(r#"return hash.sha256("abc"), hash.ripemd160("abc"), #hash.sha256_raw("abc")"#)
+            .eval()
+            .unwrap();
+        assert_eq!(
+            digests,
+            (
+                "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad".to_string(),
+                "8eb208f7e05d987a9b044a8e98c6b087f15a0bfc".to_string(),
+                32
+            )
+        );
+    }
+
+    #[test]
+    fn dedup_filter() {
+        let lua = lua();
+        let filter: mlua::Function = lua
+            .load(indoc! {r#"
+            local seen = {}
+            return function(tx)
+                local key = hash.sha256(tx.raw)
+                if seen[key] then return false end
+                seen[key] = true
+                return true
+            end
+            "#})
+            .eval()
+            .unwrap();
+        let tx = |raw: &str| {
+            let tx = lua.create_table().unwrap();
+            tx.set("raw", raw).unwrap();
+            tx
+        };
+        let verdicts = ["a", "b", "a", "c", "b"]
+            .iter()
+            .map(|raw| filter.call::<_, bool>(tx(raw)).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(verdicts, vec![true, true, false, true, false]);
+    }
+}
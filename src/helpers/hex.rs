@@ -0,0 +1,88 @@
+//! The `hex` module: lowercase hex encoding and case-insensitive decoding.
+
+use mlua::Lua;
+
+use crate::RuntimeOptions;
+
+pub(crate) fn install(lua: &Lua, _options: &RuntimeOptions) -> mlua::Result<()> {
+    let module = lua.create_table()?;
+    module.set(
+        "encode",
+        lua.create_function(|_, input: mlua::String| Ok(hex::encode(input.as_bytes())))?,
+    )?;
+    module.set(
+        "decode",
+        lua.create_function(|lua, input: mlua::String| {
+            lua.create_string(decode(input.as_bytes())?)
+        })?,
+    )?;
+    lua.globals().set("hex", module)
+}
+
+/// Decode hex, accepting an optional `0x` prefix and either case.
+pub(crate) fn decode(input: &[u8]) -> mlua::Result<Vec<u8>> {
+    let input = input
+        .strip_prefix(b"0x")
+        .or_else(|| input.strip_prefix(b"0X"))
+        .unwrap_or(input);
+    hex::decode(input).map_err(|err| mlua::Error::runtime(format!("hex.decode: {err}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+
+    fn lua() -> Lua {
+        let lua = Lua::new();
+        install(&lua, &RuntimeOptions::default()).unwrap();
+        lua
+    }
+
+    #[test]
+    fn round_trip() {
+        let lua = lua();
+        let result: bool = lua
+            .load(indoc! {r#"
+            local raw = string.char(0, 171, 205, 239)
+            return hex.encode(raw) == "00abcdef"
+                and hex.decode("00abcdef") == raw
+                and hex.decode("0x00ABcdEF") == raw
+                and hex.decode(hex.encode("croncat")) == "croncat"
+            "#})
+            .eval()
+            .unwrap();
+        assert!(result);
+    }
+
+    #[test]
+    fn invalid_input() {
+        let lua = lua();
+        for input in ["abc", "0xzz"] {
+            let message: String = lua
+                .load(r#"local ok, err = pcall(hex.decode, ...) return tostring(err)"#)
+                .call(input)
+                .unwrap();
+            assert!(message.contains("hex.decode"), "{message}");
+        }
+    }
+
+    #[test]
+    fn filter_on_hash_length() {
+        let lua = lua();
+        let filter: mlua::Function = lua
+            .load(indoc! {r#"
+            return function(tx)
+                return #hex.decode(tx.hash) == 32
+            end
+            "#})
+            .eval()
+            .unwrap();
+        let tx = lua.create_table().unwrap();
+        tx.set("hash", format!("0x{}", "AB".repeat(32))).unwrap();
+        assert!(filter.call::<_, bool>(tx.clone()).unwrap());
+        tx.set("hash", "abcd").unwrap();
+        assert!(!filter.call::<_, bool>(tx).unwrap());
+    }
+}
@@ -0,0 +1,226 @@
+//! The `json` module: `json.decode(string)` and `json.encode(value)`.
+
+use std::fmt;
+
+use mlua::{Lua, LuaSerdeExt, Value};
+use serde::de::{self, DeserializeSeed, MapAccess, SeqAccess, Visitor};
+
+use crate::{convert::MAX_SAFE_INTEGER, RuntimeOptions};
+
+pub(crate) fn install(lua: &Lua, options: &RuntimeOptions) -> mlua::Result<()> {
+    let big_integers_as_strings = options.json_big_integers_as_strings;
+
+    let json = lua.create_table()?;
+    json.set(
+        "decode",
+        lua.create_function(move |lua, input: mlua::String| {
+            decode(lua, input.as_bytes(), big_integers_as_strings)
+        })?,
+    )?;
+    json.set(
+        "encode",
+        lua.create_function(|_, value: Value| {
+            serde_json::to_string(&value)
+                .map_err(|err| mlua::Error::runtime(format!("json.encode: {err}")))
+        })?,
+    )?;
+    json.set("null", lua.null())?;
+    lua.globals().set("json", json)
+}
+
+/// Decode a JSON document into a Lua value.
+pub(crate) fn decode<'lua>(
+    lua: &'lua Lua,
+    input: &[u8],
+    big_integers_as_strings: bool,
+) -> mlua::Result<Value<'lua>> {
+    let mut deserializer = serde_json::Deserializer::from_slice(input);
+    let seed = LuaValueSeed::new(lua, big_integers_as_strings);
+    seed.deserialize(&mut deserializer)
+        .and_then(|value| deserializer.end().map(|_| value))
+        .map_err(|err| mlua::Error::runtime(format!("json.decode: {err}")))
+}
+
+/// Deserializes JSON straight into Lua values.
+///
+/// Integers a Lua number can't hold exactly become strings when `big_integers_as_strings` is
+/// set. Integers outside of the 64-bit range are parsed as floats by serde_json and can't be
+/// recovered. Other self-describing formats can use it too: byte strings become Lua strings.
+#[derive(Clone, Copy)]
+pub(crate) struct LuaValueSeed<'lua> {
+    lua: &'lua Lua,
+    big_integers_as_strings: bool,
+}
+
+impl<'lua> LuaValueSeed<'lua> {
+    pub(crate) fn new(lua: &'lua Lua, big_integers_as_strings: bool) -> Self {
+        Self {
+            lua,
+            big_integers_as_strings,
+        }
+    }
+
+    fn integer<E: de::Error>(self, value: i128) -> Result<Value<'lua>, E> {
+        if value.unsigned_abs() <= u128::from(MAX_SAFE_INTEGER) {
+            // Lua 5.4 keeps integers apart from floats; elsewhere both are doubles.
+            Ok(mlua::Integer::try_from(value)
+                .map(Value::Integer)
+                .unwrap_or(Value::Number(value as f64)))
+        } else if self.big_integers_as_strings {
+            self.string(&value.to_string())
+        } else {
+            Ok(Value::Number(value as f64))
+        }
+    }
+
+    fn string<E: de::Error>(self, value: &str) -> Result<Value<'lua>, E> {
+        self.lua
+            .create_string(value)
+            .map(Value::String)
+            .map_err(E::custom)
+    }
+}
+
+impl<'de, 'lua> DeserializeSeed<'de> for LuaValueSeed<'lua> {
+    type Value = Value<'lua>;
+
+    fn deserialize<D: de::Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de, 'lua> Visitor<'de> for LuaValueSeed<'lua> {
+    type Value = Value<'lua>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a JSON value")
+    }
+
+    fn visit_bool<E: de::Error>(self, value: bool) -> Result<Self::Value, E> {
+        Ok(Value::Boolean(value))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Self::Value, E> {
+        self.integer(value.into())
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Self::Value, E> {
+        self.integer(value.into())
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<Self::Value, E> {
+        Ok(Value::Number(value))
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+        self.string(value)
+    }
+
+    fn visit_bytes<E: de::Error>(self, value: &[u8]) -> Result<Self::Value, E> {
+        self.lua
+            .create_string(value)
+            .map(Value::String)
+            .map_err(E::custom)
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(self.lua.null())
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let table = self
+            .lua
+            .create_table_with_capacity(seq.size_hint().unwrap_or(0), 0)
+            .map_err(de::Error::custom)?;
+        table.set_metatable(Some(self.lua.array_metatable()));
+        let mut index = 1;
+        while let Some(value) = seq.next_element_seed(self)? {
+            table.raw_set(index, value).map_err(de::Error::custom)?;
+            index += 1;
+        }
+        Ok(Value::Table(table))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let table = self.lua.create_table().map_err(de::Error::custom)?;
+        while let Some(key) = map.next_key_seed(self)? {
+            let value = map.next_value_seed(self)?;
+            table.raw_set(key, value).map_err(de::Error::custom)?;
+        }
+        Ok(Value::Table(table))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+
+    fn lua_with(options: RuntimeOptions) -> Lua {
+        let lua = Lua::new();
+        install(&lua, &options).unwrap();
+        lua
+    }
+
+    #[test]
+    fn decode() {
+        let lua = lua_with(RuntimeOptions::default());
+        let result: bool = lua
+            .load(indoc! {r#"
+            local msg = json.decode('{"execute": {"tasks": [1, "two", null, true]}}')
+            local tasks = msg.execute.tasks
+            return #tasks == 4 and tasks[1] == 1 and tasks[2] == "two"
+                and tasks[3] == json.null and tasks[4] == true
+            "#})
+            .eval()
+            .unwrap();
+        assert!(result);
+    }
+
+    #[test]
+    fn decode_error_position() {
+        let lua = lua_with(RuntimeOptions::default());
+        let message: String = lua
+            .load(r#"local ok, err = pcall(json.decode, '{"a": 1,}') return tostring(err)"#)
+            .eval()
+            .unwrap();
+        assert!(message.contains("line 1 column 9"), "{message}");
+    }
+
+    #[test]
+    fn decode_big_integers() {
+        let script = r#"return json.decode('{"amount": 18446744073709551615}').amount"#;
+
+        let lua = lua_with(RuntimeOptions::default());
+        let amount: Value = lua.load(script).eval().unwrap();
+        assert_eq!(
+            amount.as_str().map(str::to_owned),
+            Some("18446744073709551615".to_string())
+        );
+
+        let lua = lua_with(RuntimeOptions {
+            json_big_integers_as_strings: false,
+            ..Default::default()
+        });
+        let amount: Value = lua.load(script).eval().unwrap();
+        assert!(matches!(amount, Value::Number(_)));
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        let lua = lua_with(RuntimeOptions::default());
+        let encoded: String = lua
+            .load(r#"return json.encode(json.decode('{"list":[1,2,3],"name":"croncat"}'))"#)
+            .eval()
+            .unwrap();
+        let encoded: serde_json::Value = serde_json::from_str(&encoded).unwrap();
+        assert_eq!(
+            encoded,
+            serde_json::json!({ "list": [1, 2, 3], "name": "croncat" })
+        );
+    }
+}
@@ -0,0 +1,203 @@
+//! The `memo` module: caching the results of expensive pure computations.
+//!
+//! `memo.cache(key, fn)` calls `fn()` the first time it sees `key` and returns the cached result
+//! afterwards. Keys are strings or numbers; only the first value `fn` returns is kept. Each
+//! filter system has a cache of its own, shared by its filters, holding at most
+//! `memo_cache_size` entries and emptied when the system reloads.
+
+use std::{cell::RefCell, rc::Rc};
+
+use mlua::{Lua, RegistryKey, Value};
+
+use super::cache::LruCache;
+use crate::RuntimeOptions;
+
+/// The entries of a cache and its counters.
+struct Entries {
+    cache: LruCache<Vec<u8>, RegistryKey>,
+    hits: u64,
+    misses: u64,
+}
+
+/// A `memo` cache, the one of a filter system. The runtime uses the last one
+/// [`activate`]d.
+#[derive(Clone)]
+pub(crate) struct Memo(Rc<RefCell<Entries>>);
+
+impl Memo {
+    /// An empty cache, of the size the options of the runtime give.
+    pub(crate) fn new(lua: &Lua) -> Self {
+        let capacity = lua.app_data_ref::<Active>().map_or_else(
+            || RuntimeOptions::default().memo_cache_size,
+            |active| active.capacity,
+        );
+        Self::with_capacity(capacity)
+    }
+
+    fn with_capacity(capacity: usize) -> Self {
+        Self(Rc::new(RefCell::new(Entries {
+            cache: LruCache::new(capacity),
+            hits: 0,
+            misses: 0,
+        })))
+    }
+
+    /// Drop every cached value.
+    pub(crate) fn clear(&self, lua: &Lua) {
+        self.0.borrow_mut().cache.clear();
+        lua.expire_registry_values();
+    }
+}
+
+/// The cache the runtime uses, and the size of new ones, kept in its app data.
+struct Active {
+    memo: Memo,
+    capacity: usize,
+}
+
+pub(crate) fn install(lua: &Lua, options: &RuntimeOptions) -> mlua::Result<()> {
+    lua.set_app_data(Active {
+        memo: Memo::with_capacity(options.memo_cache_size),
+        capacity: options.memo_cache_size,
+    });
+
+    let module = lua.create_table()?;
+    module.set(
+        "cache",
+        lua.create_function(|lua, (key, f): (Value, mlua::Function)| {
+            let key = cache_key(&key)?;
+            let memo = active(lua)?;
+            {
+                let mut entries = memo.0.borrow_mut();
+                if let Some(value) = entries.cache.get(&key) {
+                    let value = lua.registry_value::<Value>(value)?;
+                    entries.hits += 1;
+                    return Ok(value);
+                }
+                entries.misses += 1;
+            }
+            // The borrow is released while `f` runs, so it may use the cache too.
+            let value: Value = f.call(())?;
+            let stored = lua.create_registry_value(value.clone())?;
+            memo.0.borrow_mut().cache.insert(key, stored);
+            Ok(value)
+        })?,
+    )?;
+    lua.globals().set("memo", module)
+}
+
+/// The cache the runtime uses.
+fn active(lua: &Lua) -> mlua::Result<Memo> {
+    lua.app_data_ref::<Active>()
+        .map(|active| active.memo.clone())
+        .ok_or_else(|| mlua::Error::runtime("memo.cache: not available"))
+}
+
+/// Use `memo` from now on.
+pub(crate) fn activate(lua: &Lua, memo: &Memo) {
+    if let Some(mut active) = lua.app_data_mut::<Active>() {
+        active.memo = memo.clone();
+    }
+}
+
+/// Whether the runtime uses `memo`.
+pub(crate) fn is_active(lua: &Lua, memo: &Memo) -> bool {
+    lua.app_data_ref::<Active>()
+        .is_some_and(|active| Rc::ptr_eq(&active.memo.0, &memo.0))
+}
+
+/// The cache key of a Lua key, tagged with its type so `1` and `"1"` don't collide.
+fn cache_key(key: &Value) -> mlua::Result<Vec<u8>> {
+    let (tag, bytes) = match key {
+        Value::String(s) => (b's', s.as_bytes().to_vec()),
+        Value::Integer(n) => (b'n', (*n as f64).to_be_bytes().to_vec()),
+        Value::Number(n) => (b'n', n.to_be_bytes().to_vec()),
+        other => {
+            return Err(mlua::Error::runtime(format!(
+                "memo.cache: key must be a string or a number, got {}",
+                other.type_name()
+            )))
+        }
+    };
+    Ok([&[tag][..], &bytes].concat())
+}
+
+/// The hits and misses of the cache the runtime uses so far.
+pub(crate) fn counts(lua: &Lua) -> (u64, u64) {
+    lua.app_data_ref::<Active>().map_or((0, 0), |active| {
+        let entries = active.memo.0.borrow();
+        (entries.hits, entries.misses)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+
+    fn lua() -> Lua {
+        let lua = Lua::new();
+        install(&lua, &RuntimeOptions::default()).unwrap();
+        lua
+    }
+
+    #[test]
+    fn cache() {
+        let lua = lua();
+        let calls: u32 = lua
+            .load(indoc! {r#"
+            local calls = 0
+            local function parse() calls = calls + 1 return { shape = "proxy_call" } end
+            for _ = 1, 3 do
+                assert(memo.cache("proxy_call", parse).shape == "proxy_call")
+            end
+            assert(memo.cache(1, function() return "number" end) == "number")
+            assert(memo.cache("1", function() return "string" end) == "string")
+            return calls
+            "#})
+            .eval()
+            .unwrap();
+        assert_eq!(calls, 1);
+        assert_eq!(counts(&lua), (2, 3));
+
+        active(&lua).unwrap().clear(&lua);
+        let value: String = lua
+            .load(r#"return memo.cache("1", function() return "again" end)"#)
+            .eval()
+            .unwrap();
+        assert_eq!(value, "again");
+    }
+
+    #[test]
+    fn bounded() {
+        let lua = Lua::new();
+        let options = RuntimeOptions {
+            memo_cache_size: 2,
+            ..Default::default()
+        };
+        install(&lua, &options).unwrap();
+        let calls: u32 = lua
+            .load(indoc! {r#"
+            local calls = 0
+            local function compute() calls = calls + 1 return calls end
+            for _, key in ipairs({ "a", "b", "c", "a" }) do
+                memo.cache(key, compute)
+            end
+            return calls
+            "#})
+            .eval()
+            .unwrap();
+        assert_eq!(calls, 4);
+    }
+
+    #[test]
+    fn invalid_key() {
+        let lua = lua();
+        let message: String = lua
+            .load(r#"local ok, err = pcall(memo.cache, {}, print) return tostring(err)"#)
+            .eval()
+            .unwrap();
+        assert!(message.contains("memo.cache: key must be"), "{message}");
+    }
+}
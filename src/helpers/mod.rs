@@ -0,0 +1,86 @@
+//! Helper modules preloaded into every filter runtime.
+//!
+//! Each helper is exposed as a global table, so scripts can use it without a `require`. The
+//! tables are frozen: they are shared by every filter, so scripts must not be able to replace
+//! their functions.
+
+use std::collections::HashSet;
+
+use mlua::{Lua, Value};
+
+use crate::{frozen, RuntimeOptions};
+
+mod addr;
+mod b64;
+mod bech32;
+mod bigint;
+pub(crate) mod cache;
+mod coins;
+mod cron;
+mod events;
+mod fmt;
+#[cfg(feature = "crypto-helpers")]
+mod hash;
+mod hex;
+mod json;
+pub(crate) mod memo;
+#[cfg(feature = "msgpack-helpers")]
+mod msgpack;
+#[cfg(feature = "cosmos")]
+mod proto;
+mod re;
+mod str;
+mod tbl;
+mod time;
+mod wasm;
+mod wildcard;
+
+/// Install every helper module into the globals of `lua`.
+pub(crate) fn install(lua: &Lua, options: &RuntimeOptions) -> mlua::Result<()> {
+    let globals = lua.globals();
+    let existing: HashSet<String> = globals
+        .clone()
+        .pairs::<String, Value>()
+        .map(|pair| pair.map(|(name, _)| name))
+        .collect::<mlua::Result<_>>()?;
+    install_modules(lua, options)?;
+
+    let installed: Vec<(String, Value)> = globals
+        .clone()
+        .pairs::<String, Value>()
+        .filter(|pair| !matches!(pair, Ok((name, _)) if existing.contains(name)))
+        .collect::<mlua::Result<_>>()?;
+    for (name, module) in installed {
+        if module.is_table() {
+            globals.set(name.as_str(), frozen::freeze(lua, &name, module)?)?;
+        }
+    }
+    Ok(())
+}
+
+fn install_modules(lua: &Lua, options: &RuntimeOptions) -> mlua::Result<()> {
+    json::install(lua, options)?;
+    memo::install(lua, options)?;
+    #[cfg(feature = "msgpack-helpers")]
+    msgpack::install(lua, options)?;
+    re::install(lua, options)?;
+    b64::install(lua, options)?;
+    bech32::install(lua, options)?;
+    addr::install(lua, options)?;
+    bigint::install(lua, options)?;
+    coins::install(lua, options)?;
+    cron::install(lua, options)?;
+    events::install(lua, options)?;
+    fmt::install(lua, options)?;
+    hex::install(lua, options)?;
+    #[cfg(feature = "crypto-helpers")]
+    hash::install(lua, options)?;
+    str::install(lua, options)?;
+    tbl::install(lua, options)?;
+    time::install(lua, options)?;
+    wasm::install(lua, options)?;
+    wildcard::install(lua, options)?;
+    #[cfg(feature = "cosmos")]
+    proto::install(lua, options)?;
+    Ok(())
+}
@@ -0,0 +1,148 @@
+//! The `msgpack` module: `msgpack.decode(bytes)` and `msgpack.encode(value)`.
+//!
+//! Only available with the `msgpack-helpers` feature. Binary values, binary keys and strings
+//! that aren't valid UTF-8 all decode to Lua strings, and Lua strings that aren't valid UTF-8
+//! encode as binary values.
+
+use std::io::Cursor;
+
+use mlua::{Lua, Value};
+use serde::de::DeserializeSeed;
+
+use super::json::LuaValueSeed;
+use crate::RuntimeOptions;
+
+pub(crate) fn install(lua: &Lua, options: &RuntimeOptions) -> mlua::Result<()> {
+    let big_integers_as_strings = options.json_big_integers_as_strings;
+
+    let module = lua.create_table()?;
+    module.set(
+        "decode",
+        lua.create_function(move |lua, input: mlua::String| {
+            decode(lua, input.as_bytes(), big_integers_as_strings)
+        })?,
+    )?;
+    module.set(
+        "encode",
+        lua.create_function(|lua, value: Value| {
+            let bytes = rmp_serde::to_vec(&value)
+                .map_err(|err| mlua::Error::runtime(format!("msgpack.encode: {err}")))?;
+            lua.create_string(bytes)
+        })?,
+    )?;
+    lua.globals().set("msgpack", module)
+}
+
+/// Decode a MessagePack document into a Lua value.
+fn decode<'lua>(
+    lua: &'lua Lua,
+    input: &[u8],
+    big_integers_as_strings: bool,
+) -> mlua::Result<Value<'lua>> {
+    let mut cursor = Cursor::new(input);
+    let result = LuaValueSeed::new(lua, big_integers_as_strings)
+        .deserialize(&mut rmp_serde::Deserializer::new(&mut cursor));
+    let offset = cursor.position();
+    match result {
+        Ok(_) if offset < input.len() as u64 => Err(mlua::Error::runtime(format!(
+            "msgpack.decode: trailing bytes at offset {offset}"
+        ))),
+        Ok(value) => Ok(value),
+        Err(err) => Err(mlua::Error::runtime(format!(
+            "msgpack.decode: {err} at offset {offset}"
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+
+    fn lua() -> Lua {
+        let lua = Lua::new();
+        install(&lua, &RuntimeOptions::default()).unwrap();
+        lua
+    }
+
+    #[test]
+    fn decode() {
+        #[derive(serde::Serialize)]
+        struct Event {
+            kind: &'static str,
+            amounts: Vec<u64>,
+            #[serde(with = "serde_bytes_like")]
+            payload: Vec<u8>,
+        }
+        mod serde_bytes_like {
+            pub fn serialize<S: serde::Serializer>(v: &[u8], s: S) -> Result<S::Ok, S::Error> {
+                s.serialize_bytes(v)
+            }
+        }
+
+        let event = Event {
+            kind: "wasm",
+            amounts: vec![1, 18446744073709551615],
+            payload: vec![0xff, 0x00, 0xfe],
+        };
+        let lua = lua();
+        let result: bool = lua
+            .load(indoc! {r#"
+            local event = msgpack.decode(...)
+            return event.kind == "wasm" and event.amounts[1] == 1
+                and event.amounts[2] == "18446744073709551615"
+                and event.payload == "\255\0\254"
+            "#})
+            .call(
+                lua.create_string(rmp_serde::to_vec_named(&event).unwrap())
+                    .unwrap(),
+            )
+            .unwrap();
+        assert!(result);
+    }
+
+    #[test]
+    fn binary_keys_and_strings() {
+        // A map from the binary key 0xff to the str with the invalid UTF-8 byte 0xfe.
+        let input = [0x81, 0xc4, 0x01, 0xff, 0xa1, 0xfe];
+        let lua = lua();
+        let result: bool = lua
+            .load(r#"return msgpack.decode(...)["\255"] == "\254""#)
+            .call(lua.create_string(input).unwrap())
+            .unwrap();
+        assert!(result);
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        let lua = lua();
+        let encoded: mlua::String = lua
+            .load(r#"return msgpack.encode({ denom = "ujuno", raw = "\255" })"#)
+            .eval()
+            .unwrap();
+        // The invalid UTF-8 string went out as a binary value.
+        assert!(encoded
+            .as_bytes()
+            .windows(3)
+            .any(|w| w == [0xc4, 0x01, 0xff]));
+        let result: bool = lua
+            .load(r#"local v = msgpack.decode(...) return v.denom == "ujuno" and v.raw == "\255""#)
+            .call(encoded)
+            .unwrap();
+        assert!(result);
+    }
+
+    #[test]
+    fn decode_error_offset() {
+        // An array announcing three elements but holding one.
+        let input = [0x93, 0x01];
+        let lua = lua();
+        let message: String = lua
+            .load(r#"local ok, err = pcall(msgpack.decode, ...) return tostring(err)"#)
+            .call(lua.create_string(input).unwrap())
+            .unwrap();
+        assert!(message.contains("msgpack.decode"), "{message}");
+        assert!(message.contains("offset 2"), "{message}");
+    }
+}
@@ -0,0 +1,204 @@
+//! The `proto` module: decoding common Cosmos `Any` messages into tables.
+//!
+//! Only available with the `cosmos` feature. `proto.decode_any(type_url, b64_value)` returns
+//! `nil` for type URLs it doesn't know, so scripts can fall through to their own handling.
+
+use cosmos_sdk_proto::{
+    cosmos::{
+        bank::v1beta1::MsgSend, base::v1beta1::Coin,
+        distribution::v1beta1::MsgWithdrawDelegatorReward, staking::v1beta1::MsgDelegate,
+    },
+    cosmwasm::wasm::v1::MsgExecuteContract,
+    prost::Message,
+};
+use mlua::{Lua, Table, Value};
+
+use super::{b64, json};
+use crate::RuntimeOptions;
+
+pub(crate) fn install(lua: &Lua, options: &RuntimeOptions) -> mlua::Result<()> {
+    let big_integers_as_strings = options.json_big_integers_as_strings;
+
+    let module = lua.create_table()?;
+    module.set(
+        "decode_any",
+        lua.create_function(move |lua, (type_url, value): (String, mlua::String)| {
+            let bytes = b64::decode(value.as_bytes())?;
+            decode_any(lua, &type_url, &bytes, big_integers_as_strings)
+        })?,
+    )?;
+    lua.globals().set("proto", module)
+}
+
+/// Decode the protobuf bytes of a message of one of the supported types.
+pub(crate) fn decode_any<'lua>(
+    lua: &'lua Lua,
+    type_url: &str,
+    bytes: &[u8],
+    big_integers_as_strings: bool,
+) -> mlua::Result<Value<'lua>> {
+    let table = lua.create_table()?;
+    table.set("type_url", type_url)?;
+    match type_url {
+        "/cosmos.bank.v1beta1.MsgSend" => {
+            let msg = decode::<MsgSend>(type_url, bytes)?;
+            table.set("from_address", msg.from_address)?;
+            table.set("to_address", msg.to_address)?;
+            table.set("amount", coins(lua, &msg.amount)?)?;
+        }
+        "/cosmwasm.wasm.v1.MsgExecuteContract" => {
+            let msg = decode::<MsgExecuteContract>(type_url, bytes)?;
+            table.set("sender", msg.sender)?;
+            table.set("contract", msg.contract)?;
+            // Contracts nearly always take JSON; keep the raw bytes around when they don't.
+            if let Ok(decoded) = json::decode(lua, &msg.msg, big_integers_as_strings) {
+                table.set("msg", decoded)?;
+            }
+            table.set("msg_raw", lua.create_string(&msg.msg)?)?;
+            table.set("funds", coins(lua, &msg.funds)?)?;
+        }
+        "/cosmos.staking.v1beta1.MsgDelegate" => {
+            let msg = decode::<MsgDelegate>(type_url, bytes)?;
+            table.set("delegator_address", msg.delegator_address)?;
+            table.set("validator_address", msg.validator_address)?;
+            if let Some(amount) = &msg.amount {
+                table.set("amount", coin(lua, amount)?)?;
+            }
+        }
+        "/cosmos.distribution.v1beta1.MsgWithdrawDelegatorReward" => {
+            let msg = decode::<MsgWithdrawDelegatorReward>(type_url, bytes)?;
+            table.set("delegator_address", msg.delegator_address)?;
+            table.set("validator_address", msg.validator_address)?;
+        }
+        _ => return Ok(Value::Nil),
+    }
+    Ok(Value::Table(table))
+}
+
+fn decode<M: Message + Default>(type_url: &str, bytes: &[u8]) -> mlua::Result<M> {
+    M::decode(bytes)
+        .map_err(|err| mlua::Error::runtime(format!("proto.decode_any: {type_url}: {err}")))
+}
+
+fn coin<'lua>(lua: &'lua Lua, coin: &Coin) -> mlua::Result<Table<'lua>> {
+    let table = lua.create_table()?;
+    table.set("denom", coin.denom.as_str())?;
+    table.set("amount", coin.amount.as_str())?;
+    Ok(table)
+}
+
+fn coins<'lua>(lua: &'lua Lua, coins: &[Coin]) -> mlua::Result<Table<'lua>> {
+    lua.create_sequence_from(
+        coins
+            .iter()
+            .map(|c| coin(lua, c))
+            .collect::<mlua::Result<Vec<_>>>()?,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use indoc::indoc;
+
+    use super::*;
+
+    fn lua() -> Lua {
+        let lua = Lua::new();
+        install(&lua, &RuntimeOptions::default()).unwrap();
+        lua
+    }
+
+    fn encoded(msg: impl Message) -> String {
+        STANDARD.encode(msg.encode_to_vec())
+    }
+
+    fn ujuno(amount: &str) -> Coin {
+        Coin {
+            denom: "ujuno".to_string(),
+            amount: amount.to_string(),
+        }
+    }
+
+    #[test]
+    fn msg_send() {
+        let lua = lua();
+        let value = encoded(MsgSend {
+            from_address: "juno1from".to_string(),
+            to_address: "juno1to".to_string(),
+            amount: vec![ujuno("1500000")],
+        });
+        let result: bool = lua
+            .load(indoc! {r#"
+            local msg = proto.decode_any("/cosmos.bank.v1beta1.MsgSend", ...)
+            return msg.from_address == "juno1from" and msg.to_address == "juno1to"
+                and msg.amount[1].denom == "ujuno" and msg.amount[1].amount == "1500000"
+            "#})
+            .call(value)
+            .unwrap();
+        assert!(result);
+    }
+
+    #[test]
+    fn msg_execute_contract() {
+        let lua = lua();
+        let value = encoded(MsgExecuteContract {
+            sender: "juno1agent".to_string(),
+            contract: "juno1manager".to_string(),
+            msg: br#"{"proxy_call":{}}"#.to_vec(),
+            funds: vec![ujuno("1")],
+        });
+        let result: bool = lua
+            .load(indoc! {r#"
+            local msg = proto.decode_any("/cosmwasm.wasm.v1.MsgExecuteContract", ...)
+            return msg.contract == "juno1manager" and msg.msg.proxy_call ~= nil
+                and msg.msg_raw == '{"proxy_call":{}}' and #msg.funds == 1
+            "#})
+            .call(value)
+            .unwrap();
+        assert!(result);
+    }
+
+    #[test]
+    fn staking_messages() {
+        let lua = lua();
+        let delegate = encoded(MsgDelegate {
+            delegator_address: "juno1delegator".to_string(),
+            validator_address: "junovaloper1validator".to_string(),
+            amount: Some(ujuno("42")),
+        });
+        let withdraw = encoded(MsgWithdrawDelegatorReward {
+            delegator_address: "juno1delegator".to_string(),
+            validator_address: "junovaloper1validator".to_string(),
+        });
+        let result: bool = lua
+            .load(indoc! {r#"
+            local delegate, withdraw = ...
+            local d = proto.decode_any("/cosmos.staking.v1beta1.MsgDelegate", delegate)
+            local w = proto.decode_any("/cosmos.distribution.v1beta1.MsgWithdrawDelegatorReward", withdraw)
+            return d.amount.amount == "42" and w.validator_address == "junovaloper1validator"
+            "#})
+            .call((delegate, withdraw))
+            .unwrap();
+        assert!(result);
+    }
+
+    #[test]
+    fn unknown_and_malformed() {
+        let lua = lua();
+        let unknown: Value = lua
+            .load(r#"return proto.decode_any("/cosmos.gov.v1beta1.MsgVote", "")"#)
+            .eval()
+            .unwrap();
+        assert!(unknown.is_nil());
+
+        let message: String = lua
+            .load(indoc! {r#"
+            local ok, err = pcall(proto.decode_any, "/cosmos.bank.v1beta1.MsgSend", "/////w==")
+            return tostring(err)
+            "#})
+            .eval()
+            .unwrap();
+        assert!(message.contains("proto.decode_any"), "{message}");
+    }
+}
@@ -0,0 +1,145 @@
+//! The `re` module: regular expressions backed by the `regex` crate.
+//!
+//! Patterns are compiled on first use and kept in a bounded cache keyed by the pattern string.
+
+use std::sync::{Arc, Mutex};
+
+use mlua::{Lua, Value};
+use regex::bytes::Regex;
+
+use super::cache::LruCache;
+use crate::RuntimeOptions;
+
+type Cache = Arc<Mutex<LruCache<Vec<u8>, Regex>>>;
+
+pub(crate) fn install(lua: &Lua, options: &RuntimeOptions) -> mlua::Result<()> {
+    let cache: Cache = Arc::new(Mutex::new(LruCache::new(options.regex_cache_size)));
+
+    let re = lua.create_table()?;
+    let patterns = cache.clone();
+    re.set(
+        "is_match",
+        lua.create_function(move |_, (pattern, s): (mlua::String, mlua::String)| {
+            let regex = compile(&patterns, &pattern)?;
+            Ok(regex.is_match(s.as_bytes()))
+        })?,
+    )?;
+    let patterns = cache.clone();
+    re.set(
+        "find",
+        lua.create_function(move |lua, (pattern, s): (mlua::String, mlua::String)| {
+            let regex = compile(&patterns, &pattern)?;
+            match regex.find(s.as_bytes()) {
+                Some(found) => Ok((
+                    Value::String(lua.create_string(found.as_bytes())?),
+                    Value::Integer(found.start() as mlua::Integer + 1),
+                    Value::Integer(found.end() as mlua::Integer),
+                )),
+                None => Ok((Value::Nil, Value::Nil, Value::Nil)),
+            }
+        })?,
+    )?;
+    let patterns = cache;
+    re.set(
+        "captures",
+        lua.create_function(move |lua, (pattern, s): (mlua::String, mlua::String)| {
+            let regex = compile(&patterns, &pattern)?;
+            let Some(captures) = regex.captures(s.as_bytes()) else {
+                return Ok(Value::Nil);
+            };
+            let table = lua.create_table()?;
+            for (index, group) in captures.iter().enumerate() {
+                if let Some(group) = group {
+                    table.raw_set(index, lua.create_string(group.as_bytes())?)?;
+                }
+            }
+            for name in regex.capture_names().flatten() {
+                if let Some(group) = captures.name(name) {
+                    table.raw_set(name, lua.create_string(group.as_bytes())?)?;
+                }
+            }
+            Ok(Value::Table(table))
+        })?,
+    )?;
+    lua.globals().set("re", re)
+}
+
+/// Fetch a compiled pattern from the cache, compiling it on a miss.
+fn compile(cache: &Cache, pattern: &mlua::String) -> mlua::Result<Regex> {
+    let mut cache = cache.lock().unwrap_or_else(|err| err.into_inner());
+    if let Some(regex) = cache.get(pattern.as_bytes()) {
+        return Ok(regex.clone());
+    }
+    let source = pattern
+        .to_str()
+        .map_err(|_| mlua::Error::runtime("re: pattern is not valid UTF-8"))?;
+    let regex = Regex::new(source)
+        .map_err(|err| mlua::Error::runtime(format!("re: invalid pattern: {err}")))?;
+    cache.insert(pattern.as_bytes().to_vec(), regex.clone());
+    Ok(regex)
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+
+    fn lua() -> Lua {
+        let lua = Lua::new();
+        install(&lua, &RuntimeOptions::default()).unwrap();
+        lua
+    }
+
+    #[test]
+    fn is_match_and_find() {
+        let lua = lua();
+        let result: bool = lua
+            .load(indoc! {r#"
+            local text, first, last = re.find("juno1[a-z0-9]{4}", "to juno1abcd")
+            return re.is_match("^memo:\\d+$", "memo:42")
+                and not re.is_match("^memo:\\d+$", "memo:x")
+                and text == "juno1abcd" and first == 4 and last == 12
+                and re.find("osmo1", "juno1abcd") == nil
+            "#})
+            .eval()
+            .unwrap();
+        assert!(result);
+    }
+
+    #[test]
+    fn captures() {
+        let lua = lua();
+        let result: bool = lua
+            .load(indoc! {r#"
+            local caps = re.captures("(?P<prefix>[a-z]+)1(\\w+)?", "juno1")
+            return caps[0] == "juno1" and caps[1] == "juno" and caps.prefix == "juno"
+                and caps[2] == nil and re.captures("\\d", "abc") == nil
+            "#})
+            .eval()
+            .unwrap();
+        assert!(result);
+    }
+
+    #[test]
+    fn invalid_pattern() {
+        let lua = lua();
+        let message: String = lua
+            .load(r#"local ok, err = pcall(re.is_match, "(", "x") return tostring(err)"#)
+            .eval()
+            .unwrap();
+        assert!(message.contains("re: invalid pattern"), "{message}");
+        assert!(message.contains("unclosed group"), "{message}");
+    }
+
+    #[test]
+    fn patterns_are_cached() {
+        let cache: Cache = Arc::new(Mutex::new(LruCache::new(1)));
+        let lua = Lua::new();
+        let first = compile(&cache, &lua.create_string("a+").unwrap()).unwrap();
+        let again = compile(&cache, &lua.create_string("a+").unwrap()).unwrap();
+        assert_eq!(first.as_str(), again.as_str());
+        compile(&cache, &lua.create_string("b+").unwrap()).unwrap();
+        assert!(cache.lock().unwrap().get("a+".as_bytes()).is_none());
+    }
+}
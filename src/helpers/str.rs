@@ -0,0 +1,108 @@
+//! The `str` module: string functions stock Lua lacks.
+//!
+//! `str.split(s, sep)` splits on every occurrence of the literal separator `sep`, which may be
+//! several characters long. Like Python's `str.split` with a separator, empty pieces are kept:
+//! a string without the separator yields a single piece, and an empty string yields `{""}`. An
+//! empty separator is an error. `str.trim` strips ASCII whitespace from both ends.
+
+use mlua::Lua;
+
+use crate::RuntimeOptions;
+
+pub(crate) fn install(lua: &Lua, _options: &RuntimeOptions) -> mlua::Result<()> {
+    let module = lua.create_table()?;
+    module.set(
+        "split",
+        lua.create_function(|lua, (s, sep): (mlua::String, mlua::String)| {
+            let (s, sep) = (s.as_bytes(), sep.as_bytes());
+            if sep.is_empty() {
+                return Err(mlua::Error::runtime("str.split: empty separator"));
+            }
+            let pieces = lua.create_table()?;
+            let mut start = 0;
+            let mut i = 0;
+            while i + sep.len() <= s.len() {
+                if &s[i..i + sep.len()] == sep {
+                    pieces.raw_push(lua.create_string(&s[start..i])?)?;
+                    i += sep.len();
+                    start = i;
+                } else {
+                    i += 1;
+                }
+            }
+            pieces.raw_push(lua.create_string(&s[start..])?)?;
+            Ok(pieces)
+        })?,
+    )?;
+    module.set(
+        "trim",
+        lua.create_function(|lua, s: mlua::String| lua.create_string(s.as_bytes().trim_ascii()))?,
+    )?;
+    module.set(
+        "startswith",
+        lua.create_function(|_, (s, prefix): (mlua::String, mlua::String)| {
+            Ok(s.as_bytes().starts_with(prefix.as_bytes()))
+        })?,
+    )?;
+    module.set(
+        "endswith",
+        lua.create_function(|_, (s, suffix): (mlua::String, mlua::String)| {
+            Ok(s.as_bytes().ends_with(suffix.as_bytes()))
+        })?,
+    )?;
+    let string: mlua::Table = lua.globals().get("string")?;
+    module.set("lower", string.get::<_, mlua::Function>("lower")?)?;
+    module.set("upper", string.get::<_, mlua::Function>("upper")?)?;
+    lua.globals().set("str", module)
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+
+    fn lua() -> Lua {
+        let lua = Lua::new();
+        install(&lua, &RuntimeOptions::default()).unwrap();
+        lua
+    }
+
+    fn split(lua: &Lua, s: &str, sep: &str) -> Vec<String> {
+        lua.load("return str.split(...)").call((s, sep)).unwrap()
+    }
+
+    #[test]
+    fn split_pieces() {
+        let lua = lua();
+        assert_eq!(split(&lua, "a,b,c", ","), ["a", "b", "c"]);
+        assert_eq!(split(&lua, "a::b::", "::"), ["a", "b", ""]);
+        assert_eq!(split(&lua, "a:b::c", "::"), ["a:b", "c"]);
+        assert_eq!(split(&lua, ",a,,b", ","), ["", "a", "", "b"]);
+        assert_eq!(split(&lua, "no separator", ","), ["no separator"]);
+        assert_eq!(split(&lua, "", ","), [""]);
+        // Separators are literal, not Lua patterns.
+        assert_eq!(split(&lua, "1.2.3", "."), ["1", "2", "3"]);
+
+        let message: String = lua
+            .load(r#"local ok, err = pcall(str.split, "abc", "") return tostring(err)"#)
+            .eval()
+            .unwrap();
+        assert!(message.contains("str.split: empty separator"), "{message}");
+    }
+
+    #[test]
+    fn trim_and_affixes() {
+        let lua = lua();
+        let result: bool = lua
+            .load(indoc! {r#"
+            return str.trim("  juno1abc \t\n") == "juno1abc" and str.trim("") == ""
+                and str.startswith("juno1abc", "juno1") and not str.startswith("juno", "juno1")
+                and str.endswith("ujuno", "juno") and str.endswith("ujuno", "")
+                and str.lower("UJUNO") == "ujuno" and str.upper("ujuno") == "UJUNO"
+            "#})
+            .eval()
+            .unwrap();
+        assert!(result);
+    }
+}
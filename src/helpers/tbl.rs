@@ -0,0 +1,240 @@
+//! The `tbl` module: navigating and comparing nested tables.
+//!
+//! `tbl.get(value, path)` walks a path like `body.messages[1].contract`, returning `nil` as soon
+//! as a segment is missing. Keys containing dots can be quoted in brackets:
+//! `events["wasm.action"]`. `tbl.eq` compares values structurally, and `tbl.contains` looks an
+//! item up in a list using the same comparison.
+
+use std::collections::HashSet;
+
+use mlua::{Lua, Table, Value};
+
+use crate::RuntimeOptions;
+
+/// A segment of a `tbl.get` path.
+#[derive(Debug, PartialEq)]
+enum Segment {
+    Key(String),
+    Index(i64),
+}
+
+pub(crate) fn install(lua: &Lua, _options: &RuntimeOptions) -> mlua::Result<()> {
+    let module = lua.create_table()?;
+    module.set(
+        "get",
+        lua.create_function(|lua, (value, path): (Value, String)| {
+            let segments = parse_path(&path)
+                .map_err(|err| mlua::Error::runtime(format!("tbl.get: {path:?}: {err}")))?;
+            let mut value = value;
+            for segment in segments {
+                let Value::Table(table) = value else {
+                    return Ok(Value::Nil);
+                };
+                value = match segment {
+                    Segment::Key(key) => table.get(lua.create_string(&key)?)?,
+                    Segment::Index(index) => table.get(index)?,
+                };
+            }
+            Ok(value)
+        })?,
+    )?;
+    module.set(
+        "eq",
+        lua.create_function(|_, (a, b): (Value, Value)| deep_eq(&a, &b, &mut HashSet::new()))?,
+    )?;
+    module.set(
+        "contains",
+        lua.create_function(|_, (list, item): (Table, Value)| {
+            for value in list.sequence_values::<Value>() {
+                if deep_eq(&value?, &item, &mut HashSet::new())? {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        })?,
+    )?;
+    lua.globals().set("tbl", module)
+}
+
+/// Split a path into its segments.
+fn parse_path(path: &str) -> Result<Vec<Segment>, String> {
+    let mut segments = Vec::new();
+    let mut chars = path.chars().peekable();
+    let mut key = String::new();
+    // Whether a key may start here: at the beginning or right after a dot.
+    let mut expect_key = true;
+    while let Some(c) = chars.next() {
+        match c {
+            '.' => {
+                if expect_key && key.is_empty() {
+                    return Err("empty segment".to_string());
+                }
+                if !key.is_empty() {
+                    segments.push(Segment::Key(std::mem::take(&mut key)));
+                }
+                expect_key = true;
+            }
+            '[' => {
+                if !key.is_empty() {
+                    segments.push(Segment::Key(std::mem::take(&mut key)));
+                }
+                match chars.peek() {
+                    Some(&quote @ ('"' | '\'')) => {
+                        chars.next();
+                        let mut quoted = String::new();
+                        loop {
+                            match chars.next() {
+                                Some(c) if c == quote => break,
+                                Some(c) => quoted.push(c),
+                                None => return Err("unterminated quoted key".to_string()),
+                            }
+                        }
+                        if chars.next() != Some(']') {
+                            return Err("expected `]` after a quoted key".to_string());
+                        }
+                        segments.push(Segment::Key(quoted));
+                    }
+                    _ => {
+                        let mut index = String::new();
+                        loop {
+                            match chars.next() {
+                                Some(']') => break,
+                                Some(c) => index.push(c),
+                                None => return Err("unterminated index".to_string()),
+                            }
+                        }
+                        let index = index
+                            .trim()
+                            .parse()
+                            .map_err(|_| format!("invalid index `{index}`"))?;
+                        segments.push(Segment::Index(index));
+                    }
+                }
+                expect_key = false;
+            }
+            c => {
+                if !expect_key && key.is_empty() {
+                    return Err("expected `.` or `[` after `]`".to_string());
+                }
+                key.push(c);
+            }
+        }
+    }
+    if !key.is_empty() {
+        segments.push(Segment::Key(key));
+    } else if expect_key && !path.is_empty() {
+        return Err("empty segment".to_string());
+    }
+    Ok(segments)
+}
+
+/// Compare two values structurally, tables by their contents.
+///
+/// `seen` holds the pairs of tables being compared, so cyclic tables terminate.
+fn deep_eq<'lua>(
+    a: &Value<'lua>,
+    b: &Value<'lua>,
+    seen: &mut HashSet<(usize, usize)>,
+) -> mlua::Result<bool> {
+    let (Value::Table(a), Value::Table(b)) = (a, b) else {
+        return Ok(a == b);
+    };
+    if a == b || !seen.insert((a.to_pointer() as usize, b.to_pointer() as usize)) {
+        return Ok(true);
+    }
+    let mut keys = 0;
+    for pair in a.clone().pairs::<Value, Value>() {
+        let (key, value) = pair?;
+        if !deep_eq(&value, &b.raw_get::<_, Value>(key)?, seen)? {
+            return Ok(false);
+        }
+        keys += 1;
+    }
+    Ok(b.clone().pairs::<Value, Value>().count() == keys)
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+
+    fn lua() -> Lua {
+        let lua = Lua::new();
+        install(&lua, &RuntimeOptions::default()).unwrap();
+        lua
+    }
+
+    #[test]
+    fn paths() {
+        use Segment::*;
+        assert_eq!(
+            parse_path("body.messages[1].contract").unwrap(),
+            [
+                Key("body".to_string()),
+                Key("messages".to_string()),
+                Index(1),
+                Key("contract".to_string()),
+            ]
+        );
+        assert_eq!(
+            parse_path(r#"events["wasm.action"]['a"b']"#).unwrap(),
+            [
+                Key("events".to_string()),
+                Key("wasm.action".to_string()),
+                Key("a\"b".to_string()),
+            ]
+        );
+        for invalid in ["a..b", ".a", "a.", "a[x]", "a[1", r#"a["b]"#, "a[1]b"] {
+            assert!(parse_path(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn get() {
+        let lua = lua();
+        let result: bool = lua
+            .load(indoc! {r#"
+            local tx = {
+                body = { messages = { { contract = "juno1manager" } } },
+                events = { ["wasm.action"] = "proxy_call" },
+            }
+            return tbl.get(tx, "body.messages[1].contract") == "juno1manager"
+                and tbl.get(tx, 'events["wasm.action"]') == "proxy_call"
+                and tbl.get(tx, "body.messages[2].contract") == nil
+                and tbl.get(tx, "body.messages[1].contract.nested") == nil
+                and tbl.get(nil, "body") == nil
+                and tbl.get(tx, "") == tx
+            "#})
+            .eval()
+            .unwrap();
+        assert!(result);
+
+        let message: String = lua
+            .load(r#"local ok, err = pcall(tbl.get, {}, "a..b") return tostring(err)"#)
+            .eval()
+            .unwrap();
+        assert!(message.contains("tbl.get"), "{message}");
+    }
+
+    #[test]
+    fn eq_and_contains() {
+        let lua = lua();
+        let result: bool = lua
+            .load(indoc! {r#"
+            local a = { denom = "ujuno", amount = "1", nested = { 1, 2, { x = true } } }
+            local b = { denom = "ujuno", amount = "1", nested = { 1, 2, { x = true } } }
+            local c = { denom = "ujuno", amount = "1", nested = { 1, 2 } }
+            local cyclic_a, cyclic_b = {}, {}
+            cyclic_a.self, cyclic_b.self = cyclic_a, cyclic_b
+            return tbl.eq(a, b) and not tbl.eq(a, c) and not tbl.eq(c, a)
+                and tbl.eq(1, 1) and not tbl.eq("1", 1)
+                and tbl.eq(cyclic_a, cyclic_b)
+                and tbl.contains({ c, b }, a) and not tbl.contains({ c }, a)
+                and tbl.contains({ "juno", "osmo" }, "osmo")
+            "#})
+            .eval()
+            .unwrap();
+        assert!(result);
+    }
+}
@@ -0,0 +1,99 @@
+//! The `time` module: RFC3339 timestamps as unix seconds plus nanoseconds.
+
+use mlua::Lua;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+use crate::RuntimeOptions;
+
+pub(crate) fn install(lua: &Lua, options: &RuntimeOptions) -> mlua::Result<()> {
+    let fixed_now = options.fixed_now();
+
+    let module = lua.create_table()?;
+    module.set(
+        "parse_rfc3339",
+        lua.create_function(|_, input: String| {
+            let time = OffsetDateTime::parse(&input, &Rfc3339).map_err(|err| {
+                mlua::Error::runtime(format!("time.parse_rfc3339: {input:?}: {err}"))
+            })?;
+            Ok((time.unix_timestamp(), time.nanosecond()))
+        })?,
+    )?;
+    module.set(
+        "format_rfc3339",
+        lua.create_function(|_, (secs, nanos): (i64, Option<u32>)| {
+            let time = OffsetDateTime::from_unix_timestamp(secs)
+                .and_then(|time| time.replace_nanosecond(nanos.unwrap_or(0)))
+                .map_err(|err| mlua::Error::runtime(format!("time.format_rfc3339: {err}")))?;
+            time.format(&Rfc3339)
+                .map_err(|err| mlua::Error::runtime(format!("time.format_rfc3339: {err}")))
+        })?,
+    )?;
+    module.set(
+        "now",
+        lua.create_function(move |_, ()| {
+            Ok(fixed_now.unwrap_or_else(|| {
+                let now = OffsetDateTime::now_utc();
+                (now.unix_timestamp(), now.nanosecond())
+            }))
+        })?,
+    )?;
+    lua.globals().set("time", module)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use indoc::indoc;
+
+    use super::*;
+
+    fn lua() -> Lua {
+        let lua = Lua::new();
+        install(&lua, &RuntimeOptions::default()).unwrap();
+        lua
+    }
+
+    #[test]
+    fn parse_and_format() {
+        let lua = lua();
+        let result: bool = lua
+            .load(indoc! {r#"
+            local secs, nanos = time.parse_rfc3339("2023-01-10T12:00:00.123456789Z")
+            local offset = time.parse_rfc3339("2023-01-10T14:00:00+02:00")
+            return secs == 1673352000 and nanos == 123456789 and offset == secs
+                and time.format_rfc3339(secs) == "2023-01-10T12:00:00Z"
+                and time.format_rfc3339(secs, nanos) == "2023-01-10T12:00:00.123456789Z"
+            "#})
+            .eval()
+            .unwrap();
+        assert!(result);
+    }
+
+    #[test]
+    fn now() {
+        let lua = lua();
+        let secs: i64 = lua.load("return time.now()").eval().unwrap();
+        assert!(secs > 1673352000);
+
+        let lua = Lua::new();
+        let options = RuntimeOptions {
+            deterministic: true,
+            fixed_time: SystemTime::UNIX_EPOCH + Duration::new(1673352000, 5),
+            ..Default::default()
+        };
+        install(&lua, &options).unwrap();
+        let now: (i64, u32) = lua.load("return time.now()").eval().unwrap();
+        assert_eq!(now, (1673352000, 5));
+    }
+
+    #[test]
+    fn invalid_timestamp() {
+        let lua = lua();
+        let message: String = lua
+            .load(r#"local ok, err = pcall(time.parse_rfc3339, "yesterday") return tostring(err)"#)
+            .eval()
+            .unwrap();
+        assert!(message.contains("time.parse_rfc3339"), "{message}");
+    }
+}
@@ -0,0 +1,127 @@
+//! The `wasm` module: decoding CosmWasm execute messages.
+//!
+//! Malformed input returns `nil` plus an error string rather than raising, since filters
+//! usually just skip such values.
+
+use mlua::{Lua, Table, Value};
+
+use super::{b64, json};
+use crate::RuntimeOptions;
+
+pub(crate) fn install(lua: &Lua, options: &RuntimeOptions) -> mlua::Result<()> {
+    let big_integers_as_strings = options.json_big_integers_as_strings;
+
+    let module = lua.create_table()?;
+    module.set(
+        "decode_msg",
+        lua.create_function(move |lua, input: mlua::String| {
+            let input = input.as_bytes().trim_ascii();
+            let decoded = if input.starts_with(b"{") || input.starts_with(b"[") {
+                json::decode(lua, input, big_integers_as_strings)
+            } else {
+                b64::decode(input)
+                    .and_then(|bytes| json::decode(lua, &bytes, big_integers_as_strings))
+            };
+            Ok(match decoded {
+                Ok(msg) => (msg, None),
+                Err(err) => (Value::Nil, Some(message(err))),
+            })
+        })?,
+    )?;
+    module.set(
+        "msg_key",
+        lua.create_function(|_, msg: Value| {
+            let Value::Table(msg) = msg else {
+                return Ok((
+                    None,
+                    Some(format!("expected a table, got {}", msg.type_name())),
+                ));
+            };
+            Ok(match single_key(&msg)? {
+                Ok(key) => (Some(key), None),
+                Err(err) => (None, Some(err)),
+            })
+        })?,
+    )?;
+    lua.globals().set("wasm", module)
+}
+
+/// The only key of a table, or a description of why there isn't exactly one string key.
+fn single_key(msg: &Table) -> mlua::Result<Result<String, String>> {
+    let mut keys = Vec::new();
+    for pair in msg.clone().pairs::<Value, Value>() {
+        let (key, _) = pair?;
+        keys.push(key);
+        if keys.len() > 1 {
+            return Ok(Err("message has more than one top-level key".to_string()));
+        }
+    }
+    Ok(match keys.pop() {
+        Some(Value::String(key)) => Ok(key.to_str()?.to_string()),
+        Some(_) => Err("message key is not a string".to_string()),
+        None => Err("message has no top-level key".to_string()),
+    })
+}
+
+/// The message of an error raised by another helper.
+fn message(err: mlua::Error) -> String {
+    match err {
+        mlua::Error::RuntimeError(message) => message,
+        err => err.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+
+    fn lua() -> Lua {
+        let lua = Lua::new();
+        install(&lua, &RuntimeOptions::default()).unwrap();
+        lua
+    }
+
+    #[test]
+    fn decode_json_and_base64() {
+        let lua = lua();
+        let result: bool = lua
+            .load(indoc! {r#"
+            local raw = wasm.decode_msg('{"proxy_call": {}}')
+            -- {"create_task":{"task":{"interval":"Once"}}}
+            local wrapped = wasm.decode_msg("eyJjcmVhdGVfdGFzayI6eyJ0YXNrIjp7ImludGVydmFsIjoiT25jZSJ9fX0=")
+            return wasm.msg_key(raw) == "proxy_call"
+                and wasm.msg_key(wrapped) == "create_task"
+                and wrapped.create_task.task.interval == "Once"
+            "#})
+            .eval()
+            .unwrap();
+        assert!(result);
+    }
+
+    #[test]
+    fn malformed_input() {
+        let lua = lua();
+        let (msg, err): (Value, String) = lua
+            .load(r#"return wasm.decode_msg("not a message")"#)
+            .eval()
+            .unwrap();
+        assert!(msg.is_nil());
+        assert!(err.starts_with("b64.decode"), "{err}");
+
+        let (msg, err): (Value, String) = lua
+            .load(r#"return wasm.decode_msg('{"a": ')"#)
+            .eval()
+            .unwrap();
+        assert!(msg.is_nil());
+        assert!(err.starts_with("json.decode"), "{err}");
+
+        let (key, err): (Value, String) = lua
+            .load(r#"return wasm.msg_key(wasm.decode_msg('{"a": 1, "b": 2}'))"#)
+            .eval()
+            .unwrap();
+        assert!(key.is_nil());
+        assert!(err.contains("more than one"), "{err}");
+    }
+}
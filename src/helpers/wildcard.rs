@@ -0,0 +1,106 @@
+//! The `wildcard` and `wildcard_ci` functions: shell-style wildcard matching.
+//!
+//! `*` matches any run of characters and `?` exactly one; everything else is literal, so dots,
+//! dollar signs and percent signs mean themselves. Patterns match the whole string. They are
+//! compiled to regular expressions on first use and kept in a bounded cache, like `re` does.
+
+use std::sync::{Arc, Mutex};
+
+use mlua::Lua;
+use regex::bytes::Regex;
+
+use super::cache::LruCache;
+use crate::RuntimeOptions;
+
+type Cache = Arc<Mutex<LruCache<(Vec<u8>, bool), Regex>>>;
+
+pub(crate) fn install(lua: &Lua, options: &RuntimeOptions) -> mlua::Result<()> {
+    let cache: Cache = Arc::new(Mutex::new(LruCache::new(options.regex_cache_size)));
+
+    for (name, ignore_case) in [("wildcard", false), ("wildcard_ci", true)] {
+        let patterns = cache.clone();
+        let function =
+            lua.create_function(move |_, (pattern, s): (mlua::String, mlua::String)| {
+                let regex = compile(&patterns, &pattern, ignore_case)?;
+                Ok(regex.is_match(s.as_bytes()))
+            })?;
+        lua.globals().set(name, function)?;
+    }
+    Ok(())
+}
+
+/// Fetch a compiled pattern from the cache, translating it on a miss.
+fn compile(cache: &Cache, pattern: &mlua::String, ignore_case: bool) -> mlua::Result<Regex> {
+    let key = (pattern.as_bytes().to_vec(), ignore_case);
+    let mut cache = cache.lock().unwrap_or_else(|err| err.into_inner());
+    if let Some(regex) = cache.get(&key) {
+        return Ok(regex.clone());
+    }
+    let source = pattern
+        .to_str()
+        .map_err(|_| mlua::Error::runtime("wildcard: pattern is not valid UTF-8"))?;
+    let regex = Regex::new(&translate(source, ignore_case))
+        .map_err(|err| mlua::Error::runtime(format!("wildcard: invalid pattern: {err}")))?;
+    cache.insert(key, regex.clone());
+    Ok(regex)
+}
+
+/// The regular expression equivalent to a wildcard pattern.
+fn translate(pattern: &str, ignore_case: bool) -> String {
+    let mut regex = String::from(if ignore_case { "(?is)^" } else { "(?s)^" });
+    let mut literal = [0; 4];
+    for c in pattern.chars() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            c => regex.push_str(&regex::escape(c.encode_utf8(&mut literal))),
+        }
+    }
+    regex.push('$');
+    regex
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lua() -> Lua {
+        let lua = Lua::new();
+        install(&lua, &RuntimeOptions::default()).unwrap();
+        lua
+    }
+
+    fn matches(lua: &Lua, function: &str, pattern: &str, s: &str) -> bool {
+        let function: mlua::Function = lua.globals().get(function).unwrap();
+        function.call((pattern, s)).unwrap()
+    }
+
+    #[test]
+    fn wildcards() {
+        let lua = lua();
+        assert!(matches(&lua, "wildcard", "juno1abc*", "juno1abcdef"));
+        assert!(matches(&lua, "wildcard", "*factory*", "juno1factory2"));
+        assert!(matches(&lua, "wildcard", "a?c", "abc"));
+        assert!(!matches(&lua, "wildcard", "a?c", "ac"));
+        assert!(!matches(&lua, "wildcard", "juno1abc", "juno1abcdef"));
+        assert!(matches(&lua, "wildcard", "*", "line\nbreak"));
+    }
+
+    #[test]
+    fn special_characters_are_literal() {
+        let lua = lua();
+        assert!(matches(&lua, "wildcard", "v1.2.*", "v1.2.3"));
+        assert!(!matches(&lua, "wildcard", "v1.2.*", "v1x2y3"));
+        assert!(matches(&lua, "wildcard", "$CRON*", "$CRONCAT"));
+        assert!(!matches(&lua, "wildcard", "$CRON*", "CRONCAT"));
+        assert!(matches(&lua, "wildcard", "100%", "100%"));
+        assert!(matches(&lua, "wildcard", "[a]+(b)", "[a]+(b)"));
+    }
+
+    #[test]
+    fn case_sensitivity() {
+        let lua = lua();
+        assert!(!matches(&lua, "wildcard", "JUNO1*", "juno1abc"));
+        assert!(matches(&lua, "wildcard_ci", "JUNO1*", "juno1abc"));
+    }
+}
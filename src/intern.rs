@@ -0,0 +1,591 @@
+//! Converting values into Lua with interned strings.
+//!
+//! With [`RuntimeOptions::intern_strings`](crate::RuntimeOptions::intern_strings), values are
+//! converted by a serializer of our own instead of mlua's: it builds the same Lua values, but
+//! looks strings up in a bounded cache of Lua strings first, so that an address repeated across
+//! a batch is turned into a Lua string once. The cache is keyed by the hash of the string and
+//! holds registry references; the least recently used entries are evicted when it is full.
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
+use mlua::{IntoLua, Lua, LuaSerdeExt, RegistryKey, Table, Value};
+use serde::ser::{self, Serialize};
+
+use crate::{
+    convert::{self, ValueConversion, MAX_SAFE_INTEGER},
+    helpers::cache::LruCache,
+};
+
+/// Longer strings are rarely repeated, so they are converted without the cache.
+const MAX_INTERNED_LEN: usize = 256;
+
+/// The string cache and its counters, kept in the runtime's app data.
+struct Interner {
+    cache: LruCache<u64, RegistryKey>,
+    hits: u64,
+    misses: u64,
+}
+
+pub(crate) fn install(lua: &Lua, capacity: usize) {
+    lua.set_app_data(Interner {
+        cache: LruCache::new(capacity),
+        hits: 0,
+        misses: 0,
+    });
+}
+
+/// How many strings were found in the cache and how many had to be created, so far.
+#[cfg(test)]
+fn counts(lua: &Lua) -> (u64, u64) {
+    lua.app_data_ref::<Interner>()
+        .map_or((0, 0), |interner| (interner.hits, interner.misses))
+}
+
+/// The Lua string holding `bytes`, from the cache if it is there.
+fn intern<'lua>(lua: &'lua Lua, bytes: &[u8]) -> mlua::Result<mlua::String<'lua>> {
+    if bytes.len() > MAX_INTERNED_LEN {
+        return lua.create_string(bytes);
+    }
+    let Some(mut interner) = lua.app_data_mut::<Interner>() else {
+        return lua.create_string(bytes);
+    };
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    let hash = hasher.finish();
+    if let Some(key) = interner.cache.get(&hash) {
+        let string: mlua::String = lua.registry_value(key)?;
+        // Different strings may share a hash; the newer one takes the slot then.
+        if string.as_bytes() == bytes {
+            interner.hits += 1;
+            return Ok(string);
+        }
+    }
+    interner.misses += 1;
+    let string = lua.create_string(bytes)?;
+    let key = lua.create_registry_value(string.clone())?;
+    interner.cache.insert(hash, key);
+    Ok(string)
+}
+
+/// A serializer producing Lua values like mlua's does, with interned strings.
+///
+/// It applies the other conversion settings too, `remaining` being how many more tables it
+/// may open.
+#[derive(Clone, Copy)]
+pub(crate) struct Interning<'lua> {
+    lua: &'lua Lua,
+    conversion: ValueConversion,
+    remaining: usize,
+}
+
+impl<'lua> Interning<'lua> {
+    pub(crate) fn new(lua: &'lua Lua, conversion: ValueConversion, remaining: usize) -> Self {
+        Self {
+            lua,
+            conversion,
+            remaining,
+        }
+    }
+
+    /// The serializer for the values of a table opened `levels` deeper.
+    fn nest(self, levels: usize) -> mlua::Result<Self> {
+        Ok(Self {
+            remaining: convert::nest(self.conversion.max_depth, self.remaining, levels)?,
+            ..self
+        })
+    }
+
+    fn string(self, bytes: &[u8]) -> mlua::Result<Value<'lua>> {
+        intern(self.lua, bytes).map(Value::String)
+    }
+
+    fn integer<I: IntoLua<'lua> + ToString>(
+        self,
+        v: I,
+        magnitude: u128,
+    ) -> mlua::Result<Value<'lua>> {
+        if self.conversion.big_integers_as_strings && magnitude > u128::from(MAX_SAFE_INTEGER) {
+            return self.string(v.to_string().as_bytes());
+        }
+        v.into_lua(self.lua)
+    }
+
+    /// `{ [variant] = value }`, the way mlua represents enum variants holding data.
+    fn variant(self, variant: &'static str, value: Value<'lua>) -> mlua::Result<Value<'lua>> {
+        let table = self.lua.create_table_with_capacity(0, 1)?;
+        table.raw_set(self.string(variant.as_bytes())?, value)?;
+        Ok(Value::Table(table))
+    }
+
+    fn seq(self, table: Table<'lua>, variant: Option<&'static str>) -> Seq<'lua> {
+        Seq {
+            serializer: self,
+            table,
+            next: 0,
+            variant,
+        }
+    }
+
+    fn map(self, table: Table<'lua>, variant: Option<&'static str>) -> Map<'lua> {
+        Map {
+            serializer: self,
+            table,
+            key: None,
+            variant,
+        }
+    }
+}
+
+impl<'lua> ser::Serializer for Interning<'lua> {
+    type Ok = Value<'lua>;
+    type Error = mlua::Error;
+    type SerializeSeq = Seq<'lua>;
+    type SerializeTuple = Seq<'lua>;
+    type SerializeTupleStruct = Seq<'lua>;
+    type SerializeTupleVariant = Seq<'lua>;
+    type SerializeMap = Map<'lua>;
+    type SerializeStruct = Map<'lua>;
+    type SerializeStructVariant = Map<'lua>;
+
+    fn serialize_bool(self, v: bool) -> mlua::Result<Value<'lua>> {
+        Ok(Value::Boolean(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> mlua::Result<Value<'lua>> {
+        v.into_lua(self.lua)
+    }
+
+    fn serialize_i16(self, v: i16) -> mlua::Result<Value<'lua>> {
+        v.into_lua(self.lua)
+    }
+
+    fn serialize_i32(self, v: i32) -> mlua::Result<Value<'lua>> {
+        v.into_lua(self.lua)
+    }
+
+    fn serialize_i64(self, v: i64) -> mlua::Result<Value<'lua>> {
+        self.integer(v, v.unsigned_abs().into())
+    }
+
+    fn serialize_i128(self, v: i128) -> mlua::Result<Value<'lua>> {
+        self.integer(v, v.unsigned_abs())
+    }
+
+    fn serialize_u8(self, v: u8) -> mlua::Result<Value<'lua>> {
+        v.into_lua(self.lua)
+    }
+
+    fn serialize_u16(self, v: u16) -> mlua::Result<Value<'lua>> {
+        v.into_lua(self.lua)
+    }
+
+    fn serialize_u32(self, v: u32) -> mlua::Result<Value<'lua>> {
+        v.into_lua(self.lua)
+    }
+
+    fn serialize_u64(self, v: u64) -> mlua::Result<Value<'lua>> {
+        self.integer(v, v.into())
+    }
+
+    fn serialize_u128(self, v: u128) -> mlua::Result<Value<'lua>> {
+        self.integer(v, v)
+    }
+
+    fn serialize_f32(self, v: f32) -> mlua::Result<Value<'lua>> {
+        v.into_lua(self.lua)
+    }
+
+    fn serialize_f64(self, v: f64) -> mlua::Result<Value<'lua>> {
+        v.into_lua(self.lua)
+    }
+
+    fn serialize_char(self, v: char) -> mlua::Result<Value<'lua>> {
+        self.string(v.encode_utf8(&mut [0; 4]).as_bytes())
+    }
+
+    fn serialize_str(self, v: &str) -> mlua::Result<Value<'lua>> {
+        self.string(v.as_bytes())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> mlua::Result<Value<'lua>> {
+        self.string(v)
+    }
+
+    fn serialize_none(self) -> mlua::Result<Value<'lua>> {
+        Ok(self.lua.null())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> mlua::Result<Value<'lua>> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> mlua::Result<Value<'lua>> {
+        Ok(self.lua.null())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> mlua::Result<Value<'lua>> {
+        Ok(self.lua.null())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> mlua::Result<Value<'lua>> {
+        self.string(variant.as_bytes())
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> mlua::Result<Value<'lua>> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> mlua::Result<Value<'lua>> {
+        let value = value.serialize(self.nest(1)?)?;
+        self.variant(variant, value)
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> mlua::Result<Seq<'lua>> {
+        let serializer = self.nest(1)?;
+        let table = self.lua.create_table_with_capacity(len.unwrap_or(0), 0)?;
+        table.set_metatable(Some(self.lua.array_metatable()));
+        Ok(serializer.seq(table, None))
+    }
+
+    fn serialize_tuple(self, len: usize) -> mlua::Result<Seq<'lua>> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> mlua::Result<Seq<'lua>> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> mlua::Result<Seq<'lua>> {
+        let serializer = self.nest(2)?;
+        let table = self.lua.create_table_with_capacity(len, 0)?;
+        Ok(serializer.seq(table, Some(variant)))
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> mlua::Result<Map<'lua>> {
+        let serializer = self.nest(1)?;
+        let table = self.lua.create_table_with_capacity(0, len.unwrap_or(0))?;
+        Ok(serializer.map(table, None))
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> mlua::Result<Map<'lua>> {
+        let serializer = self.nest(1)?;
+        let table = self.lua.create_table_with_capacity(0, len)?;
+        Ok(serializer.map(table, None))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> mlua::Result<Map<'lua>> {
+        let serializer = self.nest(2)?;
+        let table = self.lua.create_table_with_capacity(0, len)?;
+        Ok(serializer.map(table, Some(variant)))
+    }
+}
+
+/// A sequence, tuple or tuple variant being built.
+pub(crate) struct Seq<'lua> {
+    serializer: Interning<'lua>,
+    table: Table<'lua>,
+    next: usize,
+    variant: Option<&'static str>,
+}
+
+impl<'lua> Seq<'lua> {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> mlua::Result<()> {
+        self.next += 1;
+        self.table
+            .raw_set(self.next, value.serialize(self.serializer)?)
+    }
+
+    fn finish(self) -> mlua::Result<Value<'lua>> {
+        match self.variant {
+            Some(variant) => self.serializer.variant(variant, Value::Table(self.table)),
+            None => Ok(Value::Table(self.table)),
+        }
+    }
+}
+
+impl<'lua> ser::SerializeSeq for Seq<'lua> {
+    type Ok = Value<'lua>;
+    type Error = mlua::Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> mlua::Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> mlua::Result<Value<'lua>> {
+        self.finish()
+    }
+}
+
+impl<'lua> ser::SerializeTuple for Seq<'lua> {
+    type Ok = Value<'lua>;
+    type Error = mlua::Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> mlua::Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> mlua::Result<Value<'lua>> {
+        self.finish()
+    }
+}
+
+impl<'lua> ser::SerializeTupleStruct for Seq<'lua> {
+    type Ok = Value<'lua>;
+    type Error = mlua::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> mlua::Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> mlua::Result<Value<'lua>> {
+        self.finish()
+    }
+}
+
+impl<'lua> ser::SerializeTupleVariant for Seq<'lua> {
+    type Ok = Value<'lua>;
+    type Error = mlua::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> mlua::Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> mlua::Result<Value<'lua>> {
+        self.finish()
+    }
+}
+
+/// A map, struct or struct variant being built.
+pub(crate) struct Map<'lua> {
+    serializer: Interning<'lua>,
+    table: Table<'lua>,
+    key: Option<Value<'lua>>,
+    variant: Option<&'static str>,
+}
+
+impl<'lua> Map<'lua> {
+    fn field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> mlua::Result<()> {
+        let key = self.serializer.string(key.as_bytes())?;
+        self.table.raw_set(key, value.serialize(self.serializer)?)
+    }
+
+    fn finish(self) -> mlua::Result<Value<'lua>> {
+        match self.variant {
+            Some(variant) => self.serializer.variant(variant, Value::Table(self.table)),
+            None => Ok(Value::Table(self.table)),
+        }
+    }
+}
+
+impl<'lua> ser::SerializeMap for Map<'lua> {
+    type Ok = Value<'lua>;
+    type Error = mlua::Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> mlua::Result<()> {
+        self.key = Some(key.serialize(self.serializer)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> mlua::Result<()> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| mlua::Error::runtime("serialize_value called before serialize_key"))?;
+        self.table.raw_set(key, value.serialize(self.serializer)?)
+    }
+
+    fn end(self) -> mlua::Result<Value<'lua>> {
+        self.finish()
+    }
+}
+
+impl<'lua> ser::SerializeStruct for Map<'lua> {
+    type Ok = Value<'lua>;
+    type Error = mlua::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> mlua::Result<()> {
+        self.field(key, value)
+    }
+
+    fn end(self) -> mlua::Result<Value<'lua>> {
+        self.finish()
+    }
+}
+
+impl<'lua> ser::SerializeStructVariant for Map<'lua> {
+    type Ok = Value<'lua>;
+    type Error = mlua::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> mlua::Result<()> {
+        self.field(key, value)
+    }
+
+    fn end(self) -> mlua::Result<Value<'lua>> {
+        self.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde::Serialize;
+
+    use super::*;
+
+    #[derive(Serialize)]
+    enum Msg {
+        Send { to: String, amount: u64 },
+        Delegate(String, u64),
+        Withdraw(String),
+        Claim,
+    }
+
+    #[derive(Serialize)]
+    struct Tx {
+        from: String,
+        memo: Option<String>,
+        big: u128,
+        tags: BTreeMap<String, bool>,
+        msgs: Vec<Msg>,
+        unit: (),
+        pair: (char, [u8; 2]),
+    }
+
+    fn tx(from: &str) -> Tx {
+        Tx {
+            from: from.to_string(),
+            memo: None,
+            big: u128::MAX,
+            tags: BTreeMap::from([("agent".to_string(), true)]),
+            msgs: vec![
+                Msg::Send {
+                    to: "juno1to".to_string(),
+                    amount: 42,
+                },
+                Msg::Delegate("junovaloper1".to_string(), 7),
+                Msg::Withdraw("junovaloper1".to_string()),
+                Msg::Claim,
+            ],
+            unit: (),
+            pair: ('x', [1, 2]),
+        }
+    }
+
+    fn lua(capacity: usize) -> Lua {
+        let lua = Lua::new();
+        install(&lua, capacity);
+        lua
+    }
+
+    #[test]
+    fn same_values_as_mlua() {
+        for big_integers_as_strings in [false, true] {
+            let lua = lua(16);
+            let conversion = ValueConversion {
+                big_integers_as_strings,
+                ..Default::default()
+            };
+            lua.set_app_data(conversion);
+            let expected = crate::convert::to_lua(&lua, &tx("juno1from")).unwrap();
+            let interned = tx("juno1from")
+                .serialize(Interning::new(&lua, conversion, conversion.max_depth))
+                .unwrap();
+            let interned: serde_json::Value = lua.from_value(interned).unwrap();
+            let expected: serde_json::Value = lua.from_value(expected).unwrap();
+            assert_eq!(interned, expected);
+        }
+    }
+
+    #[test]
+    fn repeated_strings_are_reused() {
+        // Longer than the strings Lua 5.4 interns by itself.
+        let address = "juno1qg5ega6dykkxc307y25pecuufrjkxkaggkkxh7nad0vhyhtuhw3sqaa3c5";
+
+        let growth = |interning: bool| {
+            let lua = lua(64);
+            lua.gc_stop();
+            let batch = lua.create_table().unwrap();
+            let before = lua.used_memory();
+            for i in 0..1000 {
+                let tx = tx(address);
+                let value = match interning {
+                    true => {
+                        let conversion = ValueConversion::default();
+                        tx.serialize(Interning::new(&lua, conversion, conversion.max_depth))
+                    }
+                    false => lua.to_value(&tx),
+                };
+                batch.raw_set(i + 1, value.unwrap()).unwrap();
+            }
+            (lua.used_memory() - before, counts(&lua))
+        };
+
+        let (plain, _) = growth(false);
+        let (interned, (hits, misses)) = growth(true);
+        // The address, the field and variant names and the few other strings.
+        assert!(misses < 20, "{misses}");
+        assert!(hits > 1000 * 10, "{hits}");
+        // Lua 5.4 copies every long string; LuaJIT and Luau already share identical strings, so
+        // there only the cache itself makes a difference.
+        #[cfg(feature = "lua54")]
+        assert!(
+            interned + 1000 * address.len() <= plain,
+            "{interned} vs {plain}"
+        );
+        #[cfg(not(feature = "lua54"))]
+        assert!(interned <= plain + 4096, "{interned} vs {plain}");
+    }
+
+    #[test]
+    fn eviction() {
+        let lua = lua(1);
+        for s in ["a", "b", "a"] {
+            let value = intern(&lua, s.as_bytes()).unwrap();
+            assert_eq!(value.as_bytes(), s.as_bytes());
+        }
+        assert_eq!(counts(&lua), (0, 3));
+
+        let long = "x".repeat(MAX_INTERNED_LEN + 1);
+        intern(&lua, long.as_bytes()).unwrap();
+        assert_eq!(counts(&lua), (0, 3));
+    }
+}
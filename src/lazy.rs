@@ -0,0 +1,612 @@
+//! Passing values as proxies converting their fields on demand, see [`ValuePassing::Lazy`].
+//!
+//! A proxy is a userdata whose `__index` finds the field in the `Serialize` output of the
+//! borrowed value and converts only that one, remembering it for later reads. Finding a field
+//! walks the other fields without serializing them. Values that aren't structs or maps, and
+//! keys that aren't strings, fall back to converting the whole value once, which is also what
+//! `pairs` iterates.
+//!
+//! The borrow ends with the evaluation: reading a field not read before raises an error
+//! afterwards.
+//!
+//! [`ValuePassing::Lazy`]: crate::ValuePassing::Lazy
+
+use std::marker::PhantomData;
+
+use mlua::{
+    AnyUserData, Function, IntoLuaMulti, Lua, MetaMethod, MultiValue, Scope, Table, UserData,
+    UserDataMethods, Value,
+};
+use serde::ser::{self, Serialize, Serializer};
+
+use crate::convert;
+
+/// The userdata behind proxies. Its user value holds the functions reading the value, and the
+/// fields converted so far.
+struct Lazy;
+
+impl UserData for Lazy {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_meta_function(
+            MetaMethod::Index,
+            |_, (proxy, key): (AnyUserData, Value)| index(&proxy, key),
+        );
+        #[cfg(feature = "luau")]
+        methods.add_meta_function(MetaMethod::Iter, |lua, proxy: AnyUserData| {
+            let next: Function = lua.globals().raw_get("next")?;
+            Ok((next, whole(&proxy)?))
+        });
+    }
+}
+
+/// Replace the `pairs` global of `lua` with one that iterates proxies in full.
+pub(crate) fn install(lua: &Lua) -> mlua::Result<()> {
+    let globals = lua.globals();
+    let pairs = lua.create_registry_value(globals.get::<_, Function>("pairs")?)?;
+    let next = lua.create_registry_value(globals.get::<_, Function>("next")?)?;
+    let lazy_pairs =
+        lua.create_function(move |lua, args: MultiValue| match args.iter().next() {
+            Some(Value::UserData(proxy)) if proxy.is::<Lazy>() => {
+                let next = lua.registry_value::<Function>(&next)?;
+                (next, whole(proxy)?, Value::Nil).into_lua_multi(lua)
+            }
+            _ => lua.registry_value::<Function>(&pairs)?.call(args.clone()),
+        })?;
+    globals.set("pairs", lazy_pairs)
+}
+
+/// A proxy for `value`, valid for the lifetime of `scope`.
+pub(crate) fn proxy<'lua, 'scope, T: Serialize>(
+    lua: &'lua Lua,
+    scope: &Scope<'lua, 'scope>,
+    value: &'scope T,
+) -> mlua::Result<AnyUserData<'lua>> {
+    let state = lua.create_table()?;
+    state.raw_set("fields", lua.create_table()?)?;
+    state.raw_set(
+        "field",
+        scope.create_function(move |lua, name: mlua::String| {
+            match value.serialize(Field {
+                lua,
+                name: name.as_bytes(),
+            })? {
+                Lookup::Found(field) => Ok((true, field)),
+                Lookup::Missing => Ok((true, Value::Nil)),
+                Lookup::Unsupported => Ok((false, Value::Nil)),
+            }
+        })?,
+    )?;
+    state.raw_set(
+        "whole",
+        scope.create_function(move |lua, ()| convert::to_lua(lua, value))?,
+    )?;
+    let proxy = lua.create_userdata(Lazy)?;
+    proxy.set_user_value(state)?;
+    Ok(proxy)
+}
+
+/// Read the field `key` of `proxy`, converting it unless it was read before.
+fn index<'lua>(proxy: &AnyUserData<'lua>, key: Value<'lua>) -> mlua::Result<Value<'lua>> {
+    if key.is_nil() {
+        return Ok(Value::Nil);
+    }
+    let state: Table = proxy.user_value()?;
+    let fields: Table = state.raw_get("fields")?;
+    let cached: Value = fields.raw_get(key.clone())?;
+    if !cached.is_nil() {
+        return Ok(cached);
+    }
+    let value = match &key {
+        Value::String(name) => {
+            let field: Function = state.raw_get("field")?;
+            match field.call::<_, (bool, Value)>(name.clone())? {
+                (true, value) => value,
+                (false, _) => whole(proxy)?.raw_get(key.clone())?,
+            }
+        }
+        _ => whole(proxy)?.raw_get(key.clone())?,
+    };
+    fields.raw_set(key, value.clone())?;
+    Ok(value)
+}
+
+/// The whole value of `proxy`, converted on first use.
+fn whole<'lua>(proxy: &AnyUserData<'lua>) -> mlua::Result<Table<'lua>> {
+    let state: Table = proxy.user_value()?;
+    if let Value::Table(whole) = state.raw_get("converted")? {
+        return Ok(whole);
+    }
+    let convert: Function = state.raw_get("whole")?;
+    let whole = match convert.call(())? {
+        Value::Table(whole) => whole,
+        _ => {
+            return Err(mlua::Error::runtime(
+                "lazy: only struct and map values can be indexed",
+            ))
+        }
+    };
+    state.raw_set("converted", whole.clone())?;
+    Ok(whole)
+}
+
+/// What looking a field up in the `Serialize` output of a value found.
+enum Lookup<'lua> {
+    Found(Value<'lua>),
+    /// The value is a struct or map without the field.
+    Missing,
+    /// The value is neither a struct nor a map.
+    Unsupported,
+}
+
+/// Converts the field called `name` of a struct or map, skipping the others.
+struct Field<'a, 'lua> {
+    lua: &'lua Lua,
+    name: &'a [u8],
+}
+
+macro_rules! unsupported {
+    ($($method:ident($($ty:ty),*);)*) => {
+        $(fn $method(self, $(_: $ty),*) -> mlua::Result<Lookup<'lua>> {
+            Ok(Lookup::Unsupported)
+        })*
+    };
+}
+
+impl<'a, 'lua> Serializer for Field<'a, 'lua> {
+    type Ok = Lookup<'lua>;
+    type Error = mlua::Error;
+    type SerializeSeq = Skip<'lua>;
+    type SerializeTuple = Skip<'lua>;
+    type SerializeTupleStruct = Skip<'lua>;
+    type SerializeTupleVariant = Skip<'lua>;
+    type SerializeMap = Entries<'a, 'lua>;
+    type SerializeStruct = Entries<'a, 'lua>;
+    type SerializeStructVariant = Skip<'lua>;
+
+    unsupported! {
+        serialize_bool(bool);
+        serialize_i8(i8);
+        serialize_i16(i16);
+        serialize_i32(i32);
+        serialize_i64(i64);
+        serialize_i128(i128);
+        serialize_u8(u8);
+        serialize_u16(u16);
+        serialize_u32(u32);
+        serialize_u64(u64);
+        serialize_u128(u128);
+        serialize_f32(f32);
+        serialize_f64(f64);
+        serialize_char(char);
+        serialize_str(&str);
+        serialize_bytes(&[u8]);
+        serialize_none();
+        serialize_unit();
+        serialize_unit_struct(&'static str);
+        serialize_unit_variant(&'static str, u32, &'static str);
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> mlua::Result<Lookup<'lua>> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> mlua::Result<Lookup<'lua>> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> mlua::Result<Lookup<'lua>> {
+        Ok(Lookup::Unsupported)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> mlua::Result<Skip<'lua>> {
+        Ok(Skip(PhantomData))
+    }
+
+    fn serialize_tuple(self, _len: usize) -> mlua::Result<Skip<'lua>> {
+        Ok(Skip(PhantomData))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> mlua::Result<Skip<'lua>> {
+        Ok(Skip(PhantomData))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> mlua::Result<Skip<'lua>> {
+        Ok(Skip(PhantomData))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> mlua::Result<Entries<'a, 'lua>> {
+        Ok(Entries {
+            field: self,
+            matched: false,
+            found: None,
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> mlua::Result<Entries<'a, 'lua>> {
+        self.serialize_map(None)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> mlua::Result<Skip<'lua>> {
+        Ok(Skip(PhantomData))
+    }
+}
+
+/// Looks through the entries of a struct or map for the field.
+struct Entries<'a, 'lua> {
+    field: Field<'a, 'lua>,
+    /// Whether the key of the entry whose value comes next is the field.
+    matched: bool,
+    found: Option<Value<'lua>>,
+}
+
+impl<'lua> Entries<'_, 'lua> {
+    fn end(self) -> Lookup<'lua> {
+        match self.found {
+            Some(value) => Lookup::Found(value),
+            None => Lookup::Missing,
+        }
+    }
+}
+
+impl<'lua> ser::SerializeMap for Entries<'_, 'lua> {
+    type Ok = Lookup<'lua>;
+    type Error = mlua::Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> mlua::Result<()> {
+        self.matched = self.found.is_none() && key.serialize(KeyIs(self.field.name))?;
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> mlua::Result<()> {
+        if std::mem::take(&mut self.matched) {
+            self.found = Some(convert::nested_to_lua(self.field.lua, value, 1)?);
+        }
+        Ok(())
+    }
+
+    fn end(self) -> mlua::Result<Lookup<'lua>> {
+        Ok(Entries::end(self))
+    }
+}
+
+impl<'lua> ser::SerializeStruct for Entries<'_, 'lua> {
+    type Ok = Lookup<'lua>;
+    type Error = mlua::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> mlua::Result<()> {
+        if self.found.is_none() && key.as_bytes() == self.field.name {
+            self.found = Some(convert::nested_to_lua(self.field.lua, value, 1)?);
+        }
+        Ok(())
+    }
+
+    fn end(self) -> mlua::Result<Lookup<'lua>> {
+        Ok(Entries::end(self))
+    }
+}
+
+/// Ignores the elements of a value without fields.
+struct Skip<'lua>(PhantomData<Value<'lua>>);
+
+macro_rules! skip {
+    ($($trait:ident::$method:ident;)*) => {
+        $(impl<'lua> ser::$trait for Skip<'lua> {
+            type Ok = Lookup<'lua>;
+            type Error = mlua::Error;
+
+            fn $method<T: Serialize + ?Sized>(&mut self, _value: &T) -> mlua::Result<()> {
+                Ok(())
+            }
+
+            fn end(self) -> mlua::Result<Lookup<'lua>> {
+                Ok(Lookup::Unsupported)
+            }
+        })*
+    };
+}
+
+skip! {
+    SerializeSeq::serialize_element;
+    SerializeTuple::serialize_element;
+    SerializeTupleStruct::serialize_field;
+    SerializeTupleVariant::serialize_field;
+}
+
+impl<'lua> ser::SerializeStructVariant for Skip<'lua> {
+    type Ok = Lookup<'lua>;
+    type Error = mlua::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        _key: &'static str,
+        _value: &T,
+    ) -> mlua::Result<()> {
+        Ok(())
+    }
+
+    fn end(self) -> mlua::Result<Lookup<'lua>> {
+        Ok(Lookup::Unsupported)
+    }
+}
+
+/// Tells whether a map key is the string `.0`, treating any other kind of key as a mismatch.
+struct KeyIs<'a>(&'a [u8]);
+
+macro_rules! mismatch {
+    ($($method:ident($($ty:ty),*);)*) => {
+        $(fn $method(self, $(_: $ty),*) -> mlua::Result<bool> {
+            Ok(false)
+        })*
+    };
+}
+
+impl Serializer for KeyIs<'_> {
+    type Ok = bool;
+    type Error = mlua::Error;
+    type SerializeSeq = ser::Impossible<bool, mlua::Error>;
+    type SerializeTuple = ser::Impossible<bool, mlua::Error>;
+    type SerializeTupleStruct = ser::Impossible<bool, mlua::Error>;
+    type SerializeTupleVariant = ser::Impossible<bool, mlua::Error>;
+    type SerializeMap = ser::Impossible<bool, mlua::Error>;
+    type SerializeStruct = ser::Impossible<bool, mlua::Error>;
+    type SerializeStructVariant = ser::Impossible<bool, mlua::Error>;
+
+    mismatch! {
+        serialize_bool(bool);
+        serialize_i8(i8);
+        serialize_i16(i16);
+        serialize_i32(i32);
+        serialize_i64(i64);
+        serialize_i128(i128);
+        serialize_u8(u8);
+        serialize_u16(u16);
+        serialize_u32(u32);
+        serialize_u64(u64);
+        serialize_u128(u128);
+        serialize_f32(f32);
+        serialize_f64(f64);
+        serialize_bytes(&[u8]);
+        serialize_none();
+        serialize_unit();
+        serialize_unit_struct(&'static str);
+        serialize_unit_variant(&'static str, u32, &'static str);
+    }
+
+    fn serialize_char(self, v: char) -> mlua::Result<bool> {
+        Ok(v.encode_utf8(&mut [0; 4]).as_bytes() == self.0)
+    }
+
+    fn serialize_str(self, v: &str) -> mlua::Result<bool> {
+        Ok(v.as_bytes() == self.0)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> mlua::Result<bool> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> mlua::Result<bool> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> mlua::Result<bool> {
+        Ok(false)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> mlua::Result<Self::SerializeSeq> {
+        Err(ser::Error::custom("lazy: unsupported map key"))
+    }
+
+    fn serialize_tuple(self, _len: usize) -> mlua::Result<Self::SerializeTuple> {
+        Err(ser::Error::custom("lazy: unsupported map key"))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> mlua::Result<Self::SerializeTupleStruct> {
+        Err(ser::Error::custom("lazy: unsupported map key"))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> mlua::Result<Self::SerializeTupleVariant> {
+        Err(ser::Error::custom("lazy: unsupported map key"))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> mlua::Result<Self::SerializeMap> {
+        Err(ser::Error::custom("lazy: unsupported map key"))
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> mlua::Result<Self::SerializeStruct> {
+        Err(ser::Error::custom("lazy: unsupported map key"))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> mlua::Result<Self::SerializeStructVariant> {
+        Err(ser::Error::custom("lazy: unsupported map key"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::BTreeMap,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use serde::Serialize;
+
+    use super::*;
+
+    static CONVERTED: AtomicUsize = AtomicUsize::new(0);
+
+    /// A number counting its conversions.
+    struct Counted(u64);
+
+    impl Serialize for Counted {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            CONVERTED.fetch_add(1, Ordering::SeqCst);
+            serializer.serialize_u64(self.0)
+        }
+    }
+
+    #[derive(Serialize)]
+    struct Tx {
+        from: Counted,
+        to: Counted,
+        amount: Counted,
+        fee: Counted,
+        gas: Counted,
+        height: Counted,
+        extra: BTreeMap<String, Counted>,
+    }
+
+    fn tx() -> Tx {
+        Tx {
+            from: Counted(1),
+            to: Counted(2),
+            amount: Counted(3),
+            fee: Counted(4),
+            gas: Counted(5),
+            height: Counted(6),
+            extra: [("memo".to_string(), Counted(7))].into(),
+        }
+    }
+
+    #[test]
+    fn only_read_fields_are_converted() {
+        let lua = Lua::new();
+        install(&lua).unwrap();
+        let run = |script: &str, value: &dyn erased::Value| {
+            CONVERTED.store(0, Ordering::SeqCst);
+            let result: Value = lua
+                .scope(|scope| {
+                    let proxy = value.proxy(&lua, scope)?;
+                    lua.load(script).call(proxy)
+                })
+                .unwrap();
+            (
+                result.as_i64().unwrap_or_default(),
+                CONVERTED.load(Ordering::SeqCst),
+            )
+        };
+
+        let tx = tx();
+        // Fields read twice are converted once.
+        let script = "local tx = ... return tx.from + tx.amount + tx.from";
+        assert_eq!(run(script, &tx), (5, 2));
+        assert_eq!(run("local tx = ... return tx.missing", &tx), (0, 0));
+        // Maps convert the entry read.
+        let extra = &tx.extra;
+        assert_eq!(run("local extra = ... return extra.memo", extra), (7, 1));
+        assert_eq!(run("local tx = ... return tx.extra.memo", &tx), (7, 1));
+
+        // Iterating converts everything.
+        let script = indoc::indoc! {r#"
+            local tx, sum = ..., 0
+            for key, value in pairs(tx) do
+                if type(value) == "number" then sum = sum + value end
+            end
+            return sum
+        "#};
+        assert_eq!(run(script, &tx), (21, 7));
+        // Plain tables still iterate.
+        let script = "local sum = 0 for _, v in pairs({ 1, 2 }) do sum = sum + v end return sum";
+        assert_eq!(run(script, &tx), (3, 0));
+        // Sequences fall back to converting the whole value, once.
+        let coins = vec![Counted(10), Counted(20)];
+        assert_eq!(
+            run("local coins = ... return coins[1] + coins[2]", &coins),
+            (30, 2)
+        );
+    }
+
+    #[test]
+    fn expires_with_the_call() {
+        let lua = Lua::new();
+        install(&lua).unwrap();
+        let tx = tx();
+        lua.scope(|scope| {
+            let proxy = proxy(&lua, scope, &tx)?;
+            lua.globals().set("kept", proxy)?;
+            lua.load("assert(kept.from == 1)").exec()
+        })
+        .unwrap();
+        lua.load("assert(kept.from == 1)").exec().unwrap();
+        let err = lua.load("return kept.to").exec().unwrap_err();
+        assert!(err.to_string().contains("destructed"), "{err}");
+    }
+
+    /// Proxies of values of different types, behind one closure.
+    mod erased {
+        use super::*;
+
+        pub trait Value {
+            fn proxy<'lua, 'scope>(
+                &'scope self,
+                lua: &'lua Lua,
+                scope: &Scope<'lua, 'scope>,
+            ) -> mlua::Result<AnyUserData<'lua>>;
+        }
+
+        impl<T: Serialize> Value for T {
+            fn proxy<'lua, 'scope>(
+                &'scope self,
+                lua: &'lua Lua,
+                scope: &Scope<'lua, 'scope>,
+            ) -> mlua::Result<AnyUserData<'lua>> {
+                super::proxy(lua, scope, self)
+            }
+        }
+    }
+}
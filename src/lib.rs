@@ -5,53 +5,959 @@
 //! It is designed to be used in a server environment where the filter scripts are loaded from
 //! a configuration file.
 //!
+//! ## Backends
+//!
+//! The Lua implementation is picked with a cargo feature: `luajit` (the default), `lua54` or
+//! `luau` (the latter two with `--no-default-features`). [`LuaVersion::compiled`] tells which
+//! one a build uses. Helpers behave the same under all of them, and none of them relies on Lua
+//! 5.3+ semantics such as integer division or `math.type`.
+//!
+//! LuaJIT and Luau have no 64-bit integers: every number is a double, so amounts above 2^53
+//! lose precision unless [`RuntimeOptions::big_integers_as_strings`] passes them as strings.
+//!
+//! Under Luau:
+//!
+//! - there is no `package` library and no `jit` module, and `require` only loads modules from
+//!   [`RuntimeOptions::library_paths`];
+//! - precompiled chunks must be Luau bytecode, and functions can't be dumped to bytecode;
+//! - cancellation is checked at function calls and loop iterations rather than every thousand
+//!   instructions;
+//! - the [`RuntimeOptions::sandbox`] option also turns on Luau's own sandbox, which makes the
+//!   standard libraries read-only.
+//!
+//! Engines other than Lua plug in as a [`FilterBackend`], see [`FilterSystem::with_backend`].
+//! The `rhai` feature adds a Rhai backend, and a backend running each script on the engine of
+//! its [`FilterConfig::language`]. The `wasm` feature adds a backend running WebAssembly
+//! modules, for filters that need stronger isolation than a Lua sandbox.
+//!
+//! The `cosmos` feature adds `CosmosTx`, a Cosmos transaction with its events, ready to be
+//! filtered, built from `cosmrs` and Tendermint RPC types.
+//!
+
+use std::{
+    cell::{Cell, OnceCell, RefCell},
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt,
+    io::{BufRead, Write},
+    ops::ControlFlow,
+    panic::AssertUnwindSafe,
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime},
+};
 
-use std::{collections::HashMap, path::PathBuf};
+use mlua::{prelude::LuaUserData, Lua};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-use mlua::{prelude::LuaUserData, Lua, LuaSerdeExt};
-use serde::{Deserialize, Serialize};
+#[cfg(feature = "tokio")]
+mod async_load;
+mod audit;
+mod backend;
+mod borrowed;
+mod bundle;
+mod convert;
+#[cfg(feature = "cosmos")]
+mod cosmos;
+mod deterministic;
+mod encoding;
+mod env;
+mod error;
+mod expression;
+mod filter_id;
+mod frozen;
+mod gc;
+mod health;
+mod helpers;
+mod intern;
+mod lazy;
+mod limits;
+mod lint;
+mod measure;
+mod observer;
+mod panic;
+#[cfg(feature = "rayon")]
+mod parallel;
+mod pool;
+mod precompile;
+mod print;
+mod profile;
+mod quarantine;
+mod recording;
+#[cfg(feature = "tokio")]
+mod reload;
+mod replay;
+mod report;
+mod require;
+mod rng;
+mod scratch;
+mod script_cache;
+mod signature;
+mod stats;
+#[cfg(feature = "stream")]
+mod stream;
+mod telemetry;
+mod testing;
+mod trace;
+mod transform;
+mod verdict_cache;
+mod watchdog;
+
+pub use audit::{AuditRecord, AuditSink, ChannelSink, NdjsonFileSink};
+pub use backend::{decode_source, BackendError, Decision, FilterBackend, LuaBackend};
+#[cfg(feature = "rhai")]
+pub use backend::{MixedBackend, MixedFilter, RhaiBackend, RhaiFilter};
+#[cfg(feature = "wasm")]
+pub use backend::{WasmBackend, WasmFilter};
+use bundle::ScriptSource;
+pub use bundle::{BundledFunction, BundledLibrary, BundledScript, FilterBundle, BUNDLE_VERSION};
+use convert::{TooDeep, ValueConversion};
+#[cfg(feature = "cosmos")]
+pub use cosmos::{
+    CosmosTx, TxAttribute, TxCoin, TxDecodeError, TxEvent, TxFee, TxMessage, WasmEvent,
+};
+pub use encoding::EncodingError;
+pub use error::{
+    AuditError, BundleError, ConfigError, Error, FilterError, LoadError, LoadOrigin,
+    RecordingError, ResolveError, ReturnKind, ScriptError, SignatureProblem, StreamError,
+};
+use expression::Expression;
+pub use filter_id::FilterId;
+pub use gc::{GcAfterBatch, GcConfig, GcMode};
+pub use health::{Health, HealthThresholds, LoadOutcome, Status};
+use limits::{LoadLimits, ReturnLimits};
+pub use lint::{LintFinding, LintReport};
+pub use observer::FilterObserver;
+#[cfg(feature = "rayon")]
+pub use parallel::ParallelFilterSystem;
+pub use pool::{Checkout, FilterPool, PooledFilterSystem};
+use precompile::CompileThreads;
+use profile::Profiler;
+pub use profile::{FilterSamples, LineSamples, ProfileReport};
+use quarantine::Breaker;
+pub use quarantine::Quarantine;
+pub use recording::{FilterDiff, VerdictDiff, VerifyReport};
+#[cfg(feature = "tokio")]
+pub use reload::{ReloadHandle, ReloadSummary};
+pub use replay::{Mismatch, ReplayOptions, ReplayReport, VerdictCounts};
+pub use report::{LoadFailure, LoadReport, LoadedFilter, ScriptOrigin};
+use script_cache::ScriptCache;
+#[cfg(feature = "signing")]
+pub use signature::sign_script;
+pub use stats::{ChainCounters, ChainStats, FilterStats};
+use stats::{ChainOutcome, ChainTally};
+#[cfg(feature = "stream")]
+pub use stream::{FilterMapWith, FilterWith, FilteredStreamExt};
+pub use testing::{Outcome, TestCase, TestFailure, TestReport, TestTarget};
+pub use verdict_cache::VerdictCacheStats;
+use verdict_cache::{CacheKey, VerdictCache};
+pub use watchdog::InterruptHandle;
+use watchdog::{Fuel, Trip, Watchdog};
 
 /// The filter configuration file structure.
-#[derive(Deserialize)]
+#[derive(Clone, Default, Deserialize)]
 pub struct Config {
-    chains: HashMap<String, Vec<FilterConfig>>,
+    /// The filters of each chain.
+    ///
+    /// [`FilterRuntime::load_all`] gives the filters of the [`WILDCARD_CHAIN`] to every
+    /// other chain; loading a configuration otherwise treats it as any chain.
+    pub chains: HashMap<String, Vec<FilterConfig>>,
+    /// Shared Lua modules, exposed to every filter script as globals of the same name.
+    ///
+    /// They are evaluated in name order, before any filter script.
+    #[serde(default)]
+    pub libraries: BTreeMap<String, PathBuf>,
+    /// Environment variables scripts may read with `env(name)`.
+    #[serde(default)]
+    pub expose_env: Vec<String>,
+    /// Per-chain constants, exposed to the filters of each chain as a read-only `chain` global.
+    #[serde(default)]
+    pub constants: HashMap<String, serde_yaml::Value>,
+    /// The language of the scripts of each chain, unless a filter sets its own, see
+    /// [`FilterConfig::language`].
+    #[serde(default)]
+    pub languages: HashMap<String, ScriptLanguage>,
+    /// How the verdicts of the filters of each chain combine into whether it keeps a value,
+    /// such as `(croncat AND NOT blocklisted) OR admin`, rather than any filter matching.
+    ///
+    /// Names are the names of filters of the chain, combined with `AND`, `OR`, `NOT` and
+    /// parentheses. Filters are only called once the expression needs their verdict; dry-run
+    /// filters, filters that failed under a lenient error policy and filters out of fuel
+    /// count as not matching. Only a [`FilterSystem`] evaluates expressions.
+    #[serde(default)]
+    pub expressions: HashMap<String, String>,
+    /// Base64 Ed25519 public keys scripts must be signed with, see [`FilterConfig::signature`].
+    ///
+    /// Scripts are loaded without checking their signatures while this is empty.
+    #[serde(default)]
+    pub trusted_keys: Vec<String>,
+}
+
+impl Config {
+    /// Parse a configuration from YAML, and validate it.
+    pub fn from_yaml(yaml: &str) -> Result<Self, ConfigError> {
+        let config: Config = serde_yaml::from_str(yaml)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Read a configuration from the YAML file at `path`, and validate it.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let yaml = std::fs::read_to_string(path).map_err(|source| ConfigError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Self::from_yaml(&yaml)
+    }
+
+    /// Check the configuration makes sense on its own, whatever runtime loads it.
+    ///
+    /// Loading a configuration validates it too, so building one by hand is fine.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        signature::trusted_keys(self)?;
+        for (chain, filters) in &self.chains {
+            if let Some(filter) = filters
+                .iter()
+                .find(|filter| filter.script.as_os_str().is_empty())
+            {
+                return Err(ConfigError::MissingScript {
+                    chain: chain.clone(),
+                    filter: filter.name.clone(),
+                });
+            }
+        }
+        for (chain, expression) in &self.expressions {
+            Expression::parse(expression).map_err(|err| ConfigError::Expression {
+                chain: chain.clone(),
+                column: err.column,
+                message: err.message,
+            })?;
+        }
+        Ok(())
+    }
+
+    /// The language `filter` of `chain` is written in, if the configuration says.
+    pub fn language(&self, chain: &str, filter: &FilterConfig) -> Option<ScriptLanguage> {
+        filter
+            .language
+            .or_else(|| self.languages.get(chain).copied())
+    }
+
+    /// The scripts of every filter, in loading order.
+    fn script_paths(&self) -> Vec<PathBuf> {
+        self.chains
+            .values()
+            .flatten()
+            .map(|filter| filter.script.clone())
+            .collect()
+    }
 }
 
 /// The name and script location of a filter.
-#[derive(Deserialize)]
+///
+/// The script returns either a table of filter functions by name, or a single filter
+/// function, registered under `name`.
+#[derive(Clone, Default, Deserialize, Serialize)]
 pub struct FilterConfig {
-    name: String,
-    script: PathBuf,
+    pub name: String,
+    pub script: PathBuf,
+    /// How many times a failing filter call is retried.
+    #[serde(default)]
+    pub retries: u32,
+    /// How long to wait between retries, in milliseconds.
+    #[serde(default)]
+    pub retry_backoff_ms: u64,
+    /// How much fuel each filter of the script may burn per batch, see
+    /// [`Filter::with_fuel_budget`].
+    #[serde(default)]
+    pub fuel_budget: Option<u64>,
+    /// How many bytes each filter of the script may grow memory by in a call, see
+    /// [`Filter::with_max_call_memory`].
+    #[serde(default)]
+    pub max_call_memory: Option<u64>,
+    /// Whether the filters of the script run without their verdicts counting, see
+    /// [`Filter::with_dry_run`].
+    #[serde(default)]
+    pub dry_run: bool,
+    /// How many values in a row each filter of the script may fail on before it is
+    /// quarantined, rather than the threshold of the system, see
+    /// [`FilterSystem::set_quarantine`].
+    #[serde(default)]
+    pub quarantine_after: Option<u32>,
+    /// The language the script is written in, Lua unless it or its chain says otherwise.
+    ///
+    /// A [`FilterSystem`] only runs Lua on its runtime; other languages need a backend
+    /// running them, see [`FilterSystem::with_backend`].
+    #[serde(default)]
+    pub language: Option<ScriptLanguage>,
+    /// The base64 Ed25519 signature of the script file, by one of [`Config::trusted_keys`].
+    ///
+    /// A [`FilterSystem`] checks it against the decoded script, so the scripts it loads are
+    /// signed as UTF-8 without a byte order mark. Scripts compiled on a backend are checked
+    /// as they were read.
+    #[serde(default)]
+    pub signature: Option<String>,
+}
+
+impl FilterConfig {
+    /// The retry policy of the filters in this script.
+    pub fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            retries: self.retries,
+            backoff: Duration::from_millis(self.retry_backoff_ms),
+        }
+    }
+}
+
+/// The language of a filter script, `lua` or `rhai` in configurations.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScriptLanguage {
+    #[default]
+    Lua,
+    /// Rhai, run by the `RhaiBackend` of the `rhai` feature.
+    Rhai,
+}
+
+impl fmt::Display for ScriptLanguage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScriptLanguage::Lua => f.write_str("Lua"),
+            ScriptLanguage::Rhai => f.write_str("Rhai"),
+        }
+    }
+}
+
+/// How a filter retries calls that raised an error.
+///
+/// Only errors raised by the filter call itself are retried; cancellation is not.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// How many extra attempts are made after the first one fails.
+    pub retries: u32,
+    /// How long to wait before each extra attempt.
+    pub backoff: Duration,
 }
 
 /// A filter backed by a Lua function.
 pub struct Filter<'lua, T> {
     pub name: String,
-    filter: mlua::Function<'lua>,
+    /// The chain the filter was loaded for, if it came from a configuration.
+    chain: Option<String>,
+    /// The name of the filter of the configuration it was loaded from, if it came from one.
+    config_name: Option<String>,
+    /// The script the filter was loaded from, if it came from a configuration.
+    script: Option<PathBuf>,
+    /// What the script was loaded from, for bundles.
+    source: Option<Rc<ScriptSource>>,
+    filter: Callable<'lua, T>,
+    api_version: u32,
+    retry_policy: RetryPolicy,
+    state: Vec<mlua::Value<'lua>>,
+    fuel_budget: Option<u64>,
+    /// The fuel burnt in the current batch.
+    fuel_burnt: Cell<u64>,
+    max_call_memory: Option<u64>,
+    dry_run: bool,
+    quarantine_after: Option<u32>,
+    breaker: Breaker,
+    stats: RefCell<FilterStats>,
+    metrics: telemetry::CallMetrics,
     _marker: std::marker::PhantomData<T>,
 }
 
+/// What a [`Filter`] calls.
+enum Callable<'lua, T> {
+    Lua(mlua::Function<'lua>),
+    /// A filter the backend of a filter system compiled, see [`FilterSystem::with_backend`].
+    Backend(BackendCall<'lua, T>),
+}
+
+/// A filter compiled by a backend, calling it with the value.
+type BackendCall<'lua, T> = Box<dyn Fn(&T) -> Result<Decision, BackendError> + 'lua>;
+
+/// Why a filter call failed, before it is reported as a [`FilterError`].
+enum Failure {
+    Lua(mlua::Error),
+    Backend(BackendError),
+    Panic(String),
+    Oversized(ReturnKind, usize),
+    /// The verdict was of the named type rather than a boolean, with strict returns.
+    InvalidReturn(&'static str),
+    /// The call grew memory by the first count of bytes, over the second.
+    MemoryExceeded(u64, u64),
+}
+
+/// The context provider of a [`FilterSystem`], converting what it gives into Lua.
+type ContextProvider<'lua> = Box<dyn Fn(&'lua Lua) -> mlua::Result<mlua::Value<'lua>> + Send>;
+
+/// The `chain` constants of a chain, and what its scripts run in.
+struct ChainEnvironment<'lua> {
+    sandboxed: bool,
+    constants: Option<mlua::Value<'lua>>,
+    /// The environment shared by the scripts of the chain, unless each gets its own.
+    shared: Option<mlua::Table<'lua>>,
+}
+
+/// A value on its way to the filters, converted into Lua by the first filter that needs it
+/// and shared by the others.
+struct Argument<'s, 'lua, 'scope, T> {
+    scope: &'s mlua::Scope<'lua, 'scope>,
+    value: &'scope T,
+    converted: OnceCell<mlua::Value<'lua>>,
+}
+
+impl<'s, 'lua, 'scope, T> Argument<'s, 'lua, 'scope, T>
+where
+    T: LuaUserData + Serialize + Send + Sync,
+{
+    fn new(scope: &'s mlua::Scope<'lua, 'scope>, value: &'scope T) -> Self {
+        Self {
+            scope,
+            value,
+            converted: OnceCell::new(),
+        }
+    }
+
+    /// The value as the filters receive it, per the runtime's [`ValuePassing`].
+    fn get(&self, lua: &'lua Lua) -> mlua::Result<mlua::Value<'lua>> {
+        if let Some(converted) = self.converted.get() {
+            return Ok(converted.clone());
+        }
+        let converted = match ValueConversion::of(lua).value_passing {
+            ValuePassing::SerdeTable => convert::to_lua(lua, self.value)?,
+            ValuePassing::ScratchTable => scratch::to_lua(lua, self.value)?,
+            ValuePassing::UserData => mlua::Value::UserData(
+                self.scope
+                    .create_nonstatic_userdata(borrowed::Borrowed(self.value))?,
+            ),
+            ValuePassing::Lazy => mlua::Value::UserData(lazy::proxy(lua, self.scope, self.value)?),
+        };
+        Ok(self.converted.get_or_init(|| converted).clone())
+    }
+}
+
 impl<'lua, T> Filter<'lua, T>
 where
-    T: LuaUserData + Serialize + Clone + Send + Sync + 'lua,
+    T: LuaUserData + Serialize + Send + Sync + 'lua,
 {
-    /// Create a new filter.
+    /// Create a new filter, called with the latest convention of [`API_VERSIONS`].
     pub fn new(name: String, filter: mlua::Function<'lua>) -> Self {
+        Self::calling(name, Callable::Lua(filter))
+    }
+
+    /// A filter calling `filter`.
+    fn calling(name: String, filter: Callable<'lua, T>) -> Self {
         Self {
             name,
+            chain: None,
+            config_name: None,
+            script: None,
+            source: None,
             filter,
+            api_version: *API_VERSIONS.end(),
+            retry_policy: RetryPolicy::default(),
+            state: Vec::new(),
+            fuel_budget: None,
+            fuel_burnt: Cell::new(0),
+            max_call_memory: None,
+            dry_run: false,
+            quarantine_after: None,
+            breaker: Breaker::default(),
+            stats: RefCell::default(),
+            metrics: telemetry::CallMetrics::default(),
             _marker: std::marker::PhantomData,
         }
     }
 
+    /// Call the filter with the convention of `version`, one of [`API_VERSIONS`].
+    pub fn with_api_version(mut self, version: u32) -> Self {
+        self.api_version = version;
+        self
+    }
+
+    /// Set the retry policy of the filter.
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Set the values holding the filter's persistent state, measured for
+    /// [`FilterStats::state_bytes`].
+    ///
+    /// Filter systems pass what a script returned and, for sandboxed scripts, their
+    /// environment, so filters of the same script report the same state. Locals the
+    /// function captures aren't visible to the host and aren't counted.
+    pub fn with_state(mut self, state: Vec<mlua::Value<'lua>>) -> Self {
+        self.state = state;
+        self
+    }
+
+    /// Set how much fuel the filter may burn per batch, one unit per Lua instruction.
+    ///
+    /// A call running out of it is aborted and counts as not matching, and the filter is
+    /// skipped for the rest of the batch. Budgets are only enforced by filter systems, which
+    /// then meter fuel, see [`FilterSystem::meter_fuel`].
+    pub fn with_fuel_budget(mut self, fuel: u64) -> Self {
+        self.fuel_budget = Some(fuel);
+        self
+    }
+
+    /// Fail calls that grow the runtime's memory by more than `bytes`, as measured for
+    /// [`FilterStats::peak_delta_bytes`].
+    ///
+    /// The call runs to completion and fails with [`FilterError::CallMemoryExceeded`]
+    /// afterwards. Filter systems measure memory exactly for filters with such a limit, see
+    /// [`FilterSystem::measure_memory`].
+    pub fn with_max_call_memory(mut self, bytes: u64) -> Self {
+        self.max_call_memory = Some(bytes);
+        self
+    }
+
+    /// Run the filter in dry-run mode, or not.
+    ///
+    /// Filter systems still call a dry-run filter and count its verdicts in its stats and in
+    /// detailed verdicts, see [`Verdict::dry_run`], but they never keep a value for it. Its
+    /// errors are ignored as under [`ErrorPolicy::Lenient`], whatever the policy of the system.
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Quarantine the filter once it fails on `errors` values in a row, whatever the
+    /// threshold of the filter system, see [`FilterSystem::set_quarantine`].
+    pub fn with_quarantine_after(mut self, errors: u32) -> Self {
+        self.quarantine_after = Some(errors);
+        self
+    }
+
+    /// The fuel the filter may still burn in the current batch, if it has a budget.
+    fn fuel_left(&self) -> Option<u64> {
+        self.fuel_budget
+            .map(|budget| budget.saturating_sub(self.fuel_burnt.get()))
+    }
+
+    /// Whether the filter burnt its whole budget in the current batch.
+    fn out_of_fuel(&self) -> bool {
+        self.fuel_budget
+            .is_some_and(|budget| self.fuel_burnt.get() > budget)
+    }
+
+    /// The fully qualified name of the filter.
+    pub fn id(&self) -> FilterId {
+        FilterId {
+            chain: self.chain.clone(),
+            config_name: self.config_name.clone(),
+            function_name: self.name.clone(),
+        }
+    }
+
+    /// The counters of the filter.
+    pub fn stats(&self) -> FilterStats {
+        FilterStats {
+            name: self.name.clone(),
+            id: self.id(),
+            dry_run: self.dry_run,
+            quarantined: self.breaker.is_open(),
+            state_bytes: measure::size_of(&self.state) as u64,
+            ..self.stats.borrow().clone()
+        }
+    }
+
     /// Filter a transaction by a value.
-    pub fn filter(&self, lua: &'lua Lua, value: T) -> Result<bool, mlua::Error> {
-        let value = lua.to_value(&value)?;
-        let result = self.filter.call(value)?;
-        Ok(result)
+    pub fn filter(&self, lua: &'lua Lua, value: T) -> Result<bool, FilterError> {
+        lua.scope(|scope| {
+            let argument = Argument::new(scope, &value);
+            Ok(self.call(lua, &argument, &mlua::Value::Nil, false))
+        })?
+        .map(|(matched, _)| matched)
+    }
+
+    /// Call the filter function with a value and the context argument, retrying per the retry
+    /// policy.
+    ///
+    /// Returns the verdict along with the second value the function returned, its reason.
+    /// With `strict_returns`, a verdict that isn't a boolean is an error; otherwise it goes by
+    /// Lua truthiness, see [`FilterSystem::strict_returns`].
+    fn call(
+        &self,
+        lua: &'lua Lua,
+        argument: &Argument<'_, 'lua, '_, T>,
+        context: &mlua::Value<'lua>,
+        strict_returns: bool,
+    ) -> Result<(bool, mlua::Value<'lua>), FilterError> {
+        if panic::is_poisoned(lua) {
+            return Err(FilterError::Poisoned);
+        }
+        self.metrics.measure(&self.name, self.chain.as_deref(), || {
+            self.call_counted(lua, argument, context, strict_returns)
+        })
+    }
+
+    /// [`call`](Self::call), once the runtime is known to be usable, counting the call in the
+    /// stats.
+    fn call_counted(
+        &self,
+        lua: &'lua Lua,
+        argument: &Argument<'_, 'lua, '_, T>,
+        context: &mlua::Value<'lua>,
+        strict_returns: bool,
+    ) -> Result<(bool, mlua::Value<'lua>), FilterError> {
+        let env_denied = env::denied(lua);
+        let (memo_hits, memo_misses) = helpers::memo::counts(lua);
+        let memory = lua.used_memory();
+        let mut attempts = 0;
+        let result = loop {
+            attempts += 1;
+            // Nothing here is used again after a panic but the Lua state, which mlua keeps
+            // consistent and `panic::recover` checks.
+            let attempt = AssertUnwindSafe(|| match &self.filter {
+                Callable::Lua(function) => {
+                    let value = argument.get(lua).map_err(Failure::Lua)?;
+                    let result = match self.api_version {
+                        1 => function.call::<_, (mlua::Value, mlua::Value)>(value),
+                        _ => {
+                            function.call::<_, (mlua::Value, mlua::Value)>((value, context.clone()))
+                        }
+                    };
+                    result.map_err(Failure::Lua)
+                }
+                Callable::Backend(filter) => {
+                    let decision = filter(argument.value).map_err(Failure::Backend)?;
+                    let reason = decision.reason.map(|reason| lua.create_string(reason));
+                    let reason = reason.transpose().map_err(Failure::Lua)?;
+                    Ok((
+                        mlua::Value::Boolean(decision.matched),
+                        reason.map_or(mlua::Value::Nil, mlua::Value::String),
+                    ))
+                }
+            });
+            let result = match std::panic::catch_unwind(attempt) {
+                Ok(result) => result,
+                Err(payload) => break Err(Failure::Panic(panic::recover(lua, payload))),
+            };
+            // Neither aborted calls nor values too deep to convert get better on a retry.
+            let retry = match &result {
+                Err(Failure::Lua(err)) => Trip::find(err).is_none() && TooDeep::find(err).is_none(),
+                Err(Failure::Backend(_)) => true,
+                _ => false,
+            };
+            if !retry || attempts > self.retry_policy.retries {
+                break result;
+            }
+            std::thread::sleep(self.retry_policy.backoff);
+        };
+        let result = result.and_then(|(matched, reason)| match limits::oversized(lua, &reason) {
+            Some((kind, size)) => Err(Failure::Oversized(kind, size)),
+            None => Ok((matched, reason)),
+        });
+        let mut coerced = false;
+        let result = result.and_then(|(matched, reason)| match matched {
+            mlua::Value::Boolean(matched) => Ok((matched, reason)),
+            other if strict_returns => Err(Failure::InvalidReturn(other.type_name())),
+            other => {
+                coerced = true;
+                Ok((!other.is_nil(), reason))
+            }
+        });
+
+        let mut stats = self.stats.borrow_mut();
+        stats.invocations += 1;
+        stats.retries += u64::from(attempts - 1);
+        stats.coerced_returns += u64::from(coerced);
+        stats.env_denied += env::denied(lua) - env_denied;
+        let (hits, misses) = helpers::memo::counts(lua);
+        stats.memo_hits += hits - memo_hits;
+        stats.memo_misses += misses - memo_misses;
+        let delta = lua.used_memory().saturating_sub(memory) as u64;
+        stats.peak_delta_bytes = stats.peak_delta_bytes.max(delta);
+        let result = result.and_then(|verdict| match self.max_call_memory {
+            Some(limit) if delta > limit => Err(Failure::MemoryExceeded(delta, limit)),
+            _ => Ok(verdict),
+        });
+        match result {
+            Ok((matched, reason)) => {
+                stats.matches += u64::from(matched);
+                Ok((matched, reason))
+            }
+            Err(Failure::Panic(message)) => {
+                stats.errors += 1;
+                Err(FilterError::Panic {
+                    filter: self.id().to_string(),
+                    message,
+                })
+            }
+            Err(Failure::Backend(error)) => {
+                stats.errors += 1;
+                Err(FilterError::Backend {
+                    filter: self.id().to_string(),
+                    error,
+                })
+            }
+            Err(Failure::MemoryExceeded(bytes, limit)) => {
+                stats.errors += 1;
+                Err(FilterError::CallMemoryExceeded {
+                    filter: self.id().to_string(),
+                    bytes,
+                    limit,
+                })
+            }
+            Err(Failure::Oversized(kind, size)) => {
+                stats.errors += 1;
+                Err(FilterError::OversizedReturn {
+                    filter: self.id().to_string(),
+                    kind,
+                    size,
+                })
+            }
+            Err(Failure::InvalidReturn(got_type)) => {
+                stats.errors += 1;
+                Err(FilterError::InvalidReturn {
+                    filter: self.id().to_string(),
+                    got_type,
+                })
+            }
+            Err(Failure::Lua(err)) => {
+                // Running out of fuel is a skip rather than a failure.
+                if Trip::find(&err) == Some(Trip::OutOfFuel) {
+                    stats.budget_exhausted += 1;
+                    return Err(err.into());
+                }
+                stats.errors += 1;
+                match Trip::find(&err) {
+                    Some(Trip::Interrupted) => {
+                        return Err(FilterError::Interrupted {
+                            filter: self.id().to_string(),
+                        })
+                    }
+                    Some(Trip::Timeout(elapsed)) => {
+                        return Err(FilterError::Timeout {
+                            filter: self.id().to_string(),
+                            elapsed,
+                        })
+                    }
+                    _ => {}
+                }
+                if TooDeep::find(&err).is_some() {
+                    return Err(err.into());
+                }
+                Err(FilterError::Script(Box::new(ScriptError::new(
+                    self.id().to_string(),
+                    self.script.clone(),
+                    attempts,
+                    err,
+                ))))
+            }
+        }
+    }
+}
+
+/// Options used to build a filter runtime.
+#[derive(Clone, Debug)]
+pub struct RuntimeOptions {
+    /// Decode JSON integers a Lua number can't hold exactly as strings, instead of rounding them.
+    pub json_big_integers_as_strings: bool,
+    /// How many compiled patterns the `re` helper keeps around.
+    pub regex_cache_size: usize,
+    /// Pass integers a Lua number can't hold exactly (above 2^53) to filters as decimal strings.
+    ///
+    /// Off by default, since it changes the type scripts see for those fields. The `bigint`
+    /// helper compares and combines such strings exactly.
+    pub big_integers_as_strings: bool,
+    /// Directories `require` may load modules from.
+    ///
+    /// When set, `require` resolves module names only within these roots; otherwise the stock
+    /// Lua loader is left in place.
+    pub library_paths: Vec<PathBuf>,
+    /// Make verdicts independent of the wall clock and of randomness.
+    ///
+    /// `os.time`, `os.date` and `time.now` report `fixed_time` instead of the current time,
+    /// `os.clock` always returns zero, and `math.random` is seeded from `random_seed`.
+    pub deterministic: bool,
+    /// The seed of `math.random` in deterministic mode.
+    pub random_seed: u64,
+    /// The current time as seen by scripts in deterministic mode.
+    pub fixed_time: SystemTime,
+    /// How filtered values reach the filter functions.
+    pub value_passing: ValuePassing,
+    /// How many bytes of `print` output a detailed evaluation keeps per filter call.
+    pub print_capture_limit: usize,
+    /// How many results the `memo` helper keeps around, for each filter system.
+    pub memo_cache_size: usize,
+    /// Convert repeated strings of the filtered values, such as addresses, into Lua once.
+    ///
+    /// Strings up to 256 bytes are looked up in a cache of Lua strings before being copied
+    /// into Lua, which saves work and memory when the same strings recur across a batch.
+    pub intern_strings: bool,
+    /// How many strings the `intern_strings` cache keeps around.
+    pub intern_cache_size: usize,
+    /// How many levels of nested structs, maps and sequences a value converted into Lua may
+    /// have.
+    ///
+    /// Values nested deeper fail with [`FilterError::ValueTooDeep`] instead of overflowing the
+    /// stack while they are converted. Values passed as [`ValuePassing::UserData`] aren't
+    /// converted, so aren't checked.
+    pub max_value_depth: usize,
+    /// How many bytes a string a filter returns, such as its reason, may hold.
+    ///
+    /// Longer strings fail the call with [`FilterError::OversizedReturn`] rather than being
+    /// copied out of Lua.
+    pub max_return_string_len: usize,
+    /// How many entries a table a filter returns may hold, like `max_return_string_len`.
+    pub max_return_table_entries: usize,
+    /// The Lua dialect the runtime must run. Defaults to the one compiled in.
+    pub lua: LuaVersion,
+    /// Give every script its own global environment.
+    ///
+    /// Scripts still read the shared globals, but the globals they assign stay visible to
+    /// themselves only, so one script can't replace a global another one uses.
+    pub sandbox: bool,
+    /// A directory to keep compiled scripts in, so later loads of the same scripts skip
+    /// compiling them.
+    ///
+    /// Entries are keyed by a hash of the script source. Ones that are corrupt or were written
+    /// by another Lua dialect are recompiled and replaced.
+    pub script_cache: Option<PathBuf>,
+    /// How many threads read and compile the scripts of a configuration before they are loaded.
+    ///
+    /// Zero, the default, uses as many threads as there are cores, and one compiles each script
+    /// as it is loaded. Either way scripts are evaluated and registered in configuration order,
+    /// and fail the same way.
+    pub compile_threads: usize,
+    /// How many distinct filter scripts a configuration may name.
+    ///
+    /// This and the other load limits are checked before anything in the configuration is
+    /// evaluated, and fail [`FilterSystem::load`] with an error naming the limit.
+    pub max_scripts: usize,
+    /// How many filters a chain of a configuration may list.
+    pub max_filters_per_chain: usize,
+    /// How many filters all the chains of a configuration may list together.
+    pub max_total_filters: usize,
+    /// How large a filter script or library file may be, in bytes.
+    pub max_script_size_bytes: u64,
+}
+
+impl RuntimeOptions {
+    /// The time scripts see as the current one, as unix seconds and nanoseconds, if it is fixed.
+    pub(crate) fn fixed_now(&self) -> Option<(i64, u32)> {
+        self.deterministic.then(|| {
+            let since_epoch = self
+                .fixed_time
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default();
+            (since_epoch.as_secs() as i64, since_epoch.subsec_nanos())
+        })
+    }
+}
+
+impl Default for RuntimeOptions {
+    fn default() -> Self {
+        Self {
+            json_big_integers_as_strings: true,
+            regex_cache_size: 256,
+            big_integers_as_strings: false,
+            library_paths: Vec::new(),
+            deterministic: false,
+            random_seed: 0,
+            fixed_time: SystemTime::UNIX_EPOCH,
+            value_passing: ValuePassing::default(),
+            print_capture_limit: 64 * 1024,
+            memo_cache_size: 1024,
+            intern_strings: false,
+            intern_cache_size: 4096,
+            max_value_depth: convert::DEFAULT_MAX_DEPTH,
+            max_return_string_len: 1 << 20,
+            max_return_table_entries: 1 << 20,
+            sandbox: false,
+            script_cache: None,
+            compile_threads: 0,
+            max_scripts: 4096,
+            max_filters_per_chain: 4096,
+            max_total_filters: 16384,
+            max_script_size_bytes: 16 << 20,
+            lua: LuaVersion::compiled(),
+        }
+    }
+}
+
+/// Marks a runtime built with [`RuntimeOptions::sandbox`], in its app data.
+struct Sandboxed;
+
+/// The names of the libraries a filter system exposed as globals, in its runtime's app data.
+struct ExposedLibraries(Vec<String>);
+
+/// A Lua state set up per `options`, for filter runtimes and the Lua backend.
+fn create_runtime(options: RuntimeOptions) -> Result<Lua, mlua::Error> {
+    let compiled = LuaVersion::compiled();
+    if options.lua != compiled {
+        return Err(mlua::Error::runtime(format!(
+            "{:?} was requested but this build only supports {compiled:?}; enable its cargo feature instead",
+            options.lua
+        )));
+    }
+    let runtime = Lua::new();
+    runtime.set_app_data(ValueConversion {
+        big_integers_as_strings: options.big_integers_as_strings,
+        value_passing: options.value_passing,
+        intern_strings: options.intern_strings,
+        max_depth: options.max_value_depth,
+    });
+    runtime.set_app_data(ReturnLimits {
+        max_string_len: options.max_return_string_len,
+        max_table_entries: options.max_return_table_entries,
+    });
+    intern::install(&runtime, options.intern_cache_size);
+    match options.value_passing {
+        ValuePassing::ScratchTable => scratch::install(&runtime)?,
+        ValuePassing::Lazy => lazy::install(&runtime)?,
+        ValuePassing::SerdeTable | ValuePassing::UserData => {}
+    }
+    helpers::install(&runtime, &options)?;
+    env::install(&runtime)?;
+    print::install(&runtime, options.print_capture_limit)?;
+    if options.sandbox {
+        runtime.set_app_data(Sandboxed);
+        // On top of the per-script environments, Luau makes the libraries read-only.
+        #[cfg(feature = "luau")]
+        runtime.sandbox(true)?;
+    }
+    runtime.set_app_data(CompileThreads(options.compile_threads));
+    runtime.set_app_data(LoadLimits {
+        max_scripts: options.max_scripts,
+        max_filters_per_chain: options.max_filters_per_chain,
+        max_total_filters: options.max_total_filters,
+        max_script_size_bytes: options.max_script_size_bytes,
+    });
+    if let Some(dir) = &options.script_cache {
+        runtime.set_app_data(ScriptCache::new(dir.clone()));
+    }
+    if options.deterministic {
+        deterministic::install(&runtime, &options)?;
+    }
+    if !options.library_paths.is_empty() {
+        require::install(&runtime, &options.library_paths)?;
     }
+    Ok(runtime)
 }
 
+/// The calling conventions of filter functions this runtime supports.
+///
+/// A script declares the one it is written for with an `api_version` entry next to its
+/// functions, `return { api_version = 2, filter = ... }`; scripts that don't are version 1.
+///
+/// - 1: filters are called with the value only.
+/// - 2: filters are also given the context of
+///   [`filter_with_context`](FilterSystem::filter_with_context) or of the context provider
+///   as their second argument, `nil` without one.
+pub const API_VERSIONS: std::ops::RangeInclusive<u32> = 1..=2;
+
+/// The chain whose filters [`FilterRuntime::load_all`] gives to every chain.
+pub const WILDCARD_CHAIN: &str = "*";
+
 /// The filter runtime (Lua).
 pub struct FilterRuntime<T> {
     runtime: Lua,
@@ -60,191 +966,8057 @@ pub struct FilterRuntime<T> {
 
 impl<T> FilterRuntime<T>
 where
-    T: LuaUserData + Serialize + Clone + Send + Sync,
+    T: LuaUserData + Serialize + Send + Sync,
 {
     /// Create a new filter runtime.
     pub fn new() -> Self {
-        Self {
-            runtime: Lua::new(),
+        Self::new_with_options(RuntimeOptions::default())
+            .expect("failed to set up the filter runtime")
+    }
+
+    /// Create a new filter runtime with the given options.
+    pub fn new_with_options(options: RuntimeOptions) -> Result<Self, mlua::Error> {
+        Ok(Self {
+            runtime: create_runtime(options)?,
             _marker: std::marker::PhantomData,
-        }
+        })
     }
 
     /// Load a filter configuration.
-    pub fn load(&self, config: Config) -> Result<FilterSystem<'_, T>, mlua::Error> {
+    pub fn load(&self, config: Config) -> Result<FilterSystem<'_, T>, LoadError> {
         let mut system = FilterSystem::new(&self.runtime);
         system.load(config)?;
         Ok(system)
     }
+
+    /// Load a filter configuration, compiling its scripts on `backend`, see
+    /// [`FilterSystem::with_backend`].
+    pub fn load_with_backend<'a, B>(
+        &'a self,
+        backend: B,
+        config: Config,
+    ) -> Result<FilterSystem<'a, T, B>, LoadError>
+    where
+        B: FilterBackend + 'a,
+        B::Filter: 'a,
+    {
+        let mut system = FilterSystem::with_backend(&self.runtime, backend);
+        system.load(config)?;
+        Ok(system)
+    }
+
+    /// Load a filter configuration into a filter system per chain, all on this runtime.
+    ///
+    /// The filters of the [`WILDCARD_CHAIN`], if any, are loaded into the system of every other
+    /// chain, before its own. Each system can then be reloaded on its own, with
+    /// [`FilterSystem::load`] or [`FilterSystem::load_chain`], leaving the others as they are.
+    /// Chains are loaded in name order, and the first that fails fails the whole call.
+    ///
+    /// The systems share the runtime and its options; a chain needing options of its own is
+    /// loaded on a runtime of its own. Each system keeps the environment variables, libraries
+    /// and `memo` cache of its own loads, which its scripts see while it runs.
+    pub fn load_all(
+        &self,
+        mut config: Config,
+    ) -> Result<HashMap<String, FilterSystem<'_, T>>, LoadError> {
+        let mut chains: BTreeMap<_, _> = std::mem::take(&mut config.chains).into_iter().collect();
+        let defaults = chains.remove(WILDCARD_CHAIN).unwrap_or_default();
+        let mut systems = HashMap::with_capacity(chains.len());
+        for (chain, filters) in chains {
+            let filters = [defaults.clone(), filters].concat();
+            let config = Config {
+                chains: HashMap::from([(chain.clone(), filters)]),
+                ..config.clone()
+            };
+            systems.insert(chain, self.load(config)?);
+        }
+        Ok(systems)
+    }
+
+    /// Load the filters of a bundle, see [`FilterSystem::export_bundle`].
+    pub fn load_bundle(&self, bundle: FilterBundle) -> Result<FilterSystem<'_, T>, LoadError> {
+        let mut system = FilterSystem::new(&self.runtime);
+        system.load_bundle(bundle)?;
+        Ok(system)
+    }
+
+    /// Load a filter configuration, reading its files without blocking the async runtime, see
+    /// [`FilterSystem::load_async`].
+    #[cfg(feature = "tokio")]
+    pub async fn load_async(&self, config: Config) -> Result<FilterSystem<'_, T>, LoadError> {
+        let mut system = FilterSystem::new(&self.runtime);
+        system.load_async(config).await?;
+        Ok(system)
+    }
+
+    /// Tune the garbage collector of the runtime.
+    ///
+    /// Fails for [`GcMode::Generational`] unless the runtime is Lua 5.4.
+    pub fn gc_config(&self, config: GcConfig) -> Result<(), mlua::Error> {
+        gc::configure(&self.runtime, config)
+    }
+
+    /// The memory the runtime currently uses, in bytes.
+    pub fn used_memory(&self) -> usize {
+        self.runtime.used_memory()
+    }
+
+    /// Seed `math.random`, the only source of randomness of scripts, so runs with the same
+    /// seed and inputs give the same verdicts, without the rest of deterministic mode.
+    ///
+    /// Deterministic mode seeds it with [`RuntimeOptions::random_seed`]; this overrides it.
+    pub fn seed_rng(&self, seed: u64) -> Result<(), mlua::Error> {
+        rng::seed(&self.runtime, seed)
+    }
+
+    /// Reseed `math.random` at the start of every batch, such as a
+    /// [`filter`](FilterSystem::filter) call, or not. Off by default.
+    ///
+    /// The seed of each batch is derived from the one given to [`seed_rng`](Self::seed_rng),
+    /// 0 if none was, and from how many batches were reseeded since, so a batch gets the same
+    /// random numbers whatever its filters drew from the previous ones.
+    pub fn reseed_per_batch(&self, enabled: bool) -> Result<(), mlua::Error> {
+        rng::reseed_per_batch(&self.runtime, enabled)
+    }
+
+    /// Expose a Rust function to every script, as the global `name`.
+    ///
+    /// A dotted name such as `host.is_agent` puts the function in a namespace table, creating
+    /// it if needed. Scripts look globals up when they run, so functions registered after
+    /// [`load`](Self::load) are visible to the filters already loaded.
+    ///
+    /// The function runs on the Lua thread, in the middle of a filter call, and must be
+    /// `'static`: share embedder data with it through an `Arc` rather than a borrow. It shouldn't
+    /// call back into the filter system that is evaluating, and errors it returns are raised in
+    /// the calling script like any other Lua error.
+    ///
+    /// ```
+    /// use std::{collections::HashSet, sync::Arc};
+    ///
+    /// use croncat_indexer_filter::FilterRuntime;
+    ///
+    /// #[derive(Clone, serde::Serialize)]
+    /// struct Tx {
+    ///     from: String,
+    /// }
+    /// impl mlua::UserData for Tx {}
+    ///
+    /// let agents: Arc<HashSet<String>> = Arc::new(["juno1agent".to_string()].into());
+    /// let runtime = FilterRuntime::<Tx>::new();
+    /// runtime
+    ///     .register_function("host.is_agent", move |_, address: String| {
+    ///         Ok(agents.contains(&address))
+    ///     })
+    ///     .unwrap();
+    /// ```
+    pub fn register_function<'lua, A, R, F>(&'lua self, name: &str, f: F) -> Result<(), mlua::Error>
+    where
+        A: mlua::FromLuaMulti<'lua>,
+        R: mlua::IntoLuaMulti<'lua>,
+        F: Fn(&'lua Lua, A) -> mlua::Result<R> + Send + 'static,
+    {
+        let function = self.runtime.create_function(f)?;
+        let (namespace, name) = match name.rsplit_once('.') {
+            Some((namespace, name)) => (Some(namespace), name),
+            None => (None, name),
+        };
+        let mut table = self.runtime.globals();
+        for segment in namespace
+            .into_iter()
+            .flat_map(|namespace| namespace.split('.'))
+        {
+            table = match table.get::<_, mlua::Value>(segment)? {
+                mlua::Value::Table(namespace) => namespace,
+                mlua::Value::Nil => {
+                    let namespace = self.runtime.create_table()?;
+                    table.set(segment, namespace.clone())?;
+                    namespace
+                }
+                _ => {
+                    return Err(mlua::Error::runtime(format!(
+                        "register_function: `{segment}` is not a table"
+                    )))
+                }
+            };
+        }
+        table.set(name, function)
+    }
+}
+
+impl<T> Default for FilterRuntime<T>
+where
+    T: LuaUserData + Serialize + Send + Sync,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// How a filter system reacts when a filter raises an error.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Abort the whole call with the first error.
+    #[default]
+    FailFast,
+    /// Treat the failing filter as not matching and keep going.
+    Lenient,
+}
+
+/// How filtered values are handed to the filter functions.
+///
+/// Whatever the mode, a value is converted once and the same Lua value is passed to every
+/// filter evaluating it, so changes a filter makes to it are seen by the filters after it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ValuePassing {
+    /// Convert the whole value into a Lua table through its `Serialize` implementation.
+    #[default]
+    SerdeTable,
+    /// Pass a reference to the value as userdata, so only the fields a script reads through its
+    /// `UserData` implementation are converted.
+    ///
+    /// Scripts can't iterate such values with `pairs`, and the reference is only valid for the
+    /// duration of the call: keeping it around in a global and using it later raises an error.
+    /// Methods and setters taking the value mutably raise an error too. Each value gets a
+    /// metatable of its own, so this pays off for values with few fields read out of many.
+    UserData,
+    /// Like [`SerdeTable`](Self::SerdeTable), but fill the fields of struct and map values into
+    /// a single table the runtime reuses, instead of allocating one per value.
+    ///
+    /// The table is cleared and refilled for the next value, so scripts must not keep a
+    /// reference to the value itself around; copy the fields they need instead. Nested tables
+    /// aren't reused and can be kept.
+    ScratchTable,
+    /// Pass a proxy converting the fields a script reads from the `Serialize` output of the
+    /// value, one at a time, when it reads them.
+    ///
+    /// `pairs` over the proxy converts the whole value. The proxy borrows the value while it is
+    /// evaluated: fields not read by then raise an error afterwards.
+    Lazy,
+}
+
+/// A Lua dialect, backed by the cargo feature of the same name.
+///
+/// Only one is compiled into a given build.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LuaVersion {
+    /// Lua 5.1, as implemented by LuaJIT (`luajit`).
+    Lua51,
+    /// Lua 5.4 (`lua54`).
+    Lua54,
+    /// Luau (`luau`).
+    Luau,
+}
+
+impl LuaVersion {
+    /// The dialect this build was compiled with.
+    pub const fn compiled() -> Self {
+        #[cfg(feature = "luajit")]
+        return LuaVersion::Lua51;
+        #[cfg(feature = "lua54")]
+        return LuaVersion::Lua54;
+        #[cfg(feature = "luau")]
+        return LuaVersion::Luau;
+    }
+}
+
+/// Progress of a chunked filtering call, reported after each chunk.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChunkProgress {
+    /// Number of values evaluated so far.
+    pub processed: usize,
+    /// Number of values that matched so far.
+    pub kept: usize,
+    /// Time since the call started.
+    pub elapsed: Duration,
+    /// Number of filter errors absorbed by the lenient error policy so far.
+    pub errors: usize,
+}
+
+/// The outcome of a [`FilterSystem::filter_with_deadline`] call.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PartialResult<'a, T> {
+    /// The values that matched, in order.
+    pub kept: Vec<&'a T>,
+    /// The position of the first value that wasn't fully evaluated, or the number of values
+    /// if they all were.
+    pub stopped_at: usize,
+    /// Whether the deadline stopped the call before every value was evaluated.
+    pub deadline_hit: bool,
+}
+
+/// The outcome of a [`FilterSystem::filter_streaming`] call.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StreamSummary {
+    /// Number of values evaluated.
+    pub processed: usize,
+    /// Number of values that matched and were handed to the sink.
+    pub kept: usize,
+    /// Number of filter errors absorbed by the lenient error policy.
+    pub errors: usize,
+}
+
+/// The detailed outcome of filtering a single value.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Verdict {
+    /// Whether any filter matched the value, or for a chain with an expression, whether it
+    /// held, see [`Config::expressions`].
+    pub matched: bool,
+    /// The ids of the filters that matched, see [`FilterId`].
+    pub matched_by: Vec<String>,
+    /// What the filters printed, one entry per `print` call, prefixed with the filter name.
+    pub debug_output: Vec<String>,
+    /// The reasons filters gave for their verdicts, as `(filter id, reason)` pairs.
+    ///
+    /// A filter gives a reason by returning a string after its verdict:
+    /// `return false, "sender not allowlisted"`.
+    pub reasons: Vec<(String, String)>,
+    /// The ids of the filters whose fuel ran out, during this evaluation or earlier in the
+    /// batch, so they didn't give a verdict.
+    pub budget_exhausted: Vec<String>,
+    /// The verdicts of the dry-run filters, as `(filter id, matched)` pairs, see
+    /// [`Filter::with_dry_run`]. They are left out of `matched` and `matched_by`.
+    pub dry_run: Vec<(String, bool)>,
+    /// Whether the value would have matched if the verdicts of dry-run filters counted.
+    pub would_match: bool,
+    /// What the matching filters returned after their verdict, other than reasons and `nil`,
+    /// as `(filter id, output)` pairs, see [`FilterSystem::filter_transform`].
+    pub outputs: Vec<(String, serde_json::Value)>,
 }
 
 /// A Lua runtime to filter incoming values
-pub struct FilterSystem<'lua, T> {
+///
+/// The scripts run on the runtime, unless the system compiles them on a backend, see
+/// [`with_backend`](Self::with_backend); `FilterSystem<'lua, T>` is the system running them
+/// on the runtime.
+pub struct FilterSystem<'lua, T, B = LuaBackend> {
     runtime: &'lua Lua,
+    /// The backend compiling the scripts, shared with the filters it compiled.
+    backend: Option<Rc<RefCell<B>>>,
     filters: Vec<Filter<'lua, T>>,
+    error_policy: ErrorPolicy,
+    gc_after_batch: GcAfterBatch,
+    gc_runs: Cell<u64>,
+    interrupt: OnceCell<Arc<AtomicBool>>,
+    /// The reload requested through reload handles, once there is one.
+    #[cfg(feature = "tokio")]
+    reloads: OnceCell<Arc<std::sync::Mutex<reload::Queue>>>,
+    call_timeout: Option<Duration>,
+    call_started: Rc<Cell<Instant>>,
+    /// The fuel counter, when fuel is metered.
+    fuel: Option<Fuel>,
+    batch_fuel: Option<u64>,
+    /// The fuel burnt in the current batch.
+    batch_fuel_burnt: Cell<u64>,
+    measure_memory: bool,
+    /// The profiler, while profiling.
+    profiler: Option<Profiler>,
+    profile_interval: u32,
+    partial_load: bool,
+    strict_returns: bool,
+    quarantine: Option<Quarantine>,
+    observer: Option<Box<dyn FilterObserver>>,
+    /// Gives the context of each batch, see [`set_context_provider`](Self::set_context_provider).
+    context_provider: Option<ContextProvider<'lua>>,
+    /// The context the provider gave for the batch under way.
+    batch_context: RefCell<mlua::Value<'lua>>,
+    verdict_cache: Option<RefCell<VerdictCache>>,
+    cache_key: Option<CacheKey<T>>,
+    audit_sink: Option<RefCell<Box<dyn AuditSink>>>,
+    lint: bool,
+    strict_lint: bool,
+    /// The findings of the lint pass not taken yet.
+    lint_reports: Vec<LintReport>,
+    /// How the verdicts of the filters of each chain combine, see [`Config::expressions`].
+    expressions: HashMap<String, Expression>,
+    /// The libraries and constants loaded, for bundles.
+    loaded: bundle::Loaded,
+    /// The libraries evaluated, exposed to scripts as globals while the system runs.
+    libraries: BTreeMap<String, mlua::Value<'lua>>,
+    /// The environment variables exposed to scripts, in name order.
+    expose_env: Vec<String>,
+    memo: helpers::memo::Memo,
+    /// The keys scripts must be signed with, from the last configuration loaded.
+    trusted_keys: Vec<ed25519_dalek::VerifyingKey>,
+    load_report: LoadReport,
+    /// How many times the observer panicked.
+    observer_panics: Cell<u64>,
+    chain_tallies: RefCell<BTreeMap<String, ChainTally>>,
+    /// When a value was last filtered without error.
+    last_success: Cell<Option<Instant>>,
+    last_load: Option<LoadOutcome>,
+    health_thresholds: HealthThresholds,
 }
 
 impl<'lua, T> FilterSystem<'lua, T>
 where
-    T: LuaUserData + Serialize + Clone + Send + Sync + 'lua,
+    T: LuaUserData + Serialize + Send + Sync + 'lua,
 {
     /// Create a new filter system.
     pub fn new(runtime: &'lua Lua) -> Self {
+        Self::with(runtime, None)
+    }
+}
+
+impl<'lua, T, B> FilterSystem<'lua, T, B>
+where
+    T: LuaUserData + Serialize + Send + Sync + 'lua,
+    B: FilterBackend + 'lua,
+    B::Filter: 'lua,
+{
+    /// Create a new filter system compiling the scripts it loads on `backend`, rather than
+    /// running them on `runtime`.
+    ///
+    /// The filters the backend compiles are called like the others, with the error policy,
+    /// retries, dry runs, quarantines, expressions, verdicts and stats of the system. What
+    /// happens within a call is up to the backend: fuel, timeouts, memory limits, sandboxing,
+    /// `chain` constants and linting are features of the Lua runtime, and the backend gets the
+    /// [`FilterConfig`] of each script to apply the limits it supports. Libraries are loaded on
+    /// the backend too.
+    pub fn with_backend(runtime: &'lua Lua, backend: B) -> Self {
+        Self::with(runtime, Some(Rc::new(RefCell::new(backend))))
+    }
+
+    fn with(runtime: &'lua Lua, backend: Option<Rc<RefCell<B>>>) -> Self {
         Self {
             runtime,
+            backend,
             filters: Vec::new(),
+            error_policy: ErrorPolicy::default(),
+            gc_after_batch: GcAfterBatch::default(),
+            gc_runs: Cell::new(0),
+            interrupt: OnceCell::new(),
+            #[cfg(feature = "tokio")]
+            reloads: OnceCell::new(),
+            call_timeout: None,
+            call_started: Rc::new(Cell::new(Instant::now())),
+            fuel: None,
+            batch_fuel: None,
+            batch_fuel_burnt: Cell::new(0),
+            measure_memory: false,
+            profiler: None,
+            profile_interval: profile::DEFAULT_INTERVAL,
+            partial_load: false,
+            strict_returns: false,
+            quarantine: None,
+            observer: None,
+            context_provider: None,
+            batch_context: RefCell::new(mlua::Value::Nil),
+            verdict_cache: None,
+            cache_key: None,
+            audit_sink: None,
+            lint: false,
+            strict_lint: false,
+            lint_reports: Vec::new(),
+            expressions: HashMap::new(),
+            loaded: bundle::Loaded::default(),
+            libraries: BTreeMap::new(),
+            expose_env: Vec::new(),
+            memo: helpers::memo::Memo::new(runtime),
+            trusted_keys: Vec::new(),
+            load_report: LoadReport::default(),
+            observer_panics: Cell::new(0),
+            chain_tallies: RefCell::default(),
+            last_success: Cell::new(None),
+            last_load: None,
+            health_thresholds: HealthThresholds::default(),
         }
     }
 
-    /// Load a filter configuration.
-    pub fn load(&mut self, config: Config) -> Result<(), mlua::Error> {
-        for (_chain, filters) in config.chains {
-            for filter in filters {
-                let script = std::fs::read_to_string(filter.script)?;
-                let module: mlua::Table = self.runtime.load(&script).eval()?;
-                for pair in module.pairs::<String, mlua::Function>() {
-                    let (name, filter) = pair?;
-                    let filter = Filter::new(name, filter);
-                    self.filters.push(filter);
-                }
-            }
-        }
-        Ok(())
+    /// The Lua dialect the filters run in, to validate scripts against.
+    pub fn dialect(&self) -> LuaVersion {
+        LuaVersion::compiled()
     }
 
-    /// Filter a single value.
-    pub fn filter_one(&self, value: T) -> Result<bool, mlua::Error> {
-        let mut filtered = false;
+    /// The backend the scripts are compiled on, if any.
+    pub fn backend(&self) -> Option<std::cell::Ref<'_, B>> {
+        self.backend.as_ref().map(|backend| backend.borrow())
+    }
+
+    /// The backend the scripts are compiled on, if any, to configure it.
+    pub fn backend_mut(&mut self) -> Option<std::cell::RefMut<'_, B>> {
+        self.backend.as_ref().map(|backend| backend.borrow_mut())
+    }
+
+    /// Set how filter errors are handled.
+    pub fn set_error_policy(&mut self, error_policy: ErrorPolicy) {
+        self.error_policy = error_policy;
+    }
+
+    /// Quarantine filters that fail on `quarantine.after` values in a row, skipping them as
+    /// not matching until its cool-down is over or a reload replaces them; or stop, with none.
+    ///
+    /// Filters with a threshold of their own, [`FilterConfig::quarantine_after`], are
+    /// quarantined even without one for the system, until reloaded unless it has a cool-down.
+    /// The error tripping the quarantine is handled per the error policy, and the observer
+    /// told with [`FilterObserver::on_quarantine`]. Quarantined filters are taken out of
+    /// quarantine.
+    pub fn set_quarantine(&mut self, quarantine: Option<Quarantine>) {
+        self.quarantine = quarantine;
         for filter in &self.filters {
-            if filter.filter(&self.runtime, value.clone())? {
-                filtered = true
-            }
+            filter.breaker.reset();
         }
-        Ok(filtered)
+        self.clear_verdict_cache();
     }
 
-    /// Filter a list of values.
-    pub fn filter(&self, values: Vec<T>) -> Result<Vec<T>, mlua::Error> {
-        let mut result = Vec::new();
-        for tx in values {
-            if self.filter_one(tx.clone())? {
-                result.push(tx);
-            }
-        }
-        Ok(result)
+    /// A handle to abort the filter call running at the moment, from any thread.
+    ///
+    /// Once a handle exists, every evaluation runs under the instruction hook, which on LuaJIT
+    /// means without the JIT compiler. An interrupted call fails with
+    /// [`FilterError::Interrupted`]; interrupting while no filter runs has no effect.
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        let flag = self.interrupt.get_or_init(Arc::default);
+        InterruptHandle::new(flag.clone())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use indoc::indoc;
-    use serde::Serialize;
+    /// Call `provider` once at the start of each batch, passing what it gives to the filters
+    /// as their context, like [`filter_with_context`](Self::filter_with_context) does;
+    /// replaces the provider set before.
+    ///
+    /// Every batch API calls it, single values counting as batches of their own; a context
+    /// passed explicitly takes precedence. If it panics, or its context doesn't convert into
+    /// Lua, the batch fails with [`FilterError::ContextProvider`].
+    pub fn set_context_provider<C: Serialize>(
+        &mut self,
+        provider: impl Fn() -> C + Send + 'static,
+    ) {
+        self.context_provider = Some(Box::new(move |lua| convert::to_lua(lua, &provider())));
+    }
 
-    use super::*;
+    /// Stop calling the context provider, giving filters no context again.
+    pub fn clear_context_provider(&mut self) {
+        self.context_provider = None;
+        *self.batch_context.get_mut() = mlua::Value::Nil;
+    }
 
-    #[derive(Clone, Serialize, Deserialize)]
-    pub struct MockTx {
-        pub chain: String,
-        pub from: String,
-        pub to: String,
-        pub amount: u64,
+    /// Abort filter calls running longer than `timeout`, retries included.
+    ///
+    /// Calls that time out fail with [`FilterError::Timeout`]. The clock is checked from the
+    /// instruction hook, so like [`interrupt_handle`](Self::interrupt_handle) this runs every
+    /// evaluation under the hook, and a call stuck inside a Rust helper is only stopped once
+    /// the helper returns. Cancellation and interrupts are checked too; whichever trips first
+    /// ends the call.
+    pub fn set_call_timeout(&mut self, timeout: Duration) {
+        self.call_timeout = Some(timeout);
     }
-    impl mlua::UserData for MockTx {}
 
-    macro_rules! test_filter {
-        ($name:ident, $script:expr, $expected:expr) => {
-            #[test]
-            fn $name() {
-                let lua = mlua::Lua::new();
-                let module: mlua::Table = lua.load($script).eval().unwrap();
+    /// Count the fuel filters burn, one unit per Lua instruction, in
+    /// [`FilterStats::fuel`].
+    ///
+    /// Fuel is burnt from the instruction hook, so like
+    /// [`interrupt_handle`](Self::interrupt_handle) this runs every evaluation under the hook.
+    /// Filter budgets and [`set_batch_fuel`](Self::set_batch_fuel) turn it on.
+    pub fn meter_fuel(&mut self) {
+        self.fuel.get_or_insert_with(Fuel::default);
+    }
 
-                for pair in module.pairs::<String, mlua::Function>() {
-                    let (_name, filter) = pair.unwrap();
-                    let filter = Filter::new("$name".to_string(), filter);
-                    let tx = MockTx {
-                        chain: "uni-5".to_string(),
-                        from: "0xDEADBEEF".to_string(),
-                        to: "0xBEEFFEEF".to_string(),
-                        amount: 0,
-                    };
-                    let result = filter.filter(&lua, tx).unwrap();
-                    assert_eq!(result, $expected);
-                }
-            }
-        };
+    /// Set how much fuel all filters together may burn per batch, such as a
+    /// [`filter`](Self::filter) call.
+    ///
+    /// Once it is burnt, the call running is aborted and every filter is skipped for the
+    /// rest of the batch, as if each had exhausted its own budget.
+    pub fn set_batch_fuel(&mut self, fuel: u64) {
+        self.batch_fuel = Some(fuel);
+        self.meter_fuel();
     }
 
-    #[test]
-    fn config() {
-        let input = indoc! {r#"
-        chains:
-            uni-5:
-                - name: Testnet Manager
-                  script: filters/test-filter.lua
-        "#};
+    /// Stop the collector during filter calls, so [`FilterStats::peak_delta_bytes`] counts
+    /// everything a call allocates rather than what survived collections.
+    ///
+    /// Off by default: memory a call discards is only freed after it, so a call making a lot
+    /// of garbage holds all of it at once. Loading a filter with a
+    /// [`max_call_memory`](FilterConfig::max_call_memory) turns it on.
+    pub fn measure_memory(&mut self, enabled: bool) {
+        self.measure_memory = enabled;
+    }
+
+    /// Sample which line of which script runs during filter calls, for
+    /// [`take_profile`](Self::take_profile).
+    ///
+    /// Off by default, and free then. Samples are taken from the instruction hook, every
+    /// [`set_profile_interval`](Self::set_profile_interval) instructions, so like
+    /// [`interrupt_handle`](Self::interrupt_handle) this runs every evaluation under the hook,
+    /// with LuaJIT's compiler off; the hook itself costs more the shorter the interval. Under
+    /// Luau, a sample is taken at every function call and loop iteration instead.
+    ///
+    /// Turning profiling off forgets the samples not taken yet.
+    pub fn profile(&mut self, enabled: bool) {
+        match (enabled, &self.profiler) {
+            (true, None) => self.profiler = Some(Profiler::new(self.profile_interval)),
+            (false, _) => self.profiler = None,
+            (true, Some(_)) => {}
+        }
+    }
+
+    /// Lint the scripts loaded from now on, or not. Off by default.
+    ///
+    /// Findings are kept, for scripts that have any, until taken with
+    /// [`take_lint_reports`](Self::take_lint_reports).
+    pub fn lint(&mut self, enabled: bool) {
+        self.lint = enabled;
+    }
+
+    /// Fail the load of scripts with lint findings, or not. Off by default; turning it on
+    /// turns linting on.
+    pub fn strict_lint(&mut self, enabled: bool) {
+        self.strict_lint = enabled;
+        self.lint |= enabled;
+    }
+
+    /// The findings of the lint pass since this was last called, one report per script with
+    /// findings, in load order.
+    pub fn take_lint_reports(&mut self) -> Vec<LintReport> {
+        std::mem::take(&mut self.lint_reports)
+    }
+
+    /// Keep the filters that loaded when others fail to, in
+    /// [`load_collecting`](Self::load_collecting). Off by default.
+    pub fn partial_load(&mut self, enabled: bool) {
+        self.partial_load = enabled;
+    }
+
+    /// Fail filter calls whose verdict isn't a boolean with [`FilterError::InvalidReturn`], or
+    /// not. Off by default: verdicts then go by Lua truthiness, `nil` and `false` rejecting
+    /// the value and anything else matching it, and the filter's
+    /// [`coerced_returns`](FilterStats::coerced_returns) counts the verdicts that weren't
+    /// booleans.
+    pub fn strict_returns(&mut self, enabled: bool) {
+        self.strict_returns = enabled;
+    }
+
+    /// Call `observer` with the verdict of every filter call from now on, replacing the
+    /// observer set before, see [`FilterObserver`].
+    pub fn set_observer(&mut self, observer: impl FilterObserver + 'static) {
+        self.observer = Some(Box::new(observer));
+    }
+
+    /// Stop calling the observer set with [`set_observer`](Self::set_observer), returning it.
+    pub fn take_observer(&mut self) -> Option<Box<dyn FilterObserver>> {
+        self.observer.take()
+    }
+
+    /// How many times the observer panicked, which filtering ignores.
+    pub fn observer_panics(&self) -> u64 {
+        self.observer_panics.get()
+    }
+
+    /// Tell the observer, if any, how the call of `filter` went.
+    fn observe<V>(&self, filter: &Filter<'lua, T>, result: &Result<(bool, V), FilterError>) {
+        let Some(observer) = &self.observer else {
+            return;
+        };
+        let chain = filter.chain.as_deref().unwrap_or_default();
+        let id = filter.id().to_string();
+        let observe = AssertUnwindSafe(|| match result {
+            Ok((true, _)) => observer.on_match(&id, chain),
+            Ok((false, _)) => observer.on_reject(&id, chain),
+            Err(err) => observer.on_error(&id, chain, err),
+        });
+        if std::panic::catch_unwind(observe).is_err() {
+            self.observer_panics.set(self.observer_panics.get() + 1);
+        }
+    }
+
+    /// Count a call of `filter` that failed or not towards its quarantine, telling the
+    /// observer, if any, when it quarantines the filter.
+    fn count_towards_quarantine(&self, filter: &Filter<'lua, T>, failed: bool) {
+        let after = filter.quarantine_after;
+        let Some(after) = after.or(self.quarantine.map(|quarantine| quarantine.after)) else {
+            return;
+        };
+        let Some(errors) = filter.breaker.record(failed, after) else {
+            return;
+        };
+        filter.stats.borrow_mut().quarantines += 1;
+        // Cached verdicts may be the filter's.
+        self.clear_verdict_cache();
+        let Some(observer) = &self.observer else {
+            return;
+        };
+        let chain = filter.chain.as_deref().unwrap_or_default();
+        let id = filter.id().to_string();
+        let observe = AssertUnwindSafe(|| observer.on_quarantine(&id, chain, errors));
+        if std::panic::catch_unwind(observe).is_err() {
+            self.observer_panics.set(self.observer_panics.get() + 1);
+        }
+    }
+
+    /// Set how many instructions run between two profiling samples, 100 by default.
+    ///
+    /// Samples taken at the previous interval are forgotten.
+    pub fn set_profile_interval(&mut self, instructions: u32) {
+        self.profile_interval = instructions.max(1);
+        if self.profiler.is_some() {
+            self.profiler = Some(Profiler::new(self.profile_interval));
+        }
+    }
+
+    /// The samples taken since profiling was turned on or this was last called, which are
+    /// then forgotten. Empty if profiling is off.
+    pub fn take_profile(&self) -> ProfileReport {
+        match &self.profiler {
+            Some(profiler) => profiler.take(),
+            None => ProfileReport {
+                interval: self.profile_interval,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Set what the collector does after each batch, such as a [`filter`](Self::filter) call.
+    ///
+    /// Single-value calls like [`filter_one`](Self::filter_one) never trigger a collection.
+    pub fn set_gc_after_batch(&mut self, gc_after_batch: GcAfterBatch) {
+        self.gc_after_batch = gc_after_batch;
+    }
+
+    /// How many collections ran after batches so far.
+    pub fn gc_count(&self) -> u64 {
+        self.gc_runs.get()
+    }
+
+    /// The memory the runtime currently uses, in bytes.
+    pub fn used_memory(&self) -> usize {
+        self.runtime.used_memory()
+    }
+
+    /// How the filter system is doing, for readiness probes: what is loaded, the memory in
+    /// use, when a value was last filtered successfully and how the last load went, with an
+    /// overall status derived from the [thresholds](Self::set_health_thresholds).
+    ///
+    /// Only reads counters kept as filtering goes, so it can be called every few seconds.
+    /// Filters are disabled while their fuel budget, or the batch's, is burnt.
+    pub fn health(&self) -> Health {
+        Health {
+            status: Status::Healthy,
+            problems: Vec::new(),
+            filters: self.filters.len(),
+            disabled_filters: self
+                .filters
+                .iter()
+                .filter(|filter| self.out_of_fuel(filter))
+                .count(),
+            quarantined_filters: self
+                .filters
+                .iter()
+                .filter(|filter| filter.breaker.is_open())
+                .count(),
+            used_memory: self.runtime.used_memory(),
+            memory_limit: None,
+            since_last_success: self.last_success.get().map(|at| at.elapsed()),
+            last_load: self.last_load.clone(),
+            poisoned: panic::is_poisoned(self.runtime),
+        }
+        .assess(&self.health_thresholds)
+    }
+
+    /// Set when [`health`](Self::health) reports the system as degraded or unhealthy.
+    pub fn set_health_thresholds(&mut self, thresholds: HealthThresholds) {
+        self.health_thresholds = thresholds;
+    }
+
+    /// Load a filter configuration.
+    pub fn load(&mut self, config: Config) -> Result<(), LoadError> {
+        let mut load = || {
+            config.validate()?;
+            limits::check_config(self.runtime, &config)?;
+            let libraries = config
+                .libraries
+                .values()
+                .map(|path| encoding::read_to_string(path));
+            let scripts = self.prepare(&config.script_paths());
+            let mut report = LoadReport::default();
+            self.load_read(&config, libraries, scripts, &mut report, false)?;
+            self.set_load_report(report);
+            Ok(())
+        };
+        let result = load();
+        self.last_load = Some(LoadOutcome::of(&result));
+        result
+    }
+
+    /// What the last successful load registered: the filters of each chain, with the
+    /// functions of their scripts and how the scripts were compiled.
+    ///
+    /// Covers the last call to [`load`](Self::load), [`reload`](Self::reload),
+    /// [`load_chain`](Self::load_chain) or the like that succeeded; its failures are left out
+    /// for [`load_collecting`](Self::load_collecting), which returns them.
+    pub fn load_report(&self) -> &LoadReport {
+        &self.load_report
+    }
+
+    /// A snapshot of the filters loaded from configurations, with the sources of their scripts
+    /// and libraries, to load elsewhere with [`FilterRuntime::load_bundle`].
+    ///
+    /// The bundle reflects the filters as they are, removals and dry-run switches included.
+    /// Filters added by hand rather than loaded aren't part of it.
+    pub fn export_bundle(&self) -> FilterBundle {
+        let mut scripts: Vec<(&Rc<ScriptSource>, BundledScript)> = Vec::new();
+        for filter in &self.filters {
+            let (Some(source), Some(chain)) = (&filter.source, &filter.chain) else {
+                continue;
+            };
+            let index = match scripts
+                .iter()
+                .position(|(known, _)| Rc::ptr_eq(known, source))
+            {
+                Some(index) => index,
+                None => {
+                    scripts.push((source, BundledScript::new(chain, source)));
+                    scripts.len() - 1
+                }
+            };
+            scripts[index].1.functions.push(BundledFunction {
+                name: filter.name.clone(),
+                dry_run: filter.dry_run,
+            });
+        }
+        let scripts: Vec<BundledScript> = scripts.into_iter().map(|(_, script)| script).collect();
+        let loaded = |chain: &String| scripts.iter().any(|script| script.chain == *chain);
+        let constants = self.loaded.constants.iter();
+        let expressions = self.expressions.iter();
+        FilterBundle {
+            version: BUNDLE_VERSION,
+            libraries: self.loaded.libraries.values().cloned().collect(),
+            constants: constants
+                .filter(|(chain, _)| loaded(chain))
+                .map(|(chain, constants)| (chain.clone(), constants.clone()))
+                .collect(),
+            expose_env: self.expose_env.clone(),
+            expressions: expressions
+                .filter(|(chain, _)| loaded(chain))
+                .map(|(chain, expression)| (chain.clone(), expression.to_string()))
+                .collect(),
+            trusted_keys: signature::encode(&self.trusted_keys),
+            scripts,
+        }
+    }
+
+    /// Load the filters of a bundle, like the configuration it was exported from but without
+    /// reading any file.
+    pub fn load_bundle(&mut self, bundle: FilterBundle) -> Result<(), LoadError> {
+        let load = || {
+            let bundle::Unbundled {
+                config,
+                libraries,
+                scripts,
+                functions,
+            } = bundle.unbundle()?;
+            config.validate()?;
+            limits::check_config(self.runtime, &config)?;
+            let scripts = scripts.into_iter().map(|source| precompile::Script {
+                source: Ok(source.into_bytes()),
+                read_time: Duration::ZERO,
+                bytecode: None,
+            });
+            let mut report = LoadReport::default();
+            let from = self.filters.len();
+            self.load_read(
+                &config,
+                libraries.into_iter().map(Ok),
+                scripts,
+                &mut report,
+                false,
+            )?;
+            // Only the functions of each script that were loaded when exporting are kept.
+            let mut loaded = self.filters.split_off(from).into_iter();
+            for (script, kept) in report.loaded.iter_mut().zip(functions) {
+                for mut filter in loaded.by_ref().take(script.functions.len()) {
+                    let function = kept.iter().find(|function| function.name == filter.name);
+                    if let Some(function) = function {
+                        filter.dry_run = function.dry_run;
+                        self.filters.push(filter);
+                    }
+                }
+                let kept = |name: &String| kept.iter().any(|function| function.name == *name);
+                script.functions.retain(kept);
+            }
+            self.set_load_report(report);
+            Ok(())
+        };
+        let result = load();
+        self.last_load = Some(LoadOutcome::of(&result));
+        result
+    }
+
+    /// Record what a load registered, its chains starting their counters since load over.
+    fn set_load_report(&mut self, report: LoadReport) {
+        let tallies = self.chain_tallies.get_mut();
+        for loaded in &report.loaded {
+            tallies.entry(loaded.chain.clone()).or_default().loaded();
+        }
+        self.load_report = report;
+        self.clear_verdict_cache();
+    }
+
+    /// Load a filter configuration, trying every library and script even once some failed.
+    ///
+    /// Returns a report of what loaded and what failed, as an error if anything failed. The
+    /// filters are then only loaded under [`partial_load`](Self::partial_load), and none of
+    /// them otherwise. A configuration that is invalid, or over the load limits, fails before
+    /// anything is tried.
+    pub fn load_collecting(&mut self, config: Config) -> Result<LoadReport, LoadReport> {
+        let mut report = LoadReport::default();
+        let checked = config
+            .validate()
+            .and_then(|()| limits::check_config(self.runtime, &config));
+        if let Err(error) = checked {
+            report.failures.push(LoadFailure {
+                chain: None,
+                name: None,
+                script: None,
+                error: error.into(),
+            });
+            self.last_load = Some(LoadOutcome::of(&Err::<(), _>(&report)));
+            return Err(report);
+        }
+        let libraries = config
+            .libraries
+            .values()
+            .map(|path| encoding::read_to_string(path));
+        let scripts = self.prepare(&config.script_paths());
+        let before = self.filters.len();
+        if let Err(error) = self.load_read(&config, libraries, scripts, &mut report, true) {
+            report.failures.push(LoadFailure {
+                chain: None,
+                name: None,
+                script: None,
+                error,
+            });
+        }
+        if report.failures.is_empty() || self.partial_load {
+            self.set_load_report(LoadReport {
+                loaded: report.loaded.clone(),
+                failures: Vec::new(),
+            });
+            self.last_load = Some(LoadOutcome::of(&Ok::<_, &LoadReport>(())));
+            return Ok(report);
+        }
+        self.filters.truncate(before);
+        self.release();
+        self.last_load = Some(LoadOutcome::of(&Err::<(), _>(&report)));
+        Err(report)
+    }
+
+    /// Load a filter configuration, reading its files without blocking the async runtime.
+    ///
+    /// Only available with the `tokio` feature. Loading fails the same way and yields the same
+    /// filters as [`load`](Self::load). Only the files are read asynchronously: the Lua runtime
+    /// isn't `Send`, so the scripts are evaluated on the task awaiting this, which should be the
+    /// one the runtime lives on.
+    #[cfg(feature = "tokio")]
+    pub async fn load_async(&mut self, config: Config) -> Result<(), LoadError> {
+        let load = async {
+            config.validate()?;
+            limits::check_config(self.runtime, &config)?;
+            let libraries: Vec<PathBuf> = config.libraries.values().cloned().collect();
+            let libraries = async_load::read_all(&libraries).await;
+            let libraries = libraries
+                .into_iter()
+                .map(|(source, _)| source.and_then(encoding::decode));
+            let scripts = async_load::read_all(&config.script_paths()).await;
+            let scripts = scripts
+                .into_iter()
+                .map(|(source, read_time)| precompile::Script {
+                    source,
+                    read_time,
+                    bytecode: None,
+                });
+            let mut report = LoadReport::default();
+            self.load_read(&config, libraries, scripts, &mut report, false)?;
+            self.set_load_report(report);
+            Ok(())
+        };
+        let result = load.await;
+        self.last_load = Some(LoadOutcome::of(&result));
+        result
+    }
+
+    /// Load a configuration from the contents of its libraries and scripts, in order,
+    /// recording the filters that loaded in `report`.
+    ///
+    /// With `collect`, every library and script is tried, see
+    /// [`load_filters`](Self::load_filters).
+    fn load_read(
+        &mut self,
+        config: &Config,
+        libraries: impl IntoIterator<Item = std::io::Result<String>>,
+        scripts: impl IntoIterator<Item = precompile::Script>,
+        report: &mut LoadReport,
+        collect: bool,
+    ) -> Result<(), LoadError> {
+        let _span = trace::load(config.chains.len(), config.libraries.len());
+        self.expose_env.extend(config.expose_env.iter().cloned());
+        self.expose_env.sort();
+        self.expose_env.dedup();
+        self.expose()?;
+        self.trusted_keys = signature::trusted_keys(config)?;
+        for ((name, path), source) in config.libraries.iter().zip(libraries) {
+            let library = source.as_ref().ok().map(|source| BundledLibrary {
+                name: name.clone(),
+                path: path.clone(),
+                source: source.clone(),
+            });
+            match (self.load_library(name, path, source), collect) {
+                (Ok(()), _) => {
+                    if let Some(library) = library {
+                        self.loaded.libraries.insert(name.clone(), library);
+                    }
+                }
+                (Err(error), true) => report.failures.push(LoadFailure {
+                    chain: None,
+                    name: Some(name.clone()),
+                    script: Some(path.clone()),
+                    error,
+                }),
+                (Err(error), false) => return Err(error),
+            }
+        }
+        let mut scripts = scripts.into_iter();
+        for (chain, filters) in &config.chains {
+            let constants = config.constants.get(chain);
+            if let Some(constants) = constants {
+                let loaded = &mut self.loaded.constants;
+                loaded.insert(chain.clone(), constants.clone());
+            }
+            let filters: Vec<FilterConfig> = filters
+                .iter()
+                .map(|filter| FilterConfig {
+                    language: config.language(chain, filter),
+                    ..filter.clone()
+                })
+                .collect();
+            let loaded =
+                self.load_filters(chain, &filters, constants, &mut scripts, report, collect)?;
+            if let Some(expression) = config.expressions.get(chain) {
+                let expression =
+                    Expression::parse(expression).map_err(|err| ConfigError::Expression {
+                        chain: chain.clone(),
+                        column: err.column,
+                        message: err.message,
+                    })?;
+                let filters = loaded.iter().chain(&self.filters);
+                let names: Vec<&str> = filters
+                    .filter(|filter| filter.chain.as_deref() == Some(chain))
+                    .map(|filter| filter.name.as_str())
+                    .collect();
+                match (known_filters(chain, &expression, &names), collect) {
+                    (Ok(()), _) => {
+                        self.expressions.insert(chain.clone(), expression);
+                    }
+                    // The chain isn't loaded without its expression.
+                    (Err(error), true) => {
+                        report.loaded.retain(|loaded| loaded.chain != *chain);
+                        report.failures.push(LoadFailure {
+                            chain: Some(chain.clone()),
+                            name: None,
+                            script: None,
+                            error,
+                        });
+                        continue;
+                    }
+                    (Err(error), false) => return Err(error),
+                }
+            }
+            self.filters.extend(loaded);
+        }
+        Ok(())
+    }
+
+    /// Evaluate the library at `path`, read as `source`, into the global `name`, or load it on
+    /// the backend.
+    fn load_library(
+        &mut self,
+        name: &str,
+        path: &Path,
+        source: std::io::Result<String>,
+    ) -> Result<(), LoadError> {
+        let origin = || {
+            Box::new(LoadOrigin::Library {
+                name: name.to_string(),
+                path: path.to_path_buf(),
+            })
+        };
+        let source = source.map_err(|error| LoadError::read(origin(), error))?;
+        let started = Instant::now();
+        if let Some(backend) = &self.backend {
+            let mut backend = backend.borrow_mut();
+            backend
+                .load_library(name, path, source.as_bytes())
+                .map_err(|error| backend::load_error(origin(), error))?;
+            trace::library_loaded(name, path, started.elapsed());
+            return Ok(());
+        }
+        let library = self
+            .runtime
+            .load(&source)
+            .set_name(precompile::chunk_name(path))
+            .eval::<mlua::Value>()
+            .map_err(|error| LoadError::Lua {
+                origin: origin(),
+                error,
+            })?;
+        self.runtime.globals().set(name, library.clone())?;
+        self.libraries.insert(name.to_string(), library);
+        trace::library_loaded(name, path, started.elapsed());
+        Ok(())
+    }
+
+    /// Load the filters of `chain`, whose scripts come out of `scripts` in order, recording
+    /// them in `report`.
+    ///
+    /// With `collect`, every script is tried and the failures recorded in `report` too;
+    /// without, loading stops at the first failure.
+    fn load_filters(
+        &mut self,
+        chain: &str,
+        filters: &[FilterConfig],
+        constants: Option<&serde_yaml::Value>,
+        scripts: &mut impl Iterator<Item = precompile::Script>,
+        report: &mut LoadReport,
+        collect: bool,
+    ) -> Result<Vec<Filter<'lua, T>>, LoadError> {
+        let _span = trace::load_chain(chain, filters.len());
+        let environment = match (self.chain_environment(constants), collect) {
+            (Ok(environment), _) => environment,
+            (Err(error), true) => {
+                report.failures.push(LoadFailure {
+                    chain: Some(chain.to_string()),
+                    name: None,
+                    script: None,
+                    error,
+                });
+                scripts.take(filters.len()).for_each(drop);
+                return Ok(Vec::new());
+            }
+            (Err(error), false) => return Err(error),
+        };
+        let mut loaded = Vec::new();
+        for filter in filters {
+            let script = scripts
+                .next()
+                .expect("a script is prepared for every filter");
+            let result = match (self.backend.clone(), filter.language) {
+                (Some(backend), _) => self.compile_filter(&backend, chain, filter, script),
+                (None, Some(language)) if language != ScriptLanguage::Lua => {
+                    Err(LoadError::Language {
+                        origin: Box::new(LoadOrigin::Script {
+                            chain: chain.to_string(),
+                            path: filter.script.clone(),
+                        }),
+                        language,
+                    })
+                }
+                (None, _) => self.load_filter(chain, filter, script, &environment),
+            };
+            match (result, collect) {
+                (Ok((filters, loaded_filter)), _) => {
+                    report.loaded.push(loaded_filter);
+                    loaded.extend(filters);
+                }
+                (Err(error), true) => report.failures.push(LoadFailure {
+                    chain: Some(chain.to_string()),
+                    name: Some(filter.name.clone()),
+                    script: Some(filter.script.clone()),
+                    error,
+                }),
+                (Err(error), false) => return Err(error),
+            }
+        }
+        Ok(loaded)
+    }
+
+    /// The `chain` constants of a chain, and the environment its scripts share if they do.
+    fn chain_environment(
+        &self,
+        constants: Option<&serde_yaml::Value>,
+    ) -> Result<ChainEnvironment<'lua>, LoadError> {
+        let sandboxed = self.runtime.app_data_ref::<Sandboxed>().is_some();
+        let constants = match constants {
+            Some(constants) => {
+                let constants = convert::to_lua(self.runtime, constants)?;
+                Some(frozen::freeze(self.runtime, "chain", constants)?)
+            }
+            None => None,
+        };
+        let shared = match (&constants, sandboxed) {
+            (Some(constants), false) => Some(self.environment(constants, false)?),
+            _ => None,
+        };
+        Ok(ChainEnvironment {
+            sandboxed,
+            constants,
+            shared,
+        })
+    }
+
+    /// Evaluate the script of `filter` and load the functions it returns, describing them
+    /// for the load report.
+    fn load_filter(
+        &mut self,
+        chain: &str,
+        filter: &FilterConfig,
+        script: precompile::Script,
+        environment: &ChainEnvironment<'lua>,
+    ) -> Result<(Vec<Filter<'lua, T>>, LoadedFilter), LoadError> {
+        let ChainEnvironment {
+            sandboxed,
+            constants,
+            shared,
+        } = environment;
+        let script_path = &filter.script;
+        let filter_name = filter.name.clone();
+        let retry_policy = filter.retry_policy();
+        let dry_run = filter.dry_run;
+        let fuel_budget = filter.fuel_budget;
+        if fuel_budget.is_some() {
+            self.meter_fuel();
+        }
+        let max_call_memory = filter.max_call_memory;
+        if max_call_memory.is_some() {
+            self.measure_memory(true);
+        }
+        let quarantine_after = filter.quarantine_after;
+        let precompile::Script {
+            source,
+            read_time,
+            bytecode,
+        } = script;
+        let origin = || {
+            Box::new(LoadOrigin::Script {
+                chain: chain.to_string(),
+                path: filter.script.clone(),
+            })
+        };
+        let script = source
+            .and_then(encoding::decode)
+            .map_err(|error| LoadError::read(origin(), error))?;
+        // Scripts are UTF-8 without a byte order mark once decoded, which is what gets signed.
+        self.verify(chain, filter, script.as_bytes())?;
+        let name = precompile::chunk_name(&filter.script);
+        let source = Rc::new(ScriptSource {
+            config: filter.clone(),
+            source: script.clone(),
+        });
+        let mut state = Vec::new();
+        let environment = if let Some(environment) = shared {
+            Some(environment.clone())
+        } else if *sandboxed {
+            let constants = constants.clone().unwrap_or(mlua::Value::Nil);
+            let environment = self.environment(&constants, true)?;
+            state.push(mlua::Value::Table(environment.clone()));
+            Some(environment)
+        } else {
+            None
+        };
+        let recorder = match self.lint {
+            true => {
+                let target = environment
+                    .clone()
+                    .unwrap_or_else(|| self.runtime.globals());
+                Some(lint::Recorder::new(self.runtime, target)?)
+            }
+            false => None,
+        };
+        let environment = match &recorder {
+            Some(recorder) => Some(recorder.environment.clone()),
+            None => environment,
+        };
+        let cache = self
+            .runtime
+            .app_data_ref::<ScriptCache>()
+            .map(|cache| cache.clone());
+        let started = Instant::now();
+        let compiled = bytecode.and_then(|bytecode| {
+            script_cache::load_bytecode(self.runtime, &name, &bytecode, environment.clone()).ok()
+        });
+        let function = match (compiled, cache) {
+            (Some(function), _) => Ok((function, ScriptOrigin::Precompiled)),
+            (None, Some(cache)) => {
+                cache
+                    .load(self.runtime, &name, &script, environment)
+                    .map(|(function, hit)| match hit {
+                        true => (function, ScriptOrigin::Cached),
+                        false => (function, ScriptOrigin::Source),
+                    })
+            }
+            (None, None) => {
+                // Like `Chunk::eval`, a script that parses as an expression is compiled as one.
+                let compile = |source: &str| {
+                    let mut chunk = self
+                        .runtime
+                        .load(source)
+                        .set_name(&name)
+                        .set_mode(mlua::ChunkMode::Text);
+                    if let Some(environment) = environment.clone() {
+                        chunk = chunk.set_environment(environment);
+                    }
+                    chunk.into_function()
+                };
+                compile(&format!("return {script}"))
+                    .or_else(|_| compile(&script))
+                    .map(|function| (function, ScriptOrigin::Source))
+            }
+        };
+        let compile_time = started.elapsed();
+        let started = Instant::now();
+        let (module, script_origin): (mlua::Value, _) = function
+            .and_then(|(function, script_origin)| Ok((function.call(())?, script_origin)))
+            .map_err(|error| LoadError::Lua {
+                origin: origin(),
+                error,
+            })?;
+        let functions =
+            module_functions(&module, &filter_name)?.map_err(|message| LoadError::Module {
+                origin: origin(),
+                message,
+            })?;
+        let api_version = api_version(&module).map_err(|version| LoadError::ApiVersion {
+            origin: origin(),
+            version,
+        })?;
+        state.push(module);
+        let mut findings = Vec::new();
+        if let Some(recorder) = recorder {
+            let globals = recorder.finish()?.into_iter();
+            findings.extend(globals.map(|name| lint::LintFinding::GlobalWrite { name }));
+        }
+        let mut loaded = Vec::new();
+        for (name, filter) in functions {
+            if self.lint && lint::takes_no_parameters(self.runtime, &filter)? {
+                findings.push(lint::LintFinding::NoParameters {
+                    function: name.clone(),
+                });
+            }
+            let mut filter = Filter::new(name, filter)
+                .with_api_version(api_version)
+                .with_retry_policy(retry_policy)
+                .with_state(state.clone())
+                .with_dry_run(dry_run);
+            if let Some(fuel) = fuel_budget {
+                filter = filter.with_fuel_budget(fuel);
+            }
+            if let Some(bytes) = max_call_memory {
+                filter = filter.with_max_call_memory(bytes);
+            }
+            if let Some(errors) = quarantine_after {
+                filter = filter.with_quarantine_after(errors);
+            }
+            filter.chain = Some(chain.to_string());
+            filter.config_name = Some(filter_name.clone());
+            filter.script = Some(script_path.clone());
+            filter.source = Some(source.clone());
+            loaded.push(filter);
+        }
+        if !findings.is_empty() {
+            let report = LintReport {
+                chain: chain.to_string(),
+                script: script_path.clone(),
+                findings,
+            };
+            if self.strict_lint {
+                return Err(LoadError::Lint(Box::new(report)));
+            }
+            self.lint_reports.push(report);
+        }
+        let loaded_filter = LoadedFilter {
+            chain: chain.to_string(),
+            name: filter_name,
+            script: script_path.clone(),
+            functions: loaded.iter().map(|filter| filter.name.clone()).collect(),
+            size: script.len() as u64,
+            read_time,
+            compile_time,
+            eval_time: started.elapsed(),
+            origin: script_origin,
+        };
+        trace::script_loaded(&loaded_filter);
+        Ok((loaded, loaded_filter))
+    }
+
+    /// Compile the script of `filter` on `backend`, describing its filters for the load report.
+    fn compile_filter(
+        &self,
+        backend: &Rc<RefCell<B>>,
+        chain: &str,
+        filter: &FilterConfig,
+        script: precompile::Script,
+    ) -> Result<(Vec<Filter<'lua, T>>, LoadedFilter), LoadError> {
+        let origin = || {
+            Box::new(LoadOrigin::Script {
+                chain: chain.to_string(),
+                path: filter.script.clone(),
+            })
+        };
+        let precompile::Script {
+            source, read_time, ..
+        } = script;
+        let source = source.map_err(|error| LoadError::read(origin(), error))?;
+        self.verify(chain, filter, &source)?;
+        let started = Instant::now();
+        let compiled = backend
+            .borrow_mut()
+            .compile(filter, &source)
+            .map_err(|error| backend::load_error(origin(), error))?;
+        let compile_time = started.elapsed();
+        // Scripts that aren't text, such as WebAssembly modules, can't be bundled.
+        let bundled = String::from_utf8(source.clone()).ok().map(|source| {
+            Rc::new(ScriptSource {
+                config: filter.clone(),
+                source,
+            })
+        });
+        let mut loaded = Vec::new();
+        for (name, compiled) in compiled {
+            let backend = backend.clone();
+            let call = move |value: &T| backend.borrow().call(&compiled, value);
+            let mut loaded_filter = Filter::calling(name, Callable::Backend(Box::new(call)))
+                .with_retry_policy(filter.retry_policy())
+                .with_dry_run(filter.dry_run);
+            if let Some(errors) = filter.quarantine_after {
+                loaded_filter = loaded_filter.with_quarantine_after(errors);
+            }
+            loaded_filter.chain = Some(chain.to_string());
+            loaded_filter.config_name = Some(filter.name.clone());
+            loaded_filter.script = Some(filter.script.clone());
+            loaded_filter.source = bundled.clone();
+            loaded.push(loaded_filter);
+        }
+        let loaded_filter = LoadedFilter {
+            chain: chain.to_string(),
+            name: filter.name.clone(),
+            script: filter.script.clone(),
+            functions: loaded.iter().map(|filter| filter.name.clone()).collect(),
+            size: source.len() as u64,
+            read_time,
+            compile_time,
+            eval_time: Duration::ZERO,
+            origin: ScriptOrigin::Source,
+        };
+        trace::script_loaded(&loaded_filter);
+        Ok((loaded, loaded_filter))
+    }
+
+    /// Check the signature of the script of `filter`, whose contents are `source`, against
+    /// the trusted keys.
+    fn verify(&self, chain: &str, filter: &FilterConfig, source: &[u8]) -> Result<(), LoadError> {
+        signature::verify(&self.trusted_keys, filter, source).map_err(|problem| {
+            LoadError::Signature {
+                origin: Box::new(LoadOrigin::Script {
+                    chain: chain.to_string(),
+                    path: filter.script.clone(),
+                }),
+                filter: filter.name.clone(),
+                tried: signature::fingerprints(&self.trusted_keys),
+                problem,
+            }
+        })
+    }
+
+    /// Read the scripts at `paths`, compiling them ahead unless the backend compiles them.
+    fn prepare(&self, paths: &[PathBuf]) -> Vec<precompile::Script> {
+        match &self.backend {
+            Some(_) => paths.iter().map(|path| precompile::read(path)).collect(),
+            None => precompile::prepare(self.runtime, paths),
+        }
+    }
+
+    /// A script environment reading through to the globals, with `chain` set to the constants.
+    ///
+    /// Sandboxed environments keep the assignments of their scripts to themselves; the others
+    /// forward them to the globals.
+    fn environment(
+        &self,
+        constants: &mlua::Value<'lua>,
+        sandboxed: bool,
+    ) -> mlua::Result<mlua::Table<'lua>> {
+        let environment = self.runtime.create_table()?;
+        environment.set("chain", constants.clone())?;
+        let globals = self.runtime.globals();
+        let metatable = self.runtime.create_table()?;
+        metatable.set("__index", globals.clone())?;
+        if !sandboxed {
+            metatable.set("__newindex", globals)?;
+        }
+        environment.set_metatable(Some(metatable));
+        Ok(environment)
+    }
+
+    /// Replace the loaded filters with the ones of `config`, re-evaluating its libraries.
+    ///
+    /// The previous filters stay in place if the new configuration fails to load, with the
+    /// libraries, environment variables and trusted keys they were loaded with. Otherwise only
+    /// the environment variables and libraries of the new configuration stay exposed. The
+    /// `memo` cache is emptied either way.
+    pub fn reload(&mut self, config: Config) -> Result<(), LoadError> {
+        self.memo.clear(self.runtime);
+        let previous = std::mem::take(&mut self.filters);
+        let expressions = std::mem::take(&mut self.expressions);
+        let loaded = std::mem::take(&mut self.loaded);
+        let libraries = std::mem::take(&mut self.libraries);
+        let expose_env = std::mem::take(&mut self.expose_env);
+        let trusted_keys = self.trusted_keys.clone();
+        if let Err(err) = self.load(config) {
+            self.filters = previous;
+            self.expressions = expressions;
+            self.loaded = loaded;
+            self.libraries = libraries;
+            self.expose_env = expose_env;
+            self.trusted_keys = trusted_keys;
+            self.expose()?;
+            return Err(err);
+        }
+        drop(previous);
+        self.release();
+        Ok(())
+    }
+
+    /// A handle to request reloads from other threads, applied by
+    /// [`apply_reload`](Self::apply_reload).
+    #[cfg(feature = "tokio")]
+    pub fn reload_handle(&self) -> ReloadHandle {
+        ReloadHandle::new(self.reloads.get_or_init(Arc::default))
+    }
+
+    /// Reload with the configuration last requested through a [`ReloadHandle`], if any,
+    /// sending the outcome to its requester; returns whether there was one.
+    ///
+    /// Filtering borrows the system, so a reload can't happen in the middle of a batch: call
+    /// this between batches, whenever reloading suits.
+    #[cfg(feature = "tokio")]
+    pub fn apply_reload(&mut self) -> bool {
+        let Some(queue) = self.reloads.get() else {
+            return false;
+        };
+        let (request, superseded) = {
+            let mut queue = queue.lock().unwrap();
+            (queue.request.take(), std::mem::take(&mut queue.superseded))
+        };
+        let Some(reload::Request { config, reply }) = request else {
+            return false;
+        };
+        let started = Instant::now();
+        let result = self.reload(config).map(|()| ReloadSummary {
+            loaded: self.load_report.loaded.clone(),
+            superseded,
+            took: started.elapsed(),
+        });
+        // The requester may have stopped waiting.
+        let _ = reply.send(result);
+        true
+    }
+
+    /// Remove the filters called `name`, returning how many there were.
+    ///
+    /// `name` is matched against the [`FilterId`] of each filter, so it can be the id or a
+    /// suffix of it: the bare function name removes the filters of that name of every chain.
+    ///
+    /// What only they referenced, such as the state of their scripts, is collected right away,
+    /// and the `memo` cache is emptied since its values may come from them.
+    pub fn remove(&mut self, name: &str) -> usize {
+        let before = self.filters.len();
+        self.filters.retain(|filter| !filter.id().matches(name));
+        let removed = before - self.filters.len();
+        if removed > 0 {
+            self.release();
+        }
+        removed
+    }
+
+    /// The id of the single filter called `name`, the id of a filter or a suffix of it as in
+    /// [`remove`](Self::remove), failing if none or several filters match it.
+    pub fn filter_id(&self, name: &str) -> Result<FilterId, ResolveError> {
+        let ids = self.filters.iter().map(Filter::id);
+        let mut candidates: Vec<FilterId> = ids.filter(|id| id.matches(name)).collect();
+        match candidates.len() {
+            0 => Err(ResolveError::Unknown {
+                name: name.to_string(),
+            }),
+            1 => Ok(candidates.remove(0)),
+            _ => Err(ResolveError::Ambiguous {
+                name: name.to_string(),
+                candidates,
+            }),
+        }
+    }
+
+    /// Switch the filters called `name` in or out of dry-run mode, returning how many there
+    /// are, see [`Filter::with_dry_run`].
+    ///
+    /// `name` is matched as in [`remove`](Self::remove).
+    pub fn set_dry_run(&mut self, name: &str, dry_run: bool) -> usize {
+        let mut count = 0;
+        for filter in self
+            .filters
+            .iter_mut()
+            .filter(|filter| filter.id().matches(name))
+        {
+            filter.dry_run = dry_run;
+            count += 1;
+        }
+        self.clear_verdict_cache();
+        count
+    }
+
+    /// Load the filters of a single chain, replacing the ones it had.
+    ///
+    /// The load limits apply to the chain on its own, and the filters of other chains stay as
+    /// they are whatever happens: if loading fails, the chain keeps its previous filters. The
+    /// chain keeps the `chain` constants it was loaded with, and the libraries of the loaded
+    /// configuration aren't evaluated again. Its expression, if it has one, stays and must name only new filters.
+    pub fn load_chain(&mut self, chain: &str, filters: Vec<FilterConfig>) -> Result<(), LoadError> {
+        let config = Config {
+            chains: [(chain.to_string(), filters)].into(),
+            ..Default::default()
+        };
+        let mut load = || {
+            config.validate()?;
+            limits::check_config(self.runtime, &config)?;
+            self.activate()?;
+            let mut scripts = self.prepare(&config.script_paths()).into_iter();
+            let filters = &config.chains[chain];
+            let mut report = LoadReport::default();
+            let constants = self.loaded.constants.get(chain).cloned();
+            let loaded = self.load_filters(
+                chain,
+                filters,
+                constants.as_ref(),
+                &mut scripts,
+                &mut report,
+                false,
+            )?;
+            if let Some(expression) = self.expressions.get(chain) {
+                let names: Vec<&str> = loaded.iter().map(|filter| filter.name.as_str()).collect();
+                known_filters(chain, expression, &names)?;
+            }
+            self.unload_chain(chain);
+            self.filters.extend(loaded);
+            self.set_load_report(report);
+            Ok(())
+        };
+        let result = load();
+        self.last_load = Some(LoadOutcome::of(&result));
+        result
+    }
+
+    /// Remove the filters of `chain`, returning whether it had any.
+    ///
+    /// Like [`remove`](Self::remove), what only they referenced is collected right away.
+    pub fn unload_chain(&mut self, chain: &str) -> bool {
+        let before = self.filters.len();
+        self.filters
+            .retain(|filter| filter.chain.as_deref() != Some(chain));
+        let removed = self.filters.len() < before;
+        if removed {
+            self.release();
+        }
+        removed
+    }
+
+    /// Free what filters no longer loaded kept alive: the `memo` entries, registry values
+    /// dropped since, and the garbage left behind.
+    fn release(&self) {
+        self.memo.clear(self.runtime);
+        self.clear_verdict_cache();
+        // Collecting is only an optimization; a failing finalizer shouldn't fail the caller.
+        let _ = self.runtime.gc_collect();
+    }
+
+    /// The counters of every loaded filter.
+    pub fn stats(&self) -> Vec<FilterStats> {
+        self.filters.iter().map(Filter::stats).collect()
+    }
+
+    /// The counters of every chain with loaded filters, in name order, kept by every
+    /// filtering method, see [`ChainStats`].
+    pub fn chain_stats(&self) -> Vec<ChainStats> {
+        let chains: BTreeSet<&str> = self
+            .filters
+            .iter()
+            .map(|filter| filter.chain.as_deref().unwrap_or_default())
+            .collect();
+        let tallies = self.chain_tallies.borrow();
+        chains
+            .into_iter()
+            .map(|chain| tallies.get(chain).cloned().unwrap_or_default().stats(chain))
+            .collect()
+    }
+
+    /// Start the counters since the last reset of every chain over.
+    pub fn reset_chain_stats(&mut self) {
+        for tally in self.chain_tallies.get_mut().values_mut() {
+            tally.reset();
+        }
+    }
+
+    /// Total number of filter errors recorded so far.
+    fn error_count(&self) -> u64 {
+        self.filters
+            .iter()
+            .map(|filter| filter.stats.borrow().errors)
+            .sum()
+    }
+
+    /// Collect garbage after a batch, per the `gc_after_batch` setting.
+    fn finish_batch(&self) -> Result<(), FilterError> {
+        self.close_batch();
+        if gc::after_batch(self.runtime, self.gc_after_batch)? {
+            self.gc_runs.set(self.gc_runs.get() + 1);
+        }
+        Ok(())
+    }
+
+    /// Run every filter against a value without a context.
+    fn evaluate(&self, value: &T) -> Result<bool, FilterError> {
+        self.evaluate_with(value, &mlua::Value::Nil, None)
+    }
+
+    /// Run every filter against a value, returning whether any of them matched.
+    ///
+    /// This is the single evaluation routine behind all of the filtering APIs. With a
+    /// `verdict`, the output the filters print is captured and the matching filters recorded.
+    fn evaluate_with(
+        &self,
+        value: &T,
+        context: &mlua::Value<'lua>,
+        verdict: Option<&mut Verdict>,
+    ) -> Result<bool, FilterError> {
+        self.evaluate_selected(value, context, verdict, |_| true)
+    }
+
+    /// [`evaluate_with`](Self::evaluate_with), running only the filters `selected` accepts.
+    fn evaluate_selected(
+        &self,
+        value: &T,
+        context: &mlua::Value<'lua>,
+        verdict: Option<&mut Verdict>,
+        selected: impl Fn(&Filter<'lua, T>) -> bool,
+    ) -> Result<bool, FilterError> {
+        let watchdog = self.watchdog(None);
+        if watchdog.is_idle() {
+            return self.run_selected(value, context, verdict, selected);
+        }
+        watchdog.watch(self.runtime, || {
+            self.run_selected(value, context, verdict, selected)
+        })?
+    }
+
+    /// The watchdog for a call, checking `cancel` and the interrupt handle if there is one.
+    fn watchdog(&self, cancel: Option<&Arc<AtomicBool>>) -> Watchdog {
+        Watchdog {
+            cancel: cancel.cloned(),
+            interrupt: self.interrupt.get().cloned(),
+            timeout: self
+                .call_timeout
+                .map(|timeout| (timeout, self.call_started.clone())),
+            deadline: None,
+            fuel: self.fuel.clone(),
+            profiler: self.profiler.clone(),
+        }
+    }
+
+    /// Give every filter its full fuel budget again, and the batch its own, and get the context
+    /// of the batch from the provider if there is one.
+    fn start_batch(&self) -> Result<(), FilterError> {
+        // Single-value calls start batches without finishing them.
+        self.close_batch();
+        // Reseeding calls the `math.randomseed` saved when seeding, with an integer, so it
+        // can't fail.
+        let _ = rng::start_batch(self.runtime);
+        for filter in &self.filters {
+            filter.fuel_burnt.set(0);
+        }
+        self.batch_fuel_burnt.set(0);
+        if let Some(provider) = &self.context_provider {
+            let context = std::panic::catch_unwind(AssertUnwindSafe(|| provider(self.runtime)));
+            let context = match context {
+                Ok(context) => context.map_err(|err| err.to_string()),
+                Err(payload) => Err(format!(
+                    "panicked: {}",
+                    panic::recover(self.runtime, payload)
+                )),
+            };
+            *self.batch_context.borrow_mut() =
+                context.map_err(|message| FilterError::ContextProvider { message })?;
+        }
+        Ok(())
+    }
+
+    /// Close the batch under way for the chain counters.
+    fn close_batch(&self) {
+        for tally in self.chain_tallies.borrow_mut().values_mut() {
+            tally.close_batch();
+        }
+    }
+
+    /// Whether `filter` is skipped for the rest of the batch, having burnt its own budget or
+    /// the batch's.
+    fn out_of_fuel(&self, filter: &Filter<'lua, T>) -> bool {
+        filter.out_of_fuel()
+            || self
+                .batch_fuel
+                .is_some_and(|budget| self.batch_fuel_burnt.get() > budget)
+    }
+
+    /// The fuel the next call of `filter` may burn.
+    fn fuel_left(&self, filter: &Filter<'lua, T>) -> u64 {
+        let batch = self
+            .batch_fuel
+            .map(|budget| budget.saturating_sub(self.batch_fuel_burnt.get()));
+        [filter.fuel_left(), batch]
+            .into_iter()
+            .flatten()
+            .min()
+            .unwrap_or(u64::MAX)
+    }
+
+    /// [`evaluate_with`](Self::evaluate_with), for callers that set up the watchdog themselves.
+    fn run_filters(
+        &self,
+        value: &T,
+        context: &mlua::Value<'lua>,
+        verdict: Option<&mut Verdict>,
+    ) -> Result<bool, FilterError> {
+        self.run_selected(value, context, verdict, |_| true)
+    }
+
+    /// [`run_filters`](Self::run_filters), running only the filters `selected` accepts.
+    fn run_selected(
+        &self,
+        value: &T,
+        context: &mlua::Value<'lua>,
+        verdict: Option<&mut Verdict>,
+        selected: impl Fn(&Filter<'lua, T>) -> bool,
+    ) -> Result<bool, FilterError> {
+        self.activate()?;
+        // An interrupt only aborts the evaluation it was sent during.
+        if let Some(interrupt) = self.interrupt.get() {
+            interrupt.store(false, Ordering::Relaxed);
+        }
+        let batch_context;
+        let context = match context {
+            mlua::Value::Nil => {
+                batch_context = self.batch_context.borrow().clone();
+                &batch_context
+            }
+            context => context,
+        };
+        self.runtime.scope(|scope| {
+            let argument = Argument::new(scope, value);
+            let result = self.run_filters_on(&argument, context, verdict, selected);
+            if result.is_ok() {
+                self.last_success.set(Some(Instant::now()));
+            }
+            Ok(result)
+        })?
+    }
+
+    /// [`run_selected`](Self::run_selected), once the value is set up for conversion.
+    ///
+    /// Chains with an expression keep a value if it is true, calling their filters as it needs
+    /// them when the first of them comes up; the others keep it if any filter matched.
+    fn run_filters_on(
+        &self,
+        argument: &Argument<'_, 'lua, '_, T>,
+        context: &mlua::Value<'lua>,
+        mut verdict: Option<&mut Verdict>,
+        selected: impl Fn(&Filter<'lua, T>) -> bool,
+    ) -> Result<bool, FilterError> {
+        let mut filtered = false;
+        // Whether a dry-run filter matched, for the verdict.
+        let mut would_match = false;
+        let mut chains: Vec<(&str, ChainOutcome)> = Vec::new();
+        for filter in self.filters.iter().filter(|filter| selected(filter)) {
+            let chain = filter.chain.as_deref().unwrap_or_default();
+            let (index, first) = match chains.iter().position(|(name, _)| *name == chain) {
+                Some(index) => (index, false),
+                None => {
+                    chains.push((chain, ChainOutcome::default()));
+                    (chains.len() - 1, true)
+                }
+            };
+            let outcome = &mut chains[index].1;
+            let result = match self.expressions.get(chain) {
+                Some(_) if !first => continue,
+                Some(expression) => expression.evaluate(&mut |name| {
+                    let mut named = self.filters.iter().filter(|filter| {
+                        filter.chain.as_deref() == Some(chain)
+                            && filter.name == name
+                            && selected(filter)
+                    });
+                    named.try_fold(false, |matched, filter| match matched {
+                        true => Ok(true),
+                        false => self.run_filter(
+                            filter,
+                            argument,
+                            context,
+                            verdict.as_deref_mut(),
+                            outcome,
+                            &mut would_match,
+                        ),
+                    })
+                }),
+                None => self.run_filter(
+                    filter,
+                    argument,
+                    context,
+                    verdict.as_deref_mut(),
+                    outcome,
+                    &mut would_match,
+                ),
+            };
+            match result {
+                Ok(matched) => {
+                    filtered |= matched;
+                    outcome.kept |= matched;
+                }
+                Err(err) => {
+                    self.tally(chains);
+                    return Err(err);
+                }
+            }
+        }
+        self.tally(chains);
+        if let Some(verdict) = verdict {
+            verdict.would_match = filtered || would_match;
+        }
+        Ok(filtered)
+    }
+
+    /// Call `filter` on a value for [`run_filters_on`](Self::run_filters_on), returning
+    /// whether its verdict counts as a match, or the error that stops the evaluation.
+    fn run_filter(
+        &self,
+        filter: &Filter<'lua, T>,
+        argument: &Argument<'_, 'lua, '_, T>,
+        context: &mlua::Value<'lua>,
+        mut verdict: Option<&mut Verdict>,
+        outcome: &mut ChainOutcome,
+        would_match: &mut bool,
+    ) -> Result<bool, FilterError> {
+        let cool_down = self.quarantine.and_then(|quarantine| quarantine.cool_down);
+        if filter.breaker.skips(cool_down) {
+            return Ok(false);
+        }
+        if self.out_of_fuel(filter) {
+            filter.stats.borrow_mut().budget_exhausted += 1;
+            if let Some(verdict) = verdict.as_deref_mut() {
+                verdict.budget_exhausted.push(filter.id().to_string());
+            }
+            return Ok(false);
+        }
+        if verdict.is_some() {
+            print::start(self.runtime);
+        }
+        if self.call_timeout.is_some() {
+            self.call_started.set(Instant::now());
+        }
+        let burnt = self.fuel.as_ref().map(|fuel| {
+            fuel.allow(self.fuel_left(filter));
+            fuel.burnt()
+        });
+        if let Some(profiler) = &self.profiler {
+            profiler.enter(&filter.name);
+        }
+        let started = Instant::now();
+        let mut result = match self.measure_memory {
+            true => gc::paused(self.runtime, || {
+                filter.call(self.runtime, argument, context, self.strict_returns)
+            }),
+            false => filter.call(self.runtime, argument, context, self.strict_returns),
+        };
+        outcome.elapsed += started.elapsed();
+        if let Some(profiler) = &self.profiler {
+            profiler.leave();
+        }
+        if let (Some(fuel), Some(burnt)) = (&self.fuel, burnt) {
+            let burnt = fuel.burnt() - burnt;
+            filter.fuel_burnt.set(filter.fuel_burnt.get() + burnt);
+            self.batch_fuel_burnt
+                .set(self.batch_fuel_burnt.get() + burnt);
+            filter.stats.borrow_mut().fuel += burnt;
+        }
+        let out_of_fuel = matches!(&result, Err(err) if err.trip() == Some(Trip::OutOfFuel));
+        if !out_of_fuel {
+            self.observe(filter, &result);
+        }
+        if let Some(verdict) = verdict {
+            let id = filter.id().to_string();
+            if out_of_fuel {
+                verdict.budget_exhausted.push(id.clone());
+            }
+            let output = print::finish(self.runtime);
+            let lines = output
+                .into_iter()
+                .map(|line| format!("[{}] {line}", filter.name));
+            verdict.debug_output.extend(lines);
+            if let Ok((true, output)) = &result {
+                if !filter.dry_run && !matches!(output, mlua::Value::Nil | mlua::Value::String(_)) {
+                    match transform::to_json(self.runtime, output.clone()) {
+                        Ok(output) => verdict.outputs.push((id.clone(), output)),
+                        Err(err) => {
+                            result = Err(FilterError::Transform {
+                                filter: filter.id().to_string(),
+                                path: ".".to_string(),
+                                field: None,
+                                message: err.to_string(),
+                            })
+                        }
+                    }
+                }
+            }
+            if let Ok((matched, reason)) = &result {
+                if filter.dry_run {
+                    verdict.dry_run.push((id.clone(), *matched));
+                    *would_match |= *matched;
+                } else if *matched {
+                    verdict.matched_by.push(id.clone());
+                }
+                // Anything but a string in second position is ignored.
+                if let mlua::Value::String(reason) = reason {
+                    let reason = reason.to_string_lossy().into_owned();
+                    verdict.reasons.push((id, reason));
+                }
+            }
+        }
+        outcome.errored |= result.is_err() && !out_of_fuel;
+        if !out_of_fuel {
+            self.count_towards_quarantine(filter, result.is_err());
+        }
+        match result {
+            Ok(_) if filter.dry_run => Ok(false),
+            Ok((matched, _)) => Ok(matched),
+            Err(_) if out_of_fuel => Ok(false),
+            Err(err) if err.trip().is_some() || err.is_panic() => Err(err),
+            Err(_) if self.error_policy == ErrorPolicy::Lenient || filter.dry_run => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Count a value in the counters of the chains that filtered it.
+    fn tally(&self, chains: Vec<(&str, ChainOutcome)>) {
+        let mut tallies = self.chain_tallies.borrow_mut();
+        for (chain, outcome) in chains {
+            match tallies.get_mut(chain) {
+                Some(tally) => tally.value(outcome),
+                None => tallies.entry(chain.to_string()).or_default().value(outcome),
+            }
+        }
+    }
+
+    /// Filter a single value, or give its cached verdict, see
+    /// [`set_verdict_cache`](Self::set_verdict_cache).
+    pub fn filter_one(&self, value: T) -> Result<bool, FilterError> {
+        let Some(cache) = &self.verdict_cache else {
+            return self.filter_one_uncached(value);
+        };
+        let key = match &self.cache_key {
+            Some(key) => Some(key(&value)),
+            None => verdict_cache::hash_json(&value),
+        };
+        let Some(key) = key else {
+            return self.filter_one_uncached(value);
+        };
+        if let Some(matched) = cache.borrow_mut().get(key) {
+            return Ok(matched);
+        }
+        let matched = self.filter_one_uncached(value)?;
+        cache.borrow_mut().insert(key, matched);
+        Ok(matched)
+    }
+
+    /// Filter a single value, bypassing the verdict cache.
+    pub fn filter_one_uncached(&self, value: T) -> Result<bool, FilterError> {
+        self.start_batch()?;
+        self.evaluate(&value)
+    }
+
+    /// Cache the verdicts [`filter_one`](Self::filter_one) gives to the last `capacity`
+    /// values, so a value delivered again isn't filtered again; replaces the cache set before.
+    ///
+    /// Values are told apart by their key, see [`set_cache_key`](Self::set_cache_key), or by
+    /// the JSON they serialize to; those that don't serialize aren't cached. Verdicts are
+    /// forgotten whenever the filters change, as they load, reload, are removed or switch in
+    /// or out of dry runs, and on [`clear_verdict_cache`](Self::clear_verdict_cache).
+    ///
+    /// Only the verdict is cached: errors aren't, and a hit calls no filter, so neither their
+    /// stats nor the state they keep see the value. Filters whose verdict depends on their
+    /// state or on the context are better left uncached.
+    pub fn set_verdict_cache(&mut self, capacity: usize) {
+        self.verdict_cache = Some(RefCell::new(VerdictCache::new(capacity)));
+    }
+
+    /// Tell values apart in the verdict cache by the hash of `key`, rather than of their JSON.
+    ///
+    /// The cached verdicts are forgotten, as they were cached by the previous key.
+    pub fn set_cache_key<K: std::hash::Hash>(&mut self, key: impl Fn(&T) -> K + 'static) {
+        self.cache_key = Some(Box::new(move |value| verdict_cache::hash(&key(value))));
+        self.clear_verdict_cache();
+    }
+
+    /// Forget the cached verdicts, keeping the counters of the cache.
+    pub fn clear_verdict_cache(&self) {
+        if let Some(cache) = &self.verdict_cache {
+            cache.borrow_mut().clear();
+        }
+    }
+
+    /// The counters of the verdict cache, if there is one.
+    pub fn verdict_cache_stats(&self) -> Option<VerdictCacheStats> {
+        let cache = self.verdict_cache.as_ref()?;
+        Some(cache.borrow().stats())
+    }
+
+    /// Filter a single value, reporting which filters matched and what they printed.
+    pub fn filter_one_detailed(&self, value: T) -> Result<Verdict, FilterError> {
+        self.start_batch()?;
+        let mut verdict = Verdict::default();
+        verdict.matched = self.evaluate_with(&value, &mlua::Value::Nil, Some(&mut verdict))?;
+        Ok(verdict)
+    }
+
+    /// Run test cases against the loaded filters, reporting the ones that failed.
+    ///
+    /// Each case runs through its target filters like a value given to
+    /// [`filter_one_detailed`](Self::filter_one_detailed), so it counts in their stats, and a
+    /// case the filters fail on fails rather than stopping the run.
+    pub fn run_tests(&self, cases: Vec<TestCase<T>>) -> TestReport {
+        let mut report = TestReport::default();
+        for case in cases {
+            let selected = |filter: &Filter<'lua, T>| match &case.target {
+                TestTarget::All => true,
+                TestTarget::Filter(name) => filter.id().matches(name),
+                TestTarget::Chain(chain) => filter.chain.as_deref() == Some(chain),
+            };
+            let mut verdict = Verdict::default();
+            let actual = self
+                .start_batch()
+                .and_then(|()| {
+                    let verdict = Some(&mut verdict);
+                    self.evaluate_selected(&case.input, &mlua::Value::Nil, verdict, selected)
+                })
+                .map(|matched| match &case.expected {
+                    Outcome::Matched(_) => Outcome::Matched(matched),
+                    Outcome::MatchedBy(expected) => {
+                        let dry_run = verdict
+                            .dry_run
+                            .into_iter()
+                            .filter_map(|(id, matched)| matched.then_some(id));
+                        // Filters are expected by their id or a suffix of it.
+                        let named = |id: String| {
+                            let mut expected = expected.iter();
+                            let name = expected.find(|name| filter_id::matches(&id, name));
+                            name.cloned().unwrap_or(id)
+                        };
+                        let ids = verdict.matched_by.into_iter().chain(dry_run);
+                        Outcome::MatchedBy(ids.map(named).collect())
+                    }
+                });
+            match actual {
+                Ok(actual) if actual == case.expected => report.passed += 1,
+                actual => report.failures.push(TestFailure {
+                    case: case.name,
+                    expected: case.expected,
+                    actual,
+                }),
+            }
+        }
+        report
+    }
+
+    /// Record the verdict of every filter on `values` to `writer`, as NDJSON lines
+    /// `{"value": ..., "verdicts": {"filter": true, ...}}`, see
+    /// [`verify_recording`](Self::verify_recording).
+    ///
+    /// Filters are recorded by their id, see [`FilterId`]; dry-run filters are recorded like
+    /// the others.
+    pub fn record(&self, values: &[T], mut writer: impl Write) -> Result<(), RecordingError> {
+        self.start_batch()?;
+        for (index, value) in values.iter().enumerate() {
+            let mut verdict = Verdict::default();
+            self.evaluate_with(value, &mlua::Value::Nil, Some(&mut verdict))?;
+            let recorded = recording::Recorded {
+                value,
+                verdicts: self.verdicts(verdict),
+            };
+            serde_json::to_writer(&mut writer, &recorded).map_err(|error| {
+                RecordingError::Json {
+                    line: index + 1,
+                    error,
+                }
+            })?;
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()?;
+        self.finish_batch()?;
+        Ok(())
+    }
+
+    /// The verdict of each loaded filter in `verdict`, by id.
+    fn verdicts(&self, verdict: Verdict) -> BTreeMap<String, bool> {
+        let ids = self.filters.iter().map(|filter| filter.id().to_string());
+        recording::verdicts(ids, verdict)
+    }
+
+    /// Filter a list of values.
+    pub fn filter(&self, values: Vec<T>) -> Result<Vec<T>, FilterError> {
+        let keep = self.keep_mask(&values, &mlua::Value::Nil)?;
+        Ok(drain_kept(values, keep))
+    }
+
+    /// Send an [`AuditRecord`] of every value filtered with
+    /// [`filter_audited`](Self::filter_audited) to `sink`, replacing the sink set before.
+    ///
+    /// The other filtering APIs don't audit, sparing the cost of the snapshots.
+    pub fn set_audit_sink(&mut self, sink: impl AuditSink + 'static) {
+        self.audit_sink = Some(RefCell::new(Box::new(sink)));
+    }
+
+    /// Filter a list of values like [`filter`](Self::filter), sending a record of the decision
+    /// on each value to the audit sink, if one is set.
+    ///
+    /// Records are sent as values are evaluated, so the values before a failing one are
+    /// audited. A sink failing stops filtering.
+    pub fn filter_audited(&self, values: Vec<T>) -> Result<Vec<T>, AuditError> {
+        let Some(sink) = &self.audit_sink else {
+            return Ok(self.filter(values)?);
+        };
+        self.start_batch()?;
+        let mut keep = Vec::with_capacity(values.len());
+        for value in &values {
+            let mut verdict = Verdict::default();
+            let kept = self.evaluate_with(value, &mlua::Value::Nil, Some(&mut verdict))?;
+            let reasons = std::mem::take(&mut verdict.reasons);
+            let record = AuditRecord {
+                timestamp: SystemTime::now(),
+                value: serde_json::to_value(value).map_err(AuditError::Snapshot)?,
+                verdicts: self.verdicts(verdict),
+                reasons,
+                kept,
+            };
+            sink.borrow_mut().record(record).map_err(AuditError::Sink)?;
+            keep.push(kept);
+        }
+        self.finish_batch()?;
+        Ok(drain_kept(values, keep))
+    }
+
+    /// Filter values shared behind an [`Arc`], returning the ones that matched in order.
+    ///
+    /// The filters read each value through its `Arc`, so no value is cloned, kept or not.
+    pub fn filter_arcs(&self, values: Vec<Arc<T>>) -> Result<Vec<Arc<T>>, FilterError> {
+        let keep = self.keep_mask(values.iter().map(|value| &**value), &mlua::Value::Nil)?;
+        Ok(drain_kept(values, keep))
+    }
+
+    /// Which of `values` matched, evaluating each once. This is the batch routine behind the
+    /// APIs filtering a whole list, which then move or borrow the kept values by the mask.
+    fn keep_mask<'v>(
+        &self,
+        values: impl IntoIterator<Item = &'v T>,
+        context: &mlua::Value<'lua>,
+    ) -> Result<Vec<bool>, FilterError>
+    where
+        T: 'v,
+    {
+        self.start_batch()?;
+        let keep = values
+            .into_iter()
+            .map(|value| self.evaluate_with(value, context, None))
+            .collect::<Result<_, _>>()?;
+        self.finish_batch()?;
+        Ok(keep)
+    }
+
+    /// Filter a single value, passing `context` to the filters as their second argument.
+    ///
+    /// Filters of scripts written for version 1 of [`API_VERSIONS`] don't get it. A context
+    /// converting to `nil` gives way to the one of the context provider, if there is one.
+    pub fn filter_one_with_context<C: Serialize>(
+        &self,
+        value: T,
+        context: &C,
+    ) -> Result<bool, FilterError> {
+        let context = convert::to_lua(self.runtime, context)?;
+        self.start_batch()?;
+        self.evaluate_with(&value, &context, None)
+    }
+
+    /// Filter a list of values, passing `context` to the filters as their second argument.
+    ///
+    /// The context is converted once for the whole list and shared by every call. Filters of
+    /// scripts written for version 1 of [`API_VERSIONS`] don't get it.
+    pub fn filter_with_context<C: Serialize>(
+        &self,
+        values: Vec<T>,
+        context: &C,
+    ) -> Result<Vec<T>, FilterError> {
+        let context = convert::to_lua(self.runtime, context)?;
+        let keep = self.keep_mask(&values, &context)?;
+        Ok(drain_kept(values, keep))
+    }
+
+    /// Filter a list of values, turning the ones that matched into values of another type.
+    ///
+    /// A filter gives what a value turns into after its verdict, `return true, { id = tx.id }`,
+    /// which is deserialized into `U`; the first matching filter giving one wins, and one
+    /// giving nothing gives `nil`. Outputs that don't deserialize fail with
+    /// [`FilterError::Transform`], whatever the error policy.
+    pub fn filter_transform<U: DeserializeOwned>(
+        &self,
+        values: Vec<T>,
+    ) -> Result<Vec<(T, U)>, FilterError> {
+        self.start_batch()?;
+        let mut transformed = Vec::new();
+        for value in values {
+            let mut verdict = Verdict::default();
+            if !self.evaluate_with(&value, &mlua::Value::Nil, Some(&mut verdict))? {
+                continue;
+            }
+            // A chain whose expression matched may have no filter that did, `NOT blocklisted`
+            // say, so there is no filter to name then.
+            let (filter, output) = match verdict.outputs.into_iter().next() {
+                Some(output) => output,
+                None => {
+                    let filter = verdict.matched_by.into_iter().next();
+                    (filter.unwrap_or_default(), serde_json::Value::Null)
+                }
+            };
+            transformed.push((value, transform::deserialize(&filter, output)?));
+        }
+        self.finish_batch()?;
+        Ok(transformed)
+    }
+
+    /// Filter a list of values in chunks, reporting progress after each chunk.
+    ///
+    /// Returning `ControlFlow::Break` from `progress` stops processing and returns the values
+    /// kept so far. A `chunk_size` of zero is treated as one.
+    pub fn filter_chunked(
+        &self,
+        values: Vec<T>,
+        chunk_size: usize,
+        mut progress: impl FnMut(ChunkProgress) -> ControlFlow<()>,
+    ) -> Result<Vec<T>, FilterError> {
+        self.start_batch()?;
+        let chunk_size = chunk_size.max(1);
+        let started = Instant::now();
+        let errors = self.error_count();
+        let mut result = Vec::new();
+        let mut processed = 0;
+        let mut values = values.into_iter().peekable();
+        while values.peek().is_some() {
+            for tx in values.by_ref().take(chunk_size) {
+                processed += 1;
+                if self.evaluate(&tx)? {
+                    result.push(tx);
+                }
+            }
+            let report = ChunkProgress {
+                processed,
+                kept: result.len(),
+                elapsed: started.elapsed(),
+                errors: (self.error_count() - errors) as usize,
+            };
+            if progress(report).is_break() {
+                break;
+            }
+        }
+        self.finish_batch()?;
+        Ok(result)
+    }
+
+    /// Filter values as they come, handing each one that matched to `sink` right away.
+    ///
+    /// Nothing is kept around, so this suits inputs too large to collect. The whole input is
+    /// one batch. An error from `sink` stops processing and is returned as
+    /// [`StreamError::Sink`]; the values already handed over stay with the sink.
+    pub fn filter_streaming<I, F, E>(
+        &self,
+        input: I,
+        mut sink: F,
+    ) -> Result<StreamSummary, StreamError<E>>
+    where
+        I: IntoIterator<Item = T>,
+        F: FnMut(T) -> Result<(), E>,
+    {
+        self.start_batch()?;
+        let errors = self.error_count();
+        let mut summary = StreamSummary::default();
+        for value in input {
+            summary.processed += 1;
+            if self.evaluate(&value)? {
+                summary.kept += 1;
+                sink(value).map_err(StreamError::Sink)?;
+            }
+        }
+        summary.errors = (self.error_count() - errors) as usize;
+        self.finish_batch()?;
+        Ok(summary)
+    }
+
+    /// Filter a slice of values, returning references to the ones that matched in order.
+    pub fn filter_ref<'a>(&self, values: &'a [T]) -> Result<Vec<&'a T>, FilterError> {
+        let keep = self.keep_mask(values, &mlua::Value::Nil)?;
+        Ok(values
+            .iter()
+            .zip(keep)
+            .filter_map(|(tx, keep)| keep.then_some(tx))
+            .collect())
+    }
+
+    /// Filter a slice of values, returning the positions of the ones that matched.
+    pub fn filter_indices(&self, values: &[T]) -> Result<Vec<usize>, FilterError> {
+        let keep = self.keep_mask(values, &mlua::Value::Nil)?;
+        Ok(keep
+            .into_iter()
+            .enumerate()
+            .filter_map(|(index, keep)| keep.then_some(index))
+            .collect())
+    }
+
+    /// Filter a slice of values until `cancel` is set, returning references to the matches.
+    ///
+    /// The flag is checked between values and periodically inside running filters, so even a
+    /// long-running script is aborted promptly. Once cancelled this returns
+    /// `FilterError::Cancelled` with the positions of the values kept so far. The flag is shared
+    /// with the instruction hook, which is why it is taken as an `Arc`.
+    pub fn filter_with_cancel<'a>(
+        &self,
+        values: &'a [T],
+        cancel: &Arc<AtomicBool>,
+    ) -> Result<Vec<&'a T>, FilterError> {
+        self.start_batch()?;
+        let watchdog = self.watchdog(Some(cancel));
+        let mut kept = Vec::new();
+        for (index, tx) in values.iter().enumerate() {
+            let cancelled = || FilterError::Cancelled {
+                processed: index,
+                kept: kept.clone(),
+            };
+            if cancel.load(Ordering::Relaxed) {
+                return Err(cancelled());
+            }
+            match watchdog.watch(self.runtime, || {
+                self.run_filters(tx, &mlua::Value::Nil, None)
+            })? {
+                Ok(true) => kept.push(index),
+                Ok(false) => {}
+                Err(err) if err.trip() == Some(Trip::Cancelled) => return Err(cancelled()),
+                Err(err) => return Err(err),
+            }
+        }
+        self.finish_batch()?;
+        Ok(kept.into_iter().map(|index| &values[index]).collect())
+    }
+
+    /// Filter a slice of values until `deadline`, returning references to the matches found by
+    /// then.
+    ///
+    /// Like cancellation, the deadline is checked between values and from the instruction
+    /// hook inside running filters, so a slow filter call is cut short too; the value it was
+    /// evaluating counts as not evaluated. Hitting the deadline isn't an error: the result
+    /// tells where processing stopped.
+    pub fn filter_with_deadline<'a>(
+        &self,
+        values: &'a [T],
+        deadline: Instant,
+    ) -> Result<PartialResult<'a, T>, FilterError> {
+        self.start_batch()?;
+        let watchdog = Watchdog {
+            deadline: Some(deadline),
+            ..self.watchdog(None)
+        };
+        let mut result = PartialResult {
+            kept: Vec::new(),
+            stopped_at: values.len(),
+            deadline_hit: false,
+        };
+        for (index, tx) in values.iter().enumerate() {
+            let evaluated = match Instant::now() >= deadline {
+                true => None,
+                false => match watchdog.watch(self.runtime, || {
+                    self.run_filters(tx, &mlua::Value::Nil, None)
+                })? {
+                    Err(err) if err.trip() == Some(Trip::Deadline) => None,
+                    evaluated => Some(evaluated?),
+                },
+            };
+            match evaluated {
+                Some(true) => result.kept.push(tx),
+                Some(false) => {}
+                None => {
+                    result.stopped_at = index;
+                    result.deadline_hit = true;
+                    break;
+                }
+            }
+        }
+        self.finish_batch()?;
+        Ok(result)
+    }
+
+    /// Filter a list of values in place, dropping the ones that didn't match.
+    ///
+    /// Every value is evaluated before anything is removed, so on error the list is untouched.
+    pub fn retain(&self, values: &mut Vec<T>) -> Result<(), FilterError> {
+        let mut keep = self
+            .keep_mask(values.iter(), &mlua::Value::Nil)?
+            .into_iter();
+        values.retain(|_| keep.next().unwrap_or(false));
+        Ok(())
+    }
+}
+
+impl<'lua, T, B> FilterSystem<'lua, T, B>
+where
+    T: LuaUserData + Serialize + DeserializeOwned + Send + Sync + 'lua,
+    B: FilterBackend + 'lua,
+    B::Filter: 'lua,
+{
+    /// Replay a recording made by [`record`](Self::record) through the loaded filters,
+    /// reporting every verdict that differs from the recorded one.
+    ///
+    /// Filters missing from either side differ on every value. Blank lines are skipped.
+    pub fn verify_recording(&self, reader: impl BufRead) -> Result<VerifyReport, RecordingError> {
+        self.start_batch()?;
+        let mut report = VerifyReport::default();
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let json = |error| RecordingError::Json {
+                line: index + 1,
+                error,
+            };
+            let recorded: recording::Recorded<serde_json::Value> =
+                serde_json::from_str(&line).map_err(json)?;
+            let value: T = serde_json::from_value(recorded.value.clone()).map_err(json)?;
+            let mut verdict = Verdict::default();
+            self.evaluate_with(&value, &mlua::Value::Nil, Some(&mut verdict))?;
+            let replayed = self.verdicts(verdict);
+
+            report.values += 1;
+            let names: BTreeSet<&String> =
+                recorded.verdicts.keys().chain(replayed.keys()).collect();
+            let mut differing = false;
+            for name in names {
+                let (before, after) = (recorded.verdicts.get(name), replayed.get(name));
+                if before != after {
+                    differing = true;
+                    let (before, after) = (before.copied(), after.copied());
+                    report.push(name.clone(), index + 1, &recorded.value, before, after);
+                }
+            }
+            report.differing += usize::from(differing);
+        }
+        self.finish_batch()?;
+        Ok(report)
+    }
+
+    /// Filter the values of an NDJSON export, one per line, counting the verdicts of each
+    /// filter, see [`ReplayReport`].
+    ///
+    /// Lines that aren't values are reported rather than failing the replay; blank lines are
+    /// skipped. Lines may carry an `expected` field, see
+    /// [`replay_ndjson_with`](Self::replay_ndjson_with). Values are read through serde, so
+    /// exports of loosely typed values need a `T` wrapping [`serde_json::Value`].
+    pub fn replay_ndjson(&self, reader: impl BufRead) -> Result<ReplayReport, RecordingError> {
+        self.replay_ndjson_with(reader, ReplayOptions::default())
+    }
+
+    /// [`replay_ndjson`](Self::replay_ndjson), checking the values whose line is an object
+    /// with a boolean `expected` field, taken out of the value, get that verdict.
+    pub fn replay_ndjson_with(
+        &self,
+        reader: impl BufRead,
+        options: ReplayOptions,
+    ) -> Result<ReplayReport, RecordingError> {
+        self.start_batch()?;
+        let mut report = ReplayReport::default();
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let Ok((value, expected)) = replay::parse::<T>(&line) else {
+                report.parse_errors.push(index + 1);
+                continue;
+            };
+            let mut verdict = Verdict::default();
+            let kept = self.evaluate_with(&value, &mlua::Value::Nil, Some(&mut verdict))?;
+            report.values += 1;
+            report.kept += usize::from(kept);
+            for (name, matched) in self.verdicts(verdict) {
+                let counts = report.filters.entry(name).or_default();
+                if matched {
+                    counts.matched += 1;
+                } else {
+                    counts.rejected += 1;
+                }
+            }
+            if let Some(expected) = expected.filter(|expected| *expected != kept) {
+                report.mismatches.push(Mismatch {
+                    line: index + 1,
+                    expected,
+                    kept,
+                });
+                if options.stop_at_mismatch {
+                    break;
+                }
+            }
+        }
+        self.finish_batch()?;
+        Ok(report)
+    }
+}
+
+impl<'lua, T, B> FilterSystem<'lua, T, B> {
+    /// Expose the environment variables, `memo` cache and libraries of the system to scripts,
+    /// unless it is the last system sharing the runtime that did.
+    fn activate(&self) -> mlua::Result<()> {
+        match helpers::memo::is_active(self.runtime, &self.memo) {
+            true => Ok(()),
+            false => self.expose(),
+        }
+    }
+
+    /// Expose the environment variables, `memo` cache and libraries of the system to scripts,
+    /// in place of those of the system that did before.
+    fn expose(&self) -> mlua::Result<()> {
+        env::set(self.runtime, &self.expose_env);
+        helpers::memo::activate(self.runtime, &self.memo);
+        let globals = self.runtime.globals();
+        let exposed = self.runtime.remove_app_data::<ExposedLibraries>();
+        for name in exposed.iter().flat_map(|exposed| &exposed.0) {
+            if !self.libraries.contains_key(name) {
+                globals.raw_set(name.as_str(), mlua::Value::Nil)?;
+            }
+        }
+        for (name, library) in &self.libraries {
+            globals.raw_set(name.as_str(), library.clone())?;
+        }
+        let names = self.libraries.keys().cloned().collect();
+        self.runtime.set_app_data(ExposedLibraries(names));
+        Ok(())
+    }
+}
+
+impl<'lua, T, B> Drop for FilterSystem<'lua, T, B> {
+    /// Release the filters, like [`FilterSystem::remove`] does, so a runtime outliving its
+    /// filter systems doesn't keep their memory.
+    fn drop(&mut self) {
+        self.filters.clear();
+        self.memo.clear(self.runtime);
+        // The libraries of the system go with it, unless another system exposes its own since.
+        if helpers::memo::is_active(self.runtime, &self.memo) {
+            self.libraries.clear();
+            self.expose_env.clear();
+            let _ = self.expose();
+        }
+        let _ = self.runtime.gc_collect();
+    }
+}
+
+/// Check every filter `expression` names is among `names`, the filters of `chain`.
+fn known_filters(chain: &str, expression: &Expression, names: &[&str]) -> Result<(), LoadError> {
+    let mut filters = expression.filters().into_iter();
+    match filters.find(|(name, _)| !names.contains(name)) {
+        Some((filter, column)) => Err(LoadError::Expression {
+            chain: chain.to_string(),
+            filter: filter.to_string(),
+            column,
+        }),
+        None => Ok(()),
+    }
+}
+
+/// The filter functions a script evaluated to as `module`, by name, with a single function
+/// named `name`; or why there are none.
+pub(crate) fn module_functions<'lua>(
+    module: &mlua::Value<'lua>,
+    name: &str,
+) -> mlua::Result<Result<Vec<(String, mlua::Function<'lua>)>, String>> {
+    let no_filters = |message: String| {
+        Ok(Err(format!(
+            "{message}; scripts return a table of filter functions by name, or a single filter \
+             function"
+        )))
+    };
+    match module {
+        mlua::Value::Table(module) => {
+            let mut functions = Vec::new();
+            for pair in module.clone().pairs::<mlua::Value, mlua::Value>() {
+                match pair? {
+                    (mlua::Value::String(name), _) if name == "api_version" => {}
+                    (mlua::Value::String(name), mlua::Value::Function(filter)) => {
+                        functions.push((name.to_str()?.to_string(), filter));
+                    }
+                    (name, filter) => {
+                        let name = match name.as_str() {
+                            Some(name) => format!("`{name}`"),
+                            None => format!("a key of type {}", name.type_name()),
+                        };
+                        let filter = filter.type_name();
+                        return no_filters(format!("{name} holds a value of type {filter}"));
+                    }
+                }
+            }
+            if functions.is_empty() {
+                return no_filters("it returned an empty table".to_string());
+            }
+            Ok(Ok(functions))
+        }
+        mlua::Value::Function(filter) => Ok(Ok(vec![(name.to_string(), filter.clone())])),
+        mlua::Value::Nil => no_filters("it returned nothing".to_string()),
+        other => no_filters(format!("it returned a value of type {}", other.type_name())),
+    }
+}
+
+/// The [`API_VERSIONS`] entry a script module declares, 1 if it declares none, or how it
+/// declares one the runtime doesn't support.
+pub(crate) fn api_version(module: &mlua::Value) -> Result<u32, String> {
+    let mlua::Value::Table(module) = module else {
+        return Ok(1);
+    };
+    let declared = match module.raw_get::<_, mlua::Value>("api_version") {
+        Ok(mlua::Value::Nil) => return Ok(1),
+        Ok(declared) => declared,
+        Err(err) => return Err(err.to_string()),
+    };
+    let version = match &declared {
+        mlua::Value::Integer(version) => u32::try_from(*version).ok(),
+        mlua::Value::Number(version) if version.fract() == 0.0 => Some(*version as u32),
+        _ => None,
+    };
+    match version {
+        Some(version) if API_VERSIONS.contains(&version) => Ok(version),
+        _ => Err(match declared {
+            mlua::Value::Integer(version) => version.to_string(),
+            mlua::Value::Number(version) => version.to_string(),
+            mlua::Value::String(version) => format!("{:?}", version.to_string_lossy()),
+            other => format!("of type {}", other.type_name()),
+        }),
+    }
+}
+
+/// Move the values whose flag in `keep` is set out of `values`, in order.
+fn drain_kept<T>(values: Vec<T>, keep: Vec<bool>) -> Vec<T> {
+    values
+        .into_iter()
+        .zip(keep)
+        .filter_map(|(value, keep)| keep.then_some(value))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, sync::atomic::AtomicUsize};
+
+    use indoc::indoc;
+    use serde::Serialize;
+
+    use super::*;
+
+    #[derive(Clone, Serialize, Deserialize)]
+    pub struct MockTx {
+        pub chain: String,
+        pub from: String,
+        pub to: String,
+        pub amount: u64,
+    }
+    impl mlua::UserData for MockTx {}
+
+    macro_rules! test_filter {
+        ($name:ident, $script:expr, $expected:expr) => {
+            #[test]
+            fn $name() {
+                let lua = mlua::Lua::new();
+                let module: mlua::Table = lua.load($script).eval().unwrap();
+
+                for pair in module.pairs::<String, mlua::Function>() {
+                    let (_name, filter) = pair.unwrap();
+                    let filter = Filter::new("$name".to_string(), filter);
+                    let tx = MockTx {
+                        chain: "uni-5".to_string(),
+                        from: "0xDEADBEEF".to_string(),
+                        to: "0xBEEFFEEF".to_string(),
+                        amount: 0,
+                    };
+                    let result = filter.filter(&lua, tx).unwrap();
+                    assert_eq!(result, $expected);
+                }
+            }
+        };
+    }
+
+    fn load_script<'lua, T>(lua: &'lua Lua, script: &str) -> FilterSystem<'lua, T>
+    where
+        T: LuaUserData + Serialize + Send + Sync + 'lua,
+    {
+        let mut system = FilterSystem::new(lua);
+        let module: mlua::Table = lua.load(script).eval().unwrap();
+        for pair in module.pairs::<String, mlua::Function>() {
+            let (name, filter) = pair.unwrap();
+            system.filters.push(Filter::new(name, filter));
+        }
+        system
+    }
+
+    fn mock_tx(from: &str, amount: u64) -> MockTx {
+        MockTx {
+            chain: "uni-5".to_string(),
+            from: from.to_string(),
+            to: "0xBEEFFEEF".to_string(),
+            amount,
+        }
+    }
+
+    /// A temporary directory for the scripts of a test, removed when dropped.
+    struct Scripts {
+        dir: PathBuf,
+    }
+
+    impl Scripts {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!(
+                "croncat-indexer-filter-{name}-{}",
+                std::process::id()
+            ));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            Self { dir }
+        }
+
+        /// Write `source` to `file` in the directory, returning its path.
+        fn write(&self, file: &str, source: &str) -> PathBuf {
+            let path = self.dir.join(file);
+            std::fs::write(&path, source).unwrap();
+            path
+        }
+
+        /// Write `source` to `<name>.lua`, returning the configuration of a filter `name`
+        /// running it.
+        fn filter(&self, name: &str, source: &str) -> FilterConfig {
+            FilterConfig {
+                name: name.to_string(),
+                script: self.write(&format!("{name}.lua"), source),
+                ..Default::default()
+            }
+        }
+    }
+
+    impl Drop for Scripts {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    #[test]
+    fn config() {
+        let input = indoc! {r#"
+        chains:
+            uni-5:
+                - name: Testnet Manager
+                  script: filters/test-filter.lua
+        "#};
+
+        let config: Config = serde_yaml::from_str(input).unwrap();
+        assert_eq!(config.chains.len(), 1);
+        assert_eq!(config.chains["uni-5"].len(), 1);
+        assert_eq!(config.chains["uni-5"][0].name, "Testnet Manager");
+        assert_eq!(
+            config.chains["uni-5"][0].script.to_str().unwrap(),
+            "filters/test-filter.lua"
+        );
+    }
+
+    test_filter!(
+        simple_filter,
+        indoc! {r#"
+        function filter(tx)
+            return tx.from == "0xDEADBEEF"
+        end
+
+        return {
+            filter = filter
+        }
+        "#},
+        true
+    );
+
+    #[test]
+    fn filter_system() {
+        let config = Config {
+            chains: {
+                let mut chains = HashMap::new();
+                chains.insert(
+                    "uni-5".to_string(),
+                    vec![FilterConfig {
+                        name: "Testnet Manager".to_string(),
+                        script: PathBuf::from("filters/test-filter.lua"),
+                        ..Default::default()
+                    }],
+                );
+                chains
+            },
+            ..Default::default()
+        };
+
+        let filter_runtime = FilterRuntime::new();
+        let filter_system = filter_runtime.load(config).unwrap();
+
+        let txs = vec![
+            MockTx {
+                chain: "uni-5".to_string(),
+                from: "0xDEADBEEF".to_string(),
+                to: "0xBEEFFEEF".to_string(),
+                amount: 0,
+            },
+            MockTx {
+                chain: "uni-5".to_string(),
+                from: "0xBEEFFEEF".to_string(),
+                to: "0xDEADDEAD".to_string(),
+                amount: 0,
+            },
+        ];
+
+        let filtered_txs = filter_system.filter(txs).unwrap();
+
+        assert_eq!(filtered_txs.len(), 1);
+        assert_eq!(filtered_txs[0].from, "0xDEADBEEF");
+        assert_eq!(filtered_txs[0].to, "0xBEEFFEEF");
+    }
+
+    #[test]
+    fn filter_apis_agree() {
+        let lua = Lua::new();
+        let filter_system = load_script(
+            &lua,
+            indoc! {r#"
+            return {
+                big = function(tx) return tx.amount >= 100 end,
+                manager = function(tx) return tx.from == "0xDEADBEEF" end,
+            }
+            "#},
+        );
+
+        let txs = vec![
+            mock_tx("0xDEADBEEF", 0),
+            mock_tx("0xBEEFFEEF", 10),
+            mock_tx("0xBEEFFEEF", 500),
+            mock_tx("0xDEADDEAD", 99),
+            mock_tx("0xDEADBEEF", 1000),
+        ];
+
+        let indices = filter_system.filter_indices(&txs).unwrap();
+        assert_eq!(indices, vec![0, 2, 4]);
+
+        let refs = filter_system.filter_ref(&txs).unwrap();
+        assert_eq!(refs.len(), indices.len());
+        for (tx, &index) in refs.iter().zip(&indices) {
+            assert!(std::ptr::eq(*tx, &txs[index]));
+        }
+
+        let mut retained = txs.clone();
+        filter_system.retain(&mut retained).unwrap();
+        let amounts = |txs: &[MockTx]| txs.iter().map(|tx| tx.amount).collect::<Vec<_>>();
+        assert_eq!(amounts(&retained), vec![0, 500, 1000]);
+        assert_eq!(
+            amounts(&filter_system.filter(txs.clone()).unwrap()),
+            amounts(&retained)
+        );
+
+        for (index, tx) in txs.iter().enumerate() {
+            let expected = indices.contains(&index);
+            assert_eq!(filter_system.filter_one(tx.clone()).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn retain_leaves_values_on_error() {
+        let lua = Lua::new();
+        let filter_system = load_script(
+            &lua,
+            indoc! {r#"
+            return {
+                filter = function(tx)
+                    if tx.amount > 1 then error("too big") end
+                    return true
+                end,
+            }
+            "#},
+        );
+
+        let mut txs = vec![mock_tx("0xDEADBEEF", 0), mock_tx("0xDEADBEEF", 2)];
+        assert!(filter_system.retain(&mut txs).is_err());
+        assert_eq!(txs.len(), 2);
+    }
+
+    #[test]
+    fn filter_chunked() {
+        let lua = Lua::new();
+        let filter_system = load_script(
+            &lua,
+            indoc! {r#"
+            return {
+                even = function(tx) return tx.amount % 2 == 0 end,
+            }
+            "#},
+        );
+        let txs = (0..10)
+            .map(|n| mock_tx("0xDEADBEEF", n))
+            .collect::<Vec<_>>();
+
+        let mut reports = Vec::new();
+        let chunked = filter_system
+            .filter_chunked(txs.clone(), 3, |report| {
+                reports.push(report);
+                ControlFlow::Continue(())
+            })
+            .unwrap();
+        let single = filter_system.filter(txs.clone()).unwrap();
+        let amounts = |txs: &[MockTx]| txs.iter().map(|tx| tx.amount).collect::<Vec<_>>();
+        assert_eq!(amounts(&chunked), amounts(&single));
+        assert_eq!(
+            reports
+                .iter()
+                .map(|r| (r.processed, r.kept))
+                .collect::<Vec<_>>(),
+            vec![(3, 2), (6, 3), (9, 5), (10, 5)]
+        );
+
+        let partial = filter_system
+            .filter_chunked(txs, 4, |report| {
+                if report.processed >= 4 {
+                    ControlFlow::Break(())
+                } else {
+                    ControlFlow::Continue(())
+                }
+            })
+            .unwrap();
+        assert_eq!(amounts(&partial), vec![0, 2]);
+    }
+
+    #[test]
+    fn filter_chunked_counts_lenient_errors() {
+        let lua = Lua::new();
+        let mut filter_system = load_script(
+            &lua,
+            indoc! {r#"
+            return {
+                filter = function(tx)
+                    if tx.amount == 1 then error("bad value") end
+                    return true
+                end,
+            }
+            "#},
+        );
+        let txs = (0..4).map(|n| mock_tx("0xDEADBEEF", n)).collect::<Vec<_>>();
+        assert!(filter_system
+            .filter_chunked(txs.clone(), 2, |_| ControlFlow::Continue(()))
+            .is_err());
+
+        filter_system.set_error_policy(ErrorPolicy::Lenient);
+        let mut errors = Vec::new();
+        let kept = filter_system
+            .filter_chunked(txs, 2, |report| {
+                errors.push(report.errors);
+                ControlFlow::Continue(())
+            })
+            .unwrap();
+        assert_eq!(kept.len(), 3);
+        assert_eq!(errors, vec![1, 1]);
+    }
+
+    #[test]
+    fn filter_streaming() {
+        let lua = Lua::new();
+        let mut filter_system = load_script(
+            &lua,
+            indoc! {r#"
+            return {
+                filter = function(tx)
+                    if tx.amount == 1 then error("bad value") end
+                    return tx.amount % 2 == 0
+                end,
+            }
+            "#},
+        );
+        let txs = || (0..10).map(|n| mock_tx("0xDEADBEEF", n));
+
+        let err = filter_system
+            .filter_streaming(txs(), |_| Ok::<_, String>(()))
+            .unwrap_err();
+        assert!(
+            matches!(err, StreamError::Filter(FilterError::Script(_))),
+            "{err}"
+        );
+
+        filter_system.set_error_policy(ErrorPolicy::Lenient);
+        let mut sunk = Vec::new();
+        let summary = filter_system
+            .filter_streaming(txs(), |tx| {
+                sunk.push(tx.amount);
+                Ok::<_, String>(())
+            })
+            .unwrap();
+        assert_eq!(sunk, [0, 2, 4, 6, 8]);
+        assert_eq!(
+            summary,
+            StreamSummary {
+                processed: 10,
+                kept: 5,
+                errors: 1,
+            }
+        );
+
+        // A failing sink stops the stream right away.
+        let mut processed = 0;
+        let inputs = txs().inspect(|_| processed += 1);
+        let err = filter_system
+            .filter_streaming(inputs, |tx| match tx.amount {
+                4 => Err("disk full"),
+                _ => Ok(()),
+            })
+            .unwrap_err();
+        assert!(matches!(err, StreamError::Sink("disk full")), "{err}");
+        assert_eq!(processed, 5);
+    }
+
+    #[test]
+    fn filter_with_cancel() {
+        let lua = Lua::new();
+        let filter_system = load_script(
+            &lua,
+            indoc! {r#"
+            return {
+                even = function(tx)
+                    while tx.amount == 3 do end
+                    return tx.amount % 2 == 0
+                end,
+            }
+            "#},
+        );
+        let txs = (0..6).map(|n| mock_tx("0xDEADBEEF", n)).collect::<Vec<_>>();
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        let kept = filter_system
+            .filter_with_cancel(&txs[..3], &cancel)
+            .unwrap();
+        assert_eq!(kept.len(), 2);
+
+        let canceller = {
+            let cancel = cancel.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(50));
+                cancel.store(true, Ordering::Relaxed);
+            })
+        };
+        let started = Instant::now();
+        let result = filter_system.filter_with_cancel(&txs, &cancel);
+        canceller.join().unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        match result {
+            Err(FilterError::Cancelled { processed, kept }) => {
+                assert_eq!(processed, 3);
+                assert_eq!(kept, vec![0, 2]);
+            }
+            _ => panic!("expected the call to be cancelled"),
+        }
+
+        // The system stays usable once the hook is gone.
+        assert_eq!(filter_system.filter_ref(&txs[..3]).unwrap().len(), 2);
+        assert!(matches!(
+            filter_system.filter_with_cancel(&txs, &cancel),
+            Err(FilterError::Cancelled { processed: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn config_retries() {
+        let input = indoc! {r#"
+        chains:
+            uni-5:
+                - name: Flaky Lookup
+                  script: filters/test-filter.lua
+                  retries: 2
+                  retry_backoff_ms: 10
+                  fuel_budget: 100000
+                - name: Testnet Manager
+                  script: filters/test-filter.lua
+        "#};
+
+        let config: Config = serde_yaml::from_str(input).unwrap();
+        let filters = &config.chains["uni-5"];
+        assert_eq!(
+            filters[0].retry_policy(),
+            RetryPolicy {
+                retries: 2,
+                backoff: Duration::from_millis(10),
+            }
+        );
+        assert_eq!(filters[1].retry_policy(), RetryPolicy::default());
+        assert_eq!(filters[0].fuel_budget, Some(100_000));
+        assert_eq!(filters[1].fuel_budget, None);
+    }
+
+    #[test]
+    fn env_denied_in_stats() {
+        let filter_runtime = FilterRuntime::<MockTx>::new();
+        let filter_system = load_script(
+            &filter_runtime.runtime,
+            indoc! {r#"
+            return {
+                filter = function(tx)
+                    return env("CRONCAT_OPERATOR_ADDR") == tx.from
+                end,
+            }
+            "#},
+        );
+        assert!(!filter_system.filter_one(mock_tx("0xDEADBEEF", 0)).unwrap());
+        assert_eq!(filter_system.stats()[0].env_denied, 1);
+    }
+
+    #[test]
+    fn env_survives_failed_reload() {
+        std::env::set_var("CRONCAT_RELOAD_ENV_TEST", "juno1operator");
+        let scripts = Scripts::new("env-reload");
+        let operator = scripts.filter(
+            "operator",
+            r#"return function(tx) return env("CRONCAT_RELOAD_ENV_TEST") == tx.from end"#,
+        );
+        let config = |filter: FilterConfig| Config {
+            chains: [("uni-5".to_string(), vec![filter])].into(),
+            expose_env: vec!["CRONCAT_RELOAD_ENV_TEST".to_string()],
+            ..Default::default()
+        };
+
+        let filter_runtime = FilterRuntime::<MockTx>::new();
+        let mut filter_system = filter_runtime.load(config(operator.clone())).unwrap();
+        assert!(filter_system
+            .filter_one(mock_tx("juno1operator", 0))
+            .unwrap());
+        let mut failing = config(FilterConfig {
+            script: scripts.dir.join("missing.lua"),
+            ..operator
+        });
+        failing.expose_env.clear();
+        filter_system.reload(failing).unwrap_err();
+        // The filters kept see the variables their configuration exposed.
+        assert!(filter_system
+            .filter_one(mock_tx("juno1operator", 0))
+            .unwrap());
+        assert_eq!(filter_system.stats()[0].env_denied, 0);
+    }
+
+    #[test]
+    fn filter_retries() {
+        let lua = Lua::new();
+        let module: mlua::Table = lua
+            .load(indoc! {r#"
+            local calls = 0
+            return {
+                flaky = function(tx)
+                    calls = calls + 1
+                    if calls <= 2 then error("lookup failed") end
+                    return true
+                end,
+                broken = function(tx) error("always") end,
+            }
+            "#})
+            .eval()
+            .unwrap();
+        let policy = |retries| RetryPolicy {
+            retries,
+            backoff: Duration::from_millis(1),
+        };
+
+        let flaky = Filter::new("flaky".to_string(), module.get("flaky").unwrap())
+            .with_retry_policy(policy(2));
+        assert!(flaky.filter(&lua, mock_tx("0xDEADBEEF", 0)).unwrap());
+        let stats = flaky.stats();
+        assert_eq!((stats.invocations, stats.retries, stats.errors), (1, 2, 0));
+
+        let broken = Filter::new("broken".to_string(), module.get("broken").unwrap())
+            .with_retry_policy(policy(1));
+        match broken.filter(&lua, mock_tx("0xDEADBEEF", 0)) {
+            Err(FilterError::Script(err)) => {
+                assert_eq!(err.filter, "broken");
+                assert_eq!(err.attempts, 2);
+            }
+            _ => panic!("expected the retries to be exhausted"),
+        }
+        let stats = broken.stats();
+        assert_eq!((stats.invocations, stats.retries, stats.errors), (1, 1, 1));
+    }
+
+    #[test]
+    fn helpers_are_preloaded() {
+        let filter_runtime = FilterRuntime::<MockTx>::new();
+        let filter_system = load_script(
+            &filter_runtime.runtime,
+            indoc! {r#"
+            return {
+                filter = function(tx)
+                    return json.decode(tx.to).contract == "croncat"
+                end,
+            }
+            "#},
+        );
+        let mut tx = mock_tx("0xDEADBEEF", 0);
+        tx.to = r#"{"contract": "croncat"}"#.to_string();
+        assert!(filter_system.filter_one(tx).unwrap());
+    }
+
+    #[test]
+    fn filter_with_context() {
+        #[derive(Clone, Serialize)]
+        struct TimedTx {
+            timestamp: String,
+        }
+        impl mlua::UserData for TimedTx {}
+
+        #[derive(Serialize)]
+        struct Context {
+            block_time: i64,
+        }
+
+        let filter_runtime = FilterRuntime::<TimedTx>::new();
+        let mut filter_system = FilterSystem::new(&filter_runtime.runtime);
+        let filter = filter_runtime
+            .runtime
+            .load(indoc! {r#"
+            return function(tx, ctx)
+                return time.parse_rfc3339(tx.timestamp) >= ctx.block_time - 3600
+            end
+            "#})
+            .eval()
+            .unwrap();
+        filter_system
+            .filters
+            .push(Filter::new("recent".to_string(), filter));
+
+        let txs = [
+            "2023-01-10T11:30:00Z",
+            "2023-01-10T10:59:59Z",
+            "2023-01-10T13:15:00+02:00",
+        ]
+        .into_iter()
+        .map(|timestamp| TimedTx {
+            timestamp: timestamp.to_string(),
+        })
+        .collect();
+        let context = Context {
+            // 2023-01-10T12:00:00Z
+            block_time: 1673352000,
+        };
+        let kept = filter_system.filter_with_context(txs, &context).unwrap();
+        assert_eq!(
+            kept.iter()
+                .map(|tx| tx.timestamp.as_str())
+                .collect::<Vec<_>>(),
+            vec!["2023-01-10T11:30:00Z", "2023-01-10T13:15:00+02:00"]
+        );
+    }
+
+    #[test]
+    fn context_provider() {
+        #[derive(Serialize)]
+        struct Context {
+            block: u64,
+        }
+
+        let filter_runtime = FilterRuntime::<MockTx>::new();
+        let mut filter_system = FilterSystem::new(&filter_runtime.runtime);
+        let filter = filter_runtime
+            .runtime
+            .load("return function(tx, ctx) return ctx ~= nil and tx.amount <= ctx.block end")
+            .eval()
+            .unwrap();
+        filter_system
+            .filters
+            .push(Filter::new("reached".to_string(), filter));
+        let batch = || (1..=5).map(|amount| mock_tx("0xA", amount)).collect();
+        let amounts = |kept: Vec<MockTx>| kept.iter().map(|tx| tx.amount).collect::<Vec<_>>();
+
+        // The block height is read once per batch, wherever it is tracked.
+        let height = Arc::new(std::sync::atomic::AtomicU64::new(2));
+        let block = height.clone();
+        filter_system.set_context_provider(move || Context {
+            block: block.load(Ordering::Relaxed),
+        });
+        assert_eq!(amounts(filter_system.filter(batch()).unwrap()), [1, 2]);
+        height.store(4, Ordering::Relaxed);
+        assert_eq!(
+            amounts(filter_system.filter(batch()).unwrap()),
+            [1, 2, 3, 4]
+        );
+        assert!(filter_system.filter_one(mock_tx("0xA", 4)).unwrap());
+        let context = Context { block: 1 };
+        let kept = filter_system
+            .filter_with_context(batch(), &context)
+            .unwrap();
+        assert_eq!(amounts(kept), [1]);
+
+        // Providers can be swapped, and failing ones fail the batch.
+        filter_system.set_context_provider(|| Context { block: 5 });
+        assert_eq!(filter_system.filter(batch()).unwrap().len(), 5);
+        filter_system.set_context_provider(|| -> Context { panic!("no block yet") });
+        let err = filter_system.filter(batch()).err().unwrap();
+        assert!(
+            matches!(&err, FilterError::ContextProvider { message } if message == "panicked: no block yet"),
+            "{err}"
+        );
+        assert_eq!(filter_system.stats()[0].invocations, 21);
+        filter_system.clear_context_provider();
+        assert!(filter_system.filter(batch()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn verdict_cache() {
+        let scripts = Scripts::new("verdict-cache");
+        let counting = scripts.filter(
+            "counting",
+            indoc! {r#"
+            calls = 0
+            return function(tx)
+                calls = calls + 1
+                return tx.amount > 10
+            end
+            "#},
+        );
+        let config = || Config {
+            chains: HashMap::from([("uni-5".to_string(), vec![counting.clone()])]),
+            ..Default::default()
+        };
+        let filter_runtime = FilterRuntime::<MockTx>::new();
+        let mut filter_system = filter_runtime.load(config()).unwrap();
+        let calls = || {
+            let globals = filter_runtime.runtime.globals();
+            globals.get::<_, u32>("calls").unwrap()
+        };
+        assert_eq!(filter_system.verdict_cache_stats(), None);
+        filter_system.set_verdict_cache(2);
+
+        // Values filtered again get their verdict without calling the filter.
+        assert!(filter_system.filter_one(mock_tx("0xA", 500)).unwrap());
+        assert!(filter_system.filter_one(mock_tx("0xA", 500)).unwrap());
+        assert!(!filter_system.filter_one(mock_tx("0xB", 1)).unwrap());
+        assert!(!filter_system.filter_one(mock_tx("0xB", 1)).unwrap());
+        assert_eq!(calls(), 2);
+        assert!(filter_system
+            .filter_one_uncached(mock_tx("0xA", 500))
+            .unwrap());
+        assert_eq!(calls(), 3);
+        assert_eq!(
+            filter_system.verdict_cache_stats(),
+            Some(VerdictCacheStats {
+                hits: 2,
+                misses: 2,
+                entries: 2,
+                capacity: 2,
+            })
+        );
+
+        // The least recently used verdict goes first.
+        filter_system.filter_one(mock_tx("0xA", 500)).unwrap();
+        filter_system.filter_one(mock_tx("0xC", 50)).unwrap();
+        filter_system.filter_one(mock_tx("0xA", 500)).unwrap();
+        assert_eq!(calls(), 4);
+        filter_system.filter_one(mock_tx("0xB", 1)).unwrap();
+        assert_eq!(calls(), 5);
+
+        // Reloading forgets the verdicts, as does clearing the cache.
+        filter_system.reload(config()).unwrap();
+        filter_system.filter_one(mock_tx("0xB", 1)).unwrap();
+        assert_eq!(calls(), 1);
+        filter_system.clear_verdict_cache();
+        filter_system.filter_one(mock_tx("0xB", 1)).unwrap();
+        assert_eq!(calls(), 2);
+
+        // Errors aren't cached.
+        filter_runtime
+            .runtime
+            .globals()
+            .set("calls", "many")
+            .unwrap();
+        assert!(filter_system.filter_one(mock_tx("0xD", 1)).is_err());
+        filter_runtime.runtime.globals().set("calls", 0).unwrap();
+        filter_system.filter_one(mock_tx("0xD", 1)).unwrap();
+        assert_eq!(calls(), 1);
+
+        // Keys may leave fields out.
+        filter_system.set_cache_key(|tx: &MockTx| tx.amount);
+        filter_system.filter_one(mock_tx("0xA", 500)).unwrap();
+        filter_system.filter_one(mock_tx("0xE", 500)).unwrap();
+        assert_eq!(calls(), 2);
+    }
+
+    #[test]
+    fn bundles() {
+        let scripts = Scripts::new("bundles");
+        let library = scripts.write("limits.lua", "return { big = 100 }");
+        let rules = scripts.write(
+            "rules.lua",
+            indoc! {r#"
+            return {
+                big = function(tx) return tx.amount > limits.big end,
+                factory = function(tx) return tx.to == chain.factory end,
+                blocklisted = function(tx) return tx.from == "0xBAD" end,
+                noisy = function(tx) return true end,
+            }
+            "#},
+        );
+        let other = scripts.write("other.lua", "return function(tx) return tx.amount == 7 end");
+        let input = format!(
+            indoc! {r#"
+            libraries:
+                limits: {}
+            constants:
+                uni-5:
+                    factory: "0xFACADE"
+            expressions:
+                uni-5: (big OR factory) AND NOT blocklisted
+            chains:
+                uni-5:
+                    - name: Rules
+                      script: {}
+                juno-1:
+                    - name: other
+                      script: {}
+            "#},
+            library.display(),
+            rules.display(),
+            other.display()
+        );
+        let config: Config = serde_yaml::from_str(&input).unwrap();
+        let fixtures = [
+            ("0xA", 500),
+            ("0xBAD", 500),
+            ("0xA", 7),
+            ("0xA", 1),
+            ("0xBAD", 7),
+        ];
+        let verdicts = |filter_system: &FilterSystem<MockTx>| -> Vec<bool> {
+            let fixtures = fixtures.into_iter();
+            fixtures
+                .map(|(from, amount)| filter_system.filter_one(mock_tx(from, amount)).unwrap())
+                .collect()
+        };
+
+        let filter_runtime = FilterRuntime::<MockTx>::new();
+        let mut filter_system = filter_runtime.load(config).unwrap();
+        assert_eq!(filter_system.remove("noisy"), 1);
+        assert_eq!(filter_system.set_dry_run("other", true), 1);
+        let expected = verdicts(&filter_system);
+        let json = filter_system.export_bundle().to_json().unwrap();
+        drop(scripts);
+
+        // Loading the bundle reads no file and gives the same filters.
+        let bundle = FilterBundle::from_json(&json).unwrap();
+        assert_eq!(bundle.version, BUNDLE_VERSION);
+        let filters = bundle.scripts.iter().flat_map(|script| &script.functions);
+        let mut names: Vec<_> = filters.map(|function| function.name.as_str()).collect();
+        names.sort();
+        assert_eq!(names, ["big", "blocklisted", "factory", "other"]);
+        let other_runtime = FilterRuntime::<MockTx>::new();
+        let loaded = other_runtime.load_bundle(bundle).unwrap();
+        assert_eq!(verdicts(&loaded), expected);
+        let report = loaded.load_report();
+        let mut functions: Vec<_> = report
+            .loaded
+            .iter()
+            .flat_map(|loaded| loaded.functions.clone())
+            .collect();
+        functions.sort();
+        assert_eq!(functions, ["big", "blocklisted", "factory", "other"]);
+        // The functions of a script table load in no particular order.
+        let functions = |bundle: &FilterBundle| {
+            let scripts = bundle.scripts.iter();
+            let mut functions: Vec<_> = scripts
+                .flat_map(|script| script.functions.iter().map(|f| (&script.chain, f.clone())))
+                .map(|(chain, function)| (chain.clone(), function.name, function.dry_run))
+                .collect();
+            functions.sort();
+            functions
+        };
+        let bundle = FilterBundle::from_json(&json).unwrap();
+        assert_eq!(functions(&loaded.export_bundle()), functions(&bundle));
+        assert!(functions(&bundle).contains(&("juno-1".to_string(), "other".to_string(), true)));
+
+        // Other versions of the format are refused.
+        let mut value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        value["version"] = 2.into();
+        let err = FilterBundle::from_json(&value.to_string()).err().unwrap();
+        assert!(matches!(err, BundleError::Version { version: 2 }), "{err}");
+        let mut bundle = FilterBundle::from_json(&json).unwrap();
+        bundle.version = 2;
+        let err = other_runtime.load_bundle(bundle).err().unwrap();
+        assert!(
+            matches!(err, LoadError::Bundle(BundleError::Version { version: 2 })),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn filter_ids() {
+        let scripts = Scripts::new("filter-ids");
+        let manager = scripts.filter(
+            "Testnet Manager",
+            indoc! {r#"
+            return {
+                filter = function(tx) return tx.amount > 100 end,
+                other = function(tx) return false end,
+            }
+            "#},
+        );
+        let mainnet = scripts.filter(
+            "Mainnet",
+            r#"return { filter = function(tx) assert(tx.amount > 0, "empty") return false end }"#,
+        );
+        let config = Config {
+            chains: HashMap::from([
+                ("uni-5".to_string(), vec![manager]),
+                ("juno-1".to_string(), vec![mainnet]),
+            ]),
+            ..Default::default()
+        };
+        let id = |chain: &str, config_name: &str, function_name: &str| FilterId {
+            chain: Some(chain.to_string()),
+            config_name: Some(config_name.to_string()),
+            function_name: function_name.to_string(),
+        };
+        let manager_filter = id("uni-5", "Testnet Manager", "filter");
+        let mainnet_filter = id("juno-1", "Mainnet", "filter");
+
+        let filter_runtime = FilterRuntime::<MockTx>::new();
+        let mut filter_system = filter_runtime.load(config).unwrap();
+        let mut ids: Vec<_> = filter_system
+            .load_report()
+            .loaded
+            .iter()
+            .flat_map(LoadedFilter::ids)
+            .collect();
+        ids.sort();
+        let mut stats: Vec<_> = filter_system.stats().into_iter().map(|s| s.id).collect();
+        stats.sort();
+        assert_eq!(stats, ids);
+        assert_eq!(
+            ids,
+            [
+                mainnet_filter.clone(),
+                manager_filter.clone(),
+                id("uni-5", "Testnet Manager", "other"),
+            ]
+        );
+        let stats = serde_json::to_value(filter_system.stats()).unwrap();
+        let stats = stats.as_array().unwrap().iter();
+        assert!(stats
+            .map(|stats| &stats["id"])
+            .any(|id| id == "uni-5/Testnet Manager/filter"));
+
+        // The full id and its suffixes are accepted, if they name a single filter.
+        for name in ["uni-5/Testnet Manager/filter", "Testnet Manager/filter"] {
+            assert_eq!(filter_system.filter_id(name).unwrap(), manager_filter);
+        }
+        assert_eq!(
+            filter_system.filter_id("other").unwrap().function_name,
+            "other"
+        );
+        let err = filter_system.filter_id("filter").unwrap_err();
+        let ResolveError::Ambiguous { candidates, .. } = &err else {
+            panic!("{err}");
+        };
+        assert_eq!(candidates.len(), 2);
+        assert!(err.to_string().contains("`juno-1/Mainnet/filter`"), "{err}");
+        let err = filter_system.filter_id("Manager/filter").unwrap_err();
+        assert_eq!(err.to_string(), "no filter is called `Manager/filter`");
+
+        // Verdicts are told apart by id, even for functions sharing a name.
+        let mut recording = Vec::new();
+        filter_system
+            .record(&[mock_tx("0xA", 500)], &mut recording)
+            .unwrap();
+        let recorded: serde_json::Value = serde_json::from_slice(&recording).unwrap();
+        assert_eq!(
+            recorded["verdicts"],
+            serde_json::json!({
+                "juno-1/Mainnet/filter": false,
+                "uni-5/Testnet Manager/filter": true,
+                "uni-5/Testnet Manager/other": false,
+            })
+        );
+        let verdict = filter_system
+            .filter_one_detailed(mock_tx("0xA", 500))
+            .unwrap();
+        assert_eq!(verdict.matched_by, ["uni-5/Testnet Manager/filter"]);
+
+        // Errors name the filter by its id.
+        let err = filter_system.filter_one(mock_tx("0xA", 0)).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("filter juno-1/Mainnet/filter of "),
+            "{err}"
+        );
+
+        assert_eq!(
+            filter_system.set_dry_run("uni-5/Testnet Manager/filter", true),
+            1
+        );
+        assert!(!filter_system.filter_one(mock_tx("0xA", 500)).unwrap());
+        assert_eq!(filter_system.remove("juno-1/Mainnet/filter"), 1);
+        // Bare function names still name the filters of every chain.
+        assert_eq!(filter_system.remove("filter"), 1);
+        assert_eq!(filter_system.stats().len(), 1);
+    }
+
+    #[test]
+    fn api_versions() {
+        let scripts = Scripts::new("api-versions");
+        // Each filter matches when it is called with the arguments of its version.
+        let v1 = scripts.filter(
+            "v1",
+            "return { old = function(...) return select('#', ...) == 1 end }",
+        );
+        let undeclared = scripts.filter(
+            "undeclared",
+            "return function(...) return select('#', ...) == 1 end",
+        );
+        let v2 = scripts.filter(
+            "v2",
+            indoc! {r#"
+            return {
+                api_version = 2,
+                new = function(tx, ctx, ...)
+                    return select('#', ...) == 0 and ctx.block == 10
+                end,
+            }
+            "#},
+        );
+        let config = |filters: Vec<FilterConfig>| Config {
+            chains: HashMap::from([("uni-5".to_string(), filters)]),
+            ..Default::default()
+        };
+
+        let filter_runtime = FilterRuntime::<MockTx>::new();
+        let mut filter_system = filter_runtime
+            .load(config(vec![v1.clone(), undeclared, v2]))
+            .unwrap();
+        filter_system.set_error_policy(ErrorPolicy::Lenient);
+        #[derive(Serialize)]
+        struct Context {
+            block: u64,
+        }
+        let context = Context { block: 10 };
+        assert!(filter_system
+            .filter_one_with_context(mock_tx("0xA", 1), &context)
+            .unwrap());
+        let matches: Vec<_> = filter_system
+            .stats()
+            .into_iter()
+            .map(|stats| (stats.name, stats.matches, stats.errors))
+            .collect();
+        assert_eq!(
+            matches,
+            [
+                ("old".to_string(), 1, 0),
+                ("undeclared".to_string(), 1, 0),
+                ("new".to_string(), 1, 0)
+            ]
+        );
+
+        // Versions out of the supported range fail the load, naming the script.
+        for (declared, shown) in [
+            ("3", "3"),
+            ("0", "0"),
+            ("'two'", "\"two\""),
+            ("{}", "of type table"),
+        ] {
+            let future = scripts.filter(
+                "future",
+                &format!("return {{ api_version = {declared}, f = function(tx) return true end }}"),
+            );
+            let err = filter_runtime
+                .load(config(vec![v1.clone(), future]))
+                .err()
+                .unwrap();
+            assert!(matches!(err, LoadError::ApiVersion { .. }), "{err}");
+            assert_eq!(
+                err.to_string(),
+                format!(
+                    "script {} of chain `uni-5` declares api_version {shown}, this runtime \
+                     supports 1-2",
+                    scripts.dir.join("future.lua").display()
+                )
+            );
+        }
+    }
+
+    #[cfg(feature = "signing")]
+    #[test]
+    fn signatures() {
+        use base64::Engine;
+
+        let scripts = Scripts::new("signatures");
+        let (ours, theirs) = ([7; 32], [8; 32]);
+        let key = |secret: &[u8; 32]| ed25519_dalek::SigningKey::from_bytes(secret).verifying_key();
+        let public = |secret| base64::engine::general_purpose::STANDARD.encode(key(secret));
+        let fingerprint = |secret| hex::encode(&key(secret).as_bytes()[..8]);
+        let source = "return function(tx) return true end";
+        let script = |name: &str, signature: Option<String>| FilterConfig {
+            signature,
+            ..scripts.filter(name, source)
+        };
+        let config = |filter: FilterConfig| Config {
+            chains: HashMap::from([("uni-5".to_string(), vec![filter])]),
+            trusted_keys: vec![public(&theirs), public(&ours)],
+            ..Default::default()
+        };
+        let filter_runtime = FilterRuntime::<MockTx>::new();
+
+        let signed = script("signed", Some(sign_script(&ours, source.as_bytes())));
+        let filter_system = filter_runtime.load(config(signed.clone())).unwrap();
+        assert!(filter_system.filter_one(mock_tx("0xA", 1)).unwrap());
+        filter_runtime
+            .load_with_backend(LuaBackend::new(), config(signed.clone()))
+            .unwrap();
+
+        // Without trusted keys, signatures aren't checked.
+        let unsigned = script("unsigned", None);
+        filter_runtime
+            .load(Config {
+                trusted_keys: Vec::new(),
+                ..config(unsigned.clone())
+            })
+            .unwrap();
+
+        let problem =
+            |filter: FilterConfig| match filter_runtime.load(config(filter.clone())).err().unwrap()
+            {
+                LoadError::Signature {
+                    filter: name,
+                    tried,
+                    problem,
+                    ..
+                } => {
+                    assert_eq!(name, filter.name);
+                    assert_eq!(tried, [fingerprint(&theirs), fingerprint(&ours)]);
+                    problem
+                }
+                err => panic!("{err}"),
+            };
+        assert_eq!(problem(unsigned), SignatureProblem::Unsigned);
+        let malformed = script("malformed", Some("not a signature".to_string()));
+        assert_eq!(problem(malformed), SignatureProblem::Malformed);
+        let stranger = script("stranger", Some(sign_script(&[9; 32], source.as_bytes())));
+        assert_eq!(problem(stranger), SignatureProblem::Untrusted);
+        // Changing the script after signing it breaks the signature.
+        std::fs::write(&signed.script, "return function(tx) return false end").unwrap();
+        assert_eq!(problem(signed.clone()), SignatureProblem::Untrusted);
+        let err = filter_runtime
+            .load_with_backend(LuaBackend::new(), config(signed))
+            .err()
+            .unwrap();
+        assert!(
+            matches!(
+                err,
+                LoadError::Signature {
+                    problem: SignatureProblem::Untrusted,
+                    ..
+                }
+            ),
+            "{err}"
+        );
+
+        // A failed reload leaves the keys later loads check signatures against as they were.
+        let kept = script("kept", Some(sign_script(&ours, source.as_bytes())));
+        let mut filter_system = filter_runtime.load(config(kept)).unwrap();
+        let missing = FilterConfig {
+            script: scripts.dir.join("missing.lua"),
+            ..script("absent", None)
+        };
+        let err = filter_system
+            .reload(Config {
+                trusted_keys: Vec::new(),
+                ..config(missing)
+            })
+            .unwrap_err();
+        assert!(matches!(err, LoadError::Io { .. }), "{err}");
+        let err = filter_system
+            .load_chain("uni-5", vec![script("unsigned", None)])
+            .unwrap_err();
+        assert!(
+            matches!(
+                err,
+                LoadError::Signature {
+                    problem: SignatureProblem::Unsigned,
+                    ..
+                }
+            ),
+            "{err}"
+        );
+
+        // Keys that aren't Ed25519 public keys fail validation.
+        let err = Config::from_yaml("chains: {}\ntrusted_keys: [AAAA]")
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "trusted key 0 is invalid: it is 3 bytes long rather than 32"
+        );
+    }
+
+    #[test]
+    fn big_amounts_keep_their_precision() {
+        let script = indoc! {r#"
+        return {
+            filter = function(tx)
+                return bigint.cmp(tx.amount, "9007199254740993") == 0
+            end,
+        }
+        "#};
+        let tx = mock_tx("0xDEADBEEF", 9007199254740993);
+
+        // Without the option the amount reaches Lua rounded to 9007199254740992, except on Lua
+        // 5.4 which has 64-bit integers.
+        let filter_runtime = FilterRuntime::<MockTx>::new();
+        let filter_system = load_script(&filter_runtime.runtime, script);
+        assert_eq!(
+            filter_system.filter_one(tx.clone()).unwrap(),
+            cfg!(feature = "lua54")
+        );
+
+        let filter_runtime = FilterRuntime::<MockTx>::new_with_options(RuntimeOptions {
+            big_integers_as_strings: true,
+            ..Default::default()
+        })
+        .unwrap();
+        let filter_system = load_script(&filter_runtime.runtime, script);
+        assert!(filter_system.filter_one(tx).unwrap());
+    }
+
+    #[test]
+    fn config_libraries() {
+        let scripts = Scripts::new("libraries");
+        let library = scripts.write("addresses.lua", r#"return { manager = "0xDEADBEEF" }"#);
+        let script = scripts.write(
+            "filter.lua",
+            r#"return { filter = function(tx) return tx.from == addresses.manager end }"#,
+        );
+        let config = || {
+            let input = format!(
+                "libraries:\n    addresses: {}\nchains:\n    uni-5:\n        - name: Manager\n          script: {}\n",
+                library.display(),
+                script.display()
+            );
+            serde_yaml::from_str::<Config>(&input).unwrap()
+        };
+
+        let filter_runtime = FilterRuntime::<MockTx>::new();
+        let mut filter_system = filter_runtime.load(config()).unwrap();
+        assert!(filter_system.filter_one(mock_tx("0xDEADBEEF", 0)).unwrap());
+
+        // Reloading re-evaluates the libraries.
+        std::fs::write(&library, r#"return { manager = "0xBEEFFEEF" }"#).unwrap();
+        filter_system.reload(config()).unwrap();
+        assert_eq!(filter_system.stats().len(), 1);
+        assert!(filter_system.filter_one(mock_tx("0xBEEFFEEF", 0)).unwrap());
+
+        // A broken library fails the load and names the library.
+        std::fs::write(&library, "return {").unwrap();
+        let err = filter_system.reload(config()).unwrap_err();
+        assert!(err.to_string().contains("`addresses`"), "{err}");
+        assert_eq!(filter_system.stats().len(), 1);
+        assert!(filter_system.filter_one(mock_tx("0xBEEFFEEF", 0)).unwrap());
+
+        // A library evaluated by a reload that fails later on is put back as it was.
+        std::fs::write(&library, r#"return { manager = "0xFACADE" }"#).unwrap();
+        let mut broken = config();
+        let mut filter = broken.chains["uni-5"][0].clone();
+        filter.script = scripts.write("broken.lua", "return {");
+        broken.chains.get_mut("uni-5").unwrap().push(filter);
+        filter_system.reload(broken).unwrap_err();
+        assert!(filter_system.filter_one(mock_tx("0xBEEFFEEF", 0)).unwrap());
+        assert!(!filter_system.filter_one(mock_tx("0xFACADE", 0)).unwrap());
+
+        // Libraries left out of the configuration reloaded are unset.
+        let mut config = config();
+        config.libraries.clear();
+        let script = r#"return { filter = function(tx) return addresses == nil end }"#;
+        config.chains.get_mut("uni-5").unwrap()[0].script = scripts.write("unset.lua", script);
+        filter_system.reload(config).unwrap();
+        assert!(filter_system.filter_one(mock_tx("0xA", 0)).unwrap());
+    }
+
+    #[test]
+    fn deterministic_verdicts() {
+        let script = indoc! {r#"
+        return {
+            filter = function(tx)
+                local secs, nanos = time.now()
+                local noise = math.random(1000) + os.time() + secs + nanos + os.clock() * 1e9
+                return (noise + tx.amount) % 2 == 0
+            end,
+        }
+        "#};
+        let verdicts = || {
+            let filter_runtime = FilterRuntime::<MockTx>::new_with_options(RuntimeOptions {
+                deterministic: true,
+                random_seed: 42,
+                fixed_time: SystemTime::UNIX_EPOCH + Duration::from_secs(1673352000),
+                ..Default::default()
+            })
+            .unwrap();
+            let filter_system = load_script(&filter_runtime.runtime, script);
+            (0..32)
+                .map(|amount| filter_system.filter_one(mock_tx("0xDEADBEEF", amount)))
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap()
+        };
+
+        let first = verdicts();
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(first, verdicts());
+    }
+
+    #[test]
+    fn register_function() {
+        let agents: Arc<HashSet<String>> = Arc::new(["0xDEADBEEF".to_string()].into());
+        let filter_runtime = FilterRuntime::<MockTx>::new();
+        let filter_system = load_script(
+            &filter_runtime.runtime,
+            indoc! {r#"
+            return {
+                filter = function(tx)
+                    return host.is_agent(tx.from)
+                end,
+            }
+            "#},
+        );
+
+        // Registered after the script was loaded.
+        filter_runtime
+            .register_function("host.is_agent", move |_, address: String| {
+                Ok(agents.contains(&address))
+            })
+            .unwrap();
+        assert!(filter_system.filter_one(mock_tx("0xDEADBEEF", 0)).unwrap());
+        assert!(!filter_system.filter_one(mock_tx("0xBEEFFEEF", 0)).unwrap());
+
+        let err = filter_runtime
+            .register_function("json.decode.nested", |_, ()| Ok(()))
+            .unwrap_err();
+        assert!(err.to_string().contains("`decode`"), "{err}");
+    }
+
+    #[test]
+    fn deep_values() {
+        #[derive(Serialize)]
+        struct Event {
+            kind: String,
+            attributes: serde_json::Value,
+        }
+        impl mlua::UserData for Event {}
+        impl Drop for Event {
+            // Dropping thousands of nested maps recursively would overflow the stack as well.
+            fn drop(&mut self) {
+                let mut value = self.attributes.take();
+                while let Some(inner) = value.get_mut("inner").map(serde_json::Value::take) {
+                    value = inner;
+                }
+            }
+        }
+
+        let event = |kind: &str, levels: usize| {
+            // `json!` would copy `inner` through serde, recursively too.
+            let attributes = (0..levels).fold(serde_json::Value::from(1), |inner, _| {
+                serde_json::Value::Object([("inner".to_string(), inner)].into_iter().collect())
+            });
+            Event {
+                kind: kind.to_string(),
+                attributes,
+            }
+        };
+        let script = indoc! {r#"
+        return {
+            filter = function(event)
+                return event.kind == "wasm" and event.attributes ~= nil
+            end,
+        }
+        "#};
+        let options = [
+            RuntimeOptions::default(),
+            RuntimeOptions {
+                intern_strings: true,
+                ..Default::default()
+            },
+            RuntimeOptions {
+                value_passing: ValuePassing::ScratchTable,
+                ..Default::default()
+            },
+            RuntimeOptions {
+                value_passing: ValuePassing::Lazy,
+                ..Default::default()
+            },
+        ];
+        for options in options {
+            let filter_runtime = FilterRuntime::<Event>::new_with_options(options).unwrap();
+            let mut filter_system = load_script(&filter_runtime.runtime, script);
+            // The event itself is one of the 128 levels.
+            assert!(filter_system.filter_one(event("wasm", 127)).unwrap());
+            let err = filter_system.filter_one(event("wasm", 128)).unwrap_err();
+            assert!(
+                matches!(err, FilterError::ValueTooDeep { depth: 128 }),
+                "{err}"
+            );
+            let err = filter_system.filter_one(event("wasm", 10_000)).unwrap_err();
+            assert!(
+                matches!(err, FilterError::ValueTooDeep { depth: 128 }),
+                "{err}"
+            );
+            assert!(filter_system.filter_one(event("wasm", 1)).unwrap());
+
+            filter_system.set_error_policy(ErrorPolicy::Lenient);
+            let kept = filter_system
+                .filter(vec![event("wasm", 10_000), event("wasm", 2)])
+                .unwrap();
+            assert_eq!(kept.len(), 1);
+            assert_eq!(filter_system.stats()[0].errors, 3);
+        }
+    }
+
+    #[test]
+    fn value_passing_userdata() {
+        static SERIALIZED: AtomicUsize = AtomicUsize::new(0);
+
+        #[derive(Clone)]
+        struct Payload(Vec<u8>);
+        impl Serialize for Payload {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                SERIALIZED.fetch_add(1, Ordering::SeqCst);
+                serializer.serialize_bytes(&self.0)
+            }
+        }
+
+        #[derive(Clone, Serialize)]
+        struct LargeTx {
+            from: String,
+            payload: Payload,
+        }
+        impl mlua::UserData for LargeTx {
+            fn add_fields<'lua, F: mlua::UserDataFields<'lua, Self>>(fields: &mut F) {
+                fields.add_field_method_get("from", |_, tx| Ok(tx.from.clone()));
+            }
+        }
+
+        let tx = LargeTx {
+            from: "0xDEADBEEF".to_string(),
+            payload: Payload(vec![0; 1 << 20]),
+        };
+        let script = indoc! {r#"
+        return {
+            filter = function(tx)
+                return tx.from == "0xDEADBEEF"
+            end,
+        }
+        "#};
+        let filter = |value_passing| {
+            let filter_runtime = FilterRuntime::<LargeTx>::new_with_options(RuntimeOptions {
+                value_passing,
+                ..Default::default()
+            })
+            .unwrap();
+            let filter_system = load_script(&filter_runtime.runtime, script);
+            filter_system.filter_one(tx.clone()).unwrap()
+        };
+
+        assert!(filter(ValuePassing::UserData));
+        assert_eq!(SERIALIZED.load(Ordering::SeqCst), 0);
+        assert!(filter(ValuePassing::SerdeTable));
+        assert_eq!(SERIALIZED.load(Ordering::SeqCst), 1);
+        assert!(filter(ValuePassing::ScratchTable));
+        assert_eq!(SERIALIZED.load(Ordering::SeqCst), 2);
+        assert!(filter(ValuePassing::Lazy));
+        assert_eq!(SERIALIZED.load(Ordering::SeqCst), 2);
+
+        // Values borrowing their fields are passed as well.
+        #[derive(Serialize)]
+        struct BorrowingTx<'a> {
+            from: &'a str,
+        }
+        impl mlua::UserData for BorrowingTx<'_> {
+            fn add_fields<'lua, F: mlua::UserDataFields<'lua, Self>>(fields: &mut F) {
+                fields.add_field_method_get("from", |_, tx| Ok(tx.from.to_string()));
+            }
+        }
+
+        let from = "0xDEADBEEF".to_string();
+        let filter_runtime = FilterRuntime::<BorrowingTx>::new_with_options(RuntimeOptions {
+            value_passing: ValuePassing::UserData,
+            ..Default::default()
+        })
+        .unwrap();
+        let filter_system = load_script(&filter_runtime.runtime, script);
+        assert!(filter_system
+            .filter_one(BorrowingTx { from: &from })
+            .unwrap());
+    }
+
+    #[test]
+    fn chain_constants() {
+        let scripts = Scripts::new("constants");
+        let script = scripts.write(
+            "filter.lua",
+            indoc! {r#"
+            return {
+                filter = function(tx)
+                    return tx.to == chain.croncat_factory and tx.chain == chain.id
+                end,
+                poison = function(tx)
+                    chain.croncat_factory = tx.from
+                    return false
+                end,
+            }
+            "#},
+        );
+        let input = format!(
+            indoc! {r#"
+            constants:
+                uni-5:
+                    id: uni-5
+                    croncat_factory: "0xBEEFFEEF"
+            chains:
+                uni-5:
+                    - name: Factory
+                      script: {}
+            "#},
+            script.display()
+        );
+        let config: Config = serde_yaml::from_str(&input).unwrap();
+
+        let filter_runtime = FilterRuntime::<MockTx>::new();
+        let mut filter_system = filter_runtime.load(config).unwrap();
+        filter_system.set_error_policy(ErrorPolicy::Lenient);
+        assert!(filter_system.filter_one(mock_tx("0xDEADBEEF", 0)).unwrap());
+        let poison = filter_system
+            .stats()
+            .into_iter()
+            .find(|s| s.name == "poison");
+        assert_eq!(poison.unwrap().errors, 1);
+        assert!(filter_system.filter_one(mock_tx("0xDEADBEEF", 0)).unwrap());
+    }
+
+    #[test]
+    fn filter_one_detailed() {
+        let filter_runtime = FilterRuntime::<MockTx>::new();
+        let filter_system = load_script(
+            &filter_runtime.runtime,
+            indoc! {r#"
+            return {
+                manager = function(tx)
+                    print("from", tx.from)
+                    return tx.from == "0xDEADBEEF"
+                end,
+                whale = function(tx)
+                    return tx.amount > 1000
+                end,
+            }
+            "#},
+        );
+        let verdict = filter_system
+            .filter_one_detailed(mock_tx("0xDEADBEEF", 10))
+            .unwrap();
+        assert_eq!(
+            verdict,
+            Verdict {
+                matched: true,
+                matched_by: vec!["manager".to_string()],
+                debug_output: vec!["[manager] from\t0xDEADBEEF".to_string()],
+                would_match: true,
+                ..Default::default()
+            }
+        );
+        assert!(filter_system.filter_one(mock_tx("0xDEADBEEF", 10)).unwrap());
+    }
+
+    #[test]
+    fn filter_transform() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct CronTask {
+            owner: String,
+            amount: u64,
+            rules: Vec<Rule>,
+        }
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Rule {
+            height: u64,
+        }
+
+        let filter_runtime = FilterRuntime::<MockTx>::new();
+        let filter_system = load_script(
+            &filter_runtime.runtime,
+            indoc! {r#"
+            return {
+                task = function(tx)
+                    if tx.to ~= "0xBEEFFEEF" then
+                        return false
+                    end
+                    local height = tx.amount
+                    if tx.from == "0xBAD" then
+                        height = "soon"
+                    end
+                    return true, { owner = tx.from, amount = tx.amount * 2, rules = { { height = height } } }
+                end,
+            }
+            "#},
+        );
+        let mut other = mock_tx("0xA", 1);
+        other.to = "0xC".to_string();
+        let values = vec![mock_tx("0xDEADBEEF", 10), other, mock_tx("0xB", 7)];
+        let tasks = filter_system.filter_transform::<CronTask>(values).unwrap();
+        let tasks: Vec<_> = tasks
+            .into_iter()
+            .map(|(tx, task)| (tx.from, task))
+            .collect();
+        let task = |owner: &str, amount, height| CronTask {
+            owner: owner.to_string(),
+            amount,
+            rules: vec![Rule { height }],
+        };
+        assert_eq!(
+            tasks,
+            [
+                ("0xDEADBEEF".to_string(), task("0xDEADBEEF", 20, 10)),
+                ("0xB".to_string(), task("0xB", 14, 7)),
+            ]
+        );
+
+        // Mismatches name the filter, where they are and the field.
+        let err = filter_system
+            .filter_transform::<CronTask>(vec![mock_tx("0xBAD", 1)])
+            .err()
+            .unwrap();
+        let FilterError::Transform {
+            filter,
+            path,
+            field,
+            ..
+        } = &err
+        else {
+            panic!("{err}");
+        };
+        assert_eq!(
+            (filter.as_str(), path.as_str()),
+            ("task", "rules[0].height")
+        );
+        assert_eq!(field.as_deref(), Some("height"));
+        assert!(err.to_string().contains("expected u64"), "{err}");
+
+        #[derive(Debug, Deserialize)]
+        struct Receipt {
+            #[allow(dead_code)]
+            receipt: String,
+        }
+        let err = filter_system
+            .filter_transform::<Receipt>(vec![mock_tx("0xB", 1)])
+            .err()
+            .unwrap();
+        assert!(
+            matches!(&err, FilterError::Transform { field: Some(field), path, .. } if field == "receipt" && path == "."),
+            "{err}"
+        );
+    }
+
+    #[cfg(feature = "stream")]
+    #[test]
+    fn filtered_streams() {
+        use tokio_stream::StreamExt;
+
+        let filter_runtime = FilterRuntime::<MockTx>::new();
+        let filter_system = load_script(
+            &filter_runtime.runtime,
+            indoc! {r#"
+            return {
+                big = function(tx)
+                    if tx.from == "0xBAD" then
+                        error("bad sender")
+                    end
+                    return tx.amount > 100
+                end,
+            }
+            "#},
+        );
+        let tokio = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let amounts = [5, 500, 50, 200, 300, 400];
+        let kept: Vec<u64> = tokio.block_on(
+            tokio_stream::iter(amounts)
+                .map(|amount| mock_tx("0xA", amount))
+                .filter_with(&filter_system)
+                .map(|tx| tx.unwrap().amount)
+                .take(3)
+                .collect(),
+        );
+        assert_eq!(kept, [500, 200, 300]);
+        // Values past the ones taken are never pulled through the filters.
+        assert_eq!(filter_system.stats()[0].invocations, 5);
+
+        // Errors are yielded in place of their value, and the stream carries on.
+        let lines = [
+            r#"{"chain": "uni-5", "from": "0xA", "to": "0xB", "amount": 150}"#,
+            "not json",
+            r#"{"chain": "uni-5", "from": "0xBAD", "to": "0xB", "amount": 150}"#,
+            r#"{"chain": "uni-5", "from": "0xA", "to": "0xB", "amount": 1}"#,
+            r#"{"chain": "uni-5", "from": "0xC", "to": "0xB", "amount": 250}"#,
+        ];
+        let kept: Vec<Result<String, String>> = tokio.block_on(
+            tokio_stream::iter(lines)
+                .filter_map_with(&filter_system, |line| serde_json::from_str(line).ok())
+                .map(|tx| tx.map(|tx| tx.from).map_err(|err| err.to_string()))
+                .collect(),
+        );
+        assert_eq!(kept.len(), 3);
+        assert_eq!(kept[0], Ok("0xA".to_string()));
+        assert!(kept[1].as_ref().unwrap_err().contains("bad sender"));
+        assert_eq!(kept[2], Ok("0xC".to_string()));
+    }
+
+    #[test]
+    fn errors_name_the_script_and_line() {
+        let scripts = Scripts::new("errors");
+        let broken = scripts.filter(
+            "Broken",
+            indoc! {r#"
+            return {
+                filter = function(tx)
+                    local msg = nil
+                    return msg.contract == tx.to
+                end,
+            }
+            "#},
+        );
+        let script = broken.script.clone();
+        let config = Config {
+            chains: [("uni-5".to_string(), vec![broken])].into(),
+            ..Default::default()
+        };
+
+        let filter_runtime = FilterRuntime::<MockTx>::new();
+        let filter_system = filter_runtime.load(config.clone()).unwrap();
+        let err = filter_system
+            .filter_one(mock_tx("0xDEADBEEF", 0))
+            .unwrap_err();
+        let message = err.to_string();
+        assert!(
+            message.starts_with(&format!(
+                "filter uni-5/Broken/filter of {} failed",
+                script.display()
+            )),
+            "{message}"
+        );
+        assert!(
+            message.contains(&format!("{}:4:", script.display())),
+            "{message}"
+        );
+        // The traceback is only displayed on request.
+        assert!(!message.contains("stack traceback"), "{message}");
+        let traceback = err.traceback().unwrap();
+        assert!(traceback.starts_with("stack traceback"), "{traceback}");
+        assert_eq!(format!("{err:#}"), format!("{message}\n{traceback}"));
+
+        // Errors raised by Rust helpers keep the Lua stack they were called from.
+        std::fs::write(
+            &script,
+            indoc! {r#"
+            local function parse(s)
+                local value = json.decode(s)
+                return value
+            end
+            local function contract(tx)
+                return parse(tx.to).contract
+            end
+            return {
+                nested = function(tx)
+                    return contract(tx) == "croncat"
+                end,
+            }
+            "#},
+        )
+        .unwrap();
+        let filter_system = filter_runtime.load(config).unwrap();
+        let err = filter_system
+            .filter_one(mock_tx("0xDEADBEEF", 0))
+            .unwrap_err();
+        assert!(!err.to_string().contains("stack traceback"), "{err}");
+        let traceback = err.traceback().unwrap();
+        for line in [2, 6, 10] {
+            assert!(
+                traceback.contains(&format!("{}:{line}:", script.display())),
+                "{traceback}"
+            );
+        }
+    }
+
+    #[test]
+    fn verdict_reasons() {
+        let filter_runtime = FilterRuntime::<MockTx>::new();
+        let filter_system = load_script(
+            &filter_runtime.runtime,
+            indoc! {r#"
+            return {
+                allowlist = function(tx)
+                    if tx.from ~= "0xDEADBEEF" then
+                        return false, "sender not allowlisted"
+                    end
+                    return true, "allowlisted sender"
+                end,
+                odd = function(tx)
+                    return false, 42
+                end,
+                plain = function(tx)
+                    return tx.amount > 1000
+                end,
+            }
+            "#},
+        );
+
+        let verdict = filter_system
+            .filter_one_detailed(mock_tx("0xBEEFFEEF", 10))
+            .unwrap();
+        assert!(!verdict.matched);
+        assert_eq!(
+            verdict.reasons,
+            [(
+                "allowlist".to_string(),
+                "sender not allowlisted".to_string()
+            )]
+        );
+        assert!(filter_system.filter_one(mock_tx("0xDEADBEEF", 10)).unwrap());
+        assert!(filter_system
+            .filter_one(mock_tx("0xBEEFFEEF", 2000))
+            .unwrap());
+    }
+
+    #[test]
+    fn oversized_returns() {
+        let options = RuntimeOptions {
+            max_return_string_len: 16,
+            max_return_table_entries: 4,
+            ..Default::default()
+        };
+        let filter_runtime = FilterRuntime::<MockTx>::new_with_options(options).unwrap();
+        let mut filter_system = load_script(
+            &filter_runtime.runtime,
+            indoc! {r#"
+            return {
+                reason = function(tx)
+                    return tx.amount > 1000, string.rep("x", tx.amount)
+                end,
+            }
+            "#},
+        );
+
+        let verdict = filter_system
+            .filter_one_detailed(mock_tx("0xDEADBEEF", 16))
+            .unwrap();
+        assert_eq!(verdict.reasons[0].1.len(), 16);
+        let err = filter_system
+            .filter_one(mock_tx("0xDEADBEEF", 1 << 20))
+            .unwrap_err();
+        assert!(
+            matches!(
+                &err,
+                FilterError::OversizedReturn { filter, kind: ReturnKind::String, size }
+                    if filter == "reason" && *size == 1 << 20
+            ),
+            "{err}"
+        );
+
+        filter_system.set_error_policy(ErrorPolicy::Lenient);
+        let kept = filter_system
+            .filter(vec![
+                mock_tx("0xDEADBEEF", 1 << 20),
+                mock_tx("0xDEADBEEF", 0),
+            ])
+            .unwrap();
+        assert!(kept.is_empty());
+        assert_eq!(filter_system.stats()[0].errors, 2);
+        assert_eq!(filter_system.stats()[0].matches, 0);
+    }
+
+    #[test]
+    fn memo_counts_in_stats() {
+        let filter_runtime = FilterRuntime::<MockTx>::new();
+        let filter_system = load_script(
+            &filter_runtime.runtime,
+            indoc! {r#"
+            parses = 0
+            return {
+                filter = function(tx)
+                    local msg = memo.cache(tx.to, function()
+                        parses = parses + 1
+                        return json.decode(tx.to)
+                    end)
+                    return msg.contract == "croncat"
+                end,
+            }
+            "#},
+        );
+        let txs: Vec<_> = (0..5)
+            .map(|amount| MockTx {
+                to: r#"{"contract": "croncat"}"#.to_string(),
+                ..mock_tx("0xDEADBEEF", amount)
+            })
+            .collect();
+        assert_eq!(filter_system.filter(txs).unwrap().len(), 5);
+
+        let parses: u32 = filter_runtime.runtime.globals().get("parses").unwrap();
+        assert_eq!(parses, 1);
+        let stats = &filter_system.stats()[0];
+        assert_eq!((stats.memo_hits, stats.memo_misses), (4, 1));
+    }
+
+    #[test]
+    fn helpers_are_frozen() {
+        let filter_runtime = FilterRuntime::<MockTx>::new();
+        let mut filter_system = load_script(
+            &filter_runtime.runtime,
+            indoc! {r#"
+            return {
+                a_vandal = function(tx)
+                    json.decode = nil
+                    return false
+                end,
+                b_reader = function(tx)
+                    return json.decode(tx.to).contract == "croncat"
+                end,
+            }
+            "#},
+        );
+        filter_system.filters.sort_by(|a, b| a.name.cmp(&b.name));
+        filter_system.set_error_policy(ErrorPolicy::Lenient);
+
+        let mut tx = mock_tx("0xDEADBEEF", 0);
+        tx.to = r#"{"contract": "croncat"}"#.to_string();
+        assert!(filter_system.filter_one(tx.clone()).unwrap());
+        assert!(filter_system.filter_one(tx).unwrap());
+        assert_eq!(filter_system.stats()[0].errors, 2);
+    }
+
+    #[test]
+    fn sandboxed_globals() {
+        let scripts = Scripts::new("sandbox");
+        let vandal = scripts.filter(
+            "Vandal",
+            "json = false\nreturn { vandal = function(tx) return json == false end }",
+        );
+        let reader = scripts.filter(
+            "Reader",
+            r#"return { reader = function(tx) return json.decode(tx.to).contract == "croncat" end }"#,
+        );
+        let config = Config {
+            chains: [("uni-5".to_string(), vec![vandal, reader])].into(),
+            ..Default::default()
+        };
+
+        let filter_runtime = FilterRuntime::<MockTx>::new_with_options(RuntimeOptions {
+            sandbox: true,
+            ..Default::default()
+        })
+        .unwrap();
+        let filter_system = filter_runtime.load(config).unwrap();
+        let mut tx = mock_tx("0xDEADBEEF", 0);
+        tx.to = r#"{"contract": "croncat"}"#.to_string();
+        let verdict = filter_system.filter_one_detailed(tx).unwrap();
+        assert_eq!(
+            verdict.matched_by,
+            ["uni-5/Vandal/vandal", "uni-5/Reader/reader"]
+        );
+    }
+
+    #[test]
+    fn backend() {
+        let filter_runtime = FilterRuntime::<MockTx>::new();
+        let (has_jit, has_typeof): (bool, bool) = filter_runtime
+            .runtime
+            .load("return jit ~= nil, typeof ~= nil")
+            .eval()
+            .unwrap();
+        assert_eq!(has_jit, cfg!(feature = "luajit"));
+        assert_eq!(has_typeof, cfg!(feature = "luau"));
+    }
+
+    #[cfg(feature = "luajit")]
+    mod luajit {
+        use super::*;
+
+        #[test]
+        fn numbers_are_doubles() {
+            let filter_runtime = FilterRuntime::<MockTx>::new();
+            let (has_math_type, rounded): (bool, bool) = filter_runtime
+                .runtime
+                .load("return math.type ~= nil, 2^53 + 1 == 2^53")
+                .eval()
+                .unwrap();
+            assert!(!has_math_type);
+            assert!(rounded);
+        }
+
+        #[test]
+        fn big_integers_as_strings() {
+            let filter_runtime = FilterRuntime::<MockTx>::new_with_options(RuntimeOptions {
+                big_integers_as_strings: true,
+                ..Default::default()
+            })
+            .unwrap();
+            let filter_system = load_script(
+                &filter_runtime.runtime,
+                indoc! {r#"
+                return {
+                    filter = function(tx)
+                        if tx.amount == nil then
+                            return false
+                        end
+                        if type(tx.amount) == "string" then
+                            return bigint.gte(tx.amount, "9007199254740993")
+                        end
+                        return tx.amount == 42
+                    end,
+                }
+                "#},
+            );
+            let amounts = [42, 9007199254740992, 9007199254740993, u64::MAX];
+            let kept: Vec<u64> = filter_system
+                .filter(amounts.map(|amount| mock_tx("0xDEADBEEF", amount)).to_vec())
+                .unwrap()
+                .into_iter()
+                .map(|tx| tx.amount)
+                .collect();
+            assert_eq!(kept, [42, 9007199254740993, u64::MAX]);
+        }
+    }
+
+    #[test]
+    fn lua_version() {
+        let filter_runtime = FilterRuntime::<MockTx>::new();
+        let filter_system = filter_runtime.load(Config::default()).unwrap();
+        assert_eq!(filter_system.dialect(), LuaVersion::compiled());
+
+        let other = match LuaVersion::compiled() {
+            LuaVersion::Luau => LuaVersion::Lua54,
+            _ => LuaVersion::Luau,
+        };
+        let err = FilterRuntime::<MockTx>::new_with_options(RuntimeOptions {
+            lua: other,
+            ..Default::default()
+        })
+        .err()
+        .unwrap();
+        assert!(
+            err.to_string()
+                .contains(&format!("{other:?} was requested")),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn gc_after_batch() {
+        let filter_runtime = FilterRuntime::<MockTx>::new();
+        filter_runtime
+            .gc_config(GcConfig {
+                mode: GcMode::Stopped,
+                ..Default::default()
+            })
+            .unwrap();
+        let mut filter_system = load_script(
+            &filter_runtime.runtime,
+            indoc! {r#"
+            return {
+                filter = function(tx)
+                    return string.rep(tx.from, 10) ~= ""
+                end,
+            }
+            "#},
+        );
+        let txs = |n| (0..n).map(|i| mock_tx(&format!("juno1{i}"), i)).collect();
+
+        let before = filter_system.used_memory();
+        filter_system.filter(txs(2000)).unwrap();
+        let after_batch = filter_system.used_memory();
+        assert!(after_batch > before);
+        assert_eq!(filter_system.gc_count(), 0);
+
+        filter_system.set_gc_after_batch(GcAfterBatch::Full);
+        filter_system.filter(txs(1)).unwrap();
+        assert_eq!(filter_system.gc_count(), 1);
+        assert!(filter_system.used_memory() < after_batch);
+
+        filter_system.set_gc_after_batch(GcAfterBatch::Step);
+        filter_system.filter_ref(&txs(10)).unwrap();
+        filter_system.filter_one(mock_tx("juno1", 1)).unwrap();
+        assert_eq!(filter_system.gc_count(), 2);
+        assert_eq!(filter_runtime.used_memory(), filter_system.used_memory());
+    }
+
+    #[test]
+    fn interrupt_handle() {
+        let filter_runtime = FilterRuntime::<MockTx>::new();
+        let filter_system = load_script(
+            &filter_runtime.runtime,
+            indoc! {r#"
+            return {
+                stuck = function(tx)
+                    while tx.amount == 0 do end
+                    return true
+                end,
+            }
+            "#},
+        );
+        let handle = filter_system.interrupt_handle();
+        // Interrupts sent while nothing runs are dropped.
+        handle.interrupt();
+        assert!(filter_system.filter_one(mock_tx("juno1", 1)).unwrap());
+
+        let started = Instant::now();
+        let interrupter = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            handle.interrupt();
+        });
+        let err = filter_system.filter_one(mock_tx("juno1", 0)).unwrap_err();
+        interrupter.join().unwrap();
+        assert!(
+            matches!(&err, FilterError::Interrupted { filter } if filter == "stuck"),
+            "{err}"
+        );
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        assert!(filter_system.filter_one(mock_tx("juno1", 1)).unwrap());
+        assert_eq!(filter_system.stats()[0].errors, 1);
+    }
+
+    #[derive(Clone, Debug)]
+    struct Unlucky(u64);
+    impl mlua::UserData for Unlucky {}
+    impl Serialize for Unlucky {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            assert_ne!(self.0, 13, "unlucky amount");
+            serializer.serialize_u64(self.0)
+        }
+    }
+
+    #[test]
+    fn panics_become_errors() {
+        let filter_runtime = FilterRuntime::<Unlucky>::new();
+        let mut filter_system = load_script(
+            &filter_runtime.runtime,
+            "return { filter = function(amount) return amount > 10 end }",
+        );
+        // Panics are never swallowed, even by the lenient policy.
+        filter_system.set_error_policy(ErrorPolicy::Lenient);
+
+        let err = filter_system
+            .filter(vec![Unlucky(11), Unlucky(13)])
+            .unwrap_err();
+        assert!(
+            matches!(&err, FilterError::Panic { filter, message }
+                if filter == "filter" && message.contains("unlucky amount")),
+            "{err}"
+        );
+        assert!(filter_system.filter_one(Unlucky(12)).unwrap());
+        assert_eq!(filter_system.stats()[0].errors, 1);
+
+        panic::poison(&filter_runtime.runtime);
+        let err = filter_system.filter_one(Unlucky(12)).unwrap_err();
+        assert!(matches!(err, FilterError::Poisoned), "{err}");
+    }
+
+    #[test]
+    fn filter_with_deadline() {
+        let filter_runtime = FilterRuntime::<MockTx>::new();
+        let filter_system = load_script(
+            &filter_runtime.runtime,
+            indoc! {r#"
+            return {
+                busy = function(tx)
+                    -- Spins for `amount` milliseconds of CPU time, forever for zero.
+                    local started = os.clock()
+                    while tx.amount == 0 or os.clock() - started < tx.amount / 1000 do end
+                    return tx.from == "juno1"
+                end,
+            }
+            "#},
+        );
+        let txs = [
+            mock_tx("juno1", 1),
+            mock_tx("juno2", 1),
+            mock_tx("juno1", 1),
+            mock_tx("juno1", 0),
+            mock_tx("juno1", 1),
+        ];
+
+        // The stuck call is cut short.
+        let started = Instant::now();
+        let result = filter_system
+            .filter_with_deadline(&txs, started + Duration::from_millis(200))
+            .unwrap();
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert_eq!(result.kept.len(), 2);
+        assert_eq!(result.stopped_at, 3);
+        assert!(result.deadline_hit);
+
+        let result = filter_system
+            .filter_with_deadline(&txs[4..], Instant::now() + Duration::from_secs(60))
+            .unwrap();
+        assert_eq!(result.kept.len(), 1);
+        assert_eq!(result.stopped_at, 1);
+        assert!(!result.deadline_hit);
+
+        let result = filter_system
+            .filter_with_deadline(&txs, Instant::now())
+            .unwrap();
+        assert!(result.kept.is_empty());
+        assert_eq!(result.stopped_at, 0);
+        assert!(result.deadline_hit);
+    }
+
+    #[test]
+    fn call_timeout() {
+        let filter_runtime = FilterRuntime::<MockTx>::new();
+        let mut filter_system = load_script(
+            &filter_runtime.runtime,
+            indoc! {r#"
+            return {
+                busy = function(tx)
+                    -- Spins for `amount` milliseconds of CPU time, forever for zero.
+                    local started = os.clock()
+                    while tx.amount == 0 or os.clock() - started < tx.amount / 1000 do end
+                    return true
+                end,
+            }
+            "#},
+        );
+        filter_system.set_call_timeout(Duration::from_millis(300));
+
+        let started = Instant::now();
+        let err = filter_system.filter_one(mock_tx("juno1", 0)).unwrap_err();
+        assert!(
+            matches!(&err, FilterError::Timeout { filter, elapsed }
+                if filter == "busy" && *elapsed >= Duration::from_millis(300)),
+            "{err}"
+        );
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        // Each call gets the full timeout.
+        for _ in 0..3 {
+            assert!(filter_system.filter_one(mock_tx("juno1", 150)).unwrap());
+        }
+
+        // Cancellation still wins when it comes first.
+        let cancel = Arc::new(AtomicBool::new(false));
+        let canceller = {
+            let cancel = cancel.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(50));
+                cancel.store(true, Ordering::Relaxed);
+            })
+        };
+        let txs = [mock_tx("juno1", 0)];
+        let err = filter_system
+            .filter_with_cancel(&txs, &cancel)
+            .err()
+            .unwrap();
+        canceller.join().unwrap();
+        assert!(matches!(err, FilterError::Cancelled { .. }), "{err}");
+    }
+
+    #[test]
+    fn fuel_budgets() {
+        let filter_runtime = FilterRuntime::<MockTx>::new();
+        let mut filter_system = load_script(
+            &filter_runtime.runtime,
+            indoc! {r#"
+            return {
+                busy = function(tx)
+                    local sum = 0
+                    for i = 1, tx.amount do sum = sum + i end
+                    return sum >= 0
+                end,
+                cheap = function(tx)
+                    return tx.from == "juno1"
+                end,
+            }
+            "#},
+        );
+        let filters = std::mem::take(&mut filter_system.filters);
+        filter_system.filters = filters
+            .into_iter()
+            .map(|filter| match filter.name.as_str() {
+                "busy" => filter.with_fuel_budget(50_000),
+                _ => filter,
+            })
+            .collect();
+        filter_system.meter_fuel();
+        let stats = |filter_system: &FilterSystem<MockTx>, name: &str| {
+            let stats = filter_system.stats();
+            stats.into_iter().find(|stats| stats.name == name).unwrap()
+        };
+
+        // The first call runs out of fuel and the others skip the filter.
+        let txs = vec![
+            mock_tx("juno1", 1_000_000),
+            mock_tx("juno2", 10),
+            mock_tx("juno2", 10),
+        ];
+        let kept = filter_system.filter(txs).unwrap();
+        assert_eq!(kept.len(), 1);
+        let busy = stats(&filter_system, "busy");
+        assert_eq!(busy.invocations, 1);
+        assert_eq!(busy.budget_exhausted, 3);
+        assert_eq!(busy.errors, 0);
+        assert!(busy.fuel > 50_000, "{}", busy.fuel);
+        assert_eq!(stats(&filter_system, "cheap").invocations, 3);
+
+        // The next batch starts with a full budget.
+        assert!(filter_system.filter_one(mock_tx("juno2", 10)).unwrap());
+        let verdict = filter_system
+            .filter_one_detailed(mock_tx("juno1", 1_000_000))
+            .unwrap();
+        assert_eq!(verdict.matched_by, ["cheap"]);
+        assert_eq!(verdict.budget_exhausted, ["busy"]);
+
+        // Once the batch's fuel is gone, every filter is skipped.
+        filter_system.set_batch_fuel(20_000);
+        let txs = vec![mock_tx("juno2", 1_000_000), mock_tx("juno1", 10)];
+        assert!(filter_system.filter(txs).unwrap().is_empty());
+        assert_eq!(stats(&filter_system, "busy").budget_exhausted, 6);
+    }
+
+    #[test]
+    fn memory_attribution() {
+        let scripts = Scripts::new("memory");
+        let dedup = scripts.write(
+            "dedup.lua",
+            indoc! {r#"
+            seen = {}
+            return {
+                filter = function(tx)
+                    if tx.amount == 0 then
+                        blob = string.rep("x", 1048576)
+                    end
+                    local new = not seen[tx.from]
+                    seen[tx.from] = true
+                    return new
+                end,
+            }
+            "#},
+        );
+        let plain = scripts.write(
+            "plain.lua",
+            "return { filter = function(tx) return tx.amount > 1 end }",
+        );
+        let input = format!(
+            "chains:\n    uni-5:\n        - name: Dedup\n          script: {}\n        - name: Plain\n          script: {}\n",
+            dedup.display(),
+            plain.display()
+        );
+
+        // Sandboxed scripts keep their globals in their own environment.
+        let filter_runtime = FilterRuntime::<MockTx>::new_with_options(RuntimeOptions {
+            sandbox: true,
+            ..Default::default()
+        })
+        .unwrap();
+        let filter_system = filter_runtime
+            .load(serde_yaml::from_str(&input).unwrap())
+            .unwrap();
+        let txs = (0..2000)
+            .map(|i| mock_tx(&format!("juno1sender{i}"), i))
+            .collect();
+        filter_system.filter(txs).unwrap();
+
+        let stats = filter_system.stats();
+        let (dedup_stats, plain_stats) = (&stats[0], &stats[1]);
+        assert!(
+            dedup_stats.state_bytes > 1048576 + 2000 * 40,
+            "{dedup_stats:?}"
+        );
+        assert!(plain_stats.state_bytes < 1024, "{plain_stats:?}");
+        assert!(dedup_stats.peak_delta_bytes >= 65536, "{dedup_stats:?}");
+        assert!(plain_stats.peak_delta_bytes < 65536, "{plain_stats:?}");
+    }
+
+    #[test]
+    fn call_memory() {
+        let scripts = Scripts::new("call-memory");
+        // Makes a table of garbage for every unit of the amount. The tables go in a global so
+        // LuaJIT can't optimize them away.
+        let churn = scripts.filter(
+            "Churn",
+            indoc! {r#"
+            return {
+                churn = function(tx)
+                    for i = 1, tx.amount do
+                        last = { i, tostring(i) }
+                    end
+                    return true
+                end,
+            }
+            "#},
+        );
+        let config = |max_call_memory| Config {
+            chains: [(
+                "uni-5".to_string(),
+                vec![FilterConfig {
+                    max_call_memory,
+                    ..churn.clone()
+                }],
+            )]
+            .into(),
+            ..Default::default()
+        };
+        let filter_runtime = FilterRuntime::<MockTx>::new();
+        let peak = |filter_system: &FilterSystem<MockTx>| filter_system.stats()[0].peak_delta_bytes;
+
+        // The collector frees most of the garbage while the call runs, unless it is paused.
+        let mut filter_system = filter_runtime.load(config(None)).unwrap();
+        assert!(filter_system.filter_one(mock_tx("juno1", 100_000)).unwrap());
+        let sampled = peak(&filter_system);
+        filter_system.measure_memory(true);
+        assert!(filter_system.filter_one(mock_tx("juno1", 100_000)).unwrap());
+        let measured = peak(&filter_system);
+        assert!(measured > 4 << 20, "{measured}");
+        assert!(measured > sampled * 2, "{measured} {sampled}");
+        drop(filter_system);
+
+        let mut filter_system = filter_runtime.load(config(Some(1 << 20))).unwrap();
+        assert!(filter_system.filter_one(mock_tx("juno1", 100)).unwrap());
+        let err = filter_system
+            .filter_one(mock_tx("juno1", 100_000))
+            .unwrap_err();
+        assert!(
+            matches!(&err, FilterError::CallMemoryExceeded { filter, bytes, limit }
+                if filter == "uni-5/Churn/churn" && *bytes > *limit && *limit == 1 << 20),
+            "{err}"
+        );
+        filter_system.set_error_policy(ErrorPolicy::Lenient);
+        assert!(!filter_system.filter_one(mock_tx("juno1", 100_000)).unwrap());
+        assert_eq!(filter_system.stats()[0].errors, 2);
+    }
+
+    #[test]
+    fn script_cache() {
+        let scripts = Scripts::new("cache");
+        let script = scripts.write(
+            "filter.lua",
+            "manager = '0xDEADBEEF'\nreturn { filter = function(tx) return tx.from == manager end }",
+        );
+        let input = format!(
+            "chains:\n    uni-5:\n        - name: Manager\n          script: {}\n",
+            script.display()
+        );
+
+        // Cold, then warm, with the sandboxed environments applied to the cached bytecode.
+        for _ in 0..2 {
+            let filter_runtime = FilterRuntime::<MockTx>::new_with_options(RuntimeOptions {
+                sandbox: true,
+                script_cache: Some(scripts.dir.join("cache")),
+                ..Default::default()
+            })
+            .unwrap();
+            let filter_system = filter_runtime
+                .load(serde_yaml::from_str(&input).unwrap())
+                .unwrap();
+            assert!(filter_system.filter_one(mock_tx("0xDEADBEEF", 0)).unwrap());
+            assert!(filter_runtime
+                .runtime
+                .globals()
+                .get::<_, mlua::Value>("manager")
+                .unwrap()
+                .is_nil());
+        }
+        assert_eq!(
+            std::fs::read_dir(scripts.dir.join("cache"))
+                .unwrap()
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn load_limits() {
+        let scripts = Scripts::new("load-limits");
+        let small = scripts.write(
+            "small.lua",
+            "return { small = function(tx) return true end }",
+        );
+        let large = scripts.write(
+            "large.lua",
+            &format!("-- {}\nreturn {{}}", "x".repeat(4096)),
+        );
+        // Evaluating it would tell the limits weren't checked first.
+        let noisy = scripts.write("noisy.lua", "error('evaluated')");
+        let filter = |script: &PathBuf| FilterConfig {
+            name: "Limited".to_string(),
+            script: script.clone(),
+            ..Default::default()
+        };
+        let config = |chains: Vec<(&str, Vec<FilterConfig>)>| Config {
+            chains: chains
+                .into_iter()
+                .map(|(chain, filters)| (chain.to_string(), filters))
+                .collect(),
+            ..Default::default()
+        };
+        let filter_runtime = FilterRuntime::<MockTx>::new_with_options(RuntimeOptions {
+            max_scripts: 2,
+            max_filters_per_chain: 3,
+            max_total_filters: 4,
+            max_script_size_bytes: 1024,
+            ..Default::default()
+        })
+        .unwrap();
+        let err = |config| filter_runtime.load(config).err().unwrap().to_string();
+
+        let globbed = (0..40_000)
+            .map(|i| filter(&scripts.dir.join(format!("node_modules/{i}.lua"))))
+            .collect();
+        let started = Instant::now();
+        let message = err(config(vec![("uni-5", globbed)]));
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert!(
+            message.contains(
+                "chain `uni-5` lists 40000 filters, 39997 over the `max_filters_per_chain` limit of 3"
+            ),
+            "{message}"
+        );
+        let message = err(config(vec![
+            (
+                "uni-5",
+                vec![filter(&noisy), filter(&small), filter(&small)],
+            ),
+            ("juno-1", vec![filter(&small), filter(&small)]),
+        ]));
+        assert!(
+            message.contains("5 filters, 1 over the `max_total_filters` limit of 4"),
+            "{message}"
+        );
+        let message = err(config(vec![(
+            "uni-5",
+            vec![filter(&noisy), filter(&small), filter(&large)],
+        )]));
+        assert!(
+            message.contains("3 scripts, 1 over the `max_scripts` limit of 2"),
+            "{message}"
+        );
+        let message = err(config(vec![(
+            "uni-5",
+            vec![filter(&noisy), filter(&large)],
+        )]));
+        assert!(
+            message.contains(&format!(
+                "`{}` is 4109 bytes, 3085 over the `max_script_size_bytes` limit of 1024",
+                large.display()
+            )),
+            "{message}"
+        );
+
+        let filter_system = filter_runtime
+            .load(config(vec![(
+                "uni-5",
+                vec![filter(&small), filter(&small)],
+            )]))
+            .unwrap();
+        assert_eq!(filter_system.stats().len(), 2);
+    }
+
+    #[test]
+    fn load_chain() {
+        let scripts = Scripts::new("load-chain");
+        let first = scripts.filter("first", "return { first = function(tx) return true end }");
+        let second = scripts.filter(
+            "second",
+            "return { second = function(tx) return false end }",
+        );
+        let broken = scripts.filter("broken", "error('broken')");
+        let names = |filter_system: &FilterSystem<MockTx>| {
+            let mut names: Vec<String> = filter_system
+                .stats()
+                .into_iter()
+                .map(|stats| stats.name)
+                .collect();
+            names.sort();
+            names
+        };
+
+        let filter_runtime = FilterRuntime::<MockTx>::new_with_options(RuntimeOptions {
+            max_filters_per_chain: 2,
+            ..Default::default()
+        })
+        .unwrap();
+        let mut filter_system = filter_runtime
+            .load(Config {
+                chains: [
+                    ("uni-5".to_string(), vec![first.clone()]),
+                    ("juno-1".to_string(), vec![second.clone()]),
+                ]
+                .into(),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(names(&filter_system), ["first", "second"]);
+
+        filter_system
+            .load_chain("pion-1", vec![first.clone(), second.clone()])
+            .unwrap();
+        assert_eq!(
+            names(&filter_system),
+            ["first", "first", "second", "second"]
+        );
+
+        // Failures leave every chain as it was.
+        let err = filter_system
+            .load_chain("uni-5", vec![second.clone(), broken])
+            .unwrap_err();
+        assert!(err.to_string().contains("broken"), "{err}");
+        let err = filter_system
+            .load_chain(
+                "uni-5",
+                vec![second.clone(), second.clone(), second.clone()],
+            )
+            .unwrap_err();
+        assert!(err.to_string().contains("max_filters_per_chain"), "{err}");
+        assert_eq!(
+            names(&filter_system),
+            ["first", "first", "second", "second"]
+        );
+
+        filter_system
+            .load_chain("uni-5", vec![second.clone()])
+            .unwrap();
+        assert_eq!(
+            names(&filter_system),
+            ["first", "second", "second", "second"]
+        );
+
+        assert!(filter_system.unload_chain("pion-1"));
+        assert!(!filter_system.unload_chain("pion-1"));
+        assert_eq!(names(&filter_system), ["second", "second"]);
+        assert!(!filter_system.filter_one(mock_tx("juno1", 1)).unwrap());
+
+        // A chain loaded again keeps its constants.
+        let factory = scripts.filter(
+            "factory",
+            "return { factory = function(tx) return tx.to == chain.factory end }",
+        );
+        let mut filter_system = filter_runtime
+            .load(Config {
+                chains: [("uni-5".to_string(), vec![first.clone()])].into(),
+                constants: [(
+                    "uni-5".to_string(),
+                    serde_yaml::from_str("factory: '0xBEEFFEEF'").unwrap(),
+                )]
+                .into(),
+                ..Default::default()
+            })
+            .unwrap();
+        filter_system.load_chain("uni-5", vec![factory]).unwrap();
+        assert!(filter_system.filter_one(mock_tx("juno1", 1)).unwrap());
+    }
+
+    #[test]
+    fn load_all() {
+        let scripts = Scripts::new("load-all");
+        let audit = scripts.filter("audit", "return function(tx) return tx.amount > 1000 end");
+        let manager = scripts.filter(
+            "manager",
+            "return function(tx) return tx.from == '0xDEADBEEF' end",
+        );
+        let juno = scripts.filter("juno", "return function(tx) return tx.to == '0xJUNO' end");
+        let names = |filter_system: &FilterSystem<MockTx>| -> Vec<String> {
+            let stats = filter_system.stats().into_iter();
+            stats.map(|stats| stats.name).collect()
+        };
+
+        let filter_runtime = FilterRuntime::<MockTx>::new();
+        let config = Config {
+            chains: HashMap::from([
+                (WILDCARD_CHAIN.to_string(), vec![audit.clone()]),
+                ("uni-5".to_string(), vec![manager.clone()]),
+                ("juno-1".to_string(), vec![juno.clone()]),
+            ]),
+            ..Default::default()
+        };
+        let mut systems = filter_runtime.load_all(config).unwrap();
+        let mut chains: Vec<_> = systems.keys().cloned().collect();
+        chains.sort();
+        assert_eq!(chains, ["juno-1", "uni-5"]);
+        assert_eq!(names(&systems["uni-5"]), ["audit", "manager"]);
+        assert_eq!(names(&systems["juno-1"]), ["audit", "juno"]);
+        assert!(systems["uni-5"]
+            .filter_one(mock_tx("0xDEADBEEF", 1))
+            .unwrap());
+        assert!(!systems["juno-1"]
+            .filter_one(mock_tx("0xDEADBEEF", 1))
+            .unwrap());
+        assert!(systems["juno-1"].filter_one(mock_tx("0xA", 5000)).unwrap());
+
+        // Reloading a system leaves the others alone.
+        let uni = systems.get_mut("uni-5").unwrap();
+        uni.load_chain("uni-5", vec![juno.clone()]).unwrap();
+        assert_eq!(names(&systems["uni-5"]), ["juno"]);
+        assert_eq!(names(&systems["juno-1"]), ["audit", "juno"]);
+        assert_eq!(systems["juno-1"].stats()[0].invocations, 2);
+
+        // Each system keeps the environment variables, libraries and `memo` cache it was loaded
+        // with, whatever the others reload.
+        std::env::set_var("CRONCAT_LOAD_ALL_TEST_UNI", "uni");
+        std::env::set_var("CRONCAT_LOAD_ALL_TEST_JUNO", "juno");
+        let limits = |big: u64| {
+            let library = scripts.write(
+                &format!("limits-{big}.lua"),
+                &format!("return {{ big = {big} }}"),
+            );
+            BTreeMap::from([("limits".to_string(), library)])
+        };
+        let probe = scripts.filter(
+            "probe",
+            indoc! {r#"
+            return function(tx)
+                local first = memo.cache("first", function() return tx.from end)
+                local uni = env("CRONCAT_LOAD_ALL_TEST_UNI")
+                local juno = env("CRONCAT_LOAD_ALL_TEST_JUNO")
+                return true, table.concat({ tostring(uni), tostring(juno), limits.big, first }, " ")
+            end
+            "#},
+        );
+        let probed = |system: &FilterSystem<MockTx>, from: &str| {
+            let verdict = system.filter_one_detailed(mock_tx(from, 1)).unwrap();
+            verdict.reasons[0].1.clone()
+        };
+        let config = Config {
+            chains: HashMap::from([
+                ("uni-5".to_string(), vec![probe.clone()]),
+                ("juno-1".to_string(), vec![probe.clone()]),
+            ]),
+            libraries: limits(100),
+            expose_env: vec!["CRONCAT_LOAD_ALL_TEST_UNI".to_string()],
+            ..Default::default()
+        };
+        let mut systems = filter_runtime.load_all(config).unwrap();
+        assert_eq!(probed(&systems["uni-5"], "0xA"), "uni nil 100 0xA");
+        assert_eq!(probed(&systems["juno-1"], "0xB"), "uni nil 100 0xB");
+        let juno = systems.get_mut("juno-1").unwrap();
+        juno.reload(Config {
+            chains: HashMap::from([("juno-1".to_string(), vec![probe.clone()])]),
+            libraries: limits(1000),
+            expose_env: vec!["CRONCAT_LOAD_ALL_TEST_JUNO".to_string()],
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(probed(&systems["juno-1"], "0xC"), "nil juno 1000 0xC");
+        assert_eq!(probed(&systems["uni-5"], "0xD"), "uni nil 100 0xA");
+        systems.remove("juno-1");
+        assert_eq!(probed(&systems["uni-5"], "0xE"), "uni nil 100 0xA");
+
+        // A wildcard alone makes no systems, and a broken chain fails the call.
+        let config = Config {
+            chains: HashMap::from([(WILDCARD_CHAIN.to_string(), vec![audit])]),
+            ..Default::default()
+        };
+        assert!(filter_runtime.load_all(config).unwrap().is_empty());
+        let broken = scripts.filter("broken", "error('broken')");
+        let config = Config {
+            chains: HashMap::from([
+                ("uni-5".to_string(), vec![manager]),
+                ("osmosis-1".to_string(), vec![broken]),
+            ]),
+            ..Default::default()
+        };
+        assert!(filter_runtime.load_all(config).is_err());
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn load_async() {
+        let scripts = Scripts::new("load-async");
+        scripts.write("lib.lua", "return { limit = 10 }");
+        scripts.write("broken.lua", "error('broken')");
+        for i in 0..80 {
+            scripts.write(
+                &format!("{i}.lua"),
+                &format!("return {{ f{i} = function(tx) return tx.amount + {i} > lib.limit end }}"),
+            );
+        }
+        let config = |library: &str, missing: Option<usize>| {
+            let filters = (0..80)
+                .map(|i| FilterConfig {
+                    name: format!("F{i}"),
+                    script: match missing {
+                        Some(missing) if missing == i => scripts.dir.join("missing.lua"),
+                        _ => scripts.dir.join(format!("{i}.lua")),
+                    },
+                    ..Default::default()
+                })
+                .collect::<Vec<_>>();
+            Config {
+                chains: [
+                    ("uni-5".to_string(), filters[..40].to_vec()),
+                    ("juno-1".to_string(), filters[40..].to_vec()),
+                ]
+                .into(),
+                libraries: [("lib".to_string(), scripts.dir.join(library))].into(),
+                ..Default::default()
+            }
+        };
+        let tokio = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let filter_runtime = FilterRuntime::<MockTx>::new();
+        let outcome = |filter_system: Result<FilterSystem<MockTx>, LoadError>| {
+            filter_system
+                .map(|filter_system| {
+                    let mut names: Vec<String> = filter_system
+                        .stats()
+                        .into_iter()
+                        .map(|stats| stats.name)
+                        .collect();
+                    names.sort();
+                    let kept = (0..12)
+                        .map(|amount| filter_system.filter_one(mock_tx("juno1", amount)).unwrap())
+                        .collect::<Vec<_>>();
+                    (names, kept)
+                })
+                .map_err(|err| err.to_string())
+        };
+
+        for (library, missing) in [
+            ("lib.lua", None),
+            ("broken.lua", None),
+            ("lib.lua", Some(57)),
+        ] {
+            let sync = outcome(filter_runtime.load(config(library, missing)));
+            let async_ =
+                outcome(tokio.block_on(filter_runtime.load_async(config(library, missing))));
+            assert_eq!(sync, async_);
+            assert_eq!(sync.is_ok(), (library, missing) == ("lib.lua", None));
+        }
+    }
+
+    #[test]
+    fn expressions() {
+        let scripts = Scripts::new("expressions");
+        let rules = scripts.filter(
+            "rules",
+            indoc! {r#"
+            return {
+                big = function(tx) return tx.amount > 100 end,
+                blocklisted = function(tx) return tx.from == "0xBAD" end,
+                admin = function(tx) return tx.from == "0xADMIN" end,
+                boom = function(tx) error("boom") end,
+                unused = function(tx) return true end,
+            }
+            "#},
+        );
+        let other = scripts.filter("other", "return function(tx) return tx.amount == 7 end");
+        let config = |expression: &str| Config {
+            chains: HashMap::from([
+                ("uni-5".to_string(), vec![rules.clone()]),
+                ("juno-1".to_string(), vec![other.clone()]),
+            ]),
+            expressions: HashMap::from([("uni-5".to_string(), expression.to_string())]),
+            ..Default::default()
+        };
+        let invocations = |filter_system: &FilterSystem<MockTx>| {
+            let stats = filter_system.stats().into_iter();
+            let mut invocations: Vec<_> =
+                stats.map(|stats| (stats.name, stats.invocations)).collect();
+            invocations.sort();
+            invocations
+        };
+
+        let filter_runtime = FilterRuntime::<MockTx>::new();
+        let filter_system = filter_runtime
+            .load(config(
+                "(big AND NOT blocklisted) OR (admin AND boom) OR admin",
+            ))
+            .unwrap();
+        let kept: Vec<_> = [("0xA", 500), ("0xBAD", 500), ("0xA", 7), ("0xA", 1)]
+            .into_iter()
+            .map(|(from, amount)| filter_system.filter_one(mock_tx(from, amount)).unwrap())
+            .collect();
+        assert_eq!(kept, [true, false, true, false]);
+        // Filters are only called once their verdict is needed, once per value.
+        assert_eq!(
+            invocations(&filter_system),
+            [
+                ("admin".to_string(), 3),
+                ("big".to_string(), 4),
+                ("blocklisted".to_string(), 2),
+                ("boom".to_string(), 0),
+                ("other".to_string(), 4),
+                ("unused".to_string(), 0),
+            ]
+        );
+        let verdict = filter_system
+            .filter_one_detailed(mock_tx("0xADMIN", 500))
+            .unwrap();
+        assert!(verdict.matched);
+        assert_eq!(verdict.matched_by, ["uni-5/rules/big"]);
+        let err = filter_system
+            .filter_one(mock_tx("0xADMIN", 1))
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("boom"), "{err}");
+
+        let filter_system = filter_runtime
+            .load(config(
+                "NOT (NOT big OR (blocklisted AND NOT (admin OR unused)))",
+            ))
+            .unwrap();
+        let kept: Vec<_> = [("0xA", 500), ("0xBAD", 500), ("0xA", 1)]
+            .into_iter()
+            .map(|(from, amount)| filter_system.filter_one(mock_tx(from, amount)).unwrap())
+            .collect();
+        assert_eq!(kept, [true, true, false]);
+
+        // Expressions fail to load where they go wrong.
+        let err = filter_runtime
+            .load(config("big AND (admin OR missing)"))
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "the expression of chain `uni-5` names `missing` at column 19, which isn't a filter \
+             of the chain"
+        );
+        let err = filter_runtime.load(config("big AND (admin")).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "the expression of chain `uni-5` is invalid at column 9: `(` is never closed"
+        );
+
+        // An expression can match without any filter matching, giving no output.
+        let filter_system = filter_runtime.load(config("NOT blocklisted")).unwrap();
+        let transformed = filter_system
+            .filter_transform::<Option<u64>>(vec![mock_tx("0xA", 1), mock_tx("0xBAD", 1)])
+            .unwrap();
+        assert_eq!(transformed.len(), 1);
+        assert_eq!(transformed[0].0.from, "0xA");
+        assert_eq!(transformed[0].1, None);
+
+        // Chains reloaded on their own keep their expression, which must still hold.
+        let mut filter_system = filter_runtime.load(config("big OR admin")).unwrap();
+        let err = filter_system
+            .load_chain("uni-5", vec![other.clone()])
+            .err()
+            .unwrap();
+        assert!(
+            matches!(err, LoadError::Expression { column: 1, .. }),
+            "{err}"
+        );
+        assert!(filter_system.filter_one(mock_tx("0xADMIN", 1)).unwrap());
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn reload_handle() {
+        let scripts = Scripts::new("reload-handle");
+        let config = |name: &str, source: &str| Config {
+            chains: HashMap::from([("uni-5".to_string(), vec![scripts.filter(name, source)])]),
+            ..Default::default()
+        };
+        let low = config("low", "return function(tx) return tx.amount < 10 end");
+        let high = config("high", "return function(tx) return tx.amount >= 10 end");
+        let broken = config("broken", "error('broken')");
+        let filter_runtime = FilterRuntime::<MockTx>::new();
+        let mut filter_system = filter_runtime.load(low.clone()).unwrap();
+        let handle = filter_system.reload_handle();
+        let batch = || (0..20).map(|amount| mock_tx("0xA", amount)).collect();
+
+        // A reload fired from another thread lands between two batches.
+        let requester = {
+            let handle = handle.clone();
+            std::thread::spawn(move || handle.request_reload(high).blocking_recv())
+        };
+        let mut kept = Vec::new();
+        loop {
+            let batch = filter_system.filter(batch()).unwrap();
+            kept.push(batch.iter().map(|tx| tx.amount).collect::<Vec<_>>());
+            if filter_system.apply_reload() {
+                break;
+            }
+        }
+        let summary = requester.join().unwrap().unwrap().unwrap();
+        assert_eq!(summary.superseded, 0);
+        let loaded: Vec<_> = summary.loaded.iter().map(|filter| &filter.name).collect();
+        assert_eq!(loaded, ["high"]);
+        assert!(kept
+            .iter()
+            .all(|amounts| *amounts == (0..10).collect::<Vec<_>>()));
+        let amounts: Vec<_> = filter_system
+            .filter(batch())
+            .unwrap()
+            .into_iter()
+            .map(|tx| tx.amount)
+            .collect();
+        assert_eq!(amounts, (10..20).collect::<Vec<_>>());
+        assert!(!filter_system.apply_reload());
+
+        // Queued requests coalesce to the latest, and failed reloads keep the filters.
+        let mut replaced = handle.request_reload(low.clone());
+        let failed = handle.request_reload(broken);
+        assert!(filter_system.apply_reload());
+        assert!(replaced.try_recv().is_err());
+        let err = failed.blocking_recv().unwrap().err().unwrap();
+        assert!(matches!(err, LoadError::Lua { .. }), "{err}");
+        assert_eq!(filter_system.filter(batch()).unwrap().len(), 10);
+        drop(handle.request_reload(low.clone()));
+        let reloaded = handle.request_reload(low.clone());
+        assert!(filter_system.apply_reload());
+        assert_eq!(reloaded.blocking_recv().unwrap().unwrap().superseded, 1);
+
+        // Requests left once the system is gone are never answered.
+        let waiting = handle.request_reload(low.clone());
+        drop(filter_system);
+        assert!(waiting.blocking_recv().is_err());
+        assert!(handle.request_reload(low).blocking_recv().is_err());
+    }
+
+    #[test]
+    fn profile() {
+        let scripts = Scripts::new("profile");
+        let profiled = scripts.filter(
+            "Profiled",
+            indoc! {r#"
+            return {
+                hot = function(tx)
+                    local total = 0
+                    for i = 1, 100000 do
+                        total = total + i % 7
+                    end
+                    return total < 0
+                end,
+                cold = function(tx)
+                    return tx.amount > 1
+                end,
+            }
+            "#},
+        );
+        let script = profiled.script.clone();
+        let filter_runtime = FilterRuntime::<MockTx>::new();
+        let mut filter_system = filter_runtime
+            .load(Config {
+                chains: [("uni-5".to_string(), vec![profiled])].into(),
+                ..Default::default()
+            })
+            .unwrap();
+        let run = |filter_system: &FilterSystem<MockTx>| {
+            for amount in 0..4 {
+                filter_system.filter_one(mock_tx("juno1", amount)).unwrap();
+            }
+        };
+
+        run(&filter_system);
+        assert_eq!(filter_system.take_profile().samples, 0);
+
+        filter_system.profile(true);
+        run(&filter_system);
+        let report = filter_system.take_profile();
+        assert!(report.samples > 100, "{report:?}");
+        assert_eq!(report.filters[0].filter, "hot");
+        assert!(report.filters[0].samples > report.samples * 9 / 10);
+        let top = &report.top_lines(1)[0];
+        assert_eq!(top.script, script.display().to_string());
+        assert!([4, 5].contains(&top.line), "{top:?}");
+        assert_eq!(report.top_lines(1000).len(), report.lines.len());
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["filters"][0]["filter"], "hot");
+        assert_eq!(filter_system.take_profile().samples, 0);
+
+        #[cfg(not(feature = "luau"))]
+        {
+            filter_system.set_profile_interval(1000);
+            run(&filter_system);
+            let sparse = filter_system.take_profile();
+            assert_eq!(sparse.interval, 1000);
+            assert!(sparse.samples < report.samples / 5, "{sparse:?}");
+        }
+
+        filter_system.profile(false);
+        run(&filter_system);
+        assert_eq!(filter_system.take_profile().samples, 0);
+    }
+
+    #[test]
+    fn load_errors() {
+        let scripts = Scripts::new("load-errors");
+        let good = scripts.write("good.lua", "return { good = function(tx) return true end }");
+        let failing = scripts.write("failing.lua", "error('failing on load')");
+        let number = scripts.write("number.lua", "return 42");
+        let field = scripts.write("field.lua", "return { limit = 10 }");
+        let yaml = |path: &Path| {
+            format!(
+                "chains:\n    uni-5:\n        - name: Checked\n          script: {}\n",
+                path.display()
+            )
+        };
+
+        assert!(matches!(
+            Config::from_yaml("chains: [").err().unwrap(),
+            ConfigError::Parse(_)
+        ));
+        let err = Config::from_yaml("chains:\n    uni-5:\n        - name: Scriptless\n")
+            .err()
+            .unwrap();
+        assert!(matches!(err, ConfigError::Parse(_)), "{err}");
+        let err = Config::from_yaml(&yaml(Path::new(""))).err().unwrap();
+        assert!(
+            matches!(&err, ConfigError::MissingScript { chain, filter }
+                if chain == "uni-5" && filter == "Checked"),
+            "{err}"
+        );
+        let err = Config::from_path(scripts.dir.join("missing.yaml"))
+            .err()
+            .unwrap();
+        assert!(
+            matches!(&err, ConfigError::Io { source, .. }
+                if source.kind() == std::io::ErrorKind::NotFound),
+            "{err}"
+        );
+        let config_path = scripts.write("config.yaml", &yaml(&good));
+        assert_eq!(Config::from_path(&config_path).unwrap().chains.len(), 1);
+
+        let filter_runtime = FilterRuntime::<MockTx>::new_with_options(RuntimeOptions {
+            max_filters_per_chain: 1,
+            ..Default::default()
+        })
+        .unwrap();
+        let load = |path: &Path| {
+            let config = Config::from_yaml(&yaml(path)).unwrap();
+            filter_runtime.load(config).err().unwrap()
+        };
+        let script_origin = |path: &Path| LoadOrigin::Script {
+            chain: "uni-5".to_string(),
+            path: path.to_path_buf(),
+        };
+
+        let missing = scripts.dir.join("missing.lua");
+        let err = load(&missing);
+        assert!(
+            matches!(&err, LoadError::Io { origin, error }
+                if **origin == script_origin(&missing)
+                    && error.kind() == std::io::ErrorKind::NotFound),
+            "{err}"
+        );
+        let err = load(&failing);
+        assert!(
+            matches!(&err, LoadError::Lua { origin, .. } if **origin == script_origin(&failing)),
+            "{err}"
+        );
+        assert!(err.to_string().contains("failing on load"), "{err}");
+        let err = load(&number);
+        assert!(
+            matches!(&err, LoadError::Module { origin, message }
+                if **origin == script_origin(&number) && message.contains("of type")),
+            "{err}"
+        );
+        let err = load(&field);
+        assert!(
+            matches!(&err, LoadError::Module { message, .. } if message.contains("`limit`")),
+            "{err}"
+        );
+
+        // Byte order marks are dropped, UTF-16 is transcoded and other encodings refused.
+        let source = "return { good = function(tx) return true end }";
+        let bom = scripts.dir.join("bom.lua");
+        std::fs::write(&bom, [b"\xEF\xBB\xBF", source.as_bytes()].concat()).unwrap();
+        filter_runtime
+            .load(Config::from_yaml(&yaml(&bom)).unwrap())
+            .unwrap();
+        let utf16 = scripts.dir.join("utf16.lua");
+        let units = source.encode_utf16().flat_map(u16::to_le_bytes);
+        std::fs::write(
+            &utf16,
+            [0xFF, 0xFE].into_iter().chain(units).collect::<Vec<u8>>(),
+        )
+        .unwrap();
+        filter_runtime
+            .load(Config::from_yaml(&yaml(&utf16)).unwrap())
+            .unwrap();
+        let latin1 = scripts.dir.join("latin1.lua");
+        std::fs::write(&latin1, b"-- caf\xE9\nreturn {}").unwrap();
+        let err = load(&latin1);
+        assert!(
+            matches!(&err, LoadError::Encoding { origin, error }
+                if **origin == script_origin(&latin1)
+                    && *error == EncodingError::InvalidUtf8 { offset: 6 }),
+            "{err}"
+        );
+        assert!(err.to_string().contains("latin1.lua"), "{err}");
+
+        let mut config = Config::from_yaml(&yaml(&good)).unwrap();
+        config.libraries.insert("lib".to_string(), failing.clone());
+        let err = filter_runtime.load(config.clone()).err().unwrap();
+        assert!(
+            matches!(&err, LoadError::Lua { origin, .. }
+                if origin.chain().is_none() && origin.path() == failing),
+            "{err}"
+        );
+        config.libraries.clear();
+        let filters = config.chains.get_mut("uni-5").unwrap();
+        filters.push(filters[0].clone());
+        let err = filter_runtime.load(config).err().unwrap();
+        assert!(
+            matches!(
+                &err,
+                LoadError::Config(ConfigError::OverLimit {
+                    limit: "max_filters_per_chain",
+                    count: 2,
+                    max: 1,
+                    ..
+                })
+            ),
+            "{err}"
+        );
+
+        // Callers not telling errors apart can funnel them into one type.
+        let load_and_filter = || -> Result<bool, Error> {
+            let config = Config::from_path(&config_path)?;
+            let filter_system = filter_runtime.load(config)?;
+            Ok(filter_system.filter_one(mock_tx("juno1", 0))?)
+        };
+        assert!(load_and_filter().unwrap());
+    }
+
+    #[test]
+    fn load_collecting() {
+        let scripts = Scripts::new("load-collecting");
+        let good = scripts.write("good.lua", "return { good = function(tx) return true end }");
+        let other = scripts.write(
+            "other.lua",
+            "return { other = function(tx) return false end }",
+        );
+        let broken = scripts.write("broken.lua", "return { broken = function(tx) return end");
+        let missing = scripts.dir.join("missing.lua");
+        let filter = |name: &str, script: &PathBuf| FilterConfig {
+            name: name.to_string(),
+            script: script.clone(),
+            ..Default::default()
+        };
+        let config = || Config {
+            chains: [
+                (
+                    "uni-5".to_string(),
+                    vec![filter("Good", &good), filter("Missing", &missing)],
+                ),
+                (
+                    "juno-1".to_string(),
+                    vec![filter("Broken", &broken), filter("Other", &other)],
+                ),
+            ]
+            .into(),
+            libraries: [("lib".to_string(), broken.clone())].into(),
+            ..Default::default()
+        };
+        let filter_runtime = FilterRuntime::<MockTx>::new();
+        let mut filter_system = FilterSystem::new(&filter_runtime.runtime);
+
+        // Every failure is reported, and nothing is loaded.
+        let report = filter_system.load_collecting(config()).unwrap_err();
+        assert!(!report.is_complete());
+        let mut loaded: Vec<_> = report
+            .loaded
+            .iter()
+            .map(|filter| (filter.name.as_str(), filter.functions.clone()))
+            .collect();
+        loaded.sort();
+        assert_eq!(
+            loaded,
+            [
+                ("Good", vec!["good".to_string()]),
+                ("Other", vec!["other".to_string()])
+            ]
+        );
+        let mut failures: Vec<_> = report
+            .failures
+            .iter()
+            .map(|failure| {
+                (
+                    failure.chain.as_deref(),
+                    failure.name.as_deref(),
+                    failure.script.clone(),
+                    match &failure.error {
+                        LoadError::Io { .. } => "io",
+                        LoadError::Lua { .. } => "lua",
+                        _ => "other",
+                    },
+                )
+            })
+            .collect();
+        failures.sort();
+        assert_eq!(
+            failures,
+            [
+                (None, Some("lib"), Some(broken.clone()), "lua"),
+                (Some("juno-1"), Some("Broken"), Some(broken.clone()), "lua"),
+                (Some("uni-5"), Some("Missing"), Some(missing.clone()), "io"),
+            ]
+        );
+        let message = report.to_string();
+        assert!(
+            message.starts_with("2 filters loaded, 3 failures"),
+            "{message}"
+        );
+        assert!(
+            message.contains("filter `Missing`: failed to read"),
+            "{message}"
+        );
+        assert!(filter_system.stats().is_empty());
+
+        // Under partial loading, the good filters are kept.
+        filter_system.partial_load(true);
+        let report = filter_system.load_collecting(config()).unwrap();
+        assert_eq!((report.loaded.len(), report.failures.len()), (2, 3));
+        assert_eq!(filter_system.stats().len(), 2);
+        assert!(filter_system.filter_one(mock_tx("juno1", 0)).unwrap());
+
+        // A configuration over the limits fails as a whole.
+        let limited = FilterRuntime::<MockTx>::new_with_options(RuntimeOptions {
+            max_scripts: 1,
+            ..Default::default()
+        })
+        .unwrap();
+        let report = FilterSystem::<MockTx>::new(&limited.runtime)
+            .load_collecting(config())
+            .unwrap_err();
+        assert!(report.loaded.is_empty());
+        assert!(matches!(
+            &report.failures[..],
+            [LoadFailure {
+                error: LoadError::Config(ConfigError::OverLimit { .. }),
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn compile_threads() {
+        let scripts = Scripts::new("compile");
+        let paths: Vec<PathBuf> = (0..100)
+            .map(|i| {
+                // Every other script is an expression, like `eval` accepts.
+                let modulus = i + 2;
+                let source = match i % 2 {
+                    0 => format!(
+                        "return {{ by_{i} = function(tx) return tx.amount % {modulus} == 0 end }}"
+                    ),
+                    _ => {
+                        format!("{{ by_{i} = function(tx) return tx.amount % {modulus} == 1 end }}")
+                    }
+                };
+                scripts.write(&format!("filter-{i}.lua"), &source)
+            })
+            .collect();
+        scripts.write("failing.lua", "error('failing on load')");
+        scripts.write("broken.lua", "return {");
+        let config = |paths: &[PathBuf]| Config {
+            chains: [(
+                "uni-5".to_string(),
+                paths
+                    .iter()
+                    .map(|script| FilterConfig {
+                        name: "Generated".to_string(),
+                        script: script.clone(),
+                        ..Default::default()
+                    })
+                    .collect(),
+            )]
+            .into(),
+            ..Default::default()
+        };
+        let runtime = |compile_threads| {
+            FilterRuntime::<MockTx>::new_with_options(RuntimeOptions {
+                compile_threads,
+                ..Default::default()
+            })
+            .unwrap()
+        };
+
+        let verdicts = |compile_threads| {
+            let filter_runtime = runtime(compile_threads);
+            let filter_system = filter_runtime.load(config(&paths)).unwrap();
+            let mut names: Vec<String> = filter_system
+                .stats()
+                .into_iter()
+                .map(|stats| stats.name)
+                .collect();
+            names.sort();
+            let matched_by: Vec<Vec<String>> = (0..50)
+                .map(|amount| {
+                    let verdict = filter_system
+                        .filter_one_detailed(mock_tx("juno1", amount))
+                        .unwrap();
+                    let mut matched_by = verdict.matched_by;
+                    matched_by.sort();
+                    matched_by
+                })
+                .collect();
+            (names, matched_by)
+        };
+        let sequential = verdicts(1);
+        assert_eq!(sequential.0.len(), 100);
+        assert_eq!(verdicts(4), sequential);
+        assert_eq!(verdicts(0), sequential);
+
+        // The first script failing in configuration order is reported, the same way.
+        let failure = |paths: &[PathBuf], compile_threads| {
+            let filter_runtime = runtime(compile_threads);
+            let err = filter_runtime.load(config(paths)).err().unwrap();
+            // Tracebacks name functions after whichever table holding them Lua finds first,
+            // which varies between runtimes.
+            let err = err.to_string();
+            err.split("\nstack traceback:").next().unwrap().to_string()
+        };
+        let mut failing = paths.clone();
+        failing.insert(70, scripts.dir.join("missing.lua"));
+        failing.insert(60, scripts.dir.join("broken.lua"));
+        failing.insert(50, scripts.dir.join("failing.lua"));
+        for paths in [&failing[..], &failing[51..], &failing[62..]] {
+            let err = failure(paths, 1);
+            assert_eq!(failure(paths, 4), err);
+        }
+        assert!(failure(&failing, 4).contains("failing on load"));
+        assert!(failure(&failing[51..], 4).contains("broken.lua:1:"));
+    }
+
+    #[test]
+    fn filter_arcs() {
+        static CLONES: AtomicUsize = AtomicUsize::new(0);
+
+        #[derive(Serialize)]
+        struct Counted(u64);
+        impl Clone for Counted {
+            fn clone(&self) -> Self {
+                CLONES.fetch_add(1, Ordering::SeqCst);
+                Counted(self.0)
+            }
+        }
+        impl mlua::UserData for Counted {}
+
+        let filter_runtime = FilterRuntime::<Counted>::new();
+        let filter_system = load_script(
+            &filter_runtime.runtime,
+            indoc! {r#"
+            return {
+                even = function(n) return n % 2 == 0 end,
+            }
+            "#},
+        );
+        let values: Vec<Arc<Counted>> = (0..10).map(|i| Arc::new(Counted(i))).collect();
+        let kept = filter_system.filter_arcs(values.clone()).unwrap();
+        assert_eq!(
+            kept.iter().map(|value| value.0).collect::<Vec<_>>(),
+            [0, 2, 4, 6, 8]
+        );
+        // The kept values are the ones passed in.
+        assert!(Arc::ptr_eq(&kept[1], &values[2]));
+        assert_eq!(Arc::strong_count(&values[2]), 2);
+        assert_eq!(CLONES.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn batches_move_values() {
+        static CLONES: AtomicUsize = AtomicUsize::new(0);
+        static SERIALIZED: AtomicUsize = AtomicUsize::new(0);
+
+        struct Counted(u64);
+        impl Clone for Counted {
+            fn clone(&self) -> Self {
+                CLONES.fetch_add(1, Ordering::SeqCst);
+                Counted(self.0)
+            }
+        }
+        impl Serialize for Counted {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                SERIALIZED.fetch_add(1, Ordering::SeqCst);
+                serializer.serialize_u64(self.0)
+            }
+        }
+        impl mlua::UserData for Counted {}
+
+        let filter_runtime = FilterRuntime::<Counted>::new();
+        let mut filter_system = load_script(
+            &filter_runtime.runtime,
+            indoc! {r#"
+            return {
+                even = function(n) return n % 2 == 0 end,
+                big = function(n) return n > 90 end,
+                never = function(n) return false end,
+            }
+            "#},
+        );
+        let values = || (0..100).map(Counted).collect::<Vec<_>>();
+        let kept = |values: &[Counted]| values.iter().map(|value| value.0).collect::<Vec<_>>();
+
+        let expected = filter_system.filter(values()).unwrap();
+        assert_eq!(expected.len(), 55);
+        // One conversion per value, however many filters there are.
+        assert_eq!(SERIALIZED.load(Ordering::SeqCst), 100);
+        let expected = kept(&expected);
+        let slice = values();
+        let by_ref = filter_system.filter_ref(&slice).unwrap();
+        assert_eq!(
+            by_ref.into_iter().map(|value| value.0).collect::<Vec<_>>(),
+            expected
+        );
+        let indices = filter_system.filter_indices(&slice).unwrap();
+        assert_eq!(
+            indices
+                .iter()
+                .map(|&index| index as u64)
+                .collect::<Vec<_>>(),
+            expected
+        );
+        let with_context = filter_system.filter_with_context(values(), &()).unwrap();
+        assert_eq!(kept(&with_context), expected);
+        let mut retained = values();
+        filter_system.retain(&mut retained).unwrap();
+        assert_eq!(kept(&retained), expected);
+        filter_system.set_error_policy(ErrorPolicy::Lenient);
+        let chunked = filter_system
+            .filter_chunked(values(), 7, |_| ControlFlow::Continue(()))
+            .unwrap();
+        assert_eq!(kept(&chunked), expected);
+
+        assert_eq!(CLONES.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn unload_releases_memory() {
+        let scripts = Scripts::new("unload");
+        let script = scripts.write(
+            "filter.lua",
+            indoc! {r#"
+            local seen = {}
+            local blob = string.rep("x", 4096)
+            return {
+                filter = function(tx)
+                    seen[tx.from] = blob
+                    return memo.cache(tx.from, function() return blob .. tx.from end) ~= nil
+                end,
+            }
+            "#},
+        );
+        let input = format!(
+            "chains:\n    uni-5:\n        - name: Seen\n          script: {}\n        - name: Other\n          script: {}\n",
+            script.display(),
+            script.display()
+        );
+        let config = || serde_yaml::from_str::<Config>(&input).unwrap();
+
+        let filter_runtime = FilterRuntime::<MockTx>::new();
+        // Each filtered value leaves a distinct 4KB memo entry behind, plus its `seen` entry.
+        let fill = |filter_system: &mut FilterSystem<MockTx>, cycle: usize| {
+            for i in 0..64 {
+                filter_system
+                    .filter_one(mock_tx(&format!("juno{cycle}x{i}"), 0))
+                    .unwrap();
+            }
+        };
+        let cycle = |i: usize, settled: usize| {
+            let mut filter_system = filter_runtime.load(config()).unwrap();
+            fill(&mut filter_system, i);
+            filter_system.reload(config()).unwrap();
+            fill(&mut filter_system, i);
+            // Every other system is emptied before being dropped.
+            if i.is_multiple_of(2) {
+                assert_eq!(filter_system.remove("filter"), 2);
+                assert!(filter_system.stats().is_empty());
+                let used = filter_runtime.used_memory();
+                assert!(
+                    used < settled + 64 * 1024,
+                    "removed {i}: {used} vs {settled}"
+                );
+            }
+            drop(filter_system);
+            let used = filter_runtime.used_memory();
+            assert!(
+                used < settled + 64 * 1024,
+                "dropped {i}: {used} vs {settled}"
+            );
+        };
+        // Warm up on an even cycle last, so nothing a mistake could retain is in the baseline.
+        for i in 0..=10 {
+            cycle(i, usize::MAX / 2);
+        }
+        let settled = filter_runtime.used_memory();
+        for i in 0..300 {
+            cycle(i, settled);
+        }
+    }
+
+    #[test]
+    fn dry_run() {
+        let config = Config::from_yaml(indoc! {r#"
+        chains:
+            uni-5:
+                - name: Shadow
+                  script: filters/shadow.lua
+                  dry_run: true
+        "#})
+        .unwrap();
+        assert!(config.chains["uni-5"][0].dry_run);
+
+        let lua = Lua::new();
+        let mut filter_system = load_script::<MockTx>(
+            &lua,
+            indoc! {r#"
+            return {
+                large = function(tx) return tx.amount > 100 end,
+                shadow = function(tx)
+                    if tx.amount == 0 then error("no amount") end
+                    return tx.from == "0xDEADBEEF", "shadowed"
+                end,
+            }
+            "#},
+        );
+        assert_eq!(filter_system.set_dry_run("shadow", true), 1);
+        assert_eq!(filter_system.set_dry_run("missing", true), 0);
+
+        // The dry-run filter matches without the value being kept.
+        let verdict = filter_system
+            .filter_one_detailed(mock_tx("0xDEADBEEF", 10))
+            .unwrap();
+        assert!(!verdict.matched);
+        assert!(verdict.would_match);
+        assert!(verdict.matched_by.is_empty());
+        assert_eq!(verdict.dry_run, [("shadow".to_string(), true)]);
+        assert_eq!(
+            verdict.reasons,
+            [("shadow".to_string(), "shadowed".to_string())]
+        );
+        let values = vec![mock_tx("0xDEADBEEF", 10), mock_tx("0xBEEFFEEF", 200)];
+        let kept = filter_system.filter(values).unwrap();
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].amount, 200);
+
+        // Filters don't short-circuit: the dry-run filter runs after one that matched.
+        let verdict = filter_system
+            .filter_one_detailed(mock_tx("0xBEEFFEEF", 200))
+            .unwrap();
+        assert!(verdict.matched && verdict.would_match);
+        assert_eq!(verdict.matched_by, ["large"]);
+        assert_eq!(verdict.dry_run, [("shadow".to_string(), false)]);
+
+        // Its errors don't fail a system that fails fast.
+        let verdict = filter_system
+            .filter_one_detailed(mock_tx("0xDEADBEEF", 0))
+            .unwrap();
+        assert!(!verdict.matched && !verdict.would_match);
+        assert!(verdict.dry_run.is_empty());
+
+        let stats = filter_system.stats();
+        let shadow = stats.iter().find(|stats| stats.name == "shadow").unwrap();
+        assert!(shadow.dry_run);
+        assert_eq!(shadow.invocations, 5);
+        assert_eq!(shadow.matches, 2);
+        assert_eq!(shadow.errors, 1);
+
+        // Switched back, its verdicts count again, errors included.
+        assert_eq!(filter_system.set_dry_run("shadow", false), 1);
+        assert!(filter_system.filter_one(mock_tx("0xDEADBEEF", 10)).unwrap());
+        assert!(filter_system.filter_one(mock_tx("0xDEADBEEF", 0)).is_err());
+    }
+
+    #[test]
+    fn run_tests() {
+        let scripts = Scripts::new("run-tests");
+        let manager = scripts.filter(
+            "manager",
+            r#"return { manager = function(tx) return tx.from == "0xDEADBEEF" end }"#,
+        );
+        let whale = scripts.filter(
+            "whale",
+            indoc! {r#"
+            return {
+                whale = function(tx)
+                    if tx.amount == 0 then error("no amount") end
+                    return tx.amount > 1000
+                end,
+            }
+            "#},
+        );
+        let filter_runtime = FilterRuntime::<MockTx>::new();
+        let filter_system = filter_runtime
+            .load(Config {
+                chains: [
+                    ("uni-5".to_string(), vec![manager]),
+                    ("juno-1".to_string(), vec![whale]),
+                ]
+                .into(),
+                ..Default::default()
+            })
+            .unwrap();
+        let case = |name: &str, input, target, expected| TestCase {
+            name: name.to_string(),
+            input,
+            target,
+            expected,
+        };
+        let matched_by = |names: &[&str]| {
+            Outcome::MatchedBy(names.iter().map(|name| name.to_string()).collect())
+        };
+
+        let report = filter_system.run_tests(vec![
+            case(
+                "manager whale",
+                mock_tx("0xDEADBEEF", 5000),
+                TestTarget::All,
+                matched_by(&["manager", "whale"]),
+            ),
+            case(
+                "uni-5 only",
+                mock_tx("0xBEEFFEEF", 5000),
+                TestTarget::Chain("uni-5".to_string()),
+                Outcome::Matched(false),
+            ),
+            case(
+                "whale alone",
+                mock_tx("0xDEADBEEF", 5000),
+                TestTarget::Filter("whale".to_string()),
+                matched_by(&["whale"]),
+            ),
+        ]);
+        assert!(report.all_passed(), "{report}");
+        assert_eq!(report.to_string(), "test result: ok. 3 passed; 0 failed");
+
+        let report = filter_system.run_tests(vec![
+            case(
+                "small",
+                mock_tx("0xBEEFFEEF", 10),
+                TestTarget::All,
+                Outcome::Matched(true),
+            ),
+            case(
+                "passes",
+                mock_tx("0xBEEFFEEF", 10),
+                TestTarget::Chain("juno-1".to_string()),
+                matched_by(&[]),
+            ),
+            case(
+                "empty",
+                mock_tx("0xDEADBEEF", 0),
+                TestTarget::All,
+                Outcome::Matched(true),
+            ),
+        ]);
+        assert!(!report.all_passed());
+        assert_eq!(report.passed, 1);
+        assert_eq!(report.failures.len(), 2);
+        assert_eq!(report.failures[0].case, "small");
+        assert_eq!(report.failures[0].expected, Outcome::Matched(true));
+        assert_eq!(
+            report.failures[0].actual.as_ref().unwrap(),
+            &Outcome::Matched(false)
+        );
+        assert!(report.failures[1].actual.is_err());
+        let display = report.to_string();
+        assert!(
+            display.starts_with("case `small`: expected kept, got dropped\ncase `empty`"),
+            "{display}"
+        );
+        assert!(display.contains("no amount"), "{display}");
+        assert!(
+            display.ends_with("test result: FAILED. 1 passed; 2 failed"),
+            "{display}"
+        );
+    }
+
+    #[test]
+    fn record_and_verify() {
+        let lua = Lua::new();
+        let before = load_script::<MockTx>(
+            &lua,
+            indoc! {r#"
+            return {
+                manager = function(tx) return tx.from == "0xDEADBEEF" end,
+                whale = function(tx) return tx.amount > 1000 end,
+            }
+            "#},
+        );
+        let values: Vec<MockTx> = (0..15).map(|i| mock_tx("0xBEEFFEEF", i * 100)).collect();
+        let mut recording = Vec::new();
+        before.record(&values, &mut recording).unwrap();
+        let lines: Vec<&str> = std::str::from_utf8(&recording).unwrap().lines().collect();
+        assert_eq!(lines.len(), 15);
+        assert_eq!(
+            lines[0],
+            r#"{"value":{"chain":"uni-5","from":"0xBEEFFEEF","to":"0xBEEFFEEF","amount":0},"verdicts":{"manager":false,"whale":false}}"#
+        );
+
+        // Replaying through the same filters finds nothing.
+        let report = before.verify_recording(recording.as_slice()).unwrap();
+        assert!(report.matches());
+        assert_eq!(report.values, 15);
+
+        let after = load_script::<MockTx>(
+            &lua,
+            indoc! {r#"
+            return {
+                manager = function(tx) return tx.from == "0xDEADBEEF" end,
+                whale = function(tx) return tx.amount >= 200 end,
+                newcomer = function(tx) return false end,
+            }
+            "#},
+        );
+        let report = after.verify_recording(recording.as_slice()).unwrap();
+        assert_eq!(report.values, 15);
+        assert_eq!(report.differing, 15);
+        assert!(!report.filters.contains_key("manager"));
+
+        // Amounts from 200 to 1000 now match; only the first examples are kept.
+        let whale = &report.filters["whale"];
+        assert_eq!(whale.count, 9);
+        assert_eq!(whale.examples.len(), 9);
+        assert_eq!(whale.examples[0].line, 3);
+        assert_eq!(whale.examples[0].value["amount"], 200);
+        assert_eq!(whale.examples[0].recorded, Some(false));
+        assert_eq!(whale.examples[0].replayed, Some(true));
+        let newcomer = &report.filters["newcomer"];
+        assert_eq!(newcomer.count, 15);
+        assert_eq!(newcomer.examples.len(), VerifyReport::MAX_EXAMPLES);
+        assert_eq!(newcomer.examples[0].recorded, None);
+        assert_eq!(newcomer.examples[0].replayed, Some(false));
+
+        let err = after
+            .verify_recording(&b"{\"value\": 1, \"verdicts\": {}}\n"[..])
+            .unwrap_err();
+        assert!(matches!(err, RecordingError::Json { line: 1, .. }), "{err}");
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn metrics() {
+        use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+
+        let lua = Lua::new();
+        let mut filter_system = load_script::<MockTx>(
+            &lua,
+            indoc! {r#"
+            return {
+                whale = function(tx)
+                    if tx.amount == 0 then error("no amount") end
+                    return tx.amount > 1000
+                end,
+            }
+            "#},
+        );
+        filter_system.set_error_policy(ErrorPolicy::Lenient);
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        metrics::with_local_recorder(&recorder, || {
+            let values = vec![
+                mock_tx("0xDEADBEEF", 10),
+                mock_tx("0xDEADBEEF", 5000),
+                mock_tx("0xDEADBEEF", 0),
+            ];
+            filter_system.filter(values).unwrap();
+        });
+
+        let mut counters = BTreeMap::new();
+        let mut durations = 0;
+        for (key, _, _, value) in snapshotter.snapshot().into_vec() {
+            let key = key.key();
+            let labels: Vec<(&str, &str)> = key
+                .labels()
+                .map(|label| (label.key(), label.value()))
+                .collect();
+            assert_eq!(labels, [("filter", "whale"), ("chain", "")]);
+            match value {
+                DebugValue::Counter(count) => {
+                    counters.insert(key.name().to_string(), count);
+                }
+                DebugValue::Histogram(samples) => durations += samples.len(),
+                DebugValue::Gauge(_) => unreachable!(),
+            }
+        }
+        assert_eq!(
+            counters,
+            BTreeMap::from([
+                ("filter_errors_total".to_string(), 1),
+                ("filter_invocations_total".to_string(), 3),
+                ("filter_matches_total".to_string(), 1),
+            ])
+        );
+        assert_eq!(durations, 3);
+    }
+
+    #[test]
+    fn observer() {
+        #[derive(Clone, Default)]
+        struct Events(Rc<RefCell<Vec<String>>>);
+
+        impl FilterObserver for Events {
+            fn on_match(&self, filter: &str, chain: &str) {
+                self.0
+                    .borrow_mut()
+                    .push(format!("match {filter} [{chain}]"));
+            }
+
+            fn on_reject(&self, filter: &str, _chain: &str) {
+                if filter == "panicky" {
+                    panic!("observer panicked");
+                }
+                self.0.borrow_mut().push(format!("reject {filter}"));
+            }
+
+            fn on_error(&self, filter: &str, _chain: &str, error: &FilterError) {
+                let error = error.to_string();
+                assert!(error.contains("no amount"), "{error}");
+                self.0.borrow_mut().push(format!("error {filter}"));
+            }
+        }
+
+        let lua = Lua::new();
+        let mut filter_system = load_script::<MockTx>(
+            &lua,
+            indoc! {r#"
+            return {
+                a_manager = function(tx) return tx.from == "0xDEADBEEF" end,
+                b_whale = function(tx)
+                    if tx.amount == 0 then error("no amount") end
+                    return tx.amount > 1000
+                end,
+                c_panicky = function(tx) return false end,
+            }
+            "#},
+        );
+        // The script table's order is unspecified; sort the filters so the order is known.
+        filter_system.filters.sort_by(|a, b| a.name.cmp(&b.name));
+        for filter in &mut filter_system.filters {
+            filter.name = filter.name[2..].to_string();
+        }
+        filter_system.filters[0].chain = Some("uni-5".to_string());
+        let events = Events::default();
+        filter_system.set_observer(events.clone());
+
+        // Every filter is called, in order, after one matched: filters don't short-circuit.
+        let kept = filter_system
+            .filter(vec![mock_tx("0xDEADBEEF", 10), mock_tx("0xBEEFFEEF", 5000)])
+            .unwrap();
+        assert_eq!(kept.len(), 2);
+        assert_eq!(
+            events.0.take(),
+            [
+                "match uni-5/manager [uni-5]",
+                "reject whale",
+                "reject uni-5/manager",
+                "match whale []",
+            ]
+        );
+        // The observer panicked on both values without failing them.
+        assert_eq!(filter_system.observer_panics(), 2);
+
+        // A failing filter is observed, then stops the evaluation before the next filter.
+        filter_system
+            .filter_one(mock_tx("0xDEADBEEF", 0))
+            .unwrap_err();
+        assert_eq!(
+            events.0.take(),
+            ["match uni-5/manager [uni-5]", "error whale"]
+        );
+
+        filter_system.set_error_policy(ErrorPolicy::Lenient);
+        assert!(!filter_system.filter_one(mock_tx("0xBEEFFEEF", 0)).unwrap());
+        assert_eq!(events.0.take(), ["reject uni-5/manager", "error whale"]);
+        assert_eq!(filter_system.observer_panics(), 3);
+
+        assert!(filter_system.take_observer().is_some());
+        filter_system.filter_one(mock_tx("0xDEADBEEF", 10)).unwrap();
+        assert!(events.0.take().is_empty());
+    }
+
+    #[test]
+    fn quarantine() {
+        #[derive(Clone, Default)]
+        struct Events(Rc<RefCell<Vec<String>>>);
+
+        impl FilterObserver for Events {
+            fn on_quarantine(&self, filter: &str, chain: &str, errors: u32) {
+                let event = format!("quarantine {filter} [{chain}] after {errors}");
+                self.0.borrow_mut().push(event);
+            }
+        }
+
+        let scripts = Scripts::new("quarantine");
+        let script = scripts.dir.join("rules.lua");
+        let write = |broken: &str| {
+            let source = format!(
+                "return {{ broken = function(tx) {broken} end, \
+                 big = function(tx) return tx.amount > 100 end }}"
+            );
+            scripts.write("rules.lua", &source);
+        };
+        let config = |quarantine_after| Config {
+            chains: [(
+                "uni-5".to_string(),
+                vec![FilterConfig {
+                    name: "Rules".to_string(),
+                    script: script.clone(),
+                    quarantine_after,
+                    ..Default::default()
+                }],
+            )]
+            .into(),
+            ..Default::default()
+        };
+        let stats = |filter_system: &FilterSystem<MockTx>| {
+            let stats = filter_system.stats().into_iter();
+            let broken = stats.clone().find(|stats| stats.name == "broken").unwrap();
+            let big = stats.clone().find(|stats| stats.name == "big").unwrap();
+            (broken, big)
+        };
+        let amounts = [500, 1, 500, 1, 500, 1];
+        let filter = |filter_system: &FilterSystem<MockTx>| -> Vec<bool> {
+            let amounts = amounts.into_iter();
+            amounts
+                .map(|amount| filter_system.filter_one(mock_tx("0xA", amount)).unwrap())
+                .collect()
+        };
+
+        write(r#"error("always")"#);
+        let filter_runtime = FilterRuntime::<MockTx>::new();
+        let mut filter_system = filter_runtime.load(config(None)).unwrap();
+        filter_system.set_error_policy(ErrorPolicy::Lenient);
+        filter_system.set_quarantine(Some(Quarantine {
+            after: 3,
+            cool_down: None,
+        }));
+        let events = Events::default();
+        filter_system.set_observer(events.clone());
+
+        // The failing filter stops being called after 3 errors, and the others keep going.
+        assert_eq!(
+            filter(&filter_system),
+            [true, false, true, false, true, false]
+        );
+        let (broken, big) = stats(&filter_system);
+        assert_eq!((broken.invocations, broken.errors), (3, 3));
+        assert!(broken.quarantined);
+        assert_eq!(broken.quarantines, 1);
+        assert_eq!((big.invocations, big.quarantined), (6, false));
+        assert_eq!(
+            events.0.take(),
+            ["quarantine uni-5/Rules/broken [uni-5] after 3"]
+        );
+        let health = filter_system.health();
+        assert_eq!(health.quarantined_filters, 1);
+        assert_eq!(health.status, Status::Degraded);
+        assert_eq!(health.problems, ["1 filter is quarantined"]);
+
+        // A reload replacing it brings it back.
+        write("return tx.amount == 1");
+        filter_system.reload(config(None)).unwrap();
+        assert_eq!(filter(&filter_system), [true; 6]);
+        let (broken, _) = stats(&filter_system);
+        assert_eq!((broken.invocations, broken.quarantined), (6, false));
+        assert_eq!(filter_system.health().quarantined_filters, 0);
+
+        // After its cool-down, a quarantined filter runs again, back to quarantine on its
+        // first error.
+        write(r#"error("always")"#);
+        filter_system.reload(config(None)).unwrap();
+        filter_system.set_quarantine(Some(Quarantine {
+            after: 1,
+            cool_down: Some(Duration::from_millis(100)),
+        }));
+        filter(&filter_system);
+        assert_eq!(stats(&filter_system).0.invocations, 1);
+        std::thread::sleep(Duration::from_millis(150));
+        filter(&filter_system);
+        let (broken, _) = stats(&filter_system);
+        assert_eq!((broken.invocations, broken.quarantines), (2, 2));
+        assert_eq!(
+            events.0.take(),
+            [
+                "quarantine uni-5/Rules/broken [uni-5] after 1",
+                "quarantine uni-5/Rules/broken [uni-5] after 2"
+            ]
+        );
 
-        let config: Config = serde_yaml::from_str(input).unwrap();
-        assert_eq!(config.chains.len(), 1);
-        assert_eq!(config.chains["uni-5"].len(), 1);
-        assert_eq!(config.chains["uni-5"][0].name, "Testnet Manager");
+        // A threshold of the configuration applies without one for the system, errors
+        // failing values until it is reached.
+        filter_system.set_quarantine(None);
+        filter_system.reload(config(Some(2))).unwrap();
+        filter_system.set_error_policy(ErrorPolicy::FailFast);
+        for _ in 0..2 {
+            let err = filter_system.filter_one(mock_tx("0xA", 1)).err().unwrap();
+            assert!(err.to_string().contains("always"), "{err}");
+        }
+        assert!(filter_system.filter_one(mock_tx("0xA", 500)).unwrap());
+        assert_eq!(stats(&filter_system).0.invocations, 2);
+    }
+
+    #[test]
+    fn filter_audited() {
+        let lua = Lua::new();
+        let mut filter_system = load_script::<MockTx>(
+            &lua,
+            indoc! {r#"
+            return {
+                whale = function(tx) return tx.amount > 1000, "amount " .. tx.amount end,
+            }
+            "#},
+        );
+        let values = || vec![mock_tx("0xDEADBEEF", 10), mock_tx("0xDEADBEEF", 5000)];
+        // Without a sink, nothing is audited.
+        assert_eq!(filter_system.filter_audited(values()).unwrap().len(), 1);
+
+        let (sink, receiver) = ChannelSink::new();
+        filter_system.set_audit_sink(sink);
+        let started = SystemTime::now();
+        let kept = filter_system.filter_audited(values()).unwrap();
+        assert_eq!(kept.len(), 1);
+        let records: Vec<AuditRecord> = receiver.try_iter().collect();
+        assert_eq!(records.len(), 2);
+        assert!(records[0].timestamp >= started);
+        assert_eq!(records[0].value["amount"], 10);
+        assert!(!records[0].verdicts["whale"]);
         assert_eq!(
-            config.chains["uni-5"][0].script.to_str().unwrap(),
-            "filters/test-filter.lua"
+            records[0].reasons,
+            [("whale".to_string(), "amount 10".to_string())]
         );
+        assert!(!records[0].kept);
+        assert!(records[1].kept);
+
+        // Plain filtering doesn't audit.
+        filter_system.filter(values()).unwrap();
+        assert_eq!(receiver.try_iter().count(), 0);
+
+        drop(receiver);
+        let err = filter_system.filter_audited(values()).err().unwrap();
+        assert!(matches!(err, AuditError::Sink(_)), "{err}");
     }
 
-    test_filter!(
-        simple_filter,
-        indoc! {r#"
-        function filter(tx)
-            return tx.from == "0xDEADBEEF"
-        end
+    #[test]
+    fn lint() {
+        let scripts = Scripts::new("lint");
+        let clean = scripts.filter(
+            "clean",
+            indoc! {r#"
+            local threshold = 1000
+            return {
+                whale = function(tx) return tx.amount > threshold end,
+                any = function(...) return true end,
+            }
+            "#},
+        );
+        let sloppy = scripts.filter(
+            "sloppy",
+            indoc! {r#"
+            threshold = 1000
+            helper = function(tx) return tx.amount > threshold end
+            return {
+                whale = function(tx)
+                    calls = (calls or 0) + 1
+                    return helper(tx)
+                end,
+                always = function() return true end,
+            }
+            "#},
+        );
+        let table = scripts.filter(
+            "table",
+            "return { whale = function(tx) return true end, threshold = 1000 }",
+        );
+        let config = |filters| Config {
+            chains: [("uni-5".to_string(), filters)].into(),
+            ..Default::default()
+        };
 
-        return {
-            filter = filter
+        let filter_runtime = FilterRuntime::<MockTx>::new();
+        let mut filter_system = FilterSystem::<MockTx>::new(&filter_runtime.runtime);
+        filter_system.load(config(vec![sloppy.clone()])).unwrap();
+        assert!(filter_system.take_lint_reports().is_empty());
+
+        let mut filter_system = FilterSystem::new(&filter_runtime.runtime);
+        filter_system.lint(true);
+        filter_system
+            .load(config(vec![clean.clone(), sloppy.clone()]))
+            .unwrap();
+        let reports = filter_system.take_lint_reports();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].script, sloppy.script);
+        let mut findings = reports[0].findings.clone();
+        // The order of the filters in the script's table is unspecified.
+        findings[2..].sort_by_key(|finding| finding.to_string());
+        assert_eq!(
+            findings,
+            [
+                LintFinding::GlobalWrite {
+                    name: "threshold".to_string()
+                },
+                LintFinding::GlobalWrite {
+                    name: "helper".to_string()
+                },
+                LintFinding::NoParameters {
+                    function: "always".to_string()
+                },
+            ]
+        );
+        // Globals still reach the filters, and those they assign when called aren't findings.
+        assert!(filter_system
+            .filter_one(mock_tx("0xDEADBEEF", 5000))
+            .unwrap());
+        assert_eq!(
+            filter_runtime
+                .runtime
+                .globals()
+                .get::<_, u32>("calls")
+                .unwrap(),
+            1
+        );
+        assert!(filter_system.take_lint_reports().is_empty());
+
+        // Values that aren't functions fail the load, linted or not.
+        let err = filter_system.load(config(vec![table])).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("`threshold` holds a value of type integer"),
+            "{err}"
+        );
+
+        let mut filter_system = FilterSystem::<MockTx>::new(&filter_runtime.runtime);
+        filter_system.strict_lint(true);
+        filter_system.load(config(vec![clean])).unwrap();
+        let err = filter_system.load(config(vec![sloppy])).unwrap_err();
+        let LoadError::Lint(report) = &err else {
+            panic!("{err}");
+        };
+        assert_eq!(report.findings.len(), 3);
+        assert!(
+            err.to_string().contains(
+                "sloppy.lua of chain `uni-5`: assigns the global `threshold`; assigns the global `helper`; filter"
+            ),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn load_report() {
+        let scripts = Scripts::new("load-report");
+        let manager = scripts.filter(
+            "manager",
+            r#"return { manager = function(tx) return tx.from == "0xDEADBEEF" end }"#,
+        );
+        let whale = scripts.filter(
+            "whale",
+            "return { whale = function(tx) return tx.amount > 1000 end }",
+        );
+        let config = || Config {
+            chains: [("uni-5".to_string(), vec![manager.clone(), whale.clone()])].into(),
+            ..Default::default()
+        };
+        let origins = |filter_system: &FilterSystem<MockTx>| {
+            let report = filter_system.load_report();
+            report
+                .loaded
+                .iter()
+                .map(|loaded| loaded.origin)
+                .collect::<Vec<_>>()
+        };
+
+        let filter_runtime = FilterRuntime::<MockTx>::new_with_options(RuntimeOptions {
+            compile_threads: 1,
+            script_cache: Some(scripts.dir.join("cache")),
+            ..Default::default()
+        })
+        .unwrap();
+        let mut filter_system = FilterSystem::<MockTx>::new(&filter_runtime.runtime);
+        assert!(filter_system.load_report().loaded.is_empty());
+        filter_system.load(config()).unwrap();
+        let report = filter_system.load_report();
+        assert!(report.is_complete());
+        assert_eq!(report.loaded.len(), 2);
+        assert_eq!(report.loaded[0].chain, "uni-5");
+        assert_eq!(report.loaded[0].name, "manager");
+        assert_eq!(report.loaded[0].functions, ["manager"]);
+        assert_eq!(
+            report.loaded[1].size,
+            std::fs::metadata(&whale.script).unwrap().len()
+        );
+        assert_eq!(origins(&filter_system), [ScriptOrigin::Source; 2]);
+
+        let total = report.loaded[0].load_time() + report.loaded[1].load_time();
+        assert_eq!(report.chain_load_times()["uni-5"], total);
+        assert!(report.loaded[1].compile_time <= report.loaded[1].load_time());
+        let slowest = report.slowest(1);
+        assert_eq!(slowest.len(), 1);
+        assert!(report
+            .loaded
+            .iter()
+            .all(|loaded| loaded.load_time() <= slowest[0].load_time()));
+
+        let display = report.to_string();
+        assert!(
+            display.starts_with(&format!(
+                "2 filters loaded, 0 failures\nchain `uni-5` (loaded in {total:?}):\n  "
+            )),
+            "{display}"
+        );
+        assert!(
+            display.contains(&format!(
+                "{} `whale` ({} bytes, source, read in ",
+                whale.script.display(),
+                report.loaded[1].size
+            )),
+            "{display}"
+        );
+        assert!(
+            display.contains("): whale\nslowest scripts:\n  "),
+            "{display}"
+        );
+        assert_eq!(
+            display
+                .lines()
+                .filter(|line| line.contains(" of chain `uni-5`: "))
+                .count(),
+            2
+        );
+        let json = serde_json::to_value(report).unwrap();
+        assert_eq!(json["loaded"][1]["functions"][0], "whale");
+        assert_eq!(json["loaded"][1]["origin"], "source");
+        assert!(json["loaded"][1]["compile_time"].is_f64());
+        assert!(json["loaded"][1]["read_time"].is_f64());
+        assert!(json["loaded"][1]["eval_time"].is_f64());
+
+        // The scripts are in the cache now.
+        filter_system.reload(config()).unwrap();
+        assert_eq!(origins(&filter_system), [ScriptOrigin::Cached; 2]);
+
+        let filter_runtime = FilterRuntime::<MockTx>::new_with_options(RuntimeOptions {
+            compile_threads: 2,
+            ..Default::default()
+        })
+        .unwrap();
+        let mut filter_system = FilterSystem::<MockTx>::new(&filter_runtime.runtime);
+        filter_system.load(config()).unwrap();
+        assert_eq!(origins(&filter_system), [ScriptOrigin::Precompiled; 2]);
+
+        // A chain loaded on its own replaces the report.
+        filter_system
+            .load_chain("juno-1", vec![whale.clone()])
+            .unwrap();
+        let report = filter_system.load_report();
+        assert_eq!(report.loaded.len(), 1);
+        assert_eq!(report.loaded[0].chain, "juno-1");
+    }
+
+    #[test]
+    fn health() {
+        let filter_runtime = FilterRuntime::<MockTx>::new();
+        let empty = FilterSystem::<MockTx>::new(&filter_runtime.runtime);
+        let health = empty.health();
+        assert_eq!(health.status, Status::Unhealthy);
+        assert_eq!(health.problems, ["no filters are loaded"]);
+        assert_eq!(health.last_load, None);
+
+        let mut filter_system = load_script::<MockTx>(
+            &filter_runtime.runtime,
+            "return { whale = function(tx) return tx.amount > 1000 end }",
+        );
+        let health = filter_system.health();
+        assert_eq!(health.status, Status::Healthy);
+        assert_eq!(health.filters, 1);
+        assert_eq!(health.disabled_filters, 0);
+        assert!(health.used_memory > 0);
+        assert_eq!(health.since_last_success, None);
+
+        // Successful calls are timed, and idling too long degrades.
+        filter_system.filter_one(mock_tx("juno1", 10)).unwrap();
+        let idle = filter_system.health().since_last_success.unwrap();
+        assert!(idle < Duration::from_secs(60), "{idle:?}");
+        filter_system.set_health_thresholds(HealthThresholds {
+            max_idle: Some(Duration::ZERO),
+            ..Default::default()
+        });
+        std::thread::sleep(Duration::from_millis(1));
+        assert_eq!(filter_system.health().status, Status::Degraded);
+
+        // A failed load degrades, and running over the memory limit is unhealthy.
+        filter_system.set_health_thresholds(HealthThresholds::default());
+        let missing = FilterConfig {
+            name: "missing".to_string(),
+            script: PathBuf::from("/nonexistent/missing.lua"),
+            ..Default::default()
+        };
+        filter_system
+            .load_chain("uni-5", vec![missing])
+            .unwrap_err();
+        let health = filter_system.health();
+        assert_eq!(health.status, Status::Degraded);
+        assert!(health.last_load.unwrap().error.is_some());
+        filter_system.set_health_thresholds(HealthThresholds {
+            memory_limit: Some(1),
+            ..Default::default()
+        });
+        let health = filter_system.health();
+        assert_eq!(health.status, Status::Unhealthy);
+        assert_eq!(health.problems.len(), 2);
+        assert!(health.problems[0].contains("over the limit of 1"));
+
+        let json = serde_json::to_value(&health).unwrap();
+        assert_eq!(json["status"], "unhealthy");
+        assert_eq!(json["memory_limit"], 1);
+        assert!(json["since_last_success"].is_f64());
+        assert!(json["last_load"]["at"].as_str().unwrap().ends_with('Z'));
+    }
+
+    #[test]
+    fn replay_ndjson() {
+        let lua = Lua::new();
+        let filter_system = load_script::<MockTx>(
+            &lua,
+            indoc! {r#"
+            return {
+                manager = function(tx) return tx.from == "0xDEADBEEF" end,
+                whale = function(tx) return tx.amount > 1000 end,
+            }
+            "#},
+        );
+        let export = indoc! {r#"
+            {"chain":"uni-5","from":"0xDEADBEEF","to":"0x0","amount":10,"expected":true}
+            {"chain":"uni-5","from":"0xBEEFFEEF","to":"0x0","amount":5000}
+
+            {"chain":"uni-5","from":"0xBEEFFEEF"
+            {"chain":"uni-5","from":"0xBEEFFEEF","to":"0x0","amount":10,"expected":true}
+            {"chain":"uni-5","from":"0xBEEFFEEF","to":"0x0","amount":20,"expected":true}
+        "#};
+        let report = filter_system.replay_ndjson(export.as_bytes()).unwrap();
+        assert_eq!(report.values, 4);
+        assert_eq!(report.kept, 2);
+        assert_eq!(
+            report.filters["manager"],
+            VerdictCounts {
+                matched: 1,
+                rejected: 3
+            }
+        );
+        assert_eq!(report.filters["whale"].matched, 1);
+        assert_eq!(report.parse_errors, [4]);
+        let lines: Vec<usize> = report.mismatches.iter().map(|m| m.line).collect();
+        assert_eq!(lines, [5, 6]);
+        assert!(!report.mismatches[0].kept);
+        assert!(!report.passed());
+
+        // Stopping at the first mismatch leaves the rest unfiltered.
+        let options = ReplayOptions {
+            stop_at_mismatch: true,
+        };
+        let report = filter_system
+            .replay_ndjson_with(export.as_bytes(), options)
+            .unwrap();
+        assert_eq!(report.values, 3);
+        assert_eq!(report.mismatches.len(), 1);
+    }
+
+    #[test]
+    fn module_shapes() {
+        let scripts = Scripts::new("module-shapes");
+        let filter_runtime = FilterRuntime::<MockTx>::new();
+        let load = |name: &str, source: &str| {
+            let config = Config {
+                chains: [("uni-5".to_string(), vec![scripts.filter(name, source)])].into(),
+                ..Default::default()
+            };
+            filter_runtime.load(config)
+        };
+        let names = |filter_system: &FilterSystem<MockTx>| {
+            let stats = filter_system.stats();
+            stats
+                .into_iter()
+                .map(|stats| stats.name)
+                .collect::<Vec<_>>()
+        };
+
+        let table = load("table", "return { whale = function(tx) return true end }");
+        assert_eq!(names(&table.unwrap()), ["whale"]);
+        let function = load("function", "return function(tx) return tx.amount > 10 end");
+        let function = function.unwrap();
+        assert_eq!(names(&function), ["function"]);
+        assert!(function.filter_one(mock_tx("juno1", 20)).unwrap());
+
+        for (name, source, message) in [
+            ("empty", "return {}", "it returned an empty table"),
+            ("nothing", "local x = 1", "it returned nothing"),
+            ("garbage", "return 'whale'", "a value of type string"),
+        ] {
+            let err = load(name, source).err().unwrap();
+            assert!(
+                matches!(&err, LoadError::Module { origin, message: m }
+                    if origin.path().ends_with(format!("{name}.lua")) && m.contains(message)),
+                "{err}"
+            );
+            assert!(err.to_string().contains(name), "{err}");
+            assert!(
+                err.to_string().contains("a single filter function"),
+                "{err}"
+            );
         }
-        "#},
-        true
-    );
+    }
 
     #[test]
-    fn filter_system() {
+    fn strict_returns() {
+        let lua = Lua::new();
+        let mut filter_system = load_script::<MockTx>(
+            &lua,
+            indoc! {r#"
+            return {
+                number = function(tx) return tx.amount end,
+                nothing = function(tx) end,
+                boolean = function(tx) return false end,
+            }
+            "#},
+        );
+        let coerced = |filter_system: &FilterSystem<MockTx>, name: &str| {
+            let stats = filter_system.stats();
+            let stats = stats.into_iter().find(|stats| stats.name == name).unwrap();
+            stats.coerced_returns
+        };
+
+        // Lenient by default: 0 is truthy in Lua, and nil isn't.
+        let verdict = filter_system
+            .filter_one_detailed(mock_tx("juno1", 0))
+            .unwrap();
+        assert_eq!(verdict.matched_by, ["number"]);
+        assert_eq!(coerced(&filter_system, "number"), 1);
+        assert_eq!(coerced(&filter_system, "nothing"), 1);
+        assert_eq!(coerced(&filter_system, "boolean"), 0);
+
+        filter_system.strict_returns(true);
+        filter_system.set_error_policy(ErrorPolicy::Lenient);
+        assert!(!filter_system.filter_one(mock_tx("juno1", 0)).unwrap());
+        filter_system.set_error_policy(ErrorPolicy::FailFast);
+        let err = filter_system.filter_one(mock_tx("juno1", 0)).err().unwrap();
+        assert!(
+            matches!(&err, FilterError::InvalidReturn { got_type, .. }
+                if ["integer", "number", "nil"].contains(got_type)),
+            "{err}"
+        );
+        assert_eq!(coerced(&filter_system, "number"), 1);
+    }
+
+    #[test]
+    fn chain_stats() {
+        let scripts = Scripts::new("chain-stats");
+        let whale = scripts.filter(
+            "whale",
+            "return { whale = function(tx) return tx.amount > 1000 end }",
+        );
+        let failing = scripts.filter(
+            "failing",
+            "return { failing = function(tx) if tx.amount == 0 then error('zero') end return false end }",
+        );
         let config = Config {
-            chains: {
-                let mut chains = HashMap::new();
-                chains.insert(
-                    "uni-5".to_string(),
-                    vec![FilterConfig {
-                        name: "Testnet Manager".to_string(),
-                        script: PathBuf::from("filters/test-filter.lua"),
-                    }],
-                );
-                chains
+            chains: [
+                ("uni-5".to_string(), vec![whale.clone()]),
+                ("juno-1".to_string(), vec![failing]),
+            ]
+            .into(),
+            ..Default::default()
+        };
+        let filter_runtime = FilterRuntime::<MockTx>::new();
+        let mut filter_system = filter_runtime.load(config).unwrap();
+        filter_system.set_error_policy(ErrorPolicy::Lenient);
+        let chain_stats = |filter_system: &FilterSystem<MockTx>, chain: &str| {
+            let stats = filter_system.chain_stats();
+            stats
+                .into_iter()
+                .find(|stats| stats.chain == chain)
+                .unwrap()
+        };
+
+        let txs = vec![
+            mock_tx("juno1", 5000),
+            mock_tx("juno1", 10),
+            mock_tx("juno1", 0),
+        ];
+        assert_eq!(filter_system.filter(txs).unwrap().len(), 1);
+        filter_system.filter_one(mock_tx("juno1", 2000)).unwrap();
+        let uni = chain_stats(&filter_system, "uni-5");
+        assert_eq!(uni.since_load.values, 4);
+        assert_eq!(uni.since_load.kept, 2);
+        assert_eq!(uni.since_load.rejected, 2);
+        assert_eq!(uni.since_load.errored, 0);
+        assert_eq!(uni.since_load.batches, 2);
+        assert!(uni.since_load.average_batch_latency > Duration::ZERO);
+        let juno = chain_stats(&filter_system, "juno-1");
+        assert_eq!(juno.since_load.values, 4);
+        assert_eq!(juno.since_load.kept, 0);
+        assert_eq!(juno.since_load.rejected, 3);
+        assert_eq!(juno.since_load.errored, 1);
+        assert_eq!(juno.since_reset, juno.since_load);
+
+        // Resetting keeps the counters since load; reloading a chain only starts its own over.
+        filter_system.reset_chain_stats();
+        filter_system.filter_one(mock_tx("juno1", 0)).unwrap();
+        let juno = chain_stats(&filter_system, "juno-1");
+        assert_eq!(juno.since_reset.values, 1);
+        assert_eq!(juno.since_reset.errored, 1);
+        assert_eq!(juno.since_load.values, 5);
+        filter_system.load_chain("uni-5", vec![whale]).unwrap();
+        assert_eq!(
+            chain_stats(&filter_system, "uni-5").since_load,
+            ChainCounters::default()
+        );
+        assert_eq!(chain_stats(&filter_system, "uni-5").since_reset.values, 1);
+        assert_eq!(chain_stats(&filter_system, "juno-1").since_load.values, 5);
+
+        let json = serde_json::to_value(chain_stats(&filter_system, "juno-1")).unwrap();
+        assert_eq!(json["since_load"]["errored"], 2);
+        assert!(json["since_load"]["average_batch_latency"].is_f64());
+    }
+
+    #[test]
+    fn seeded_rng() {
+        let script = "return { sample = function(tx) return math.random(100) <= tx.amount end }";
+        let txs = || (0..64).map(|_| mock_tx("juno1", 50)).collect::<Vec<_>>();
+        let verdicts = |seed: u64, batches: &[usize], per_batch: bool| {
+            let filter_runtime = FilterRuntime::<MockTx>::new();
+            filter_runtime.seed_rng(seed).unwrap();
+            filter_runtime.reseed_per_batch(per_batch).unwrap();
+            let filter_system = load_script::<MockTx>(&filter_runtime.runtime, script);
+            let mut last = Vec::new();
+            for &size in batches {
+                let mut values = txs();
+                values.truncate(size);
+                last = filter_system.keep_mask(&values, &mlua::Value::Nil).unwrap();
+            }
+            last
+        };
+
+        let seeded = verdicts(7, &[64], false);
+        assert_eq!(verdicts(7, &[64], false), seeded);
+        assert_ne!(verdicts(8, &[64], false), seeded);
+        assert!(seeded.contains(&true) && seeded.contains(&false));
+
+        // Without reseeding, the draws of a batch depend on the ones before it.
+        assert_ne!(verdicts(7, &[3, 64], false), verdicts(7, &[5, 64], false));
+        let reseeded = verdicts(7, &[3, 64], true);
+        assert_eq!(verdicts(7, &[5, 64], true), reseeded);
+        assert_ne!(verdicts(8, &[5, 64], true), reseeded);
+    }
+
+    /// A backend for rules such as `big: amount > 100`, one per line, the name defaulting to
+    /// the filter's.
+    struct RuleBackend;
+
+    impl FilterBackend for RuleBackend {
+        type Filter = (String, String, String);
+
+        fn compile(
+            &mut self,
+            config: &FilterConfig,
+            source: &[u8],
+        ) -> Result<Vec<(String, Self::Filter)>, BackendError> {
+            let lines = std::str::from_utf8(source)?
+                .lines()
+                .filter(|line| !line.trim().is_empty());
+            lines
+                .map(|line| {
+                    let (name, rule) = match line.split_once(':') {
+                        Some((name, rule)) => (name.trim().to_string(), rule),
+                        None => (config.name.clone(), line),
+                    };
+                    match rule.split_whitespace().collect::<Vec<_>>()[..] {
+                        [field, op @ ("==" | ">"), operand] => {
+                            Ok((name, (field.into(), op.into(), operand.into())))
+                        }
+                        _ => Err(format!("can't parse `{line}`").into()),
+                    }
+                })
+                .collect()
+        }
+
+        fn call<V: Serialize>(
+            &self,
+            (field, op, operand): &Self::Filter,
+            value: &V,
+        ) -> Result<Decision, BackendError> {
+            let value = serde_json::to_value(value)?;
+            let field = value.get(field).ok_or("no such field")?;
+            let matched = match op.as_str() {
+                "==" => field.as_str() == Some(operand),
+                _ => field.as_f64().ok_or("not a number")? > operand.parse::<f64>()?,
+            };
+            Ok(Decision {
+                matched,
+                reason: None,
+            })
+        }
+    }
+
+    /// The scripts of [`filter_system_scenarios`], in the language of a backend: `rules`
+    /// defines a `big` filter matching amounts over 100 and a `manager` filter matching values
+    /// from 0xDEADBEEF, `watch` matches amounts over 10, `broken` fails on every value and `bad`
+    /// doesn't compile.
+    struct ScenarioScripts {
+        rules: PathBuf,
+        watch: PathBuf,
+        broken: PathBuf,
+        bad: PathBuf,
+    }
+
+    /// Load, filter with, reload and tune filter systems `system` creates, with the filters of
+    /// `scripts`, describing what happened.
+    fn filter_system_scenarios<B>(
+        system: impl for<'r> Fn(&'r Lua) -> FilterSystem<'r, MockTx, B>,
+        scripts: &ScenarioScripts,
+    ) -> Vec<String>
+    where
+        B: FilterBackend + 'static,
+        B::Filter: 'static,
+    {
+        let filter_runtime = FilterRuntime::<MockTx>::new();
+        let filter = |name: &str, script: &PathBuf| FilterConfig {
+            name: name.to_string(),
+            script: script.clone(),
+            ..Default::default()
+        };
+        let rules = filter("rules", &scripts.rules);
+        let watch = FilterConfig {
+            dry_run: true,
+            ..filter("watch", &scripts.watch)
+        };
+        let broken = FilterConfig {
+            retries: 1,
+            ..filter("broken", &scripts.broken)
+        };
+        let config = |filters: &[&FilterConfig]| Config {
+            chains: HashMap::from([(
+                "uni-5".to_string(),
+                filters.iter().copied().cloned().collect(),
+            )]),
+            ..Default::default()
+        };
+        let ids = |system: &FilterSystem<MockTx, B>| {
+            let stats = system.stats().into_iter();
+            let mut ids: Vec<_> = stats.map(|stats| stats.id.to_string()).collect();
+            ids.sort();
+            ids
+        };
+        let mut results = Vec::new();
+
+        // Values are kept if a filter that isn't a dry run matched them.
+        let mut filter_system = system(&filter_runtime.runtime);
+        filter_system.load(config(&[&rules, &watch])).unwrap();
+        let values = vec![
+            mock_tx("0xDEADBEEF", 0),
+            mock_tx("0xA", 500),
+            mock_tx("0xB", 50),
+            mock_tx("0xC", 1),
+        ];
+        let kept = filter_system.filter(values).unwrap();
+        results.extend(kept.into_iter().map(|tx| format!("kept {}", tx.from)));
+        let verdict = filter_system
+            .filter_one_detailed(mock_tx("0xB", 50))
+            .unwrap();
+        results.push(format!(
+            "{} {:?} {:?} {}",
+            verdict.matched, verdict.matched_by, verdict.dry_run, verdict.would_match
+        ));
+        let mut stats = filter_system.stats();
+        stats.sort_by(|a, b| a.name.cmp(&b.name));
+        results.extend(stats.iter().map(|stats| {
+            let FilterStats {
+                name,
+                invocations,
+                matches,
+                dry_run,
+                ..
+            } = stats;
+            format!("{name}: {invocations} {matches} {dry_run}")
+        }));
+        let ChainCounters {
+            values,
+            kept,
+            rejected,
+            batches,
+            ..
+        } = filter_system.chain_stats()[0].since_load;
+        results.push(format!("uni-5: {values} {kept} {rejected} {batches}"));
+
+        // Filters switch out of dry runs, and go, by name.
+        let switched = filter_system.set_dry_run("watch", false);
+        let matched = filter_system.filter_one(mock_tx("0xB", 50)).unwrap();
+        results.push(format!("switched {switched} {matched}"));
+        let removed = filter_system.remove("manager");
+        let matched = filter_system.filter_one(mock_tx("0xDEADBEEF", 0)).unwrap();
+        results.push(format!("removed {removed} {matched}"));
+
+        // Chains load and unload on their own.
+        filter_system
+            .load_chain("juno-1", vec![rules.clone()])
+            .unwrap();
+        filter_system.unload_chain("uni-5");
+        results.push(format!("{:?}", ids(&filter_system)));
+
+        // A failed reload leaves the filters as they were.
+        let bad = filter("bad", &scripts.bad);
+        let err = filter_system.reload(config(&[&bad])).unwrap_err();
+        let file = scripts.bad.file_name().unwrap().to_str().unwrap();
+        assert!(err.to_string().contains(file), "{err}");
+        results.push(format!("{:?}", ids(&filter_system)));
+
+        // Chains with an expression keep the values it holds for.
+        let mut filter_system = system(&filter_runtime.runtime);
+        let expression = ("uni-5".to_string(), "big AND NOT manager".to_string());
+        let expressions = HashMap::from([expression]);
+        let config_with_expression = Config {
+            expressions,
+            ..config(&[&rules])
+        };
+        filter_system.load(config_with_expression).unwrap();
+        let values = vec![
+            mock_tx("0xDEADBEEF", 500),
+            mock_tx("0xA", 500),
+            mock_tx("0xB", 5),
+        ];
+        let kept = filter_system.filter(values).unwrap();
+        results.extend(
+            kept.into_iter()
+                .map(|tx| format!("kept {} {}", tx.from, tx.amount)),
+        );
+
+        // Verdicts are cached by value.
+        filter_system.set_verdict_cache(8);
+        for from in ["0xA", "0xA", "0xB"] {
+            filter_system.filter_one(mock_tx(from, 500)).unwrap();
+        }
+        let cache = filter_system.verdict_cache_stats().unwrap();
+        results.push(format!("cache: {} {}", cache.hits, cache.misses));
+
+        // Failing filters are retried, then fail the call, or don't match under the lenient
+        // policy, until quarantined.
+        let mut filter_system = system(&filter_runtime.runtime);
+        filter_system.load(config(&[&broken, &rules])).unwrap();
+        let err = filter_system.filter_one(mock_tx("0xA", 500)).unwrap_err();
+        assert!(err.to_string().contains("uni-5/broken/broken"), "{err}");
+        filter_system.set_error_policy(ErrorPolicy::Lenient);
+        filter_system.set_quarantine(Some(Quarantine {
+            after: 2,
+            cool_down: None,
+        }));
+        for amount in [500, 5, 500] {
+            let matched = filter_system.filter_one(mock_tx("0xA", amount)).unwrap();
+            results.push(format!("lenient {matched}"));
+        }
+        let broken = &filter_system.stats()[0];
+        results.push(format!(
+            "broken: {} {} {} {} {}",
+            broken.invocations,
+            broken.errors,
+            broken.retries,
+            broken.quarantines,
+            broken.quarantined
+        ));
+        let health = filter_system.health();
+        results.push(format!("health: {:?} {:?}", health.status, health.problems));
+        results
+    }
+
+    /// What [`filter_system_scenarios`] describes on every backend.
+    const FILTER_SYSTEM_SCENARIOS: [&str; 18] = [
+        "kept 0xDEADBEEF",
+        "kept 0xA",
+        "false [] [(\"uni-5/watch/watch\", true)] true",
+        "big: 5 1 false",
+        "manager: 5 1 false",
+        "watch: 5 3 true",
+        "uni-5: 5 2 3 2",
+        "switched 1 true",
+        "removed 1 false",
+        "[\"juno-1/rules/big\", \"juno-1/rules/manager\"]",
+        "[\"juno-1/rules/big\", \"juno-1/rules/manager\"]",
+        "kept 0xA 500",
+        "cache: 1 2",
+        "lenient true",
+        "lenient false",
+        "lenient true",
+        "broken: 3 3 3 1 true",
+        "health: Degraded [\"1 filter is quarantined\"]",
+    ];
+
+    #[test]
+    fn backends() {
+        let scripts = Scripts::new("backends");
+        let scenario_scripts = |extension: &str, sources: [&str; 4]| {
+            let [rules, watch, broken, bad] = sources;
+            ScenarioScripts {
+                rules: scripts.write(&format!("rules.{extension}"), rules),
+                watch: scripts.write(&format!("watch.{extension}"), watch),
+                broken: scripts.write(&format!("broken.{extension}"), broken),
+                bad: scripts.write(&format!("bad.{extension}"), bad),
+            }
+        };
+
+        // The same scenarios give the same results on the runtime and on every backend.
+        let expected = FILTER_SYSTEM_SCENARIOS;
+        let lua = scenario_scripts(
+            "lua",
+            [
+                indoc! {r#"
+                return {
+                    big = function(tx) return tx.amount > 100 end,
+                    manager = function(tx) return tx.from == "0xDEADBEEF" end,
+                }
+                "#},
+                "return function(tx) return tx.amount > 10 end",
+                "return function(tx) return tx.from > 5 end",
+                "return 1",
+            ],
+        );
+        let results = filter_system_scenarios(|lua| FilterSystem::new(lua), &lua);
+        assert_eq!(results, expected);
+        let results = filter_system_scenarios(
+            |lua| FilterSystem::with_backend(lua, LuaBackend::new()),
+            &lua,
+        );
+        assert_eq!(results, expected);
+        let rules = scenario_scripts(
+            "rules",
+            [
+                "big: amount > 100\nmanager: from == 0xDEADBEEF\n",
+                "amount > 10",
+                "from > 5",
+                "amount >",
+            ],
+        );
+        let results =
+            filter_system_scenarios(|lua| FilterSystem::with_backend(lua, RuleBackend), &rules);
+        assert_eq!(results, expected);
+        #[cfg(feature = "rhai")]
+        {
+            let rhai = scenario_scripts(
+                "rhai",
+                [
+                    indoc! {r#"
+                    fn big(tx) { tx.amount > 100 }
+                    fn manager(tx) { tx.from == "0xDEADBEEF" }
+                    "#},
+                    "|tx| tx.amount > 10",
+                    "|tx| tx.from / 5",
+                    "fn (",
+                ],
+            );
+            let results = filter_system_scenarios(
+                |lua| FilterSystem::with_backend(lua, RhaiBackend::new()),
+                &rhai,
+            );
+            assert_eq!(results, expected);
+        }
+
+        // Backend errors keep their type, and scripts that don't compile fail the load,
+        // naming them.
+        let filter_runtime = FilterRuntime::<MockTx>::new();
+        let config = |filter: &str, script: &PathBuf| Config {
+            chains: HashMap::from([(
+                "uni-5".to_string(),
+                vec![FilterConfig {
+                    name: filter.to_string(),
+                    script: script.clone(),
+                    ..Default::default()
+                }],
+            )]),
+            ..Default::default()
+        };
+        let system = filter_runtime
+            .load_with_backend(RuleBackend, config("broken", &rules.broken))
+            .unwrap();
+        let err = system.filter_one(mock_tx("0xA", 500)).unwrap_err();
+        assert!(
+            matches!(&err, FilterError::Backend { filter, .. } if filter == "uni-5/broken/broken"),
+            "{err}"
+        );
+        let err = filter_runtime
+            .load_with_backend(RuleBackend, config("bad", &rules.bad))
+            .err()
+            .unwrap();
+        assert!(matches!(err, LoadError::Backend { .. }), "{err}");
+        assert!(err.to_string().contains("can't parse"), "{err}");
+        let err = filter_runtime
+            .load_with_backend(LuaBackend::new(), config("bad", &lua.bad))
+            .err()
+            .unwrap();
+        assert!(
+            err.to_string().contains("returned a value of type"),
+            "{err}"
+        );
+    }
+
+    #[cfg(feature = "rhai")]
+    #[test]
+    fn rhai_backend() {
+        let scripts = Scripts::new("rhai");
+        let library = scripts.write("addresses.rhai", r#"export const MANAGER = "0xDEADBEEF";"#);
+        let rhai = scripts.write(
+            "rules.rhai",
+            indoc! {r#"
+            private fn is_manager(tx) { tx.from == addresses::MANAGER }
+            fn manager(tx) {
+                if is_manager(tx) { #{ matched: true, reason: "manager" } } else { false }
+            }
+            fn pattern(tx) { re::is_match("^0xB", tx.from) && re::find("[0-9]+", tx.to) == "0" }
+            fn json(tx) { json::decode(json::encode(tx)).amount == tx.amount && tx.amount == 7 }
+            "#},
+        );
+        let lua = scripts.write("big.lua", "return function(tx) return tx.amount > 100 end");
+        let yaml = format!(
+            indoc! {"
+            libraries:
+                addresses: {}
+            languages:
+                uni-5: rhai
+            chains:
+                uni-5:
+                    - name: rules
+                      script: {}
+                    - name: big
+                      script: {}
+                      language: lua
+            "},
+            library.display(),
+            rhai.display(),
+            lua.display()
+        );
+        let config = Config::from_yaml(&yaml).unwrap();
+        assert_eq!(
+            config.language("uni-5", &config.chains["uni-5"][1]),
+            Some(ScriptLanguage::Lua)
+        );
+
+        // Each script runs on the backend of its language.
+        let filter_runtime = FilterRuntime::<MockTx>::new();
+        let system = filter_runtime
+            .load_with_backend(MixedBackend::default(), config.clone())
+            .unwrap();
+        let mut names: Vec<_> = system.stats().into_iter().map(|stats| stats.name).collect();
+        names.sort();
+        assert_eq!(names, ["big", "json", "manager", "pattern"]);
+        let verdict = system
+            .filter_one_detailed(mock_tx("0xDEADBEEF", 0))
+            .unwrap();
+        assert_eq!(verdict.matched_by, ["uni-5/rules/manager"]);
+        assert_eq!(
+            verdict.reasons,
+            [("uni-5/rules/manager".to_string(), "manager".to_string())]
+        );
+        for (tx, matched) in [
+            (mock_tx("0xBEEF", 0), "uni-5/rules/pattern"),
+            (mock_tx("0xA", 7), "uni-5/rules/json"),
+            (mock_tx("0xA", 500), "uni-5/big/big"),
+        ] {
+            assert_eq!(
+                system.filter_one_detailed(tx).unwrap().matched_by,
+                [matched]
+            );
+        }
+        assert!(!system.filter_one(mock_tx("0xA", 50)).unwrap());
+
+        // Verdicts of other types fail the filter.
+        let odd = scripts.write("odd.rhai", "|tx| tx.amount");
+        let config = Config::from_yaml(&format!(
+            "chains:\n    uni-5:\n        - name: odd\n          script: {}\n          language: rhai\n",
+            odd.display()
+        ))
+        .unwrap();
+        let system = filter_runtime
+            .load_with_backend(RhaiBackend::new(), config.clone())
+            .unwrap();
+        let err = system.filter_one(mock_tx("0xA", 1)).unwrap_err();
+        assert!(err.to_string().contains("rather than a boolean"), "{err}");
+
+        // Lua filter systems and backends refuse Rhai scripts, and the other way around.
+        let err = filter_runtime.load(config.clone()).err().unwrap();
+        assert!(matches!(err, LoadError::Language { .. }), "{err}");
+        let err = filter_runtime
+            .load_with_backend(LuaBackend::new(), config.clone())
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("doesn't run Rhai"), "{err}");
+        let mut config = config;
+        config
+            .languages
+            .insert("uni-5".to_string(), ScriptLanguage::Lua);
+        config.chains.get_mut("uni-5").unwrap()[0].language = None;
+        let err = filter_runtime
+            .load_with_backend(RhaiBackend::new(), config)
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("doesn't run Lua"), "{err}");
+    }
+
+    #[cfg(feature = "wasm")]
+    #[test]
+    fn wasm_backend() {
+        let config = |modules: &[(&str, bool)]| {
+            let filters = modules.iter().map(|(name, dry_run)| FilterConfig {
+                name: name.to_string(),
+                script: PathBuf::from(format!("filters/wasm/{name}.wasm")),
+                dry_run: *dry_run,
+                ..Default::default()
+            });
+            Config {
+                chains: HashMap::from([("uni-5".to_string(), filters.collect())]),
+                ..Default::default()
+            }
+        };
+        let filter_runtime = FilterRuntime::<MockTx>::new();
+        let backend = || WasmBackend::new().unwrap();
+
+        // The reference modules go through the same scenarios as the scripts of the other
+        // backends.
+        let module = |name: &str| PathBuf::from(format!("filters/wasm/{name}.wasm"));
+        let scripts = ScenarioScripts {
+            rules: module("rules"),
+            watch: module("watch"),
+            broken: module("broken"),
+            bad: PathBuf::from("filters/test-filter.lua"),
+        };
+        let results =
+            filter_system_scenarios(|lua| FilterSystem::with_backend(lua, backend()), &scripts);
+        assert_eq!(results, FILTER_SYSTEM_SCENARIOS);
+
+        // The fuel and memory limits of the configuration apply to each call.
+        let mut limits = config(&[("limits", false)]);
+        let filter = &mut limits.chains.get_mut("uni-5").unwrap()[0];
+        filter.fuel_budget = Some(100_000);
+        filter.max_call_memory = Some(64 * 1024);
+        let mut system = filter_runtime
+            .load_with_backend(backend(), limits.clone())
+            .unwrap();
+        let err = system.filter_one(mock_tx("0xA", 1)).unwrap_err();
+        assert!(
+            matches!(&err, FilterError::Backend { filter, .. } if filter == "uni-5/limits/greedy"),
+            "{err}"
+        );
+        assert!(err.to_string().contains("growing memory"), "{err}");
+        system.set_error_policy(ErrorPolicy::Lenient);
+        assert!(!system.filter_one(mock_tx("0xA", 1)).unwrap());
+        let errors: Vec<_> = system.stats().iter().map(|stats| stats.errors).collect();
+        assert_eq!(errors, [2, 1]);
+
+        // Every call may grow the memory by up to the limit, whatever the calls before it grew.
+        limits.chains.get_mut("uni-5").unwrap()[0].max_call_memory = Some(3 << 19);
+        let mut system = filter_runtime.load_with_backend(backend(), limits).unwrap();
+        system.set_error_policy(ErrorPolicy::Lenient);
+        for _ in 0..3 {
+            assert!(!system.filter_one(mock_tx("0xA", 1)).unwrap());
+        }
+        let errors: Vec<_> = system.stats().iter().map(|stats| stats.errors).collect();
+        assert_eq!(errors, [0, 3]);
+
+        // Instantiating the module gets the same limits. This one grows its memory by a
+        // megabyte and then spins in its start function:
+        //
+        // (module
+        //   (memory (export "memory") 1)
+        //   (func (export "alloc") (param i32) (result i32) i32.const 0)
+        //   (func (export "filter") (param i32 i32) (result i32) i32.const 0)
+        //   (func $start i32.const 16 memory.grow drop loop br 0 end)
+        //   (start $start))
+        let scripts = Scripts::new("wasm");
+        let module = scripts.dir.join("start.wasm");
+        std::fs::write(
+            &module,
+            [
+                0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+                0x01, 0x0f, 0x03, 0x60, 0x01, 0x7f, 0x01, 0x7f, 0x60, 0x02, 0x7f, 0x7f, 0x01, 0x7f,
+                0x60, 0x00, 0x00, // types
+                0x03, 0x04, 0x03, 0x00, 0x01, 0x02, // functions
+                0x05, 0x03, 0x01, 0x00, 0x01, // memory
+                0x07, 0x1b, 0x03, 0x06, b'm', b'e', b'm', b'o', b'r', b'y', 0x02, 0x00, 0x05, b'a',
+                b'l', b'l', b'o', b'c', 0x00, 0x00, 0x06, b'f', b'i', b'l', b't', b'e', b'r', 0x00,
+                0x01, // exports
+                0x08, 0x01, 0x02, // start
+                0x0a, 0x18, 0x03, 0x04, 0x00, 0x41, 0x00, 0x0b, 0x04, 0x00, 0x41, 0x00, 0x0b, 0x0c,
+                0x00, 0x41, 0x10, 0x40, 0x00, 0x1a, 0x03, 0x40, 0x0c, 0x00, 0x0b,
+                0x0b, // code
+            ],
+        )
+        .unwrap();
+        let start = |fuel_budget, max_call_memory| {
+            let mut config = config(&[("start", false)]);
+            let filter = &mut config.chains.get_mut("uni-5").unwrap()[0];
+            filter.script = module.clone();
+            filter.fuel_budget = fuel_budget;
+            filter.max_call_memory = max_call_memory;
+            let system = filter_runtime.load_with_backend(backend(), config);
+            system.err().unwrap().to_string()
+        };
+        let err = start(None, Some(64 * 1024));
+        assert!(err.contains("growing memory"), "{err}");
+        let err = start(Some(100_000), None);
+        assert!(err.contains("fuel"), "{err}");
+
+        // Files that aren't modules, or export no filters, fail the load.
+        let mut config = config(&[("lua", false)]);
+        config.chains.get_mut("uni-5").unwrap()[0].script = "filters/test-filter.lua".into();
+        let err = filter_runtime
+            .load_with_backend(backend(), config)
+            .err()
+            .unwrap();
+        assert!(matches!(err, LoadError::Backend { .. }), "{err}");
+    }
+
+    #[cfg(feature = "cosmos")]
+    #[test]
+    fn cosmos_txs() {
+        let fixture = |name: &str| -> tendermint_rpc::endpoint::tx::Response {
+            let path = format!("filters/cosmos/{name}.json");
+            serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+        };
+        let proxy_call = CosmosTx::try_from(fixture("proxy_call")).unwrap();
+        let large_send = CosmosTx::try_from(fixture("large_send")).unwrap();
+
+        // The hash of the response is the hash of the transaction bytes.
+        let response = fixture("proxy_call");
+        assert_eq!(
+            CosmosTx::decode(&response.tx).unwrap().hash,
+            proxy_call.hash
+        );
+        assert_eq!(
+            proxy_call.hash.as_deref(),
+            Some("2BAB643870572BE983856CE2FAA673C8DC499D0FB5EA3CEE46DAF4EB7AF58159")
+        );
+        assert_eq!(
+            (proxy_call.height, proxy_call.code, proxy_call.gas_used),
+            (Some(8123456), Some(0), Some(231874))
+        );
+        assert_eq!(
+            proxy_call.messages[0].fields["msg"]["proxy_call"],
+            serde_json::json!({})
+        );
+        assert_eq!(proxy_call.events[3].attribute("action"), Some("proxy_call"));
+        assert_eq!(large_send.memo, "to the exchange");
+        assert_eq!(
+            large_send.fee.amount,
+            [TxCoin {
+                denom: "ujuno".to_string(),
+                amount: "2500".to_string()
+            }]
+        );
+        assert_eq!(large_send.fee.gas_limit, 100_000);
+
+        // Transactions of `Tx` events come out the same, hashed from their bytes.
+        let info = tendermint_rpc::event::TxInfo {
+            height: 8123457,
+            index: Some(0),
+            tx: fixture("large_send").tx,
+            result: tendermint_rpc::event::TxResult {
+                log: None,
+                gas_wanted: Some("100000".to_string()),
+                gas_used: Some("74210".to_string()),
+                events: fixture("large_send").tx_result.events,
             },
         };
+        assert_eq!(CosmosTx::try_from(info).unwrap(), large_send);
+        assert!(CosmosTx::decode(b"not a transaction").is_err());
 
-        let filter_runtime = FilterRuntime::new();
+        let filter_runtime = FilterRuntime::<CosmosTx>::new();
+        let filters = ["croncat", "large_transfers"].map(|name| FilterConfig {
+            name: name.to_string(),
+            script: PathBuf::from(format!("filters/cosmos/{name}.lua")),
+            ..Default::default()
+        });
+        let config = Config {
+            chains: HashMap::from([("juno-1".to_string(), filters.to_vec())]),
+            ..Default::default()
+        };
         let filter_system = filter_runtime.load(config).unwrap();
+        let matched_by = |tx: &CosmosTx| {
+            let verdict = filter_system.filter_one_detailed(tx.clone()).unwrap();
+            verdict.matched_by
+        };
+        assert_eq!(matched_by(&proxy_call), ["juno-1/croncat/proxy_call"]);
+        assert_eq!(
+            matched_by(&large_send),
+            ["juno-1/large_transfers/large_transfer"]
+        );
+        let mut failed = proxy_call.clone();
+        failed.code = Some(5);
+        assert!(!filter_system.filter_one(failed).unwrap());
+    }
 
-        let txs = vec![
-            MockTx {
-                chain: "uni-5".to_string(),
-                from: "0xDEADBEEF".to_string(),
-                to: "0xBEEFFEEF".to_string(),
-                amount: 0,
-            },
-            MockTx {
-                chain: "uni-5".to_string(),
-                from: "0xBEEFFEEF".to_string(),
-                to: "0xDEADDEAD".to_string(),
-                amount: 0,
-            },
-        ];
+    #[cfg(feature = "cosmos")]
+    #[test]
+    fn wasm_events() {
+        let manager = "juno1croncatmanager000000000000000000000000000";
+        let response = std::fs::read_to_string("filters/cosmos/proxy_call.json").unwrap();
+        let response: tendermint_rpc::endpoint::tx::Response =
+            serde_json::from_str(&response).unwrap();
+        let tx = CosmosTx::try_from(response).unwrap();
+        let modern = tx.wasm_events();
+        assert_eq!(modern.len(), 1);
+        assert_eq!(modern[0].attrs["_contract_address"], manager);
+        assert_eq!(modern[0].attrs["task_hash"], "juno:0f4a5b7c2d");
+        assert_eq!(
+            (&modern[0].tx_hash, modern[0].tx_index),
+            (&tx.hash, Some(3))
+        );
 
-        let filtered_txs = filter_system.filter(txs).unwrap();
+        // Legacy events have base64 attributes, one of them set twice.
+        let legacy = std::fs::read_to_string("filters/cosmos/legacy_events.json").unwrap();
+        let legacy: Vec<TxEvent> = serde_json::from_str(&legacy).unwrap();
+        let legacy: Vec<WasmEvent> = legacy.into_iter().map(WasmEvent::from).collect();
+        assert_eq!(legacy[0].attrs["action"], "execute");
+        let event = &legacy[1];
+        assert_eq!(event.attrs["_contract_address"], manager);
+        assert_eq!(
+            (
+                event.attrs["action"].as_str(),
+                event.attrs["amount"].as_str()
+            ),
+            ("proxy_call", "")
+        );
+        let actions = event
+            .attrs_all
+            .iter()
+            .filter(|attribute| attribute.key == "action");
+        let actions: Vec<_> = actions.map(|attribute| attribute.value.as_str()).collect();
+        assert_eq!(actions, ["proxy_call", "transfer"]);
+        assert_eq!(event.tx_hash, None);
 
-        assert_eq!(filtered_txs.len(), 1);
-        assert_eq!(filtered_txs[0].from, "0xDEADBEEF");
-        assert_eq!(filtered_txs[0].to, "0xBEEFFEEF");
+        // Plain attributes that happen to be valid base64 are left alone.
+        let plain = WasmEvent::from(TxEvent {
+            kind: "wasm".to_string(),
+            attributes: vec![TxAttribute {
+                key: "task".to_string(),
+                value: "1000".to_string(),
+            }],
+        });
+        assert_eq!(plain.attrs["task"], "1000");
+
+        let filter_runtime = FilterRuntime::<WasmEvent>::new();
+        let config = Config {
+            chains: HashMap::from([(
+                "juno-1".to_string(),
+                vec![FilterConfig {
+                    name: "croncat_events".to_string(),
+                    script: PathBuf::from("filters/cosmos/croncat_events.lua"),
+                    ..Default::default()
+                }],
+            )]),
+            ..Default::default()
+        };
+        let filter_system = filter_runtime.load(config).unwrap();
+        let events = [modern, legacy].concat();
+        let matched = filter_system.filter(events).unwrap();
+        let matched: Vec<_> = matched.iter().map(|event| event.tx_index).collect();
+        assert_eq!(matched, [Some(3), None]);
     }
 }
@@ -0,0 +1,159 @@
+//! Limits on the configurations a runtime loads and on the values filters return to the host.
+//!
+//! A configuration is checked against the load limits before anything in it is evaluated, from
+//! its structure and the sizes of its files alone, so a runaway configuration fails right away.
+//!
+//! A filter returns its verdict along with a reason, which the host copies out of Lua. A
+//! returned string longer than the runtime allows, or a table with more entries, is reported
+//! as oversized instead of being read.
+
+use std::{collections::BTreeSet, path::Path};
+
+use mlua::{Lua, Value};
+
+use crate::{error::ReturnKind, Config, ConfigError};
+
+/// The load limits of a runtime, stored in its app data.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct LoadLimits {
+    pub max_scripts: usize,
+    pub max_filters_per_chain: usize,
+    pub max_total_filters: usize,
+    pub max_script_size_bytes: u64,
+}
+
+/// Check `config` against the load limits of `lua`, if it has any.
+pub(crate) fn check_config(lua: &Lua, config: &Config) -> Result<(), ConfigError> {
+    let Some(limits) = lua.app_data_ref::<LoadLimits>().map(|limits| *limits) else {
+        return Ok(());
+    };
+    let mut total = 0;
+    for (chain, filters) in &config.chains {
+        let count = filters.len();
+        total += count;
+        check(
+            count as u64,
+            limits.max_filters_per_chain as u64,
+            "max_filters_per_chain",
+            || format!("chain `{chain}` lists {count} filters"),
+        )?;
+    }
+    check(
+        total as u64,
+        limits.max_total_filters as u64,
+        "max_total_filters",
+        || format!("the configuration lists {total} filters"),
+    )?;
+
+    let scripts: BTreeSet<&Path> = config
+        .chains
+        .values()
+        .flatten()
+        .map(|filter| filter.script.as_path())
+        .collect();
+    let count = scripts.len();
+    check(
+        count as u64,
+        limits.max_scripts as u64,
+        "max_scripts",
+        || format!("the configuration names {count} scripts"),
+    )?;
+    // Files that can't be read fail the load when they are, like without limits.
+    let libraries = config.libraries.values().map(|path| path.as_path());
+    for path in libraries.chain(scripts) {
+        let Ok(metadata) = std::fs::metadata(path) else {
+            continue;
+        };
+        let size = metadata.len();
+        check(
+            size,
+            limits.max_script_size_bytes,
+            "max_script_size_bytes",
+            || format!("`{}` is {size} bytes", path.display()),
+        )?;
+    }
+    Ok(())
+}
+
+/// Fail with an error naming the limit `name` if `count` is over `limit`, described by `what`.
+fn check(
+    count: u64,
+    limit: u64,
+    name: &'static str,
+    what: impl FnOnce() -> String,
+) -> Result<(), ConfigError> {
+    if count <= limit {
+        return Ok(());
+    }
+    Err(ConfigError::OverLimit {
+        what: what(),
+        limit: name,
+        count,
+        max: limit,
+    })
+}
+
+/// The return limits of a runtime, stored in its app data.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ReturnLimits {
+    /// How many bytes a returned string may hold.
+    pub max_string_len: usize,
+    /// How many entries a returned table may hold.
+    pub max_table_entries: usize,
+}
+
+impl ReturnLimits {
+    /// The limits set for `lua`, or none if it wasn't built as a filter runtime.
+    fn of(lua: &Lua) -> Self {
+        lua.app_data_ref::<ReturnLimits>()
+            .map(|limits| *limits)
+            .unwrap_or(ReturnLimits {
+                max_string_len: usize::MAX,
+                max_table_entries: usize::MAX,
+            })
+    }
+}
+
+/// The kind and size of `value` if it is over the limits of `lua`.
+pub(crate) fn oversized(lua: &Lua, value: &Value) -> Option<(ReturnKind, usize)> {
+    let limits = ReturnLimits::of(lua);
+    match value {
+        Value::String(string) => {
+            let len = string.as_bytes().len();
+            (len > limits.max_string_len).then_some((ReturnKind::String, len))
+        }
+        Value::Table(table) if limits.max_table_entries != usize::MAX => {
+            let entries = table.clone().pairs::<Value, Value>().count();
+            (entries > limits.max_table_entries).then_some((ReturnKind::Table, entries))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits() {
+        let lua = Lua::new();
+        let value = |chunk: &str| lua.load(chunk).eval::<Value>().unwrap();
+        assert_eq!(oversized(&lua, &value(r#"string.rep("a", 4096)"#)), None);
+
+        lua.set_app_data(ReturnLimits {
+            max_string_len: 4,
+            max_table_entries: 2,
+        });
+        assert_eq!(oversized(&lua, &value(r#""abcd""#)), None);
+        assert_eq!(
+            oversized(&lua, &value(r#""abcde""#)),
+            Some((ReturnKind::String, 5))
+        );
+        assert_eq!(oversized(&lua, &value("{ 1, x = 2 }")), None);
+        assert_eq!(
+            oversized(&lua, &value("{ 1, 2, x = 3 }")),
+            Some((ReturnKind::Table, 3))
+        );
+        assert_eq!(oversized(&lua, &value("12345")), None);
+    }
+}
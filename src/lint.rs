@@ -0,0 +1,171 @@
+//! Checks on filter scripts run while loading them, see
+//! [`FilterSystem::lint`](crate::FilterSystem::lint).
+//!
+//! Assignments to globals are caught by evaluating the script in an environment that records
+//! them before passing them on: once it is evaluated, the environment stops recording, so
+//! filters writing globals when called aren't reported. A filter function taking no
+//! parameters can't see the value it is filtering, which is almost always a mistake.
+//!
+//! Entries of the script's table that aren't functions need no lint: they fail the load.
+
+use std::{cell::RefCell, fmt, path::PathBuf, rc::Rc};
+
+#[cfg(any(feature = "luajit", feature = "luau"))]
+use mlua::TableExt;
+use mlua::{Function, Lua, Table, Value};
+
+/// What the lint pass found in a script.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LintReport {
+    pub chain: String,
+    pub script: PathBuf,
+    pub findings: Vec<LintFinding>,
+}
+
+/// A likely mistake in a script.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LintFinding {
+    /// The script assigned the global `name` while it was evaluated, often a missing `local`.
+    GlobalWrite { name: String },
+    /// The filter function `function` takes no parameters, so it never sees the value.
+    NoParameters { function: String },
+}
+
+impl fmt::Display for LintFinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LintFinding::GlobalWrite { name } => write!(f, "assigns the global `{name}`"),
+            LintFinding::NoParameters { function } => {
+                write!(f, "filter `{function}` takes no parameters")
+            }
+        }
+    }
+}
+
+impl fmt::Display for LintReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} of chain `{}`", self.script.display(), self.chain)?;
+        for (index, finding) in self.findings.iter().enumerate() {
+            let separator = if index == 0 { ": " } else { "; " };
+            write!(f, "{separator}{finding}")?;
+        }
+        Ok(())
+    }
+}
+
+/// An environment recording the globals a script assigns while it is evaluated.
+pub(crate) struct Recorder<'lua> {
+    /// The environment to evaluate the script in.
+    pub environment: Table<'lua>,
+    /// The environment the script would have been evaluated in otherwise.
+    target: Table<'lua>,
+    names: Rc<RefCell<Vec<String>>>,
+}
+
+impl<'lua> Recorder<'lua> {
+    /// Record the assignments to `target`, passing them on.
+    pub(crate) fn new(lua: &'lua Lua, target: Table<'lua>) -> mlua::Result<Self> {
+        let names: Rc<RefCell<Vec<String>>> = Rc::default();
+        let recorded = names.clone();
+        let newindex =
+            lua.create_function(move |_, (this, key, value): (Table, Value, Value)| {
+                if let Value::String(name) = &key {
+                    let name = name.to_string_lossy().into_owned();
+                    let mut names = recorded.borrow_mut();
+                    if !names.contains(&name) {
+                        names.push(name);
+                    }
+                }
+                // The assignment goes on to the table reads come from.
+                let target: Option<Table> = match this.get_metatable() {
+                    Some(metatable) => metatable.get("__index")?,
+                    None => None,
+                };
+                match target {
+                    Some(target) => target.set(key, value),
+                    None => this.raw_set(key, value),
+                }
+            })?;
+        let environment = lua.create_table()?;
+        let metatable = lua.create_table()?;
+        metatable.set("__index", target.clone())?;
+        metatable.set("__newindex", newindex)?;
+        environment.set_metatable(Some(metatable));
+        Ok(Self {
+            environment,
+            target,
+            names,
+        })
+    }
+
+    /// Stop recording, returning the globals the script assigned in order.
+    pub(crate) fn finish(self) -> mlua::Result<Vec<String>> {
+        if let Some(metatable) = self.environment.get_metatable() {
+            metatable.set("__newindex", self.target)?;
+        }
+        Ok(self.names.take())
+    }
+}
+
+/// Whether `function` is a Lua function declaring no parameters, not even `...`.
+pub(crate) fn takes_no_parameters(lua: &Lua, function: &Function) -> mlua::Result<bool> {
+    Ok(parameters(lua, function)? == Some((0, false)))
+}
+
+/// How many parameters `function` declares and whether it is variadic, if it can tell.
+#[cfg(feature = "luajit")]
+fn parameters(lua: &Lua, function: &Function) -> mlua::Result<Option<(u32, bool)>> {
+    let package: Option<Table> = lua.globals().get("package")?;
+    let Some(package) = package else {
+        return Ok(None);
+    };
+    let loaded: Table = package.get("loaded")?;
+    let util = match loaded.get::<_, Option<Table>>("jit.util")? {
+        Some(util) => util,
+        None => {
+            let preload: Table = package.get("preload")?;
+            let Some(open) = preload.get::<_, Option<Function>>("jit.util")? else {
+                return Ok(None);
+            };
+            open.call("jit.util")?
+        }
+    };
+    let info: Table = util.call_function("funcinfo", function.clone())?;
+    // C functions have no parameter count.
+    let Some(params) = info.get::<_, Option<u32>>("params")? else {
+        return Ok(None);
+    };
+    Ok(Some((params, info.get("isvararg")?)))
+}
+
+/// How many parameters `function` declares and whether it is variadic, if it can tell.
+#[cfg(feature = "luau")]
+fn parameters(lua: &Lua, function: &Function) -> mlua::Result<Option<(u32, bool)>> {
+    let debug: Option<Table> = lua.globals().get("debug")?;
+    let Some(debug) = debug else {
+        return Ok(None);
+    };
+    let (params, vararg): (u32, bool) = debug.call_function("info", (function.clone(), "a"))?;
+    Ok(Some((params, vararg)))
+}
+
+/// How many parameters `function` declares and whether it is variadic, if it can tell.
+///
+/// Lua 5.4 only tells the parameters of running functions: `function` is called under a hook
+/// aborting the call before its first instruction.
+#[cfg(not(any(feature = "luajit", feature = "luau")))]
+fn parameters(lua: &Lua, function: &Function) -> mlua::Result<Option<(u32, bool)>> {
+    use std::cell::Cell;
+
+    let found = Rc::new(Cell::new(None));
+    let record = found.clone();
+    lua.set_hook(mlua::HookTriggers::new().on_calls(), move |_, debug| {
+        let stack = debug.stack();
+        record.set(Some((stack.num_params as u32, stack.is_vararg)));
+        Err(mlua::Error::runtime("aborted by the lint pass"))
+    });
+    let _ = function.call::<_, ()>(());
+    lua.remove_hook();
+    // C functions declare nothing.
+    Ok(found.get().filter(|_| function.info().what != "C"))
+}
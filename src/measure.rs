@@ -0,0 +1,86 @@
+//! Estimating how much memory Lua values hold.
+//!
+//! The estimate walks tables recursively, counting every table and string once, with sizes
+//! close to what the Lua implementations allocate. Metatables aren't followed, since they are
+//! usually shared, and neither are the upvalues of functions, which the host can't see. The
+//! result is only meant to tell which filter holds most of the memory.
+
+use std::{collections::HashSet, ffi::c_void};
+
+use mlua::Value;
+
+/// The size of a table without its entries.
+const TABLE: usize = 56;
+/// The size of a table entry, key and value included when they are inline.
+const ENTRY: usize = 40;
+/// The size of a string without its contents.
+const STRING: usize = 24;
+/// The size of a function or userdata, not counting what it references.
+const OBJECT: usize = 40;
+
+/// Estimate the bytes held by `values`, counting objects they share once.
+pub(crate) fn size_of<'a, 'lua: 'a>(values: impl IntoIterator<Item = &'a Value<'lua>>) -> usize {
+    let mut seen = HashSet::new();
+    values
+        .into_iter()
+        .map(|value| measure(value, &mut seen))
+        .sum()
+}
+
+fn measure(value: &Value, seen: &mut HashSet<*const c_void>) -> usize {
+    let size = match value {
+        Value::String(string) => STRING + string.as_bytes().len(),
+        Value::Table(_) => TABLE,
+        Value::Function(_) | Value::Thread(_) | Value::UserData(_) => OBJECT,
+        _ => return 0,
+    };
+    if !seen.insert(value.to_pointer()) {
+        return 0;
+    }
+    let Value::Table(table) = value else {
+        return size;
+    };
+    let mut size = size;
+    for pair in table.clone().pairs::<Value, Value>() {
+        let Ok((key, value)) = pair else {
+            continue;
+        };
+        size += ENTRY + measure(&key, seen) + measure(&value, seen);
+    }
+    size
+}
+
+#[cfg(test)]
+mod tests {
+    use mlua::Lua;
+
+    use super::*;
+
+    #[test]
+    fn sizes() {
+        let lua = Lua::new();
+        let (small, big, shared): (Value, Value, Value) = lua
+            .load(
+                r#"
+                local seen = {}
+                for i = 1, 1000 do seen["juno1address" .. i] = true end
+                local cycle = {}
+                cycle.self = cycle
+                return { 1, 2 }, { seen = seen, cycle = cycle }, { seen, seen }
+                "#,
+            )
+            .eval()
+            .unwrap();
+
+        let small = size_of([&small]);
+        let big = size_of([&big]);
+        assert_eq!(small, TABLE + 2 * ENTRY);
+        assert!(
+            big > 1000 * (ENTRY + STRING + "juno1address".len()),
+            "{big}"
+        );
+        // Shared tables count once.
+        assert!(size_of([&shared]) < big, "{}", size_of([&shared]));
+        assert_eq!(size_of([&Value::Nil, &Value::Boolean(true)]), 0);
+    }
+}
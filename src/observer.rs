@@ -0,0 +1,36 @@
+//! Callbacks on the verdicts of filters, see [`FilterSystem::set_observer`].
+//!
+//! [`FilterSystem::set_observer`]: crate::FilterSystem::set_observer
+
+use crate::FilterError;
+
+/// Watches the verdicts filters give as they run.
+///
+/// A filter system calls its observer from the evaluation loop, right after each filter call,
+/// in filter order: the next filter only runs once the observer returns, so it should be
+/// quick, handing anything slow off to another thread. Filters are named by their
+/// [`FilterId`](crate::FilterId), and filters without a configuration have an empty chain. Dry-run filters are observed like the others; filters skipped for lack of
+/// fuel aren't. A panicking observer doesn't affect filtering: the panic is caught and
+/// counted, see [`FilterSystem::observer_panics`](crate::FilterSystem::observer_panics).
+pub trait FilterObserver {
+    /// The filter `filter` of `chain` matched a value.
+    fn on_match(&self, filter: &str, chain: &str) {
+        let _ = (filter, chain);
+    }
+
+    /// The filter `filter` of `chain` didn't match a value.
+    fn on_reject(&self, filter: &str, chain: &str) {
+        let _ = (filter, chain);
+    }
+
+    /// The filter `filter` of `chain` failed on a value, whatever the error policy does next.
+    fn on_error(&self, filter: &str, chain: &str, error: &FilterError) {
+        let _ = (filter, chain, error);
+    }
+
+    /// The filter `filter` of `chain` was quarantined after failing on `errors` values in a
+    /// row, see [`FilterSystem::set_quarantine`](crate::FilterSystem::set_quarantine).
+    fn on_quarantine(&self, filter: &str, chain: &str, errors: u32) {
+        let _ = (filter, chain, errors);
+    }
+}
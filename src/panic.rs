@@ -0,0 +1,43 @@
+//! Turning panics raised during filter calls into errors.
+//!
+//! A panic in the `Serialize` impl of a filtered value or in a registered function unwinds
+//! through mlua, which leaves the Lua state consistent. After catching one, the runtime is
+//! checked with a trivial chunk; if that fails too, the runtime is marked poisoned and every
+//! later call is refused.
+
+use std::any::Any;
+
+use mlua::Lua;
+
+/// Marks a runtime that failed its check after a panic, in its app data.
+struct Poisoned;
+
+/// Whether a panic left the runtime unusable.
+pub(crate) fn is_poisoned(lua: &Lua) -> bool {
+    lua.app_data_ref::<Poisoned>().is_some()
+}
+
+/// Check the runtime after a panic, poisoning it if it no longer runs code, and return the
+/// panic message.
+pub(crate) fn recover(lua: &Lua, payload: Box<dyn Any + Send>) -> String {
+    let usable = lua
+        .load("return 1 + 1")
+        .set_name("panic check")
+        .eval::<i32>()
+        .is_ok_and(|two| two == 2);
+    if !usable {
+        lua.set_app_data(Poisoned);
+    }
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "panic with a non-string payload".to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+pub(crate) fn poison(lua: &Lua) {
+    lua.set_app_data(Poisoned);
+}
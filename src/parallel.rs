@@ -0,0 +1,179 @@
+//! Filtering large batches on several runtimes at once, with rayon.
+//!
+//! Only available with the `rayon` feature. The batch is split into chunks, rayon hands the
+//! chunks out, and each chunk is filtered by a member of a [`FilterPool`] checked out for it.
+
+use rayon::prelude::*;
+use serde::Serialize;
+
+use mlua::prelude::LuaUserData;
+
+use crate::{Config, ErrorPolicy, FilterError, FilterPool, LoadError, RuntimeOptions};
+
+/// How many chunks a batch is split into per pool member, unless a chunk size is set, so that
+/// chunks filtering slower than others don't hold the whole call up.
+const CHUNKS_PER_MEMBER: usize = 4;
+
+/// Filters batches in parallel on the members of a [`FilterPool`].
+///
+/// ```no_run
+/// use croncat_indexer_filter::{Config, ParallelFilterSystem, RuntimeOptions};
+///
+/// #[derive(serde::Serialize)]
+/// struct Tx {
+///     from: String,
+/// }
+/// impl mlua::UserData for Tx {}
+///
+/// let config = Config::from_yaml("chains: {}").unwrap();
+/// let system = ParallelFilterSystem::<Tx>::new(8, RuntimeOptions::default(), config).unwrap();
+/// let kept = system.par_filter(vec![Tx { from: "juno1agent".to_string() }]).unwrap();
+/// ```
+pub struct ParallelFilterSystem<T> {
+    pool: FilterPool<T>,
+    chunk_size: Option<usize>,
+}
+
+impl<T> ParallelFilterSystem<T>
+where
+    T: LuaUserData + Serialize + Send + Sync + 'static,
+{
+    /// Build a pool of `size` runtimes with `options`, all loaded with `config`.
+    pub fn new(size: usize, options: RuntimeOptions, config: Config) -> Result<Self, LoadError> {
+        FilterPool::new(size, options, config).map(Self::from_pool)
+    }
+
+    /// Filter on the members of an existing pool.
+    pub fn from_pool(pool: FilterPool<T>) -> Self {
+        Self {
+            pool,
+            chunk_size: None,
+        }
+    }
+
+    /// The pool filtering the chunks, to reload or check members out of.
+    pub fn pool(&self) -> &FilterPool<T> {
+        &self.pool
+    }
+
+    /// Set how filter errors are handled, see [`FilterPool::set_error_policy`].
+    pub fn set_error_policy(&self, error_policy: ErrorPolicy) {
+        self.pool.set_error_policy(error_policy);
+    }
+
+    /// Split batches into chunks of `chunk_size` values. Zero goes back to the default, a few
+    /// chunks per pool member.
+    pub fn set_chunk_size(&mut self, chunk_size: usize) {
+        self.chunk_size = (chunk_size > 0).then_some(chunk_size);
+    }
+
+    /// Filter a list of values, keeping the matches in input order.
+    ///
+    /// The values come out the same as from [`FilterSystem::filter`]. Under
+    /// [`ErrorPolicy::FailFast`] the first failing chunk aborts the call, and the chunks not
+    /// started yet are skipped. Under [`ErrorPolicy::Lenient`] failing filters count as not
+    /// matching and are recorded in the stats of the members; errors the lenient policy never
+    /// absorbs, such as panics, still abort the call.
+    ///
+    /// [`FilterSystem::filter`]: crate::FilterSystem::filter
+    pub fn par_filter(&self, values: Vec<T>) -> Result<Vec<T>, FilterError> {
+        let chunk_size = self.chunk_size.unwrap_or_else(|| {
+            values
+                .len()
+                .div_ceil(self.pool.size() * CHUNKS_PER_MEMBER)
+                .max(1)
+        });
+        let mut chunks = Vec::with_capacity(values.len().div_ceil(chunk_size));
+        let mut values = values.into_iter();
+        loop {
+            let chunk: Vec<T> = values.by_ref().take(chunk_size).collect();
+            if chunk.is_empty() {
+                break;
+            }
+            chunks.push(chunk);
+        }
+        let kept = chunks
+            .into_par_iter()
+            .map(|chunk| self.pool.checkout().filter(chunk))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(kept.into_iter().flatten().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, fs, path::PathBuf};
+
+    use super::*;
+    use crate::{FilterConfig, FilterRuntime};
+
+    #[derive(Clone, Debug, PartialEq, Serialize)]
+    struct Tx {
+        amount: u64,
+    }
+    impl mlua::UserData for Tx {}
+
+    fn script(name: &str, source: &str) -> (PathBuf, Config) {
+        let root = std::env::temp_dir().join(format!(
+            "croncat-indexer-filter-parallel-{name}-{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&root).unwrap();
+        let script = root.join("filter.lua");
+        fs::write(&script, source).unwrap();
+        let config = Config {
+            chains: HashMap::from([(
+                "uni-5".to_string(),
+                vec![FilterConfig {
+                    name: "amounts".to_string(),
+                    script,
+                    ..Default::default()
+                }],
+            )]),
+            ..Default::default()
+        };
+        (root, config)
+    }
+
+    fn txs() -> Vec<Tx> {
+        (0..5000).map(|amount| Tx { amount }).collect()
+    }
+
+    #[test]
+    fn same_output_as_sequential() {
+        let (root, config) = script(
+            "sequential",
+            "return { filter = function(tx) return tx.amount % 7 == 3 or tx.amount > 4990 end }",
+        );
+        let runtime = FilterRuntime::<Tx>::new();
+        let sequential = runtime.load(config.clone()).unwrap().filter(txs()).unwrap();
+
+        let mut system = ParallelFilterSystem::new(4, RuntimeOptions::default(), config).unwrap();
+        assert_eq!(system.par_filter(txs()).unwrap(), sequential);
+        for chunk_size in [1, 333, 5000, 10_000] {
+            system.set_chunk_size(chunk_size);
+            assert_eq!(system.par_filter(txs()).unwrap(), sequential);
+        }
+        assert!(system.par_filter(Vec::new()).unwrap().is_empty());
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn error_policies() {
+        let (root, config) = script(
+            "errors",
+            "return { filter = function(tx) assert(tx.amount ~= 1234, 'unlucky') return tx.amount < 2000 end }",
+        );
+        let system = ParallelFilterSystem::new(3, RuntimeOptions::default(), config).unwrap();
+        let err = system.par_filter(txs()).unwrap_err();
+        assert!(err.to_string().contains("unlucky"), "{err}");
+
+        system.set_error_policy(ErrorPolicy::Lenient);
+        let kept = system.par_filter(txs()).unwrap();
+        assert_eq!(kept.len(), 1999);
+        assert!(!kept.contains(&Tx { amount: 1234 }));
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
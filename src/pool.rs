@@ -0,0 +1,513 @@
+//! A pool of identically configured filter systems, for multi-threaded servers.
+//!
+//! A Lua state can't move between threads, so every member of the pool owns its runtime on a
+//! thread of its own and runs the work sent to it there. Checking a member out gives exclusive
+//! use of it until the guard is dropped, without serializing the other members.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{mpsc, Condvar, Mutex},
+    task::{Context, Poll, Waker},
+    thread::{self, JoinHandle},
+};
+
+use mlua::prelude::LuaUserData;
+use serde::Serialize;
+
+use crate::{
+    Config, ErrorPolicy, FilterError, FilterRuntime, FilterStats, FilterSystem, LoadError,
+    RuntimeOptions, Verdict,
+};
+
+type Job<T> = Box<dyn for<'lua> FnOnce(&mut FilterSystem<'lua, T>) + Send>;
+
+/// Box a closure as a [`Job`], so that its argument type is inferred.
+fn job<T, F>(f: F) -> Job<T>
+where
+    F: for<'lua> FnOnce(&mut FilterSystem<'lua, T>) + Send + 'static,
+{
+    Box::new(f)
+}
+
+/// One runtime of the pool, reached through its thread.
+struct Member<T> {
+    jobs: mpsc::Sender<Job<T>>,
+    /// The configuration generation the member runs.
+    generation: u64,
+}
+
+impl<T> Member<T> {
+    /// Run `f` on the member's thread and wait for its result.
+    fn run<R, F>(&self, f: F) -> R
+    where
+        R: Send + 'static,
+        F: for<'lua> FnOnce(&mut FilterSystem<'lua, T>) -> R + Send + 'static,
+    {
+        let (reply, result) = mpsc::sync_channel(1);
+        self.jobs
+            .send(job(move |system| {
+                let _ = reply.send(f(system));
+            }))
+            .expect("filter pool member exited");
+        result.recv().expect("filter pool member panicked")
+    }
+}
+
+struct State<T> {
+    idle: Vec<Member<T>>,
+    generation: u64,
+    config: Config,
+    error_policy: ErrorPolicy,
+    wakers: Vec<Waker>,
+}
+
+/// A fixed number of filter systems loaded from the same configuration.
+///
+/// ```no_run
+/// use croncat_indexer_filter::{Config, FilterPool, RuntimeOptions};
+///
+/// #[derive(Clone, serde::Serialize)]
+/// struct Tx {
+///     from: String,
+/// }
+/// impl mlua::UserData for Tx {}
+///
+/// let config = Config::from_yaml("chains: {}").unwrap();
+/// let pool = FilterPool::<Tx>::new(4, RuntimeOptions::default(), config).unwrap();
+/// let system = pool.checkout();
+/// let matched = system.filter_one(Tx { from: "juno1agent".to_string() }).unwrap();
+/// ```
+pub struct FilterPool<T> {
+    state: Mutex<State<T>>,
+    returned: Condvar,
+    /// Serializes reloads.
+    reloading: Mutex<()>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl<T> FilterPool<T>
+where
+    T: LuaUserData + Serialize + Send + Sync + 'static,
+{
+    /// Build `size` runtimes with `options` and load `config` into each of them.
+    pub fn new(size: usize, options: RuntimeOptions, config: Config) -> Result<Self, LoadError> {
+        if size == 0 {
+            return Err(mlua::Error::runtime("filter pool: size must be at least 1").into());
+        }
+        let mut starting = Vec::with_capacity(size);
+        for _ in 0..size {
+            let (jobs, receiver) = mpsc::channel::<Job<T>>();
+            let (ready, started) = mpsc::sync_channel(1);
+            let options = options.clone();
+            let config = config.clone();
+            let thread = thread::spawn(move || {
+                let runtime = match FilterRuntime::<T>::new_with_options(options) {
+                    Ok(runtime) => runtime,
+                    Err(err) => return drop(ready.send(Err(err.into()))),
+                };
+                let mut system = match runtime.load(config) {
+                    Ok(system) => system,
+                    Err(err) => return drop(ready.send(Err(err))),
+                };
+                let _ = ready.send(Ok(()));
+                for job in receiver {
+                    job(&mut system);
+                }
+            });
+            starting.push((jobs, started, thread));
+        }
+
+        let mut idle = Vec::with_capacity(size);
+        let mut threads = Vec::with_capacity(size);
+        let mut result = Ok(());
+        for (jobs, started, thread) in starting {
+            let ready = started.recv().unwrap_or_else(|_| {
+                Err(mlua::Error::runtime("filter pool: member thread exited").into())
+            });
+            result = result.and(ready);
+            idle.push(Member {
+                jobs,
+                generation: 0,
+            });
+            threads.push(thread);
+        }
+        let pool = Self {
+            state: Mutex::new(State {
+                idle,
+                generation: 0,
+                config,
+                error_policy: ErrorPolicy::default(),
+                wakers: Vec::new(),
+            }),
+            returned: Condvar::new(),
+            reloading: Mutex::new(()),
+            threads,
+        };
+        result.map(|()| pool)
+    }
+
+    /// The number of members.
+    pub fn size(&self) -> usize {
+        self.threads.len()
+    }
+
+    /// Take a member, waiting for one to be returned if they are all checked out.
+    pub fn checkout(&self) -> PooledFilterSystem<'_, T> {
+        let member = self.take(|_| true);
+        self.guard(member)
+    }
+
+    /// Take a member if one is idle.
+    pub fn try_checkout(&self) -> Option<PooledFilterSystem<'_, T>> {
+        let member = self.state.lock().unwrap().idle.pop()?;
+        Some(self.guard(member))
+    }
+
+    /// Take a member without blocking the calling thread while they are all checked out.
+    ///
+    /// The filtering calls of the guard still block until the member answers.
+    pub fn checkout_async(&self) -> Checkout<'_, T> {
+        Checkout { pool: self }
+    }
+
+    /// Set how filter errors are handled by every member, from their next checkout on.
+    pub fn set_error_policy(&self, error_policy: ErrorPolicy) {
+        self.state.lock().unwrap().error_policy = error_policy;
+    }
+
+    /// Load `config` into every member, one at a time.
+    ///
+    /// Each member is reloaded as soon as it is idle, and returned once done, so at most one
+    /// member is out of service at any moment. If a member fails to load `config`, the members
+    /// already reloaded go back to the previous configuration and the error is returned.
+    pub fn reload(&self, config: Config) -> Result<(), LoadError> {
+        let _reloading = self.reloading.lock().unwrap();
+        let (previous, old) = {
+            let state = self.state.lock().unwrap();
+            (state.config.clone(), state.generation)
+        };
+        let new = old + 1;
+
+        let mut reloaded = 0;
+        let mut result = Ok(());
+        for _ in 0..self.size() {
+            let mut member = self.take(|member| member.generation == old);
+            let config = config.clone();
+            result = member.run(move |system| system.reload(config));
+            if result.is_ok() {
+                member.generation = new;
+                reloaded += 1;
+            }
+            self.give_back(member);
+            if result.is_err() {
+                break;
+            }
+        }
+
+        if let Err(err) = result {
+            for _ in 0..reloaded {
+                let mut member = self.take(|member| member.generation == new);
+                let previous = previous.clone();
+                // The previous configuration loaded before; if it no longer does, the member
+                // keeps the new one rather than being left without filters.
+                let _ = member.run(move |system| system.reload(previous));
+                member.generation = old;
+                self.give_back(member);
+            }
+            return Err(err);
+        }
+
+        let mut state = self.state.lock().unwrap();
+        state.generation = new;
+        state.config = config;
+        Ok(())
+    }
+
+    /// Wait for an idle member matching `pick` and take it.
+    fn take(&self, pick: impl Fn(&Member<T>) -> bool) -> Member<T> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(index) = state.idle.iter().position(&pick) {
+                return state.idle.swap_remove(index);
+            }
+            state = self.returned.wait(state).unwrap();
+        }
+    }
+}
+
+impl<T> FilterPool<T> {
+    fn guard(&self, member: Member<T>) -> PooledFilterSystem<'_, T> {
+        PooledFilterSystem {
+            pool: self,
+            member: Some(member),
+            error_policy: self.state.lock().unwrap().error_policy,
+        }
+    }
+
+    fn give_back(&self, member: Member<T>) {
+        let wakers = {
+            let mut state = self.state.lock().unwrap();
+            state.idle.push(member);
+            std::mem::take(&mut state.wakers)
+        };
+        // Reloads wait for particular members, so every waiter gets to look.
+        self.returned.notify_all();
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Drop for FilterPool<T> {
+    fn drop(&mut self) {
+        // Closing the job channels stops the member threads.
+        self.state.get_mut().unwrap().idle.clear();
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// A member of a [`FilterPool`], returned to it on drop.
+pub struct PooledFilterSystem<'pool, T> {
+    pool: &'pool FilterPool<T>,
+    member: Option<Member<T>>,
+    error_policy: ErrorPolicy,
+}
+
+impl<'pool, T> PooledFilterSystem<'pool, T>
+where
+    T: LuaUserData + Serialize + Send + Sync + 'static,
+{
+    /// Run `f` against the member's filter system, on its thread.
+    pub fn run<R, F>(&self, f: F) -> R
+    where
+        R: Send + 'static,
+        F: for<'lua> FnOnce(&FilterSystem<'lua, T>) -> R + Send + 'static,
+    {
+        let error_policy = self.error_policy;
+        self.member
+            .as_ref()
+            .expect("member is only taken on drop")
+            .run(move |system| {
+                system.set_error_policy(error_policy);
+                f(system)
+            })
+    }
+
+    /// See [`FilterSystem::filter_one`].
+    pub fn filter_one(&self, value: T) -> Result<bool, FilterError> {
+        self.run(move |system| system.filter_one(value))
+    }
+
+    /// See [`FilterSystem::filter_one_detailed`].
+    pub fn filter_one_detailed(&self, value: T) -> Result<Verdict, FilterError> {
+        self.run(move |system| system.filter_one_detailed(value))
+    }
+
+    /// See [`FilterSystem::filter`].
+    pub fn filter(&self, values: Vec<T>) -> Result<Vec<T>, FilterError> {
+        self.run(move |system| system.filter(values))
+    }
+
+    /// The counters of the member's filters.
+    pub fn stats(&self) -> Vec<FilterStats> {
+        self.run(|system| system.stats())
+    }
+}
+
+impl<'pool, T> Drop for PooledFilterSystem<'pool, T> {
+    fn drop(&mut self) {
+        if let Some(member) = self.member.take() {
+            self.pool.give_back(member);
+        }
+    }
+}
+
+/// The future returned by [`FilterPool::checkout_async`].
+pub struct Checkout<'pool, T> {
+    pool: &'pool FilterPool<T>,
+}
+
+impl<'pool, T> Future for Checkout<'pool, T> {
+    type Output = PooledFilterSystem<'pool, T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let pool = self.pool;
+        let mut state = pool.state.lock().unwrap();
+        match state.idle.pop() {
+            Some(member) => Poll::Ready(PooledFilterSystem {
+                pool,
+                member: Some(member),
+                error_policy: state.error_policy,
+            }),
+            None => {
+                state.wakers.push(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, fs, path::PathBuf, sync::Arc, task::Wake};
+
+    use super::*;
+    use crate::FilterConfig;
+
+    #[derive(Clone, Serialize)]
+    struct Tx {
+        amount: u64,
+    }
+    impl mlua::UserData for Tx {}
+
+    fn scripts(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!(
+            "croncat-indexer-filter-pool-{name}-{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&root).unwrap();
+        for (file, threshold) in [("low.lua", 10), ("high.lua", 100)] {
+            fs::write(
+                root.join(file),
+                format!("return {{ filter = function(tx) return tx.amount > {threshold} end }}"),
+            )
+            .unwrap();
+        }
+        fs::write(root.join("broken.lua"), "return {").unwrap();
+        root
+    }
+
+    fn config(script: PathBuf) -> Config {
+        Config {
+            chains: HashMap::from([(
+                "uni-5".to_string(),
+                vec![FilterConfig {
+                    name: "threshold".to_string(),
+                    script,
+                    ..Default::default()
+                }],
+            )]),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn concurrent_checkouts_agree() {
+        let root = scripts("concurrent");
+        let pool =
+            FilterPool::<Tx>::new(4, RuntimeOptions::default(), config(root.join("low.lua")))
+                .unwrap();
+        assert_eq!(pool.size(), 4);
+
+        thread::scope(|scope| {
+            for worker in 0..8 {
+                let pool = &pool;
+                scope.spawn(move || {
+                    for amount in 0..50 {
+                        let amount = worker * 3 + amount;
+                        let system = pool.checkout();
+                        assert_eq!(system.filter_one(Tx { amount }).unwrap(), amount > 10);
+                    }
+                });
+            }
+        });
+
+        let evaluations: u64 = std::iter::from_fn(|| pool.try_checkout())
+            .take(pool.size())
+            .collect::<Vec<_>>()
+            .iter()
+            .flat_map(PooledFilterSystem::stats)
+            .map(|stats| stats.invocations)
+            .sum();
+        assert_eq!(evaluations, 8 * 50);
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn reload() {
+        let root = scripts("reload");
+        let pool =
+            FilterPool::<Tx>::new(3, RuntimeOptions::default(), config(root.join("low.lua")))
+                .unwrap();
+
+        // Reloads proceed while other threads keep using the pool.
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                for _ in 0..100 {
+                    pool.checkout().filter_one(Tx { amount: 50 }).unwrap();
+                }
+            });
+            pool.reload(config(root.join("high.lua"))).unwrap();
+        });
+        let held: Vec<_> = (0..3).map(|_| pool.checkout()).collect();
+        for system in &held {
+            assert!(!system.filter_one(Tx { amount: 50 }).unwrap());
+        }
+        drop(held);
+
+        let err = pool.reload(config(root.join("broken.lua"))).unwrap_err();
+        assert!(err.to_string().contains("broken.lua"), "{err}");
+        let held: Vec<_> = (0..3).map(|_| pool.checkout()).collect();
+        for system in &held {
+            assert!(system.filter_one(Tx { amount: 500 }).unwrap());
+            assert!(!system.filter_one(Tx { amount: 50 }).unwrap());
+        }
+        drop(held);
+
+        let err = FilterPool::<Tx>::new(
+            2,
+            RuntimeOptions::default(),
+            config(root.join("broken.lua")),
+        )
+        .err()
+        .unwrap();
+        assert!(err.to_string().contains("broken.lua"), "{err}");
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    struct Unpark(thread::Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let waker = Waker::from(Arc::new(Unpark(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut future = std::pin::pin!(future);
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn checkout_async() {
+        let root = scripts("async");
+        let pool =
+            FilterPool::<Tx>::new(1, RuntimeOptions::default(), config(root.join("low.lua")))
+                .unwrap();
+
+        let held = pool.checkout();
+        assert!(pool.try_checkout().is_none());
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                let system = block_on(pool.checkout_async());
+                assert!(system.filter_one(Tx { amount: 11 }).unwrap());
+            });
+            thread::sleep(std::time::Duration::from_millis(20));
+            drop(held);
+        });
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,125 @@
+//! Reading and compiling filter scripts on several threads, ahead of loading them.
+//!
+//! Compiling a script only needs a Lua state, not the runtime it ends up in: each worker
+//! compiles into a state of its own and hands back bytecode, which the runtime then loads in
+//! configuration order. Scripts that fail to compile here get no bytecode and are loaded from
+//! source, so their errors are reported exactly like they would be without this step.
+
+use std::{
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+use mlua::Lua;
+
+use crate::{
+    encoding,
+    script_cache::{self, ScriptCache},
+};
+
+/// How many threads compile scripts, in the app data of a runtime, see
+/// [`RuntimeOptions::compile_threads`](crate::RuntimeOptions::compile_threads).
+#[derive(Clone, Copy)]
+pub(crate) struct CompileThreads(pub usize);
+
+/// A script read ahead of loading, with its bytecode if it was compiled.
+pub(crate) struct Script {
+    /// The contents of the script, decoded when it is loaded, see [`encoding::decode`].
+    pub source: std::io::Result<Vec<u8>>,
+    /// How long reading the script took.
+    pub read_time: Duration,
+    pub bytecode: Option<Vec<u8>>,
+}
+
+/// The chunk name of the script at `path`.
+pub(crate) fn chunk_name(path: &Path) -> String {
+    // The `@` prefix makes Lua report positions as `path:line:`.
+    format!("@{}", path.display())
+}
+
+/// Read and compile the scripts at `paths` on the threads `lua` allows, in order.
+///
+/// With a single thread, or a single script, they are only read.
+pub(crate) fn prepare(lua: &Lua, paths: &[PathBuf]) -> Vec<Script> {
+    let threads = match lua
+        .app_data_ref::<CompileThreads>()
+        .map(|threads| threads.0)
+    {
+        Some(0) | None => std::thread::available_parallelism().map_or(1, NonZeroUsize::get),
+        Some(threads) => threads,
+    };
+    let threads = threads.min(paths.len());
+    if threads <= 1 {
+        return paths.iter().map(|path| read(path)).collect();
+    }
+
+    let cache = lua.app_data_ref::<ScriptCache>().map(|cache| cache.clone());
+    let next = AtomicUsize::new(0);
+    let compiled: Vec<(usize, Script)> = std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let lua = Lua::new();
+                    let mut scripts = Vec::new();
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(path) = paths.get(index) else {
+                            break scripts;
+                        };
+                        scripts.push((index, compile(&lua, cache.as_ref(), path)));
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap_or_default())
+            .collect()
+    });
+    let mut scripts: Vec<Option<Script>> = paths.iter().map(|_| None).collect();
+    for (index, script) in compiled {
+        scripts[index] = Some(script);
+    }
+    // The scripts of a worker that panicked are read again, and loaded from source.
+    scripts
+        .into_iter()
+        .zip(paths)
+        .map(|(script, path)| script.unwrap_or_else(|| read(path)))
+        .collect()
+}
+
+/// Read the script at `path`, without compiling it.
+pub(crate) fn read(path: &Path) -> Script {
+    let started = Instant::now();
+    let source = std::fs::read(path);
+    Script {
+        source,
+        read_time: started.elapsed(),
+        bytecode: None,
+    }
+}
+
+/// Read and compile the script at `path` in `lua`, through `cache` if there is one.
+fn compile(lua: &Lua, cache: Option<&ScriptCache>, path: &Path) -> Script {
+    let started = Instant::now();
+    let source = std::fs::read(path);
+    let read_time = started.elapsed();
+    let text = source
+        .as_ref()
+        .ok()
+        .and_then(|source| encoding::decode(source.clone()).ok());
+    let bytecode = text.and_then(|source| {
+        let name = chunk_name(path);
+        match cache {
+            Some(cache) => cache.bytecode(lua, &name, &source).ok(),
+            None => script_cache::compile(lua, &name, &source).ok(),
+        }
+    });
+    Script {
+        source,
+        read_time,
+        bytecode,
+    }
+}
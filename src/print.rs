@@ -0,0 +1,104 @@
+//! Capturing `print` output per filter call.
+//!
+//! `print` never writes to stdout: its output is collected while a detailed evaluation runs,
+//! and dropped otherwise. Each captured call holds at most `limit` bytes, after which a single
+//! truncation notice is added.
+
+use mlua::{Lua, Value, Variadic};
+
+/// The capture state of a runtime, kept in its app data.
+struct PrintCapture {
+    limit: usize,
+    buffer: Option<Buffer>,
+}
+
+#[derive(Default)]
+struct Buffer {
+    lines: Vec<String>,
+    bytes: usize,
+    truncated: bool,
+}
+
+pub(crate) fn install(lua: &Lua, limit: usize) -> mlua::Result<()> {
+    lua.set_app_data(PrintCapture {
+        limit,
+        buffer: None,
+    });
+    let print = lua.create_function(|lua, args: Variadic<Value>| {
+        let Some(mut capture) = lua.app_data_mut::<PrintCapture>() else {
+            return Ok(());
+        };
+        let limit = capture.limit;
+        let Some(buffer) = capture.buffer.as_mut() else {
+            return Ok(());
+        };
+        if buffer.truncated {
+            return Ok(());
+        }
+        drop(capture);
+
+        let tostring: mlua::Function = lua.globals().get("tostring")?;
+        let mut line = String::new();
+        for (i, arg) in args.into_iter().enumerate() {
+            if i > 0 {
+                line.push('\t');
+            }
+            line.push_str(&tostring.call::<_, mlua::String>(arg)?.to_string_lossy());
+        }
+
+        let Some(mut capture) = lua.app_data_mut::<PrintCapture>() else {
+            return Ok(());
+        };
+        let Some(buffer) = capture.buffer.as_mut() else {
+            return Ok(());
+        };
+        if buffer.bytes + line.len() > limit {
+            buffer.truncated = true;
+            buffer
+                .lines
+                .push(format!("[output truncated after {limit} bytes]"));
+        } else {
+            buffer.bytes += line.len();
+            buffer.lines.push(line);
+        }
+        Ok(())
+    })?;
+    lua.globals().set("print", print)
+}
+
+/// Start capturing the output of `print`.
+pub(crate) fn start(lua: &Lua) {
+    if let Some(mut capture) = lua.app_data_mut::<PrintCapture>() {
+        capture.buffer = Some(Buffer::default());
+    }
+}
+
+/// Stop capturing, returning the lines printed since [`start`].
+pub(crate) fn finish(lua: &Lua) -> Vec<String> {
+    lua.app_data_mut::<PrintCapture>()
+        .and_then(|mut capture| capture.buffer.take())
+        .map(|buffer| buffer.lines)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capture() {
+        let lua = Lua::new();
+        install(&lua, 16).unwrap();
+
+        lua.load(r#"print("dropped")"#).exec().unwrap();
+        start(&lua);
+        lua.load(r#"print("amount", 42, nil) print("0123456789abcdef")"#)
+            .exec()
+            .unwrap();
+        assert_eq!(
+            finish(&lua),
+            ["amount\t42\tnil", "[output truncated after 16 bytes]"]
+        );
+        assert!(finish(&lua).is_empty());
+    }
+}
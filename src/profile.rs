@@ -0,0 +1,154 @@
+//! A sampling profiler for filter scripts.
+//!
+//! While profiling, the instruction hook also records which line of which script runs, every
+//! [`interval`](Profiler::interval) instructions. Samples are counted per line and, inclusive
+//! of whatever the filter called, per filter. Luau has no instruction hook: a sample is taken
+//! at every interrupt instead, at function calls and loop back-edges.
+
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use serde::Serialize;
+
+/// How many instructions run between two samples, unless set otherwise.
+pub(crate) const DEFAULT_INTERVAL: u32 = 100;
+
+/// The samples taken since a profile was last taken out of a filter system.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct ProfileReport {
+    /// How many instructions ran between two samples.
+    pub interval: u32,
+    /// How many samples were taken.
+    pub samples: u64,
+    /// The samples taken while each filter ran, most first.
+    pub filters: Vec<FilterSamples>,
+    /// The samples taken on each line of the scripts, most first.
+    pub lines: Vec<LineSamples>,
+}
+
+impl ProfileReport {
+    /// The `k` lines the most samples were taken on.
+    pub fn top_lines(&self, k: usize) -> &[LineSamples] {
+        &self.lines[..k.min(self.lines.len())]
+    }
+}
+
+/// The samples taken while a filter ran.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct FilterSamples {
+    /// The name of the filter.
+    pub filter: String,
+    pub samples: u64,
+}
+
+/// The samples taken on a line of a script.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct LineSamples {
+    /// The path of the script, or the name of the chunk if it didn't come from a file.
+    pub script: String,
+    pub line: u32,
+    pub samples: u64,
+}
+
+/// Records samples for a filter system, shared with the instruction hook.
+#[derive(Clone)]
+pub(crate) struct Profiler {
+    interval: u32,
+    samples: Rc<RefCell<Samples>>,
+}
+
+#[derive(Default)]
+struct Samples {
+    /// The filter running at the moment.
+    running: Option<String>,
+    total: u64,
+    filters: HashMap<String, u64>,
+    lines: HashMap<String, HashMap<u32, u64>>,
+}
+
+impl Profiler {
+    pub(crate) fn new(interval: u32) -> Self {
+        Self {
+            interval: interval.max(1),
+            samples: Rc::default(),
+        }
+    }
+
+    /// How many instructions run between two samples.
+    #[cfg(not(feature = "luau"))]
+    pub(crate) fn interval(&self) -> u32 {
+        self.interval
+    }
+
+    /// Attribute the samples taken from now on to `filter`.
+    pub(crate) fn enter(&self, filter: &str) {
+        self.samples.borrow_mut().running = Some(filter.to_string());
+    }
+
+    /// Stop attributing samples to the filter that was running.
+    pub(crate) fn leave(&self) {
+        self.samples.borrow_mut().running = None;
+    }
+
+    /// Record a sample of the function `debug` describes.
+    pub(crate) fn sample(&self, debug: &mlua::Debug) {
+        let samples = &mut *self.samples.borrow_mut();
+        samples.total += 1;
+        if let Some(filter) = &samples.running {
+            match samples.filters.get_mut(filter) {
+                Some(count) => *count += 1,
+                None => {
+                    samples.filters.insert(filter.clone(), 1);
+                }
+            }
+        }
+        // C functions have no line.
+        let Ok(line) = u32::try_from(debug.curr_line()) else {
+            return;
+        };
+        let source = debug.source();
+        // Scripts loaded from files are named after them, prefixed with `@`.
+        let script = match source.source.as_deref().and_then(|s| s.strip_prefix('@')) {
+            Some(path) => path,
+            None => source.short_src.as_deref().unwrap_or("?"),
+        };
+        let lines = match samples.lines.get_mut(script) {
+            Some(lines) => lines,
+            None => samples.lines.entry(script.to_string()).or_default(),
+        };
+        *lines.entry(line).or_default() += 1;
+    }
+
+    /// The samples taken so far, which are then forgotten.
+    pub(crate) fn take(&self) -> ProfileReport {
+        let samples = std::mem::take(&mut *self.samples.borrow_mut());
+        let mut filters: Vec<FilterSamples> = samples
+            .filters
+            .into_iter()
+            .map(|(filter, samples)| FilterSamples { filter, samples })
+            .collect();
+        filters.sort_by(|a, b| b.samples.cmp(&a.samples).then(a.filter.cmp(&b.filter)));
+        let mut lines: Vec<LineSamples> = samples
+            .lines
+            .into_iter()
+            .flat_map(|(script, lines)| {
+                lines.into_iter().map(move |(line, samples)| LineSamples {
+                    script: script.clone(),
+                    line,
+                    samples,
+                })
+            })
+            .collect();
+        lines.sort_by(|a, b| {
+            b.samples
+                .cmp(&a.samples)
+                .then(a.script.cmp(&b.script))
+                .then(a.line.cmp(&b.line))
+        });
+        ProfileReport {
+            interval: self.interval,
+            samples: samples.total,
+            filters,
+            lines,
+        }
+    }
+}
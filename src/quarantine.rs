@@ -0,0 +1,72 @@
+//! Setting aside filters that keep failing, see
+//! [`FilterSystem::set_quarantine`](crate::FilterSystem::set_quarantine).
+//!
+//! A filter that fails on as many values in a row as its threshold is quarantined: filter
+//! systems skip it, as if it didn't match, until its cool-down is over or a reload replaces
+//! it. Once the cool-down is over it runs again, and goes back to quarantine on its first
+//! error.
+
+use std::{
+    cell::Cell,
+    time::{Duration, Instant},
+};
+
+/// When filters that keep failing are quarantined.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quarantine {
+    /// How many values in a row a filter may fail on before it is quarantined, unless its
+    /// configuration says otherwise, see [`FilterConfig::quarantine_after`].
+    ///
+    /// [`FilterConfig::quarantine_after`]: crate::FilterConfig::quarantine_after
+    pub after: u32,
+    /// How long a quarantined filter is skipped before it runs again; until it is reloaded if
+    /// there is none.
+    pub cool_down: Option<Duration>,
+}
+
+/// How close a filter is to quarantine, or how long it has been in it.
+#[derive(Debug, Default)]
+pub(crate) struct Breaker {
+    /// How many values in a row the filter failed on.
+    errors: Cell<u32>,
+    /// When the filter was quarantined, if it is.
+    since: Cell<Option<Instant>>,
+}
+
+impl Breaker {
+    /// Whether the filter is quarantined, its cool-down over or not.
+    pub(crate) fn is_open(&self) -> bool {
+        self.since.get().is_some()
+    }
+
+    /// Whether the filter is to be skipped, quarantined for less than `cool_down`.
+    pub(crate) fn skips(&self, cool_down: Option<Duration>) -> bool {
+        match (self.since.get(), cool_down) {
+            (None, _) => false,
+            (Some(since), Some(cool_down)) => since.elapsed() < cool_down,
+            (Some(_), None) => true,
+        }
+    }
+
+    /// Count a call that failed or not, quarantining the filter once it failed on `after`
+    /// values in a row, and returning how many if it did.
+    pub(crate) fn record(&self, failed: bool, after: u32) -> Option<u32> {
+        if !failed {
+            self.reset();
+            return None;
+        }
+        let errors = self.errors.get().saturating_add(1);
+        self.errors.set(errors);
+        if errors < after {
+            return None;
+        }
+        self.since.set(Some(Instant::now()));
+        Some(errors)
+    }
+
+    /// Take the filter out of quarantine, forgetting its errors.
+    pub(crate) fn reset(&self) {
+        self.errors.set(0);
+        self.since.set(None);
+    }
+}
@@ -0,0 +1,103 @@
+//! Recordings of the verdicts of each filter, replayed to check that filters still agree.
+//!
+//! A recording is NDJSON: one `{"value": ..., "verdicts": {"filter": true, ...}}` object per
+//! value. Values go through serde, so a recording doesn't depend on the Lua runtime or on
+//! the version of this crate.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::Verdict;
+
+/// A line of a recording.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Recorded<V> {
+    pub value: V,
+    pub verdicts: BTreeMap<String, bool>,
+}
+
+/// The verdict of each filter in `verdict`, for filters with the ids `ids`.
+///
+/// Filters sharing an id matched if any of them did; filters that failed or ran out of fuel
+/// didn't match.
+pub(crate) fn verdicts(
+    ids: impl IntoIterator<Item = String>,
+    verdict: Verdict,
+) -> BTreeMap<String, bool> {
+    let mut verdicts: BTreeMap<String, bool> = ids.into_iter().map(|id| (id, false)).collect();
+    let matched = verdict
+        .matched_by
+        .into_iter()
+        .map(|name| (name, true))
+        .chain(verdict.dry_run);
+    for (name, matched) in matched {
+        *verdicts.entry(name).or_default() |= matched;
+    }
+    verdicts
+}
+
+/// How the current filters disagree with a recording, see
+/// [`FilterSystem::verify_recording`](crate::FilterSystem::verify_recording).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VerifyReport {
+    /// How many values were replayed.
+    pub values: usize,
+    /// How many of them got a different verdict from at least one filter.
+    pub differing: usize,
+    /// The differences, by filter id.
+    pub filters: BTreeMap<String, FilterDiff>,
+}
+
+impl VerifyReport {
+    /// How many differences are kept as examples for each filter.
+    pub const MAX_EXAMPLES: usize = 10;
+
+    /// Whether every filter gave the recorded verdicts.
+    pub fn matches(&self) -> bool {
+        self.differing == 0
+    }
+
+    /// Record that `filter` gave `replayed` on the value of `line` rather than `recorded`.
+    pub(crate) fn push(
+        &mut self,
+        filter: String,
+        line: usize,
+        value: &serde_json::Value,
+        recorded: Option<bool>,
+        replayed: Option<bool>,
+    ) {
+        let diff = self.filters.entry(filter).or_default();
+        diff.count += 1;
+        if diff.examples.len() < Self::MAX_EXAMPLES {
+            diff.examples.push(VerdictDiff {
+                line,
+                value: value.clone(),
+                recorded,
+                replayed,
+            });
+        }
+    }
+}
+
+/// The differences of a filter with a recording.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FilterDiff {
+    /// How many values the filter gave a different verdict on.
+    pub count: usize,
+    /// The first of them, up to [`VerifyReport::MAX_EXAMPLES`].
+    pub examples: Vec<VerdictDiff>,
+}
+
+/// A value a filter gave a different verdict on.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VerdictDiff {
+    /// The line of the value in the recording, from 1.
+    pub line: usize,
+    /// The value, as recorded.
+    pub value: serde_json::Value,
+    /// The recorded verdict; none if the filter wasn't loaded when recording.
+    pub recorded: Option<bool>,
+    /// The verdict now; none if the filter isn't loaded anymore.
+    pub replayed: Option<bool>,
+}
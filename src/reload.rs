@@ -0,0 +1,76 @@
+//! Asking a filter system to reload from another thread, see
+//! [`FilterSystem::reload_handle`](crate::FilterSystem::reload_handle).
+//!
+//! Only available with the `tokio` feature. A filter system borrows its runtime and stays on
+//! its thread, so handles only queue configurations; the system loads them once its owner
+//! calls [`FilterSystem::apply_reload`](crate::FilterSystem::apply_reload), between batches.
+
+use std::{
+    sync::{Arc, Mutex, Weak},
+    time::Duration,
+};
+
+use tokio::sync::oneshot;
+
+use crate::{Config, LoadError, LoadedFilter};
+
+/// What a reload requested through a [`ReloadHandle`] loaded.
+#[derive(Clone, Debug)]
+pub struct ReloadSummary {
+    /// The filters the configuration loaded, as in
+    /// [`FilterSystem::load_report`](crate::FilterSystem::load_report).
+    pub loaded: Vec<LoadedFilter>,
+    /// How many requests queued before this one it replaced.
+    pub superseded: usize,
+    /// How long reloading took.
+    pub took: Duration,
+}
+
+/// A reload waiting for its filter system.
+pub(crate) struct Request {
+    pub(crate) config: Config,
+    pub(crate) reply: oneshot::Sender<Result<ReloadSummary, LoadError>>,
+}
+
+/// The reload waiting for a filter system, if any.
+#[derive(Default)]
+pub(crate) struct Queue {
+    pub(crate) request: Option<Request>,
+    /// How many requests the waiting one replaced.
+    pub(crate) superseded: usize,
+}
+
+/// Requests reloads of a [`FilterSystem`](crate::FilterSystem) from any thread.
+///
+/// Handles are cheap to clone and don't keep the system alive: once it is dropped, the
+/// receivers of new and waiting requests get an error.
+#[derive(Clone)]
+pub struct ReloadHandle {
+    queue: Weak<Mutex<Queue>>,
+}
+
+impl ReloadHandle {
+    pub(crate) fn new(queue: &Arc<Mutex<Queue>>) -> Self {
+        Self {
+            queue: Arc::downgrade(queue),
+        }
+    }
+
+    /// Queue a reload of the system with `config`, receiving its outcome once applied.
+    ///
+    /// Only the latest request waits: one queued before it is dropped unapplied, and its
+    /// receiver gets an error. A failed reload leaves the previous filters in place, as with
+    /// [`FilterSystem::reload`](crate::FilterSystem::reload).
+    pub fn request_reload(
+        &self,
+        config: Config,
+    ) -> oneshot::Receiver<Result<ReloadSummary, LoadError>> {
+        let (reply, receiver) = oneshot::channel();
+        if let Some(queue) = self.queue.upgrade() {
+            let mut queue = queue.lock().unwrap();
+            let previous = queue.request.replace(Request { config, reply });
+            queue.superseded += usize::from(previous.is_some());
+        }
+        receiver
+    }
+}
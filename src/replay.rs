@@ -0,0 +1,74 @@
+//! Replaying exported values through the filters, see
+//! [`FilterSystem::replay_ndjson`](crate::FilterSystem::replay_ndjson).
+//!
+//! Each line of the input is a value as serialized by serde. A line that is an object with a
+//! boolean `expected` field has that field taken out before the value is read, as whether the
+//! value should be kept, so an export doubles as a regression test.
+
+use std::collections::BTreeMap;
+
+/// How to replay values, see
+/// [`FilterSystem::replay_ndjson_with`](crate::FilterSystem::replay_ndjson_with).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ReplayOptions {
+    /// Stop at the first value whose verdict differs from its `expected` field.
+    pub stop_at_mismatch: bool,
+}
+
+/// What replaying values through the filters found.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ReplayReport {
+    /// How many values were filtered.
+    pub values: usize,
+    /// How many of them were kept.
+    pub kept: usize,
+    /// The verdicts of each filter, by id; filters that failed or ran out of fuel didn't
+    /// match.
+    pub filters: BTreeMap<String, VerdictCounts>,
+    /// The lines that couldn't be read as values, from 1.
+    pub parse_errors: Vec<usize>,
+    /// The values whose verdict differs from their `expected` field.
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl ReplayReport {
+    /// Whether every line was read and every value got its expected verdict.
+    pub fn passed(&self) -> bool {
+        self.parse_errors.is_empty() && self.mismatches.is_empty()
+    }
+}
+
+/// How many values a filter matched and didn't.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct VerdictCounts {
+    pub matched: usize,
+    pub rejected: usize,
+}
+
+/// A value that didn't get the verdict its line expected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Mismatch {
+    /// The line of the value, from 1.
+    pub line: usize,
+    pub expected: bool,
+    pub kept: bool,
+}
+
+/// Read a line as a value and its `expected` field, if any.
+pub(crate) fn parse<T: serde::de::DeserializeOwned>(
+    line: &str,
+) -> serde_json::Result<(T, Option<bool>)> {
+    let mut value: serde_json::Value = serde_json::from_str(line)?;
+    let expected = match value.as_object_mut() {
+        Some(object) => match object.get("expected") {
+            Some(serde_json::Value::Bool(expected)) => {
+                let expected = *expected;
+                object.remove("expected");
+                Some(expected)
+            }
+            _ => None,
+        },
+        None => None,
+    };
+    Ok((serde_json::from_value(value)?, expected))
+}
@@ -0,0 +1,189 @@
+//! What loading a configuration did, script by script.
+
+use std::{collections::BTreeMap, fmt, path::PathBuf, time::Duration};
+
+use serde::{Serialize, Serializer};
+
+use crate::{FilterId, LoadError};
+
+/// The outcome of [`FilterSystem::load_collecting`](crate::FilterSystem::load_collecting), and
+/// of the last load, see [`FilterSystem::load_report`](crate::FilterSystem::load_report).
+///
+/// Displayed as a summary followed by the scripts of each chain and the failures, one per
+/// line, for startup logs.
+#[derive(Debug, Default, Serialize)]
+pub struct LoadReport {
+    /// The filters that loaded, in configuration order.
+    pub loaded: Vec<LoadedFilter>,
+    /// Everything that failed to load, in configuration order.
+    pub failures: Vec<LoadFailure>,
+}
+
+/// A filter of the configuration that loaded.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct LoadedFilter {
+    pub chain: String,
+    /// The name of the filter in the configuration.
+    pub name: String,
+    pub script: PathBuf,
+    /// The functions of the script, loaded as filters of the same names.
+    pub functions: Vec<String>,
+    /// The size of the script, in bytes.
+    pub size: u64,
+    /// How long reading the script took.
+    #[serde(serialize_with = "seconds")]
+    pub read_time: Duration,
+    /// How long compiling the script took, or loading its bytecode, evaluation aside.
+    #[serde(serialize_with = "seconds")]
+    pub compile_time: Duration,
+    /// How long evaluating the compiled script took, and registering its functions.
+    #[serde(serialize_with = "seconds")]
+    pub eval_time: Duration,
+    pub origin: ScriptOrigin,
+}
+
+/// Where the code of a loaded script came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScriptOrigin {
+    /// It was compiled from source while loading.
+    Source,
+    /// It was compiled to bytecode ahead of loading, see
+    /// [`RuntimeOptions::compile_threads`](crate::RuntimeOptions::compile_threads).
+    Precompiled,
+    /// Its bytecode was read from the script cache, see
+    /// [`RuntimeOptions::script_cache`](crate::RuntimeOptions::script_cache).
+    Cached,
+}
+
+impl fmt::Display for ScriptOrigin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScriptOrigin::Source => f.write_str("source"),
+            ScriptOrigin::Precompiled => f.write_str("precompiled"),
+            ScriptOrigin::Cached => f.write_str("cached"),
+        }
+    }
+}
+
+pub(crate) fn seconds<S: Serializer>(
+    duration: &Duration,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64())
+}
+
+/// A library, a filter, or a whole chain or configuration that failed to load.
+///
+/// Serialized with the error as its message.
+#[derive(Debug, Serialize)]
+pub struct LoadFailure {
+    /// The chain of the filter, or of the chain that failed; none for libraries and for
+    /// errors about the whole configuration.
+    pub chain: Option<String>,
+    /// The name of the filter in the configuration, or of the library.
+    pub name: Option<String>,
+    /// The script of the filter, or the library.
+    pub script: Option<PathBuf>,
+    #[serde(serialize_with = "message")]
+    pub error: LoadError,
+}
+
+fn message<S: Serializer>(error: &LoadError, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(error)
+}
+
+impl LoadedFilter {
+    /// How long loading the script took, from reading it to registering its functions.
+    pub fn load_time(&self) -> Duration {
+        self.read_time + self.compile_time + self.eval_time
+    }
+
+    /// The ids of the filters of the script, one per function.
+    pub fn ids(&self) -> Vec<FilterId> {
+        let functions = self.functions.iter();
+        functions
+            .map(|function| FilterId {
+                chain: Some(self.chain.clone()),
+                config_name: Some(self.name.clone()),
+                function_name: function.clone(),
+            })
+            .collect()
+    }
+}
+
+impl LoadReport {
+    /// How many of the slowest scripts are displayed.
+    pub const SLOWEST: usize = 5;
+
+    /// Whether everything loaded.
+    pub fn is_complete(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    /// How long loading the scripts of each chain took, by chain.
+    pub fn chain_load_times(&self) -> BTreeMap<&str, Duration> {
+        let mut times = BTreeMap::new();
+        for loaded in &self.loaded {
+            *times.entry(loaded.chain.as_str()).or_default() += loaded.load_time();
+        }
+        times
+    }
+
+    /// The scripts that took the longest to load, slowest first.
+    pub fn slowest(&self, count: usize) -> Vec<&LoadedFilter> {
+        let mut loaded: Vec<&LoadedFilter> = self.loaded.iter().collect();
+        loaded.sort_by_key(|loaded| std::cmp::Reverse(loaded.load_time()));
+        loaded.truncate(count);
+        loaded
+    }
+}
+
+impl fmt::Display for LoadReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} filters loaded, {} failures",
+            self.loaded.len(),
+            self.failures.len()
+        )?;
+        let chain_times = self.chain_load_times();
+        let mut chain = None;
+        for loaded in &self.loaded {
+            if chain != Some(&loaded.chain) {
+                let time = chain_times[loaded.chain.as_str()];
+                write!(f, "\nchain `{}` (loaded in {time:?}):", loaded.chain)?;
+                chain = Some(&loaded.chain);
+            }
+            write!(
+                f,
+                "\n  {} `{}` ({} bytes, {}, read in {:?}, compiled in {:?}, evaluated in {:?}): {}",
+                loaded.script.display(),
+                loaded.name,
+                loaded.size,
+                loaded.origin,
+                loaded.read_time,
+                loaded.compile_time,
+                loaded.eval_time,
+                loaded.functions.join(", ")
+            )?;
+        }
+        if !self.loaded.is_empty() {
+            f.write_str("\nslowest scripts:")?;
+            for loaded in self.slowest(Self::SLOWEST) {
+                let (script, time) = (loaded.script.display(), loaded.load_time());
+                write!(f, "\n  {script} of chain `{}`: {time:?}", loaded.chain)?;
+            }
+        }
+        for failure in &self.failures {
+            f.write_str("\n- ")?;
+            if let (Some(name), Some(_)) = (&failure.name, &failure.chain) {
+                write!(f, "filter `{name}`: ")?;
+            }
+            write!(f, "{}", failure.error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for LoadReport {}
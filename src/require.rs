@@ -0,0 +1,163 @@
+//! A `require` that only loads modules from the configured library directories.
+//!
+//! `require("lib.addresses")` maps to `lib/addresses.lua` under each root in turn. Module names
+//! can't name absolute paths or climb out with `..`, and symlinks leading outside the root are
+//! refused too. Loaded modules are cached in `package.loaded`, like the stock loader does.
+
+use std::path::{Path, PathBuf};
+
+use mlua::{Lua, Table, Value};
+
+/// Replace the `require` global of `lua` with one resolving only within `roots`.
+pub(crate) fn install(lua: &Lua, roots: &[PathBuf]) -> mlua::Result<()> {
+    let roots = roots
+        .iter()
+        .map(|root| {
+            root.canonicalize().map_err(|err| {
+                mlua::Error::runtime(format!("require: library path {}: {err}", root.display()))
+            })
+        })
+        .collect::<mlua::Result<Vec<_>>>()?;
+
+    let loaded: Table = match lua.globals().get::<_, Value>("package")? {
+        Value::Table(package) => package.get("loaded")?,
+        _ => lua.create_table()?,
+    };
+    // Luau doesn't list its standard libraries there, so let the global tables stand in.
+    for pair in lua.globals().pairs::<Value, Value>() {
+        if let (name, module @ Value::Table(_)) = pair? {
+            if loaded.raw_get::<_, Value>(name.clone())?.is_nil() {
+                loaded.raw_set(name, module)?;
+            }
+        }
+    }
+    let loaded = lua.create_registry_value(loaded)?;
+
+    let require = lua.create_function(move |lua, name: String| {
+        let loaded: Table = lua.registry_value(&loaded)?;
+        let cached: Value = loaded.get(name.as_str())?;
+        if !cached.is_nil() {
+            return Ok(cached);
+        }
+
+        let path = resolve(&roots, &name)?;
+        let source = std::fs::read(&path)
+            .map_err(|err| mlua::Error::runtime(format!("require: {}: {err}", path.display())))?;
+        let module: Value = lua
+            .load(source)
+            .set_name(format!("@{}", path.display()))
+            .call(name.as_str())?;
+        let module = match module {
+            Value::Nil => Value::Boolean(true),
+            module => module,
+        };
+        loaded.set(name.as_str(), module.clone())?;
+        Ok(module)
+    })?;
+    lua.globals().set("require", require)
+}
+
+/// Find the file for module `name` in the first root that has it.
+fn resolve(roots: &[PathBuf], name: &str) -> mlua::Result<PathBuf> {
+    let segments: Vec<&str> = name.split('.').collect();
+    if segments
+        .iter()
+        .any(|segment| segment.is_empty() || segment.contains(['/', '\\']))
+    {
+        return Err(mlua::Error::runtime(format!(
+            "require: invalid module name '{name}' (tried to load a path outside the library paths)"
+        )));
+    }
+    let relative = Path::new(&segments.join("/")).with_extension("lua");
+
+    for root in roots {
+        let candidate = root.join(&relative);
+        if !candidate.is_file() {
+            continue;
+        }
+        let path = candidate.canonicalize().map_err(|err| {
+            mlua::Error::runtime(format!("require: {}: {err}", candidate.display()))
+        })?;
+        if !path.starts_with(root) {
+            return Err(mlua::Error::runtime(format!(
+                "require: {} is outside the library paths",
+                path.display()
+            )));
+        }
+        return Ok(path);
+    }
+
+    let tried: Vec<String> = roots
+        .iter()
+        .map(|root| root.join(&relative).display().to_string())
+        .collect();
+    Err(mlua::Error::runtime(format!(
+        "require: module '{name}' not found (tried {})",
+        tried.join(", ")
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn library(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!(
+            "croncat-indexer-filter-{name}-{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(root.join("lib")).unwrap();
+        fs::write(
+            root.join("lib/addresses.lua"),
+            "evaluations = (evaluations or 0) + 1\nreturn { manager = 'juno1manager' }\n",
+        )
+        .unwrap();
+        root
+    }
+
+    #[test]
+    fn modules_are_evaluated_once() {
+        let root = library("once");
+        let lua = Lua::new();
+        install(&lua, std::slice::from_ref(&root)).unwrap();
+
+        for _ in 0..2 {
+            let manager: String = lua
+                .load(r#"return require("lib.addresses").manager"#)
+                .eval()
+                .unwrap();
+            assert_eq!(manager, "juno1manager");
+        }
+        let evaluations: u32 = lua.globals().get("evaluations").unwrap();
+        assert_eq!(evaluations, 1);
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn paths_outside_the_roots_are_refused() {
+        let root = library("escape");
+        let lua = Lua::new();
+        install(&lua, &[root.join("lib")]).unwrap();
+
+        for name in ["../lib/addresses", "/etc/passwd", "..lib", "lib/addresses"] {
+            let err = lua
+                .load(format!("return require({name:?})"))
+                .exec()
+                .unwrap_err();
+            assert!(err.to_string().contains(name), "{err}");
+        }
+
+        let err = lua.load(r#"require("missing")"#).exec().unwrap_err();
+        assert!(err.to_string().contains("missing.lua"), "{err}");
+
+        // Standard libraries still resolve from the cache.
+        lua.load(r#"assert(require("string") == string)"#)
+            .exec()
+            .unwrap();
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
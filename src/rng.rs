@@ -0,0 +1,81 @@
+//! Seeding `math.random`, see [`FilterRuntime::seed_rng`](crate::FilterRuntime::seed_rng).
+//!
+//! The helpers have no random source of their own, so `math.random` is the only one to seed.
+//! The `math.randomseed` of the runtime is kept when seeding, so scripts replacing it don't
+//! affect reseeding.
+
+use mlua::{Function, Lua, RegistryKey};
+
+/// How the runtime's random number generator is seeded, in its app data.
+pub(crate) struct Seeding {
+    seed: u64,
+    per_batch: bool,
+    /// How many batches were reseeded since seeding.
+    batches: u64,
+    randomseed: RegistryKey,
+}
+
+/// Seed `math.random` with `seed`, starting the derived batch seeds over.
+pub(crate) fn seed(lua: &Lua, seed: u64) -> mlua::Result<()> {
+    let per_batch = match lua.remove_app_data::<Seeding>() {
+        Some(seeding) => {
+            lua.remove_registry_value(seeding.randomseed)?;
+            seeding.per_batch
+        }
+        None => false,
+    };
+    let randomseed: Function = lua
+        .globals()
+        .get::<_, mlua::Table>("math")?
+        .get("randomseed")?;
+    randomseed.call::<_, ()>(seed as i64)?;
+    lua.set_app_data(Seeding {
+        seed,
+        per_batch,
+        batches: 0,
+        randomseed: lua.create_registry_value(randomseed)?,
+    });
+    Ok(())
+}
+
+/// Reseed `math.random` at the start of every batch, or not.
+pub(crate) fn reseed_per_batch(lua: &Lua, enabled: bool) -> mlua::Result<()> {
+    if lua.app_data_ref::<Seeding>().is_none() {
+        if !enabled {
+            return Ok(());
+        }
+        seed(lua, 0)?;
+    }
+    if let Some(mut seeding) = lua.app_data_mut::<Seeding>() {
+        seeding.per_batch = enabled;
+    }
+    Ok(())
+}
+
+/// Reseed `math.random` for a new batch, if the runtime does.
+pub(crate) fn start_batch(lua: &Lua) -> mlua::Result<()> {
+    let (seed, randomseed) = {
+        let Some(mut seeding) = lua.app_data_mut::<Seeding>() else {
+            return Ok(());
+        };
+        if !seeding.per_batch {
+            return Ok(());
+        }
+        let seed = derive(seeding.seed, seeding.batches);
+        seeding.batches += 1;
+        (seed, lua.registry_value::<Function>(&seeding.randomseed)?)
+    };
+    randomseed.call(seed)
+}
+
+/// The seed of the `batch`th batch, on 31 bits so every dialect takes it whole.
+fn derive(seed: u64, batch: u64) -> i64 {
+    (splitmix64(seed ^ splitmix64(batch)) >> 33) as i64
+}
+
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
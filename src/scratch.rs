@@ -0,0 +1,401 @@
+//! Passing values in one reused table, see [`ValuePassing::ScratchTable`].
+//!
+//! The runtime keeps a single table for the top level of the values. Every value clears it,
+//! keeping its capacity, and fills it with its fields, so structs with many fields cost no
+//! table allocation or rehash per value. Nested values are converted as usual. Values
+//! that don't serialize as a struct or map get a table of their own.
+//!
+//! [`ValuePassing::ScratchTable`]: crate::ValuePassing::ScratchTable
+
+use std::marker::PhantomData;
+
+use mlua::{Lua, RegistryKey, Table, Value};
+use serde::ser::{self, Serialize, Serializer};
+
+use crate::convert;
+
+/// How many fields the scratch table has room for up front.
+const CAPACITY: usize = 32;
+
+/// The scratch table of a runtime, in its app data.
+struct Scratch(RegistryKey);
+
+pub(crate) fn install(lua: &Lua) -> mlua::Result<()> {
+    let table = lua.create_table_with_capacity(0, CAPACITY)?;
+    lua.set_app_data(Scratch(lua.create_registry_value(table)?));
+    Ok(())
+}
+
+/// Convert `value` into Lua, into the scratch table if it is a struct or map.
+///
+/// The table is only valid until the next conversion.
+pub(crate) fn to_lua<'lua, T: Serialize + ?Sized>(
+    lua: &'lua Lua,
+    value: &T,
+) -> mlua::Result<Value<'lua>> {
+    let table = match lua.app_data_ref::<Scratch>() {
+        Some(scratch) => lua.registry_value::<Table>(&scratch.0)?,
+        None => return convert::to_lua(lua, value),
+    };
+    clear(&table)?;
+    table.set_metatable(None);
+    match value.serialize(Fill {
+        lua,
+        table,
+        key: None,
+    })? {
+        Some(table) => Ok(Value::Table(table)),
+        None => convert::to_lua(lua, value),
+    }
+}
+
+/// Remove every entry of `table`, keeping the room they took.
+///
+/// `Table::clear` of mlua 0.9 leaves a stack slot behind on every call but under Luau.
+fn clear(table: &Table) -> mlua::Result<()> {
+    let keys = table
+        .clone()
+        .pairs::<Value, Value>()
+        .map(|pair| pair.map(|(key, _)| key))
+        .collect::<mlua::Result<Vec<_>>>()?;
+    for key in keys {
+        table.raw_set(key, Value::Nil)?;
+    }
+    Ok(())
+}
+
+/// Fills the scratch table with the entries of a struct or map, and gives up on anything else
+/// without converting it.
+struct Fill<'lua> {
+    lua: &'lua Lua,
+    table: Table<'lua>,
+    /// The key of the map entry whose value comes next.
+    key: Option<Value<'lua>>,
+}
+
+type Filled<'lua> = Option<Table<'lua>>;
+
+macro_rules! give_up {
+    ($($method:ident($($ty:ty),*);)*) => {
+        $(fn $method(self, $(_: $ty),*) -> mlua::Result<Filled<'lua>> {
+            Ok(None)
+        })*
+    };
+}
+
+impl<'lua> Serializer for Fill<'lua> {
+    type Ok = Filled<'lua>;
+    type Error = mlua::Error;
+    type SerializeSeq = Skip<'lua>;
+    type SerializeTuple = Skip<'lua>;
+    type SerializeTupleStruct = Skip<'lua>;
+    type SerializeTupleVariant = Skip<'lua>;
+    type SerializeMap = Self;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Skip<'lua>;
+
+    give_up! {
+        serialize_bool(bool);
+        serialize_i8(i8);
+        serialize_i16(i16);
+        serialize_i32(i32);
+        serialize_i64(i64);
+        serialize_i128(i128);
+        serialize_u8(u8);
+        serialize_u16(u16);
+        serialize_u32(u32);
+        serialize_u64(u64);
+        serialize_u128(u128);
+        serialize_f32(f32);
+        serialize_f64(f64);
+        serialize_char(char);
+        serialize_str(&str);
+        serialize_bytes(&[u8]);
+        serialize_none();
+        serialize_unit();
+        serialize_unit_struct(&'static str);
+        serialize_unit_variant(&'static str, u32, &'static str);
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> mlua::Result<Filled<'lua>> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> mlua::Result<Filled<'lua>> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> mlua::Result<Filled<'lua>> {
+        Ok(None)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> mlua::Result<Skip<'lua>> {
+        Ok(Skip(PhantomData))
+    }
+
+    fn serialize_tuple(self, _len: usize) -> mlua::Result<Skip<'lua>> {
+        Ok(Skip(PhantomData))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> mlua::Result<Skip<'lua>> {
+        Ok(Skip(PhantomData))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> mlua::Result<Skip<'lua>> {
+        Ok(Skip(PhantomData))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> mlua::Result<Self> {
+        Ok(self)
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> mlua::Result<Self> {
+        Ok(self)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> mlua::Result<Skip<'lua>> {
+        Ok(Skip(PhantomData))
+    }
+}
+
+impl<'lua> ser::SerializeMap for Fill<'lua> {
+    type Ok = Filled<'lua>;
+    type Error = mlua::Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> mlua::Result<()> {
+        self.key = Some(convert::nested_to_lua(self.lua, key, 1)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> mlua::Result<()> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| mlua::Error::runtime("scratch: map value serialized before its key"))?;
+        self.table
+            .raw_set(key, convert::nested_to_lua(self.lua, value, 1)?)
+    }
+
+    fn end(self) -> mlua::Result<Filled<'lua>> {
+        Ok(Some(self.table))
+    }
+}
+
+impl<'lua> ser::SerializeStruct for Fill<'lua> {
+    type Ok = Filled<'lua>;
+    type Error = mlua::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> mlua::Result<()> {
+        self.table
+            .raw_set(key, convert::nested_to_lua(self.lua, value, 1)?)
+    }
+
+    fn end(self) -> mlua::Result<Filled<'lua>> {
+        Ok(Some(self.table))
+    }
+}
+
+/// Ignores the elements of a value the scratch table doesn't take.
+struct Skip<'lua>(PhantomData<Table<'lua>>);
+
+macro_rules! skip {
+    ($($trait:ident::$method:ident;)*) => {
+        $(impl<'lua> ser::$trait for Skip<'lua> {
+            type Ok = Filled<'lua>;
+            type Error = mlua::Error;
+
+            fn $method<T: Serialize + ?Sized>(&mut self, _value: &T) -> mlua::Result<()> {
+                Ok(())
+            }
+
+            fn end(self) -> mlua::Result<Filled<'lua>> {
+                Ok(None)
+            }
+        })*
+    };
+}
+
+skip! {
+    SerializeSeq::serialize_element;
+    SerializeTuple::serialize_element;
+    SerializeTupleStruct::serialize_field;
+    SerializeTupleVariant::serialize_field;
+}
+
+impl<'lua> ser::SerializeStructVariant for Skip<'lua> {
+    type Ok = Filled<'lua>;
+    type Error = mlua::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        _key: &'static str,
+        _value: &T,
+    ) -> mlua::Result<()> {
+        Ok(())
+    }
+
+    fn end(self) -> mlua::Result<Filled<'lua>> {
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use mlua::LuaSerdeExt;
+    use serde::Serialize;
+
+    use super::*;
+    use crate::convert::ValueConversion;
+
+    #[derive(Serialize)]
+    struct Tx {
+        from: String,
+        amount: u64,
+        memo: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        fee: Option<u64>,
+        coins: Vec<u64>,
+        tags: BTreeMap<String, String>,
+    }
+
+    #[derive(Serialize)]
+    enum Wrapped {
+        Tx(Tx),
+        Fee { amount: u64 },
+    }
+
+    fn tx(i: u64) -> Tx {
+        Tx {
+            from: format!("juno1sender{i}"),
+            amount: i,
+            memo: None,
+            fee: (i == 0).then_some(i),
+            coins: vec![i, i + 1],
+            tags: [("kind".to_string(), "send".to_string())].into(),
+        }
+    }
+
+    fn json(lua: &Lua, value: Value) -> serde_json::Value {
+        lua.from_value(value).unwrap()
+    }
+
+    #[test]
+    fn same_values_as_convert() {
+        let lua = Lua::new();
+        lua.set_app_data(ValueConversion {
+            big_integers_as_strings: true,
+            ..Default::default()
+        });
+        install(&lua).unwrap();
+
+        let map: BTreeMap<u64, u64> = [(1, u64::MAX), (2, 2)].into();
+        let values = [
+            json(&lua, convert::to_lua(&lua, &tx(0)).unwrap()),
+            json(&lua, convert::to_lua(&lua, &tx(1)).unwrap()),
+            json(&lua, convert::to_lua(&lua, &map).unwrap()),
+            json(&lua, convert::to_lua(&lua, &Wrapped::Tx(tx(2))).unwrap()),
+            json(
+                &lua,
+                convert::to_lua(&lua, &Wrapped::Fee { amount: 1 }).unwrap(),
+            ),
+            json(&lua, convert::to_lua(&lua, &[1, 2, 3]).unwrap()),
+            json(&lua, convert::to_lua(&lua, "juno1sender").unwrap()),
+        ];
+        // Only the first value has a `fee`, which mustn't linger in the second.
+        let scratch = [
+            json(&lua, to_lua(&lua, &tx(0)).unwrap()),
+            json(&lua, to_lua(&lua, &tx(1)).unwrap()),
+            json(&lua, to_lua(&lua, &map).unwrap()),
+            json(&lua, to_lua(&lua, &Wrapped::Tx(tx(2))).unwrap()),
+            json(&lua, to_lua(&lua, &Wrapped::Fee { amount: 1 }).unwrap()),
+            json(&lua, to_lua(&lua, &[1, 2, 3]).unwrap()),
+            json(&lua, to_lua(&lua, "juno1sender").unwrap()),
+        ];
+        assert_eq!(scratch, values);
+
+        let first = to_lua(&lua, &tx(0)).unwrap();
+        let second = to_lua(&lua, &tx(1)).unwrap();
+        assert_eq!(first.to_pointer(), second.to_pointer());
+    }
+
+    #[test]
+    fn fewer_allocations() {
+        #[derive(Serialize)]
+        struct Wide {
+            a: u32,
+            b: u32,
+            c: u32,
+            d: u32,
+            e: u32,
+            f: u32,
+            g: u32,
+            h: u32,
+            i: u32,
+            j: u32,
+            k: u32,
+            l: u32,
+        }
+
+        // With the collector stopped, the memory used grows by what the batch allocates.
+        let allocated = |scratch: bool| {
+            let lua = Lua::new();
+            install(&lua).unwrap();
+            let convert = |value: &Wide| match scratch {
+                true => to_lua(&lua, value).map(|_| ()),
+                false => convert::to_lua(&lua, value).map(|_| ()),
+            };
+            let wide = |x| Wide {
+                a: x,
+                b: x,
+                c: x,
+                d: x,
+                e: x,
+                f: x,
+                g: x,
+                h: x,
+                i: x,
+                j: x,
+                k: x,
+                l: x,
+            };
+            convert(&wide(0)).unwrap();
+            lua.gc_collect().unwrap();
+            lua.gc_stop();
+            let before = lua.used_memory();
+            for x in 0..10_000 {
+                convert(&wide(x)).unwrap();
+            }
+            lua.used_memory() - before
+        };
+        let (plain, scratch) = (allocated(false), allocated(true));
+        assert!(scratch * 10 < plain, "{scratch} vs {plain}");
+    }
+}
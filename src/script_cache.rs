@@ -0,0 +1,272 @@
+//! An on-disk cache of compiled filter scripts, see [`RuntimeOptions::script_cache`].
+//!
+//! Entries are named after a hash of the script source and start with a header naming the Lua
+//! dialect that compiled them and a checksum of the bytecode. An entry that doesn't match, or
+//! that Lua refuses, is ignored and replaced; the cache never fails a load on its own.
+//!
+//! [`RuntimeOptions::script_cache`]: crate::RuntimeOptions::script_cache
+
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use mlua::{ChunkMode, Function, Lua, Table};
+
+use crate::LuaVersion;
+
+/// Starts every cache entry.
+const MAGIC: &[u8; 8] = b"CIFLUAC\x01";
+
+/// Tells temporary files of concurrent writers apart.
+static WRITES: AtomicUsize = AtomicUsize::new(0);
+
+/// The cache directory of a runtime, in its app data.
+#[derive(Clone)]
+pub(crate) struct ScriptCache {
+    dir: PathBuf,
+}
+
+impl ScriptCache {
+    pub(crate) fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// Load `source` as a chunk named `name`, from its cache entry if it has a usable one,
+    /// telling whether it did.
+    ///
+    /// Like [`mlua::Chunk::eval`], a source that parses as an expression is compiled as one.
+    pub(crate) fn load<'lua>(
+        &self,
+        lua: &'lua Lua,
+        name: &str,
+        source: &str,
+        environment: Option<Table<'lua>>,
+    ) -> mlua::Result<(Function<'lua>, bool)> {
+        let path = self.path(source);
+        if let Some(bytecode) = read(&path, source) {
+            if let Ok(function) = load_bytecode(lua, name, &bytecode, environment.clone()) {
+                return Ok((function, true));
+            }
+        }
+        let bytecode = compile(lua, name, source)?;
+        // Bytecode that fails to load, such as a failed Luau compilation, isn't cached.
+        let function = load_bytecode(lua, name, &bytecode, environment)?;
+        // A cache that can't be written only costs the next start its compilation.
+        let _ = write(&path, source, &bytecode);
+        Ok((function, false))
+    }
+
+    /// The bytecode [`load`](Self::load) would load `source` from, checked against `lua`.
+    ///
+    /// Scripts compiled ahead of loading go through this on another runtime, so the cache is
+    /// read and written the same way.
+    pub(crate) fn bytecode(&self, lua: &Lua, name: &str, source: &str) -> mlua::Result<Vec<u8>> {
+        let path = self.path(source);
+        if let Some(bytecode) = read(&path, source) {
+            if load_bytecode(lua, name, &bytecode, None).is_ok() {
+                return Ok(bytecode);
+            }
+        }
+        let bytecode = compile(lua, name, source)?;
+        load_bytecode(lua, name, &bytecode, None)?;
+        let _ = write(&path, source, &bytecode);
+        Ok(bytecode)
+    }
+
+    /// Where the entry for `source` goes.
+    fn path(&self, source: &str) -> PathBuf {
+        self.dir
+            .join(format!("{:016x}.luac", fnv1a(source.as_bytes())))
+    }
+}
+
+/// The dialect and crate release an entry must come from.
+fn tag() -> String {
+    format!("{:?} {}", LuaVersion::compiled(), env!("CARGO_PKG_VERSION"))
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// The header of the entry for `source`, up to the bytecode checksum.
+fn header(source: &str) -> Vec<u8> {
+    let tag = tag();
+    let mut header = MAGIC.to_vec();
+    header.extend((tag.len() as u32).to_le_bytes());
+    header.extend(tag.as_bytes());
+    header.extend((source.len() as u64).to_le_bytes());
+    header
+}
+
+/// The bytecode of the entry at `path`, if it exists and was written for `source` by this build.
+fn read(path: &Path, source: &str) -> Option<Vec<u8>> {
+    let entry = std::fs::read(path).ok()?;
+    let rest = entry.strip_prefix(header(source).as_slice())?;
+    let (checksum, bytecode) = rest.split_first_chunk::<8>()?;
+    (u64::from_le_bytes(*checksum) == fnv1a(bytecode)).then(|| bytecode.to_vec())
+}
+
+/// Write the entry for `source` at `path`, through a temporary file so readers never see half
+/// of it.
+fn write(path: &Path, source: &str, bytecode: &[u8]) -> std::io::Result<()> {
+    let mut entry = header(source);
+    entry.extend(fnv1a(bytecode).to_le_bytes());
+    entry.extend(bytecode);
+    std::fs::create_dir_all(path.parent().unwrap_or(Path::new(".")))?;
+    let temporary = path.with_extension(format!(
+        "{}.{}.tmp",
+        std::process::id(),
+        WRITES.fetch_add(1, Ordering::Relaxed)
+    ));
+    std::fs::write(&temporary, entry)?;
+    std::fs::rename(&temporary, path).inspect_err(|_| {
+        let _ = std::fs::remove_file(&temporary);
+    })
+}
+
+/// Load `bytecode` as a chunk named `name`, run in `environment` if there is one.
+pub(crate) fn load_bytecode<'lua>(
+    lua: &'lua Lua,
+    name: &str,
+    bytecode: &[u8],
+    environment: Option<Table<'lua>>,
+) -> mlua::Result<Function<'lua>> {
+    let mut chunk = lua
+        .load(bytecode)
+        .set_name(name)
+        .set_mode(ChunkMode::Binary);
+    if let Some(environment) = environment {
+        chunk = chunk.set_environment(environment);
+    }
+    chunk.into_function()
+}
+
+/// Compile `source` to bytecode, reporting syntax errors like a source load would.
+#[cfg(not(feature = "luau"))]
+pub(crate) fn compile(lua: &Lua, name: &str, source: &str) -> mlua::Result<Vec<u8>> {
+    let text = |source: &str| {
+        lua.load(source.to_string())
+            .set_name(name)
+            .set_mode(ChunkMode::Text)
+            .into_function()
+    };
+    let function = match text(&format!("return {source}")) {
+        Ok(function) => function,
+        Err(_) => text(source)?,
+    };
+    Ok(function.dump(false))
+}
+
+/// Compile `source` to bytecode. Syntax errors come out when the bytecode is loaded.
+#[cfg(feature = "luau")]
+pub(crate) fn compile(_lua: &Lua, _name: &str, source: &str) -> mlua::Result<Vec<u8>> {
+    let compiler = mlua::Compiler::new();
+    // Failed compilations produce a zero byte followed by the message.
+    let expression = compiler.compile(format!("return {source}"));
+    if expression.first() != Some(&0) {
+        return Ok(expression);
+    }
+    Ok(compiler.compile(source))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "croncat-indexer-filter-script-cache-{name}-{}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn entries(dir: &Path) -> Vec<PathBuf> {
+        std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect()
+    }
+
+    const SOURCE: &str = "return { answer = function() return 42 end }";
+
+    fn answer(lua: &Lua, cache: &ScriptCache, source: &str) -> i64 {
+        let module: Table = cache
+            .load(lua, "=test", source, None)
+            .unwrap()
+            .0
+            .call(())
+            .unwrap();
+        module
+            .get::<_, Function>("answer")
+            .unwrap()
+            .call(())
+            .unwrap()
+    }
+
+    #[test]
+    fn cold_and_warm() {
+        let dir = cache_dir("warm");
+        let cache = ScriptCache::new(dir.clone());
+        let lua = Lua::new();
+        assert_eq!(answer(&lua, &cache, SOURCE), 42);
+        let entries = entries(&dir);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].extension().unwrap(), "luac");
+
+        // Swap in bytecode of another script to tell the entry is what gets loaded.
+        let other = compile(&lua, "=test", "return { answer = function() return 7 end }");
+        write(&entries[0], SOURCE, &other.unwrap()).unwrap();
+        assert_eq!(answer(&Lua::new(), &cache, SOURCE), 7);
+
+        // Expressions compile like `eval` would take them.
+        assert_eq!(
+            answer(&lua, &cache, "{ answer = function() return 1 end }"),
+            1
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn poisoned_entries() {
+        let dir = cache_dir("poisoned");
+        let cache = ScriptCache::new(dir.clone());
+        let lua = Lua::new();
+        answer(&lua, &cache, SOURCE);
+        let path = entries(&dir).remove(0);
+        let valid = std::fs::read(&path).unwrap();
+
+        let mut flipped = valid.clone();
+        *flipped.last_mut().unwrap() ^= 0xff;
+        let mut other_version = valid.clone();
+        other_version[MAGIC.len() + 4] ^= 0xff;
+        // Well-formed entries with garbage for bytecode get refused by Lua itself.
+        let mut garbage = header(SOURCE);
+        garbage.extend(fnv1a(b"garbage").to_le_bytes());
+        garbage.extend(b"garbage");
+        for poison in [
+            b"not an entry".to_vec(),
+            valid[..valid.len() / 2].to_vec(),
+            flipped,
+            other_version,
+            garbage,
+        ] {
+            std::fs::write(&path, poison).unwrap();
+            assert_eq!(answer(&Lua::new(), &cache, SOURCE), 42);
+            assert_eq!(std::fs::read(&path).unwrap(), valid);
+        }
+
+        // Syntax errors still fail the load, and aren't cached.
+        let err = cache.load(&lua, "=broken", "return {", None).unwrap_err();
+        assert!(matches!(err, mlua::Error::SyntaxError { .. }), "{err}");
+        assert_eq!(entries(&dir).len(), 1);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
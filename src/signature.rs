@@ -0,0 +1,75 @@
+//! Verifying the Ed25519 signatures of filter scripts, see [`Config::trusted_keys`].
+//!
+//! Keys and signatures are base64, like in configurations. A key is known in errors by its
+//! fingerprint, the hex of its first 8 bytes.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ed25519_dalek::{Signature, VerifyingKey};
+
+use crate::{Config, ConfigError, FilterConfig, SignatureProblem};
+
+/// The keys of `config`, checked to be Ed25519 public keys.
+pub(crate) fn trusted_keys(config: &Config) -> Result<Vec<VerifyingKey>, ConfigError> {
+    let keys = config.trusted_keys.iter().map(|key| {
+        let bytes = STANDARD.decode(key).map_err(|err| err.to_string())?;
+        let bytes = <[u8; 32]>::try_from(bytes)
+            .map_err(|bytes| format!("it is {} bytes long rather than 32", bytes.len()))?;
+        VerifyingKey::from_bytes(&bytes).map_err(|err| err.to_string())
+    });
+    let keys = keys
+        .enumerate()
+        .map(|(index, key)| key.map_err(|message| ConfigError::TrustedKey { index, message }));
+    keys.collect()
+}
+
+/// Check `source`, the script of `filter`, is signed by one of `keys`, if there are any.
+pub(crate) fn verify(
+    keys: &[VerifyingKey],
+    filter: &FilterConfig,
+    source: &[u8],
+) -> Result<(), SignatureProblem> {
+    if keys.is_empty() {
+        return Ok(());
+    }
+    let signature = filter
+        .signature
+        .as_ref()
+        .ok_or(SignatureProblem::Unsigned)?;
+    let signature = STANDARD
+        .decode(signature)
+        .ok()
+        .and_then(|signature| Signature::from_slice(&signature).ok())
+        .ok_or(SignatureProblem::Malformed)?;
+    let mut keys = keys.iter();
+    match keys.any(|key| key.verify_strict(source, &signature).is_ok()) {
+        true => Ok(()),
+        false => Err(SignatureProblem::Untrusted),
+    }
+}
+
+/// The fingerprints of `keys`, to tell which were tried.
+pub(crate) fn fingerprints(keys: &[VerifyingKey]) -> Vec<String> {
+    keys.iter()
+        .map(|key| hex::encode(&key.as_bytes()[..8]))
+        .collect()
+}
+
+/// `keys` in base64, as in [`Config::trusted_keys`].
+pub(crate) fn encode(keys: &[VerifyingKey]) -> Vec<String> {
+    keys.iter()
+        .map(|key| STANDARD.encode(key.as_bytes()))
+        .collect()
+}
+
+/// Sign a script with the Ed25519 secret key `secret_key`, giving the base64 signature to put
+/// in its [`FilterConfig::signature`].
+///
+/// `script` is the content of the script file, UTF-8 without a byte order mark for Lua, and the public key to trust is
+/// `ed25519_dalek::SigningKey::from_bytes(secret_key).verifying_key()`, in base64.
+#[cfg(feature = "signing")]
+pub fn sign_script(secret_key: &[u8; 32], script: &[u8]) -> String {
+    use ed25519_dalek::{Signer, SigningKey};
+
+    let signature = SigningKey::from_bytes(secret_key).sign(script);
+    STANDARD.encode(signature.to_bytes())
+}
@@ -0,0 +1,189 @@
+//! Counters describing how filters behaved.
+
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::FilterId;
+
+/// Counters for a single filter.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct FilterStats {
+    /// The name of the filter.
+    pub name: String,
+    /// The fully qualified name of the filter.
+    pub id: FilterId,
+    /// Number of values the filter was called with.
+    pub invocations: u64,
+    /// Number of values the filter matched.
+    pub matches: u64,
+    /// Number of values the filter failed on, once its retries were used up.
+    pub errors: u64,
+    /// Number of extra attempts made after transient errors.
+    pub retries: u64,
+    /// Number of verdicts that weren't booleans, taken by Lua truthiness, see
+    /// [`FilterSystem::strict_returns`](crate::FilterSystem::strict_returns).
+    pub coerced_returns: u64,
+    /// Number of `env` lookups of variables the configuration doesn't expose.
+    pub env_denied: u64,
+    /// Number of `memo.cache` lookups answered from the cache.
+    pub memo_hits: u64,
+    /// Number of `memo.cache` lookups that had to compute the value.
+    pub memo_misses: u64,
+    /// The largest growth of the runtime's memory during a single call, in bytes.
+    ///
+    /// Sampled before and after each call, so garbage collected meanwhile hides growth and
+    /// memory allocated by other code running then is counted.
+    pub peak_delta_bytes: u64,
+    /// The fuel the filter burnt, when the filter system meters it, see
+    /// [`FilterSystem::meter_fuel`](crate::FilterSystem::meter_fuel).
+    pub fuel: u64,
+    /// Number of values the filter gave no verdict on because its fuel budget, or the batch's,
+    /// ran out, cut off calls included.
+    pub budget_exhausted: u64,
+    /// An estimate of the memory held by the filter's script state, in bytes, see
+    /// [`Filter::with_state`](crate::Filter::with_state).
+    pub state_bytes: u64,
+    /// Whether the filter runs in dry-run mode, so its matches don't keep values, see
+    /// [`Filter::with_dry_run`](crate::Filter::with_dry_run).
+    pub dry_run: bool,
+    /// Whether the filter is quarantined, skipped for failing too often, see
+    /// [`FilterSystem::set_quarantine`](crate::FilterSystem::set_quarantine).
+    pub quarantined: bool,
+    /// Number of times the filter was quarantined.
+    pub quarantines: u64,
+}
+
+/// Counters for a chain, see [`FilterSystem::chain_stats`](crate::FilterSystem::chain_stats).
+///
+/// Its fields, and the names they are serialized under, are stable: dashboards can rely on
+/// them, and fields will only be added.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct ChainStats {
+    /// The name of the chain, empty for filters that didn't come from a configuration.
+    pub chain: String,
+    /// The counters since the chain was last loaded.
+    pub since_load: ChainCounters,
+    /// The counters since they were last reset, or since the filter system was created.
+    pub since_reset: ChainCounters,
+}
+
+/// What the filters of a chain made of the values they were called with.
+///
+/// A value is counted once per chain: errored if any filter of the chain failed on it, kept
+/// if not and any matched it, and rejected otherwise. Dry-run filters don't keep values.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct ChainCounters {
+    /// Number of values the filters of the chain were called with.
+    pub values: u64,
+    pub kept: u64,
+    pub rejected: u64,
+    pub errored: u64,
+    /// Number of batches, such as [`filter`](crate::FilterSystem::filter) calls, the chain
+    /// filtered values in.
+    pub batches: u64,
+    /// The average time the filters of the chain took over a batch, serialized as seconds.
+    #[serde(serialize_with = "crate::report::seconds")]
+    pub average_batch_latency: Duration,
+}
+
+/// How the filters of a chain did on a value.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct ChainOutcome {
+    pub kept: bool,
+    pub errored: bool,
+    pub elapsed: Duration,
+}
+
+/// The counters of a chain, as filtering goes.
+#[derive(Clone, Default)]
+pub(crate) struct ChainTally {
+    since_load: Counts,
+    since_reset: Counts,
+    /// The time the chain took in the batch under way.
+    batch_latency: Duration,
+    /// Whether the chain filtered values in the batch under way.
+    in_batch: bool,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Counts {
+    values: u64,
+    kept: u64,
+    rejected: u64,
+    errored: u64,
+    batches: u64,
+    latency: Duration,
+}
+
+impl Counts {
+    fn counters(mut self, pending: Option<Duration>) -> ChainCounters {
+        if let Some(latency) = pending {
+            self.batches += 1;
+            self.latency += latency;
+        }
+        ChainCounters {
+            values: self.values,
+            kept: self.kept,
+            rejected: self.rejected,
+            errored: self.errored,
+            batches: self.batches,
+            average_batch_latency: match self.batches {
+                0 => Duration::ZERO,
+                batches => self.latency.div_f64(batches as f64),
+            },
+        }
+    }
+}
+
+impl ChainTally {
+    /// Count a value the chain filtered.
+    pub(crate) fn value(&mut self, outcome: ChainOutcome) {
+        for counts in [&mut self.since_load, &mut self.since_reset] {
+            counts.values += 1;
+            if outcome.errored {
+                counts.errored += 1;
+            } else if outcome.kept {
+                counts.kept += 1;
+            } else {
+                counts.rejected += 1;
+            }
+        }
+        self.batch_latency += outcome.elapsed;
+        self.in_batch = true;
+    }
+
+    /// Close the batch under way, if the chain filtered values in it.
+    pub(crate) fn close_batch(&mut self) {
+        if !std::mem::take(&mut self.in_batch) {
+            return;
+        }
+        let latency = std::mem::take(&mut self.batch_latency);
+        for counts in [&mut self.since_load, &mut self.since_reset] {
+            counts.batches += 1;
+            counts.latency += latency;
+        }
+    }
+
+    /// Forget the counters since load, the chain having been loaded again.
+    pub(crate) fn loaded(&mut self) {
+        self.close_batch();
+        self.since_load = Counts::default();
+    }
+
+    /// Forget the counters since the last reset.
+    pub(crate) fn reset(&mut self) {
+        self.close_batch();
+        self.since_reset = Counts::default();
+    }
+
+    /// The counters of `chain`, the batch under way counting as a whole one.
+    pub(crate) fn stats(&self, chain: &str) -> ChainStats {
+        let pending = self.in_batch.then_some(self.batch_latency);
+        ChainStats {
+            chain: chain.to_string(),
+            since_load: self.since_load.counters(pending),
+            since_reset: self.since_reset.counters(pending),
+        }
+    }
+}
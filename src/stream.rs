@@ -0,0 +1,143 @@
+//! Filtering streams, with the `stream` feature.
+//!
+//! A [`FilterSystem`] borrows its Lua runtime, which isn't `Send`, so the adapters aren't
+//! either: they are polled on the thread owning the runtime, such as in a current-thread
+//! tokio runtime or a `LocalSet`, rather than spawned on a multi-threaded one. Each value is
+//! evaluated as a batch of its own, like [`FilterSystem::filter_one`].
+
+use std::{
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use futures_core::Stream;
+use mlua::prelude::LuaUserData;
+use pin_project_lite::pin_project;
+use serde::Serialize;
+
+use crate::{FilterError, FilterSystem};
+
+/// Adapters filtering the values of a stream through a [`FilterSystem`].
+///
+/// The streams they return yield the values that matched, and the errors of the values that
+/// failed under [`ErrorPolicy::FailFast`](crate::ErrorPolicy::FailFast), carrying on after
+/// them; stop at the first one with `take_while` or `try_collect`.
+pub trait FilteredStreamExt: Stream + Sized {
+    /// Keep the values any filter of `system` matches.
+    fn filter_with<'s, 'lua>(
+        self,
+        system: &'s FilterSystem<'lua, Self::Item>,
+    ) -> FilterWith<'s, 'lua, Self, Self::Item>
+    where
+        Self::Item: LuaUserData + Serialize + Send + Sync,
+    {
+        FilterWith {
+            stream: self,
+            system,
+        }
+    }
+
+    /// Turn items into values with `f`, skipping the items it gives none for, and keep the
+    /// values any filter of `system` matches.
+    fn filter_map_with<'s, 'lua, T, F>(
+        self,
+        system: &'s FilterSystem<'lua, T>,
+        f: F,
+    ) -> FilterMapWith<'s, 'lua, Self, T, F>
+    where
+        T: LuaUserData + Serialize + Send + Sync,
+        F: FnMut(Self::Item) -> Option<T>,
+    {
+        FilterMapWith {
+            stream: self,
+            system,
+            f,
+        }
+    }
+}
+
+impl<S: Stream> FilteredStreamExt for S {}
+
+pin_project! {
+    /// The stream of [`FilteredStreamExt::filter_with`].
+    #[must_use = "streams do nothing unless polled"]
+    pub struct FilterWith<'s, 'lua, S, T> {
+        #[pin]
+        stream: S,
+        system: &'s FilterSystem<'lua, T>,
+    }
+}
+
+impl<'lua, S, T> Stream for FilterWith<'_, 'lua, S, T>
+where
+    S: Stream<Item = T>,
+    T: LuaUserData + Serialize + Send + Sync + 'lua,
+{
+    type Item = Result<T, FilterError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        while let Some(value) = ready!(this.stream.as_mut().poll_next(cx)) {
+            if let Some(kept) = keep(this.system, value) {
+                return Poll::Ready(Some(kept));
+            }
+        }
+        Poll::Ready(None)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.stream.size_hint().1)
+    }
+}
+
+pin_project! {
+    /// The stream of [`FilteredStreamExt::filter_map_with`].
+    #[must_use = "streams do nothing unless polled"]
+    pub struct FilterMapWith<'s, 'lua, S, T, F> {
+        #[pin]
+        stream: S,
+        system: &'s FilterSystem<'lua, T>,
+        f: F,
+    }
+}
+
+impl<'lua, S, T, F> Stream for FilterMapWith<'_, 'lua, S, T, F>
+where
+    S: Stream,
+    T: LuaUserData + Serialize + Send + Sync + 'lua,
+    F: FnMut(S::Item) -> Option<T>,
+{
+    type Item = Result<T, FilterError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        while let Some(item) = ready!(this.stream.as_mut().poll_next(cx)) {
+            let Some(value) = (this.f)(item) else {
+                continue;
+            };
+            if let Some(kept) = keep(this.system, value) {
+                return Poll::Ready(Some(kept));
+            }
+        }
+        Poll::Ready(None)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.stream.size_hint().1)
+    }
+}
+
+/// What the stream yields for `value`: the value if it matched, the error if it failed.
+fn keep<'lua, T>(system: &FilterSystem<'lua, T>, value: T) -> Option<Result<T, FilterError>>
+where
+    T: LuaUserData + Serialize + Send + Sync + 'lua,
+{
+    if let Err(err) = system.start_batch() {
+        return Some(Err(err));
+    }
+    match system.evaluate(&value) {
+        Ok(true) => Some(Ok(value)),
+        Ok(false) => None,
+        Err(err) => Some(Err(err)),
+    }
+}
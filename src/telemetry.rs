@@ -0,0 +1,111 @@
+//! Metrics of filter calls, reported through the [`metrics`](https://docs.rs/metrics) facade
+//! with the `metrics` feature, to whatever recorder the host installed, such as a Prometheus
+//! exporter.
+//!
+//! Every filter reports `filter_invocations_total`, `filter_matches_total` and
+//! `filter_errors_total` counters and a `filter_call_duration_seconds` histogram, labeled by
+//! `filter` and `chain`, the latter empty for filters that didn't come from a configuration.
+//! The labels only take the names of loaded filters and chains, so there are as many series
+//! as filters. A filter registers its metrics on its first call; calls that ran out of fuel
+//! count as invocations, not errors.
+//!
+//! Without the feature, none of this is compiled in and filters carry nothing for it.
+
+#[cfg(feature = "metrics")]
+pub(crate) use enabled::CallMetrics;
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) use disabled::CallMetrics;
+
+#[cfg(feature = "metrics")]
+mod enabled {
+    use std::{cell::OnceCell, sync::Once, time::Instant};
+
+    use metrics::{Counter, Histogram, Unit};
+
+    use crate::{watchdog::Trip, FilterError};
+
+    /// The metrics of a filter, registered on its first call.
+    #[derive(Default)]
+    pub(crate) struct CallMetrics(OnceCell<Handles>);
+
+    struct Handles {
+        invocations: Counter,
+        matches: Counter,
+        errors: Counter,
+        duration: Histogram,
+    }
+
+    impl CallMetrics {
+        /// Run the call `call` of the filter `filter` of `chain`, reporting it.
+        pub(crate) fn measure<V>(
+            &self,
+            filter: &str,
+            chain: Option<&str>,
+            call: impl FnOnce() -> Result<(bool, V), FilterError>,
+        ) -> Result<(bool, V), FilterError> {
+            let started = Instant::now();
+            let result = call();
+            let handles = self
+                .0
+                .get_or_init(|| register(filter, chain.unwrap_or_default()));
+            handles.duration.record(started.elapsed());
+            handles.invocations.increment(1);
+            match &result {
+                Ok((true, _)) => handles.matches.increment(1),
+                Ok((false, _)) => {}
+                Err(err) if err.trip() == Some(Trip::OutOfFuel) => {}
+                Err(_) => handles.errors.increment(1),
+            }
+            result
+        }
+    }
+
+    fn register(filter: &str, chain: &str) -> Handles {
+        static DESCRIBE: Once = Once::new();
+        DESCRIBE.call_once(|| {
+            metrics::describe_counter!(
+                "filter_invocations_total",
+                "Number of values filters were called with."
+            );
+            metrics::describe_counter!("filter_matches_total", "Number of values filters matched.");
+            metrics::describe_counter!(
+                "filter_errors_total",
+                "Number of values filters failed on, once their retries were used up."
+            );
+            metrics::describe_histogram!(
+                "filter_call_duration_seconds",
+                Unit::Seconds,
+                "How long filter calls took, retries included."
+            );
+        });
+        let labels = [("filter", filter.to_string()), ("chain", chain.to_string())];
+        Handles {
+            invocations: metrics::counter!("filter_invocations_total", &labels),
+            matches: metrics::counter!("filter_matches_total", &labels),
+            errors: metrics::counter!("filter_errors_total", &labels),
+            duration: metrics::histogram!("filter_call_duration_seconds", &labels),
+        }
+    }
+}
+
+#[cfg(not(feature = "metrics"))]
+mod disabled {
+    use crate::FilterError;
+
+    /// Nothing, without the `metrics` feature.
+    #[derive(Default)]
+    pub(crate) struct CallMetrics {}
+
+    impl CallMetrics {
+        #[inline(always)]
+        pub(crate) fn measure<V>(
+            &self,
+            _filter: &str,
+            _chain: Option<&str>,
+            call: impl FnOnce() -> Result<(bool, V), FilterError>,
+        ) -> Result<(bool, V), FilterError> {
+            call()
+        }
+    }
+}
@@ -0,0 +1,102 @@
+//! Test cases for filter scripts, run against the loaded filters like values being filtered.
+
+use std::{collections::BTreeSet, fmt};
+
+use crate::FilterError;
+
+/// A value to run through the filters, and what they should make of it.
+pub struct TestCase<T> {
+    /// The name the case is reported under.
+    pub name: String,
+    pub input: T,
+    /// The filters the value runs through.
+    pub target: TestTarget,
+    pub expected: Outcome,
+}
+
+/// The filters a test case runs through.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum TestTarget {
+    /// Every loaded filter.
+    #[default]
+    All,
+    /// The filters of this name.
+    Filter(String),
+    /// The filters loaded for this chain.
+    Chain(String),
+}
+
+/// What filters made of a value.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// Whether the value was kept.
+    Matched(bool),
+    /// The names of the filters that matched, dry-run filters included.
+    MatchedBy(BTreeSet<String>),
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Matched(true) => f.write_str("kept"),
+            Outcome::Matched(false) => f.write_str("dropped"),
+            Outcome::MatchedBy(names) if names.is_empty() => f.write_str("matched by none"),
+            Outcome::MatchedBy(names) => {
+                let names: Vec<String> = names.iter().map(|name| format!("`{name}`")).collect();
+                write!(f, "matched by {}", names.join(", "))
+            }
+        }
+    }
+}
+
+/// The outcome of [`FilterSystem::run_tests`](crate::FilterSystem::run_tests).
+#[derive(Debug, Default)]
+pub struct TestReport {
+    /// How many cases passed.
+    pub passed: usize,
+    /// The cases that failed, in order.
+    pub failures: Vec<TestFailure>,
+}
+
+/// A test case that failed.
+#[derive(Debug)]
+pub struct TestFailure {
+    /// The name of the case.
+    pub case: String,
+    pub expected: Outcome,
+    /// What the filters made of the value, or the error they failed with.
+    pub actual: Result<Outcome, FilterError>,
+}
+
+impl TestReport {
+    /// Whether every case passed.
+    pub fn all_passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+impl fmt::Display for TestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for failure in &self.failures {
+            write!(
+                f,
+                "case `{}`: expected {}, ",
+                failure.case, failure.expected
+            )?;
+            match &failure.actual {
+                Ok(actual) => writeln!(f, "got {actual}")?,
+                Err(err) => writeln!(f, "failed: {err}")?,
+            }
+        }
+        let result = match self.all_passed() {
+            true => "ok",
+            false => "FAILED",
+        };
+        write!(
+            f,
+            "test result: {result}. {} passed; {} failed",
+            self.passed,
+            self.failures.len()
+        )
+    }
+}
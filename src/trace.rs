@@ -0,0 +1,82 @@
+//! Tracing of the load path, through the [`tracing`](https://docs.rs/tracing) crate with the
+//! `tracing` feature.
+//!
+//! Loading a configuration runs in a `load` span, and the scripts of each chain in a
+//! `load_chain` span with a `chain` field. Each library and script that loaded emits a debug
+//! event with how long each phase took, the same durations as in the
+//! [`LoadReport`](crate::LoadReport).
+//!
+//! Without the feature, none of this is compiled in.
+
+#[cfg(feature = "tracing")]
+pub(crate) use enabled::*;
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) use disabled::*;
+
+#[cfg(feature = "tracing")]
+mod enabled {
+    use std::{path::Path, time::Duration};
+
+    use tracing::span::EnteredSpan;
+
+    use crate::LoadedFilter;
+
+    /// Enter the span of a load, left when the guard is dropped.
+    pub(crate) fn load(chains: usize, libraries: usize) -> EnteredSpan {
+        tracing::info_span!("load", chains, libraries).entered()
+    }
+
+    /// Enter the span of the scripts of `chain`, left when the guard is dropped.
+    pub(crate) fn load_chain(chain: &str, filters: usize) -> EnteredSpan {
+        tracing::info_span!("load_chain", chain, filters).entered()
+    }
+
+    pub(crate) fn library_loaded(name: &str, path: &Path, eval: Duration) {
+        tracing::debug!(
+            library = name,
+            path = %path.display(),
+            eval_seconds = eval.as_secs_f64(),
+            "library loaded"
+        );
+    }
+
+    pub(crate) fn script_loaded(loaded: &LoadedFilter) {
+        tracing::debug!(
+            filter = loaded.name,
+            path = %loaded.script.display(),
+            bytes = loaded.size,
+            origin = %loaded.origin,
+            read_seconds = loaded.read_time.as_secs_f64(),
+            compile_seconds = loaded.compile_time.as_secs_f64(),
+            eval_seconds = loaded.eval_time.as_secs_f64(),
+            "script loaded"
+        );
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+mod disabled {
+    use std::{path::Path, time::Duration};
+
+    use crate::LoadedFilter;
+
+    /// Nothing, without the `tracing` feature.
+    pub(crate) struct Entered;
+
+    #[inline(always)]
+    pub(crate) fn load(_chains: usize, _libraries: usize) -> Entered {
+        Entered
+    }
+
+    #[inline(always)]
+    pub(crate) fn load_chain(_chain: &str, _filters: usize) -> Entered {
+        Entered
+    }
+
+    #[inline(always)]
+    pub(crate) fn library_loaded(_name: &str, _path: &Path, _eval: Duration) {}
+
+    #[inline(always)]
+    pub(crate) fn script_loaded(_loaded: &LoadedFilter) {}
+}
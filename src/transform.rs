@@ -0,0 +1,66 @@
+//! Turning what filters return into values of another type, see
+//! [`FilterSystem::filter_transform`](crate::FilterSystem::filter_transform).
+//!
+//! Outputs go through JSON: LuaJIT and Luau have no integers, so numbers without a fractional
+//! part are made integers first, or they wouldn't deserialize into integer fields.
+
+use mlua::{Lua, LuaSerdeExt};
+use serde::de::DeserializeOwned;
+use serde_json::{Number, Value};
+use serde_path_to_error::Segment;
+
+use crate::FilterError;
+
+/// The JSON of an output.
+pub(crate) fn to_json(lua: &Lua, output: mlua::Value) -> mlua::Result<Value> {
+    Ok(integral(lua.from_value(output)?))
+}
+
+fn integral(value: Value) -> Value {
+    match value {
+        Value::Number(number) => match number.as_f64() {
+            Some(float) if number.is_f64() && float.fract() == 0.0 => {
+                let integer = match float < 0.0 {
+                    true => (float >= i64::MIN as f64).then(|| Number::from(float as i64)),
+                    false => (float < u64::MAX as f64).then(|| Number::from(float as u64)),
+                };
+                Value::Number(integer.unwrap_or(number))
+            }
+            _ => Value::Number(number),
+        },
+        Value::Array(values) => Value::Array(values.into_iter().map(integral).collect()),
+        Value::Object(fields) => {
+            let fields = fields.into_iter();
+            Value::Object(fields.map(|(key, value)| (key, integral(value))).collect())
+        }
+        value => value,
+    }
+}
+
+/// Deserialize the output of `filter`.
+pub(crate) fn deserialize<U: DeserializeOwned>(
+    filter: &str,
+    output: Value,
+) -> Result<U, FilterError> {
+    serde_path_to_error::deserialize(output).map_err(|err| {
+        let message = err.inner().to_string();
+        let path = err.path();
+        // Missing fields are only named by the message, under the path of their parent.
+        let missing = message
+            .strip_prefix("missing field `")
+            .and_then(|rest| rest.split('`').next());
+        let field = missing.map(str::to_string).or_else(|| {
+            let mut segments = path.iter().rev();
+            segments.find_map(|segment| match segment {
+                Segment::Map { key } => Some(key.clone()),
+                _ => None,
+            })
+        });
+        FilterError::Transform {
+            filter: filter.to_string(),
+            path: path.to_string(),
+            field,
+            message,
+        }
+    })
+}
@@ -0,0 +1,86 @@
+//! Remembering the verdicts of values filtered before, see
+//! [`FilterSystem::set_verdict_cache`](crate::FilterSystem::set_verdict_cache).
+//!
+//! Values are known by a 64-bit hash of their key, the JSON of the value unless
+//! [`FilterSystem::set_cache_key`](crate::FilterSystem::set_cache_key) gives another one.
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
+use serde::Serialize;
+
+use crate::helpers::cache::LruCache;
+
+/// The hash of the key of a value, given by the caller.
+pub(crate) type CacheKey<T> = Box<dyn Fn(&T) -> u64>;
+
+/// The counters of the verdict cache of a filter system.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct VerdictCacheStats {
+    /// How many values got a cached verdict.
+    pub hits: u64,
+    /// How many values were filtered for want of one.
+    pub misses: u64,
+    /// How many verdicts are cached.
+    pub entries: usize,
+    pub capacity: usize,
+}
+
+/// Verdicts by value hash, least recently used first out.
+pub(crate) struct VerdictCache {
+    verdicts: LruCache<u64, bool>,
+    hits: u64,
+    misses: u64,
+}
+
+impl VerdictCache {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            verdicts: LruCache::new(capacity),
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// The verdict cached for `key`, counting a hit or a miss.
+    pub(crate) fn get(&mut self, key: u64) -> Option<bool> {
+        let verdict = self.verdicts.get(&key).copied();
+        match verdict {
+            Some(_) => self.hits += 1,
+            None => self.misses += 1,
+        }
+        verdict
+    }
+
+    pub(crate) fn insert(&mut self, key: u64, verdict: bool) {
+        self.verdicts.insert(key, verdict);
+    }
+
+    /// Forget every verdict, keeping the counters.
+    pub(crate) fn clear(&mut self) {
+        self.verdicts.clear();
+    }
+
+    pub(crate) fn stats(&self) -> VerdictCacheStats {
+        VerdictCacheStats {
+            hits: self.hits,
+            misses: self.misses,
+            entries: self.verdicts.len(),
+            capacity: self.verdicts.capacity(),
+        }
+    }
+}
+
+/// The hash of `key`.
+pub(crate) fn hash(key: &impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// The hash of the JSON of `value`, unless it doesn't serialize.
+pub(crate) fn hash_json(value: &impl Serialize) -> Option<u64> {
+    serde_json::to_vec(value).ok().map(|json| hash(&json))
+}
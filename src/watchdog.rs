@@ -0,0 +1,272 @@
+//! An instruction hook that aborts Lua code from the host side.
+//!
+//! Luau has no debug hooks; its interrupt callback, which runs at function calls and loop
+//! back-edges, is used instead.
+//!
+//! Reading the clock for call timeouts and deadlines costs more than checking the flags, so it
+//! is read only every few checks. The stride adapts to how fast checks come, so that the clock
+//! is read roughly every [`CLOCK_INTERVAL`] whatever the filter does.
+//!
+//! The hook also burns the fuel of filter calls: [`CHECK_INTERVAL`] units per check, or one
+//! per interrupt under Luau, so fuel is only accurate over many calls. While profiling, it
+//! runs at the sampling interval instead and burns that many units.
+
+use std::{
+    cell::Cell,
+    fmt,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use mlua::{Lua, TableExt};
+
+use crate::profile::Profiler;
+
+/// How many instructions run between two watchdog checks.
+#[cfg(not(feature = "luau"))]
+const CHECK_INTERVAL: u32 = 1000;
+
+/// How often the clock should be read while a filter call with a timeout runs.
+const CLOCK_INTERVAL: Duration = Duration::from_micros(500);
+
+/// The most checks between two clock reads.
+const MAX_STRIDE: u32 = 1024;
+
+/// Why the watchdog aborted a filter call.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Trip {
+    Cancelled,
+    Interrupted,
+    /// The call ran past its timeout; holds how long it had run.
+    Timeout(Duration),
+    /// The call used up the fuel it was allowed.
+    OutOfFuel,
+    /// The call ran past the deadline of its batch.
+    Deadline,
+}
+
+impl fmt::Display for Trip {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Trip::Cancelled => f.write_str("filtering was cancelled"),
+            Trip::Interrupted => f.write_str("the filter call was interrupted"),
+            Trip::Timeout(elapsed) => write!(f, "the filter call timed out after {elapsed:?}"),
+            Trip::OutOfFuel => f.write_str("the filter call ran out of fuel"),
+            Trip::Deadline => f.write_str("the filter call ran past the deadline"),
+        }
+    }
+}
+
+impl std::error::Error for Trip {}
+
+impl Trip {
+    /// Find the watchdog trip that caused a Lua error, if any.
+    pub(crate) fn find(err: &mlua::Error) -> Option<Trip> {
+        match err {
+            mlua::Error::ExternalError(err) => err.downcast_ref::<Trip>().copied(),
+            mlua::Error::CallbackError { cause, .. } => Trip::find(cause),
+            mlua::Error::WithContext { cause, .. } => Trip::find(cause),
+            _ => None,
+        }
+    }
+}
+
+/// The conditions a watchdog checks while Lua code runs.
+#[derive(Clone, Default)]
+pub(crate) struct Watchdog {
+    pub cancel: Option<Arc<AtomicBool>>,
+    pub interrupt: Option<Arc<AtomicBool>>,
+    /// How long a filter call may run, and when the current one started.
+    pub timeout: Option<(Duration, Rc<Cell<Instant>>)>,
+    /// When the calls must have finished, whenever they started.
+    pub deadline: Option<Instant>,
+    pub fuel: Option<Fuel>,
+    pub profiler: Option<Profiler>,
+}
+
+/// The fuel burnt by filter calls, and how much the running one may burn.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Fuel {
+    burnt: Rc<Cell<u64>>,
+    /// The value of `burnt` past which the running call is aborted.
+    limit: Rc<Cell<u64>>,
+}
+
+impl Fuel {
+    /// The fuel burnt so far.
+    pub(crate) fn burnt(&self) -> u64 {
+        self.burnt.get()
+    }
+
+    /// Let the next call burn `fuel` more units before it is aborted.
+    pub(crate) fn allow(&self, fuel: u64) {
+        self.limit.set(self.burnt.get().saturating_add(fuel));
+    }
+
+    /// Burn `units`, returning whether the running call went past its limit.
+    fn burn(&self, units: u64) -> bool {
+        let burnt = self.burnt.get() + units;
+        self.burnt.set(burnt);
+        burnt > self.limit.get()
+    }
+}
+
+/// When to read the clock next.
+struct Clock {
+    stride: Cell<u32>,
+    countdown: Cell<u32>,
+    last: Cell<Instant>,
+}
+
+impl Clock {
+    fn new() -> Self {
+        Self {
+            stride: Cell::new(1),
+            countdown: Cell::new(1),
+            last: Cell::new(Instant::now()),
+        }
+    }
+
+    /// Whether this check should read the clock.
+    fn due(&self) -> bool {
+        let countdown = self.countdown.get() - 1;
+        self.countdown.set(countdown);
+        countdown == 0
+    }
+
+    /// Record a clock read, widening the stride if reads come too often and narrowing it if
+    /// they come too rarely.
+    fn read(&self, now: Instant) {
+        let gap = now.saturating_duration_since(self.last.replace(now));
+        let stride = self.stride.get();
+        let stride = if gap < CLOCK_INTERVAL / 2 {
+            (stride * 2).min(MAX_STRIDE)
+        } else if gap > CLOCK_INTERVAL * 2 {
+            (stride / 2).max(1)
+        } else {
+            stride
+        };
+        self.stride.set(stride);
+        self.countdown.set(stride);
+    }
+}
+
+impl Watchdog {
+    /// Whether there is nothing to check.
+    pub(crate) fn is_idle(&self) -> bool {
+        self.cancel.is_none()
+            && self.interrupt.is_none()
+            && self.timeout.is_none()
+            && self.deadline.is_none()
+            && self.fuel.is_none()
+            && self.profiler.is_none()
+    }
+
+    /// Check the conditions after `instructions` ran, returning the first one that tripped.
+    fn check(&self, clock: &Clock, instructions: u64) -> Option<Trip> {
+        let set = |flag: &Option<Arc<AtomicBool>>| {
+            flag.as_ref()
+                .is_some_and(|flag| flag.load(Ordering::Relaxed))
+        };
+        let out_of_fuel = self
+            .fuel
+            .as_ref()
+            .is_some_and(|fuel| fuel.burn(instructions));
+        if set(&self.cancel) {
+            Some(Trip::Cancelled)
+        } else if set(&self.interrupt) {
+            Some(Trip::Interrupted)
+        } else if out_of_fuel {
+            Some(Trip::OutOfFuel)
+        } else {
+            if (self.timeout.is_none() && self.deadline.is_none()) || !clock.due() {
+                return None;
+            }
+            let now = Instant::now();
+            clock.read(now);
+            if self.deadline.is_some_and(|deadline| now >= deadline) {
+                return Some(Trip::Deadline);
+            }
+            let (timeout, started) = self.timeout.as_ref()?;
+            let elapsed = now.saturating_duration_since(started.get());
+            (elapsed > *timeout).then_some(Trip::Timeout(elapsed))
+        }
+    }
+
+    /// Run `f` with the watchdog hook installed.
+    ///
+    /// LuaJIT doesn't run hooks inside compiled traces, so the JIT compiler is switched off
+    /// (and its traces flushed) for the duration of the call.
+    pub(crate) fn watch<R>(&self, lua: &Lua, f: impl FnOnce() -> R) -> mlua::Result<R> {
+        let jit: Option<mlua::Table> = lua.globals().get("jit")?;
+        if let Some(jit) = &jit {
+            jit.call_function::<_, ()>("off", ())?;
+            jit.call_function::<_, ()>("flush", ())?;
+        }
+
+        let watchdog = self.clone();
+        let clock = Clock::new();
+        #[cfg(not(feature = "luau"))]
+        {
+            let interval = self
+                .profiler
+                .as_ref()
+                .map_or(CHECK_INTERVAL, Profiler::interval);
+            lua.set_hook(
+                mlua::HookTriggers::new().every_nth_instruction(interval),
+                move |_lua, debug| {
+                    if let Some(profiler) = &watchdog.profiler {
+                        profiler.sample(&debug);
+                    }
+                    match watchdog.check(&clock, interval.into()) {
+                        Some(trip) => Err(mlua::Error::external(trip)),
+                        None => Ok(()),
+                    }
+                },
+            );
+        }
+        #[cfg(feature = "luau")]
+        lua.set_interrupt(move |lua| {
+            if let (Some(profiler), Some(debug)) = (&watchdog.profiler, lua.inspect_stack(0)) {
+                profiler.sample(&debug);
+            }
+            match watchdog.check(&clock, 1) {
+                Some(trip) => Err(mlua::Error::external(trip)),
+                None => Ok(mlua::VmState::Continue),
+            }
+        });
+        let result = f();
+        #[cfg(not(feature = "luau"))]
+        lua.remove_hook();
+        #[cfg(feature = "luau")]
+        lua.remove_interrupt();
+
+        if let Some(jit) = &jit {
+            jit.call_function::<_, ()>("on", ())?;
+        }
+        Ok(result)
+    }
+}
+
+/// Aborts the filter call a filter system is running, from another thread.
+///
+/// Created by [`FilterSystem::interrupt_handle`](crate::FilterSystem::interrupt_handle).
+#[derive(Clone, Debug)]
+pub struct InterruptHandle {
+    flag: Arc<AtomicBool>,
+}
+
+impl InterruptHandle {
+    pub(crate) fn new(flag: Arc<AtomicBool>) -> Self {
+        Self { flag }
+    }
+
+    /// Abort the running filter call at the next watchdog check.
+    pub fn interrupt(&self) {
+        self.flag.store(true, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,160 @@
+//! Drives the `croncat-filter` binary.
+
+#![cfg(feature = "cli")]
+
+use std::{
+    io::Write,
+    path::PathBuf,
+    process::{Command, Output, Stdio},
+};
+
+use indoc::indoc;
+
+/// A configuration with a chain of two filters, a wildcard chain of a filter matching nothing
+/// and a chain that must not be loaded.
+fn config(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "croncat-indexer-filter-cli-{name}-{}",
+        std::process::id()
+    ));
+    std::fs::create_dir_all(&dir).unwrap();
+    let script = |file: &str, source: &str| {
+        let path = dir.join(file);
+        std::fs::write(&path, source).unwrap();
+        path.display().to_string()
+    };
+    let big = script("big.lua", "return function(tx) return tx.amount > 100 end");
+    let manager = script(
+        "manager.lua",
+        indoc! {r#"
+        return function(tx)
+            if tx.from == "0xBAD" then
+                error("bad sender")
+            end
+            return tx.from == "0xDEADBEEF"
+        end
+        "#},
+    );
+    let audit = script("audit.lua", "return function(tx) return false end");
+    let other = script("other.lua", "return function(tx) return true end");
+    let config = dir.join("filters.yaml");
+    std::fs::write(
+        &config,
+        format!(
+            indoc! {r#"
+            chains:
+              uni-5:
+                - name: big
+                  script: {big}
+                - name: manager
+                  script: {manager}
+              "*":
+                - name: audit
+                  script: {audit}
+              juno-1:
+                - name: other
+                  script: {other}
+            "#},
+            big = big,
+            manager = manager,
+            audit = audit,
+            other = other,
+        ),
+    )
+    .unwrap();
+    config
+}
+
+fn run(config: &PathBuf, flags: &[&str], input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_croncat-filter"))
+        .arg("--config")
+        .arg(config)
+        .args(["--chain", "uni-5"])
+        .args(flags)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+const INPUT: &str = indoc! {r#"
+    {"from": "0xDEADBEEF", "amount": 1}
+    {"from": "0xA", "amount": 10}
+
+    not json
+    {"from": "0xB",  "amount": 500}
+"#};
+
+#[test]
+fn pipe() {
+    let config = config("pipe");
+    let output = run(&config, &[], INPUT);
+    assert!(output.status.success());
+    // Kept lines come out as they were read.
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "{\"from\": \"0xDEADBEEF\", \"amount\": 1}\n{\"from\": \"0xB\",  \"amount\": 500}\n"
+    );
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("line 4 isn't JSON"), "{stderr}");
+    assert!(stderr.contains("4 lines, 2 kept, 1 not JSON"), "{stderr}");
+    assert!(
+        stderr.contains("uni-5/big/big: 1 matched of 3, 0 errors"),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains("uni-5/manager/manager: 1 matched of 3, 0 errors"),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains("*/audit/audit: 0 matched of 3, 0 errors"),
+        "{stderr}"
+    );
+    assert!(!stderr.contains("other"), "{stderr}");
+
+    let output = run(&config, &["--detailed"], INPUT);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "{\"from\": \"0xDEADBEEF\", \"amount\": 1}\tuni-5/manager/manager\n\
+         {\"from\": \"0xB\",  \"amount\": 500}\tuni-5/big/big\n"
+    );
+}
+
+#[test]
+fn errors() {
+    let config = config("errors");
+    let input = "{\"from\": \"0xBAD\", \"amount\": 500}\n{\"from\": \"0xDEADBEEF\"}\n";
+
+    // Filters failing stop the run, unless it is lenient.
+    let output = run(&config, &[], input);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("bad sender"), "{stderr}");
+    assert!(
+        stderr.contains("uni-5/manager/manager: 0 matched of 1, 1 errors"),
+        "{stderr}"
+    );
+
+    let output = run(&config, &["--lenient", "--detailed"], input);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.lines().count(), 2, "{stdout}");
+    assert!(
+        stdout.contains("0xBAD\", \"amount\": 500}\tuni-5/big/big"),
+        "{stdout}"
+    );
+
+    let output = run(&config, &["--chain", "osmosis-1"], "");
+    assert_eq!(output.status.code(), Some(1));
+    let output = run(&config, &["--verbose"], "");
+    assert_eq!(output.status.code(), Some(2));
+}